@@ -0,0 +1,114 @@
+use std::{borrow::Cow, collections::HashMap};
+
+use nom::combinator::all_consuming;
+
+use super::{local::rewrite_words, parse::parse_reg};
+
+// Register aliases defined by the source with .req, which can be removed again with .unreq.
+// Uses of an alias are rewritten to the register it names, so the rest of the assembler only
+// sees registers.
+//
+// eg: "count .req r4" ... "subs count,count,#1" becomes "subs r4,r4,#1"
+//
+#[derive(Default)]
+pub struct RegisterAliases {
+    aliases: HashMap<String, u8>,
+}
+
+impl RegisterAliases {
+    // Defines or removes an alias if the line is a .req or an .unreq, returning whether it was
+    // one. Names must not already be registers or aliases, and only aliases can be removed.
+    pub fn update(&mut self, line: &str) -> Result<bool, String> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match words[..] {
+            [name, ".req", register] => {
+                if !is_name(name) {
+                    return Err(format!("Invalid register alias '{}'", name));
+                }
+                if is_register(name) {
+                    return Err(format!("'{}' is already a register", name));
+                }
+                if let Some(r) = self.aliases.get(name) {
+                    return Err(format!("'{}' is already an alias of r{}", name, r));
+                }
+                let r = self.register(register).ok_or_else(|| {
+                    format!("Invalid register '{}' for alias '{}'", register, name)
+                })?;
+                self.aliases.insert(String::from(name), r);
+                Ok(true)
+            }
+            [_, ".req", ..] => Err(String::from(
+                "Invalid .req, expected <name> .req <register>",
+            )),
+            [".unreq", name] => match self.aliases.remove(name) {
+                Some(_) => Ok(true),
+                None => Err(format!("'{}' isn't a register alias", name)),
+            },
+            [".unreq", ..] => Err(String::from("Invalid .unreq, expected .unreq <name>")),
+            _ => Ok(false),
+        }
+    }
+
+    // Rewrites the uses of aliases in a line to the registers they name. The mnemonic is left
+    // alone, so an alias can share its name with one.
+    pub fn resolve<'l>(&self, line: &'l str) -> Cow<'l, str> {
+        if self.aliases.is_empty() {
+            return Cow::Borrowed(line);
+        }
+        rewrite_words(line, |index, word| {
+            Ok(self
+                .aliases
+                .get(word)
+                .filter(|_| index > 0)
+                .map(|r| format!("r{}", r)))
+        })
+        .expect("rewriting register aliases cannot fail")
+    }
+
+    // The number of a register given by its name or an alias
+    fn register(&self, name: &str) -> Option<u8> {
+        self.aliases
+            .get(name)
+            .copied()
+            .or_else(|| all_consuming(parse_reg)(name).ok().map(|(_, r)| r))
+    }
+}
+
+fn is_name(name: &str) -> bool {
+    name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+fn is_register(name: &str) -> bool {
+    all_consuming(parse_reg)(name).is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_register_aliases() {
+        let mut aliases = RegisterAliases::default();
+        assert_eq!(aliases.update("count .req r4"), Ok(true));
+        assert_eq!(aliases.update("top .req sp"), Ok(true));
+        assert_eq!(aliases.update("index .req count"), Ok(true));
+        assert_eq!(aliases.update("mov r0,#1"), Ok(false));
+
+        assert_eq!(aliases.resolve("subs count,count,#1"), "subs r4,r4,#1");
+        assert_eq!(aliases.resolve("str index,[top,#-4]!"), "str r4,[r13,#-4]!");
+        assert_eq!(aliases.resolve(".ascii \"count\""), ".ascii \"count\"");
+
+        // Names can't be reused or be registers, and must name a register
+        assert!(aliases.update("count .req r5").is_err());
+        assert!(aliases.update("lr .req r5").is_err());
+        assert!(aliases.update("r1 .req r5").is_err());
+        assert!(aliases.update("spare .req r16").is_err());
+        assert!(aliases.update("spare .req").is_err());
+
+        assert_eq!(aliases.update(".unreq count"), Ok(true));
+        assert_eq!(aliases.resolve("mov count,#1"), "mov count,#1");
+        assert!(aliases.update(".unreq count").is_err());
+        assert_eq!(aliases.update("count .req r5"), Ok(true));
+    }
+}
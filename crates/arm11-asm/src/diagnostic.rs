@@ -0,0 +1,556 @@
+use std::{error::Error, fmt};
+
+use super::include::Origin;
+use arm11_isa::{
+    parse::{ArmNomError, ArmNomErrorKind},
+    ArmError,
+};
+
+// An error in the source, located by its line and column, which are both counted from 1, and
+// the number of characters it spans, and classified by its code. It is displayed like a rustc
+// error, with the line of source and a caret under the span:
+//
+// eg: prog.s:3:8: error[E0004]: unexpected 'rx' while parsing register
+//       |
+//     3 | add r1,rx,#1
+//       |        ^^
+//
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub code: DiagnosticCode,
+    pub severity: Severity,
+    pub file: Option<String>,
+    pub line: usize,
+    pub column: usize,
+    pub len: usize,
+    pub message: String,
+    pub source_line: String,
+}
+
+// The kind of error a diagnostic reports. The codes are stable, so tools and tests can match
+// on them rather than on the wording of the message. Errors in binaries are numbered from
+// E0101, see arm11_emu::ImageError.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiagnosticCode {
+    UnknownMnemonic,
+    UnexpectedToken,
+    UnexpectedEnd,
+    InvalidRegister,
+    InvalidNumber,
+    UnencodableConstant,
+    Truncated,
+    LiteralOutOfRange,
+    LiteralPoolFull,
+    NoThumbEncoding,
+    InvalidExpression,
+    InvalidLabel,
+    InstructionInData,
+    Include,
+    DuplicateLabel,
+    NoArmEncoding,
+}
+
+impl DiagnosticCode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DiagnosticCode::UnknownMnemonic => "E0001",
+            DiagnosticCode::UnexpectedToken => "E0002",
+            DiagnosticCode::UnexpectedEnd => "E0003",
+            DiagnosticCode::InvalidRegister => "E0004",
+            DiagnosticCode::InvalidNumber => "E0005",
+            DiagnosticCode::UnencodableConstant => "E0006",
+            DiagnosticCode::Truncated => "E0007",
+            DiagnosticCode::LiteralOutOfRange => "E0008",
+            DiagnosticCode::LiteralPoolFull => "E0009",
+            DiagnosticCode::NoThumbEncoding => "E0010",
+            DiagnosticCode::InvalidExpression => "E0011",
+            DiagnosticCode::InvalidLabel => "E0012",
+            DiagnosticCode::InstructionInData => "E0013",
+            DiagnosticCode::Include => "E0014",
+            DiagnosticCode::DuplicateLabel => "E0015",
+            DiagnosticCode::NoArmEncoding => "E0016",
+        }
+    }
+
+    // The kind of ArmError a source fails to assemble with when this is its first error
+    fn kind(&self) -> fn(Box<dyn Error>) -> ArmError {
+        match self {
+            DiagnosticCode::UnencodableConstant
+            | DiagnosticCode::Truncated
+            | DiagnosticCode::LiteralOutOfRange => ArmError::Immediate,
+            DiagnosticCode::LiteralPoolFull
+            | DiagnosticCode::NoThumbEncoding
+            | DiagnosticCode::NoArmEncoding
+            | DiagnosticCode::InstructionInData => ArmError::Encode,
+            _ => ArmError::Parse,
+        }
+    }
+}
+
+// Whether a diagnostic stops the source from assembling. Errors sort before warnings, so
+// they're reported first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Severity::Error => f.write_str("error"),
+            Severity::Warning => f.write_str("warning"),
+        }
+    }
+}
+
+impl fmt::Display for DiagnosticCode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl Diagnostic {
+    // An error covering the whole of a line, ignoring indentation, for errors which aren't tied
+    // to one part of it, eg: an undefined label in an expression
+    pub fn for_line(
+        code: DiagnosticCode,
+        line: usize,
+        source_line: &str,
+        message: impl Into<String>,
+    ) -> Self {
+        let start = source_line.len() - source_line.trim_start().len();
+        Diagnostic {
+            code,
+            severity: Severity::Error,
+            file: None,
+            line,
+            column: start + 1,
+            len: source_line.trim().len().max(1),
+            message: message.into(),
+            source_line: String::from(source_line),
+        }
+    }
+
+    // Locates a parse error in the line it was parsing. The error is placed at the token where
+    // parsing stopped, and described by what was being parsed.
+    pub fn from_nom(line: usize, source_line: &str, err: nom::Err<ArmNomError<&str>>) -> Self {
+        let err = match err {
+            nom::Err::Error(e) | nom::Err::Failure(e) => e,
+            nom::Err::Incomplete(_) => {
+                return Diagnostic::for_line(
+                    DiagnosticCode::UnexpectedEnd,
+                    line,
+                    source_line,
+                    "incomplete instruction",
+                )
+            }
+        };
+
+        let offset = err
+            .input()
+            .and_then(|rest| offset_in(source_line, rest))
+            .unwrap_or(0);
+        // Point at the start of the token which failed, rather than part way through it
+        let in_token = |c: char| c.is_alphanumeric() || c == '_' || c == '#' || c == '.';
+        let offset = if source_line[offset..].starts_with(in_token) {
+            source_line[..offset].trim_end_matches(in_token).len()
+        } else {
+            offset
+        };
+        let rest = &source_line[offset..];
+        let token = rest
+            .trim_start()
+            .split(|c: char| c.is_whitespace() || c == ',' || c == ']')
+            .find(|token| !token.is_empty())
+            .unwrap_or("");
+        let offset = match offset_in(source_line, token) {
+            Some(start) if !token.is_empty() => start,
+            _ => offset + rest.len() - rest.trim_start().len(),
+        };
+
+        let (code, problem) = match err.kind {
+            ArmNomErrorKind::Operand2Constant(constant) => (
+                DiagnosticCode::UnencodableConstant,
+                format!(
+                    "constant 0x{:x} can't be encoded as an 8 bit rotated immediate",
+                    constant
+                ),
+            ),
+            ArmNomErrorKind::Truncated(field, value, truncated) => (
+                DiagnosticCode::Truncated,
+                format!(
+                    "{} {} can't be encoded, it would be truncated to {}",
+                    field, value, truncated
+                ),
+            ),
+            ArmNomErrorKind::LiteralOutOfRange(offset) => (
+                DiagnosticCode::LiteralOutOfRange,
+                format!(
+                    "literal pool is {} bytes {} this ldr, out of range of its offset; add a \
+                     .ltorg within 4KiB of it",
+                    offset.abs(),
+                    if offset < 0 { "before" } else { "after" }
+                ),
+            ),
+            ArmNomErrorKind::HexadecimalValue => (
+                DiagnosticCode::InvalidNumber,
+                String::from("invalid hexadecimal value"),
+            ),
+            ArmNomErrorKind::DecimalValue => (
+                DiagnosticCode::InvalidNumber,
+                String::from("invalid decimal value"),
+            ),
+            ArmNomErrorKind::SignedDecimalValue => (
+                DiagnosticCode::InvalidNumber,
+                String::from("invalid signed decimal value"),
+            ),
+            _ if source_line[..offset].trim().is_empty() => (
+                DiagnosticCode::UnknownMnemonic,
+                format!("unknown mnemonic '{}'", token),
+            ),
+            _ if token.is_empty() => (
+                DiagnosticCode::UnexpectedEnd,
+                String::from("unexpected end of line"),
+            ),
+            _ if err.context() == Some("parsing register") => (
+                DiagnosticCode::InvalidRegister,
+                format!("unexpected '{}'", token),
+            ),
+            _ => (
+                DiagnosticCode::UnexpectedToken,
+                format!("unexpected '{}'", token),
+            ),
+        };
+        let message = match err.context() {
+            Some(context) if offset > 0 => format!("{} while {}", problem, context),
+            _ => problem,
+        };
+
+        Diagnostic {
+            code,
+            severity: Severity::Error,
+            file: None,
+            line,
+            column: offset + 1,
+            len: token.len().max(1),
+            message,
+            source_line: String::from(source_line),
+        }
+    }
+
+    // An error for the text left over once a whole instruction or directive has been parsed,
+    // which is a mistake rather than something which can be ignored, eg: .word 1 junk
+    pub fn trailing(line: usize, source_line: &str, rest: &str, after: &str) -> Self {
+        let rest = rest.trim();
+        let offset = offset_in(source_line, rest).unwrap_or(0);
+        Diagnostic {
+            code: DiagnosticCode::UnexpectedToken,
+            severity: Severity::Error,
+            file: None,
+            line,
+            column: offset + 1,
+            len: rest.len(),
+            message: format!("unexpected '{}' after {}", rest, after),
+            source_line: String::from(source_line),
+        }
+    }
+
+    pub fn in_file(mut self, file: &str) -> Self {
+        self.file = Some(String::from(file));
+        self
+    }
+
+    // Moves the error from its line in the expanded source to the file and line it was written
+    // on. Sources without a name, eg: one passed to assemble, aren't named in the error.
+    pub(crate) fn at(mut self, origin: &Origin) -> Self {
+        self.file = Some(origin.file.clone()).filter(|file| !file.is_empty());
+        self.line = origin.line;
+        self
+    }
+
+    // Moves the error from a rewritten line back to the line as it was written, eg: after
+    // expressions are replaced with their values. Columns before or after the rewritten part
+    // are kept, and anything inside it points to its start.
+    pub fn in_source(mut self, original: &str) -> Self {
+        let rewritten = &self.source_line;
+        let prefix = original
+            .chars()
+            .zip(rewritten.chars())
+            .take_while(|(a, b)| a == b)
+            .count();
+        let suffix = original
+            .chars()
+            .rev()
+            .zip(rewritten.chars().rev())
+            .take_while(|(a, b)| a == b)
+            .count()
+            .min(original.len() - prefix)
+            .min(rewritten.len() - prefix);
+
+        let start = self.column - 1;
+        if start >= rewritten.len() - suffix {
+            self.column = start + original.len() - rewritten.len() + 1;
+        } else if start >= prefix {
+            self.column = prefix + 1;
+            self.len = original.len() - suffix - prefix;
+        }
+        self.len = self.len.min(original.len() + 1 - self.column).max(1);
+        self.source_line = String::from(original);
+        self
+    }
+}
+
+// The position of a slice of a string in that string, if it is one
+fn offset_in(outer: &str, inner: &str) -> Option<usize> {
+    let start = outer.as_ptr() as usize;
+    let position = inner.as_ptr() as usize;
+    (position >= start && position + inner.len() <= start + outer.len()).then(|| position - start)
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(file) = &self.file {
+            write!(f, "{}:", file)?;
+        }
+        writeln!(
+            f,
+            "{}:{}: {}[{}]: {}",
+            self.line, self.column, self.severity, self.code, self.message
+        )?;
+
+        let gutter = " ".repeat(self.line.to_string().len());
+        writeln!(f, "{} |", gutter)?;
+        writeln!(f, "{} | {}", self.line, self.source_line)?;
+        write!(
+            f,
+            "{} | {}{}",
+            gutter,
+            " ".repeat(self.column - 1),
+            "^".repeat(self.len)
+        )
+    }
+}
+
+impl Error for Diagnostic {}
+
+impl From<Diagnostic> for ArmError {
+    fn from(diagnostic: Diagnostic) -> Self {
+        diagnostic.code.kind()(Box::new(diagnostic))
+    }
+}
+
+// Number of distinct diagnostics shown by default, so a badly broken source doesn't bury the
+// first errors in its output
+pub const DEFAULT_MAX_ERRORS: usize = 20;
+
+// Every diagnostic found in a source, sorted so errors come before warnings and then by the file
+// and line they are on. Identical diagnostics are shown once with a count, and at most limit of them are
+// shown, followed by a summary of the rest:
+//
+// eg: prog.s:3:1: error[E0001]: unknown mnemonic 'addd' (x42)
+//       |
+//     3 | addd r1,r1,#1
+//       | ^^^^
+//
+//     45 errors; 3 more not shown, use --max-errors 0 to show them all
+//
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostics {
+    diagnostics: Vec<Diagnostic>,
+    // The number of distinct diagnostics shown, or 0 to show them all
+    limit: usize,
+}
+
+impl Diagnostics {
+    // Collects the diagnostics, of which there must be at least one
+    pub fn new(mut diagnostics: Vec<Diagnostic>) -> Self {
+        assert!(!diagnostics.is_empty(), "no diagnostics to report");
+        diagnostics.sort_by(|a, b| {
+            (a.severity, &a.file, a.line, a.column).cmp(&(b.severity, &b.file, b.line, b.column))
+        });
+        Diagnostics {
+            diagnostics,
+            limit: DEFAULT_MAX_ERRORS,
+        }
+    }
+
+    pub fn with_limit(mut self, limit: usize) -> Self {
+        self.limit = limit;
+        self
+    }
+
+    pub fn in_file(mut self, file: &str) -> Self {
+        self.diagnostics = self
+            .diagnostics
+            .into_iter()
+            .map(|d| d.in_file(file))
+            .collect();
+        self
+    }
+
+    // The diagnostic which is reported first
+    pub fn first(&self) -> &Diagnostic {
+        &self.diagnostics[0]
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Diagnostic> {
+        self.diagnostics.iter()
+    }
+
+    pub fn errors(&self) -> usize {
+        self.iter()
+            .filter(|d| d.severity == Severity::Error)
+            .count()
+    }
+
+    pub fn warnings(&self) -> usize {
+        self.diagnostics.len() - self.errors()
+    }
+
+    // Each distinct diagnostic, the first place it was found, and how many times it was found
+    fn grouped(&self) -> Vec<(&Diagnostic, usize)> {
+        let mut groups: Vec<(&Diagnostic, usize)> = Vec::new();
+        for diagnostic in &self.diagnostics {
+            let same = groups.iter_mut().find(|(first, _)| {
+                (first.severity, first.code, &first.message)
+                    == (diagnostic.severity, diagnostic.code, &diagnostic.message)
+            });
+            match same {
+                Some((_, count)) => *count += 1,
+                None => groups.push((diagnostic, 1)),
+            }
+        }
+        groups
+    }
+}
+
+impl fmt::Display for Diagnostics {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let groups = self.grouped();
+        let shown = match self.limit {
+            0 => groups.len(),
+            limit => limit.min(groups.len()),
+        };
+        for (i, (diagnostic, count)) in groups[..shown].iter().enumerate() {
+            if i > 0 {
+                write!(f, "\n\n")?;
+            }
+            if *count > 1 {
+                let mut diagnostic = (*diagnostic).clone();
+                diagnostic.message = format!("{} (x{})", diagnostic.message, count);
+                write!(f, "{}", diagnostic)?;
+            } else {
+                write!(f, "{}", diagnostic)?;
+            }
+        }
+
+        // A lone diagnostic speaks for itself
+        if self.diagnostics.len() == 1 {
+            return Ok(());
+        }
+        let plural = |n: usize, word: &str| match n {
+            1 => format!("1 {}", word),
+            n => format!("{} {}s", n, word),
+        };
+        write!(f, "\n\n{}", plural(self.errors(), "error"))?;
+        if self.warnings() > 0 {
+            write!(f, " and {}", plural(self.warnings(), "warning"))?;
+        }
+        if shown < groups.len() {
+            write!(
+                f,
+                "; {} more not shown, use --max-errors 0 to show them all",
+                groups.len() - shown
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl Error for Diagnostics {}
+
+impl From<Diagnostics> for ArmError {
+    fn from(diagnostics: Diagnostics) -> Self {
+        diagnostics.first().code.kind()(Box::new(diagnostics))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_display() {
+        let diagnostic = Diagnostic {
+            code: DiagnosticCode::InvalidRegister,
+            severity: Severity::Error,
+            file: Some(String::from("prog.s")),
+            line: 12,
+            column: 8,
+            len: 2,
+            message: String::from("unexpected 'rx' while parsing processing instruction"),
+            source_line: String::from("add r1,rx,#1"),
+        };
+        assert_eq!(
+            diagnostic.to_string(),
+            "prog.s:12:8: error[E0004]: unexpected 'rx' while parsing processing instruction\n   |\n\
+             12 | add r1,rx,#1\n   |        ^^"
+        );
+    }
+
+    #[test]
+    fn test_diagnostics() {
+        let located = |severity, line, message: &str| Diagnostic {
+            code: DiagnosticCode::UnknownMnemonic,
+            severity,
+            file: None,
+            line,
+            column: 1,
+            len: 1,
+            message: String::from(message),
+            source_line: String::from("x"),
+        };
+
+        // Errors come first, and identical diagnostics are counted
+        let diagnostics = Diagnostics::new(vec![
+            located(Severity::Warning, 1, "w"),
+            located(Severity::Error, 3, "e"),
+            located(Severity::Error, 2, "e"),
+        ]);
+        assert_eq!(diagnostics.first().line, 2);
+        assert_eq!((diagnostics.errors(), diagnostics.warnings()), (2, 1));
+        assert_eq!(
+            diagnostics.to_string(),
+            "2:1: error[E0001]: e (x2)\n  |\n2 | x\n  | ^\n\n\
+             1:1: warning[E0001]: w\n  |\n1 | x\n  | ^\n\n\
+             2 errors and 1 warning"
+        );
+        assert!(diagnostics.with_limit(1).to_string().ends_with(
+            "\n\n2 errors and 1 warning; 1 more not shown, use --max-errors 0 to show them all"
+        ));
+    }
+
+    #[test]
+    fn test_in_source() {
+        let located = |column, len| Diagnostic {
+            code: DiagnosticCode::UnexpectedToken,
+            severity: Severity::Error,
+            file: None,
+            line: 1,
+            column,
+            len,
+            message: String::new(),
+            source_line: String::from("mov r0,#0x8,rx"),
+        };
+        let original = "mov r0,#(4+4),rx";
+
+        // Before, inside and after the rewritten expression
+        let before = located(5, 2).in_source(original);
+        assert_eq!((before.column, before.len), (5, 2));
+        let inside = located(10, 1).in_source(original);
+        assert_eq!((inside.column, inside.len), (9, 5));
+        let after = located(13, 2).in_source(original);
+        assert_eq!((after.column, after.len), (15, 2));
+    }
+}
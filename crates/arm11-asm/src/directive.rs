@@ -0,0 +1,419 @@
+use std::{fs, path::Path};
+
+use nom::{
+    branch::alt,
+    bytes::complete::{is_not, tag},
+    character::complete::{char, digit1, satisfy, space0, space1},
+    combinator::{complete, map, map_opt, not, opt, recognize, value},
+    error::context,
+    multi::separated_list1,
+    sequence::{delimited, pair, preceded, terminated, tuple},
+};
+
+use super::diagnostic::{Diagnostic, DiagnosticCode};
+use super::expression::{parse_expression, Expression};
+use super::parse::{decimal_value, hexedecimal_value};
+use arm11_isa::{
+    address::{Address, SymbolTable},
+    constants::*,
+    parse::*,
+    types::*,
+};
+
+// A data directive, which places bytes directly into the binary rather than encoding an
+// instruction.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Directive {
+    // .word <expression>, ... - each value is a 32 bit number, which may use labels' addresses.
+    // .float16.16 <number>, ... also gives words, each the number in 16.16 fixed point.
+    Word(Vec<Expression>),
+    // .byte <value>, ...
+    Byte(Vec<u8>),
+    // .ascii "<string>" - the string isn't null terminated
+    Ascii(Vec<u8>),
+    // .skip <size> - reserves size bytes, filled with zeros
+    Skip(u32),
+    // .incbin "<file>"[, offset[, len]] - the contents of a file, or of part of it, which is
+    // read when the directive is parsed
+    Incbin(Vec<u8>),
+    // .ltorg - places the literal pool for the ldr = instructions since the last pool here,
+    // rather than after all the code. This reserves a word for each ldr = which might need a
+    // literal, which is worked out by the assembler as it lays out the source.
+    Ltorg(usize),
+    // .thumb or .arm - assembles the instructions which follow as Thumb or ARM instructions.
+    // These occupy no space, but align the next instruction to its size.
+    Thumb,
+    Arm,
+    // .align <n> - pads with zeros to the next multiple of 2^n bytes
+    Align(u32),
+    // .org <address> - pads with zeros up to the address, which can't be before the current one
+    Org(u32),
+}
+
+impl Directive {
+    // The number of bytes this directive occupies in the binary
+    pub fn size(&self) -> usize {
+        match self {
+            Directive::Word(values) => values.len() * BYTES_IN_WORD,
+            Directive::Byte(bytes) | Directive::Ascii(bytes) | Directive::Incbin(bytes) => {
+                bytes.len()
+            }
+            Directive::Skip(size) => *size as usize,
+            Directive::Ltorg(literals) => literals * BYTES_IN_WORD,
+            Directive::Thumb | Directive::Arm | Directive::Align(_) | Directive::Org(_) => 0,
+        }
+    }
+
+    // The alignment of the directive's address, in bytes. Words are word aligned, so that they
+    // can be loaded with ldr.
+    pub fn alignment(&self) -> usize {
+        match self {
+            Directive::Word(_) | Directive::Ltorg(_) | Directive::Arm => BYTES_IN_WORD,
+            Directive::Thumb => 2,
+            Directive::Align(n) => 1 << n,
+            _ => 1,
+        }
+    }
+
+    // Encodes the directive at the given address into bytes, looking up any labels in the
+    // symbol table
+    pub fn encode(&self, symbol_table: &SymbolTable, address: Address) -> Result<Vec<u8>> {
+        match self {
+            Directive::Word(values) => {
+                let mut bytes = Vec::with_capacity(self.size());
+                for v in values {
+                    let word = v.evaluate(symbol_table, address)?;
+                    bytes.extend_from_slice(&word.to_le_bytes());
+                }
+                Ok(bytes)
+            }
+            Directive::Byte(bytes) | Directive::Ascii(bytes) | Directive::Incbin(bytes) => {
+                Ok(bytes.clone())
+            }
+            Directive::Skip(size) => Ok(vec![0; *size as usize]),
+            // The padding before them is added when the statement is placed at its address
+            Directive::Thumb | Directive::Arm | Directive::Align(_) | Directive::Org(_) => {
+                Ok(Vec::new())
+            }
+            // The literals are filled in by the assembler
+            Directive::Ltorg(_) => Ok(vec![0; self.size()]),
+        }
+    }
+}
+
+// Parses a data directive, eg: .word 0x20200000, label. Files included with .incbin are found
+// relative to dir, which is usually the directory of the source file.
+pub fn parse_directive(
+    raw: &str,
+    line: usize,
+    dir: &Path,
+) -> std::result::Result<Directive, Diagnostic> {
+    if raw.trim().starts_with(".incbin") {
+        let (rest, (path, offset, len)) =
+            complete(parse_incbin)(raw.trim()).map_err(|e| Diagnostic::from_nom(line, raw, e))?;
+        if !rest.trim().is_empty() {
+            return Err(Diagnostic::trailing(line, raw, rest, "directive"));
+        }
+        return include_binary(&dir.join(path), offset, len)
+            .map(Directive::Incbin)
+            .map_err(|e| Diagnostic::for_line(DiagnosticCode::Include, line, raw, e));
+    }
+
+    // A negative size would wrap around to gigabytes of zeros, so it is rejected rather than
+    // parsed as a number like the other directives' values
+    if raw.trim().starts_with(".skip") {
+        let (rest, (size, negative)) =
+            complete(parse_skip)(raw.trim()).map_err(|e| Diagnostic::from_nom(line, raw, e))?;
+        if !rest.trim().is_empty() {
+            return Err(Diagnostic::trailing(line, raw, rest, "directive"));
+        }
+        if negative && size != 0 {
+            return Err(Diagnostic::for_line(
+                DiagnosticCode::InvalidExpression,
+                line,
+                raw,
+                format!(".skip size -{} can't be negative", size),
+            ));
+        }
+        return Ok(Directive::Skip(size));
+    }
+
+    let (rest, directive) = alt((
+        complete(value(Directive::Ltorg(0), tag(".ltorg"))),
+        complete(value(Directive::Thumb, tag(".thumb"))),
+        complete(value(Directive::Arm, tag(".arm"))),
+        complete(parse_word),
+        complete(parse_fixed),
+        complete(parse_byte),
+        complete(parse_ascii),
+        complete(parse_align),
+        complete(parse_org),
+    ))(raw.trim())
+    .map_err(|e| Diagnostic::from_nom(line, raw, e))?;
+
+    // Anything left over is a mistake, as it is after an instruction
+    if !rest.trim().is_empty() {
+        return Err(Diagnostic::trailing(line, raw, rest, "directive"));
+    }
+    Ok(directive)
+}
+
+fn parse_word(input: &str) -> NomResult<&str, Directive> {
+    context(
+        "parsing .word directive",
+        map(
+            preceded(
+                terminated(tag(".word"), space1),
+                separated_list1(comma_space, parse_expression),
+            ),
+            Directive::Word,
+        ),
+    )(input)
+}
+
+// Parses a .float16.16 directive, whose decimal numbers are placed as signed 16.16 fixed point
+// words, i.e. scaled by 65536 and rounded to the nearest whole number. Exponents aren't
+// supported, so a number running on into letters or another point is an error.
+// eg: .float16.16 3.25, -0.5 is .word 0x00034000, 0xffff8000
+fn parse_fixed(input: &str) -> NomResult<&str, Directive> {
+    context(
+        "parsing .float16.16 directive",
+        map(
+            preceded(
+                terminated(tag(".float16.16"), space1),
+                separated_list1(
+                    comma_space,
+                    map_opt(
+                        terminated(
+                            recognize(tuple((
+                                opt(char('-')),
+                                digit1,
+                                opt(pair(char('.'), digit1)),
+                            ))),
+                            not(satisfy(|c| c.is_alphanumeric() || c == '.')),
+                        ),
+                        |n: &str| {
+                            let fixed = (n.parse::<f64>().ok()? * 65536.0).round();
+                            (fixed >= f64::from(i32::MIN) && fixed <= f64::from(i32::MAX))
+                                .then_some(Expression::Number(fixed as i32 as u32))
+                        },
+                    ),
+                ),
+            ),
+            Directive::Word,
+        ),
+    )(input)
+}
+
+fn parse_byte(input: &str) -> NomResult<&str, Directive> {
+    context(
+        "parsing .byte directive",
+        map(
+            preceded(
+                terminated(tag(".byte"), space1),
+                separated_list1(
+                    comma_space,
+                    map_opt(parse_number, |n| {
+                        // Allow negative bytes, eg: -1 is 0xff
+                        (n <= 0xff || n >= (-0x80i32) as u32).then_some(n as u8)
+                    }),
+                ),
+            ),
+            Directive::Byte,
+        ),
+    )(input)
+}
+
+fn parse_ascii(input: &str) -> NomResult<&str, Directive> {
+    context(
+        "parsing .ascii directive",
+        map(
+            preceded(terminated(tag(".ascii"), space1), parse_string),
+            |s: String| Directive::Ascii(s.into_bytes()),
+        ),
+    )(input)
+}
+
+// Parses a .skip directive's size, and whether it was written as a negative number
+fn parse_skip(input: &str) -> NomResult<&str, (u32, bool)> {
+    context(
+        "parsing .skip directive",
+        preceded(
+            terminated(tag(".skip"), space1),
+            alt((hexedecimal_value, decimal_value)),
+        ),
+    )(input)
+}
+
+// Parses an .align directive, whose power of two is below 32 so the alignment fits in an address
+fn parse_align(input: &str) -> NomResult<&str, Directive> {
+    context(
+        "parsing .align directive",
+        map(
+            preceded(
+                terminated(tag(".align"), space1),
+                map_opt(parse_number, |n| (n < 32).then_some(n)),
+            ),
+            Directive::Align,
+        ),
+    )(input)
+}
+
+fn parse_org(input: &str) -> NomResult<&str, Directive> {
+    context(
+        "parsing .org directive",
+        map(
+            preceded(terminated(tag(".org"), space1), parse_number),
+            Directive::Org,
+        ),
+    )(input)
+}
+
+// Parses the file name, and the optional offset and length, of an .incbin directive
+fn parse_incbin(input: &str) -> NomResult<&str, (&str, u32, Option<u32>)> {
+    context(
+        "parsing .incbin directive",
+        map(
+            preceded(
+                terminated(tag(".incbin"), space1),
+                tuple((
+                    delimited(char('"'), is_not("\""), char('"')),
+                    opt(preceded(comma_space, parse_number)),
+                    opt(preceded(comma_space, parse_number)),
+                )),
+            ),
+            |(path, offset, len)| (path, offset.unwrap_or(0), len),
+        ),
+    )(input)
+}
+
+// Reads len bytes of a file from offset, or everything after offset if len isn't given
+fn include_binary(
+    path: &Path,
+    offset: u32,
+    len: Option<u32>,
+) -> std::result::Result<Vec<u8>, String> {
+    let bytes = fs::read(path).map_err(|e| format!("Can't read '{}': {}", path.display(), e))?;
+    let start = offset as usize;
+    let end = len.map_or(bytes.len(), |len| start.saturating_add(len as usize));
+    if start > bytes.len() || end > bytes.len() {
+        return Err(format!(
+            "Range 0x{:x}..0x{:x} is past the end of '{}', which is {} bytes",
+            start,
+            end,
+            path.display(),
+            bytes.len()
+        ));
+    }
+    Ok(bytes[start..end].to_vec())
+}
+
+// Parses a number without a '#' prefix, wrapping negative numbers to their two's complement
+fn parse_number(input: &str) -> NomResult<&str, u32> {
+    map(alt((hexedecimal_value, decimal_value)), |(n, is_signed)| {
+        if is_signed {
+            n.wrapping_neg()
+        } else {
+            n
+        }
+    })(input)
+}
+
+// Matches a comma, with 0 or more spaces around it.
+fn comma_space(input: &str) -> NomResult<&str, char> {
+    delimited(space0, char(','), space0)(input)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_directive() {
+        assert_eq!(
+            parse_directive(".word 0x20200000, -1, msg", 1, Path::new(""))
+                .expect("parse .word failed"),
+            Directive::Word(vec![
+                Expression::Number(0x20200000),
+                Expression::Negate(Box::new(Expression::Number(1))),
+                Expression::Label(String::from("msg")),
+            ])
+        );
+        assert_eq!(
+            parse_directive(".byte 1, 0xff, -2", 1, Path::new("")).expect("parse .byte failed"),
+            Directive::Byte(vec![0x01, 0xff, 0xfe])
+        );
+        assert_eq!(
+            parse_directive(".ascii \"hi,\\n\\\"\"", 1, Path::new(""))
+                .expect("parse .ascii failed"),
+            Directive::Ascii(b"hi,\n\"".to_vec())
+        );
+        assert_eq!(
+            parse_directive(".skip 8", 1, Path::new("")).expect("parse .skip failed"),
+            Directive::Skip(8)
+        );
+        assert_eq!(
+            parse_directive(".float16.16 3.25, -0.5, 1, 0.00001", 1, Path::new(""))
+                .expect("parse .float16.16 failed"),
+            Directive::Word(vec![
+                Expression::Number(0x00034000),
+                Expression::Number(0xffff8000),
+                Expression::Number(0x00010000),
+                Expression::Number(0x00000001),
+            ])
+        );
+        assert_eq!(
+            parse_directive(".align 3", 1, Path::new("")).expect("parse .align failed"),
+            Directive::Align(3)
+        );
+        assert_eq!(
+            parse_directive(".org 0x1000", 1, Path::new("")).expect("parse .org failed"),
+            Directive::Org(0x1000)
+        );
+        assert!(parse_directive(".align 32", 1, Path::new("")).is_err());
+        assert!(parse_directive(".float16.16 32768", 1, Path::new("")).is_err());
+        assert!(parse_directive(".float16.16 half", 1, Path::new("")).is_err());
+        for raw in [".float16.16 1e30", ".float16.16 1.5E2", ".float16.16 1.5.2"] {
+            let error = parse_directive(raw, 1, Path::new("")).expect_err(raw);
+            assert_eq!(error.column, 13, "{}", raw);
+        }
+        assert!(parse_directive(".byte 256", 1, Path::new("")).is_err());
+        for raw in [".skip -1", ".skip -0x10"] {
+            let error = parse_directive(raw, 1, Path::new("")).expect_err(raw);
+            assert_eq!(error.code, DiagnosticCode::InvalidExpression, "{}", raw);
+        }
+        assert!(parse_directive(".word", 1, Path::new("")).is_err());
+
+        // Directives end the line, like instructions
+        for raw in [
+            ".word 1 junk",
+            ".skip 4 garbage",
+            ".align 2 3",
+            ".ltorg now",
+            ".thumbfoo",
+            ".arm_whatever",
+        ] {
+            let error = parse_directive(raw, 1, Path::new("")).expect_err(raw);
+            assert_eq!(error.code, DiagnosticCode::UnexpectedToken, "{}", raw);
+        }
+        let error = parse_directive(".byte 1, 2 x", 1, Path::new("")).expect_err(".byte");
+        assert_eq!((error.column, error.len), (12, 1));
+    }
+
+    #[test]
+    fn test_parse_incbin() {
+        let dir = std::env::temp_dir();
+        let name = format!("arm11-incbin-{}.bin", std::process::id());
+        fs::write(dir.join(&name), [1, 2, 3, 4, 5]).expect("write failed");
+        let parse = |args: &str| parse_directive(&format!(".incbin \"{}\"{}", name, args), 1, &dir);
+
+        assert_eq!(parse(""), Ok(Directive::Incbin(vec![1, 2, 3, 4, 5])));
+        assert_eq!(parse(", 3"), Ok(Directive::Incbin(vec![4, 5])));
+        assert_eq!(parse(", 1, 2"), Ok(Directive::Incbin(vec![2, 3])));
+        assert!(parse(", 4, 2").is_err());
+        assert!(parse(", 6").is_err());
+        assert!(parse_directive(".incbin \"missing.bin\"", 1, &dir).is_err());
+
+        fs::remove_file(dir.join(&name)).expect("remove failed");
+    }
+}
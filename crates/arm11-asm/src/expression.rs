@@ -0,0 +1,340 @@
+use nom::{
+    branch::alt,
+    character::complete::{char, space0},
+    combinator::{all_consuming, map, value},
+    error::context,
+    sequence::{delimited, pair, preceded},
+};
+
+use super::parse::{decimal_value, hexedecimal_value, parse_label};
+use arm11_isa::{
+    address::{Address, SymbolTable},
+    parse::*,
+    types::*,
+};
+
+// A constant expression, evaluated at assembly time once the addresses of all the labels are
+// known. The result is a 32 bit word, so each step must fit in one as either an unsigned or a
+// signed number; -4 is the word 0xfffffffc, but 1 << 40 is an error rather than wrapping to 0.
+// eg: (LABEL + 4), 1 << 5, SIZE * 2, . - 8
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expression {
+    Number(u32),
+    Label(String),
+    // '.', the address of the instruction or directive the expression is in
+    Here,
+    Negate(Box<Expression>),
+    Not(Box<Expression>),
+    Binary(BinaryOp, Box<Expression>, Box<Expression>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Shl,
+    Shr,
+    And,
+    Or,
+    Xor,
+}
+
+impl Expression {
+    // Evaluates the expression, looking up any labels in the symbol table, with '.' being the
+    // address here
+    pub fn evaluate(&self, symbol_table: &SymbolTable, here: Address) -> Result<u32> {
+        self.evaluate_wide(symbol_table, here)
+            .map(|value| value as u32)
+    }
+
+    // Evaluates the expression without wrapping, checking that each step still fits in a word
+    fn evaluate_wide(&self, symbol_table: &SymbolTable, here: Address) -> Result<i64> {
+        let value = match self {
+            Expression::Number(n) => i64::from(*n),
+            Expression::Here => i64::from(here.0),
+            Expression::Label(label) => i64::from(
+                symbol_table
+                    .get(label)
+                    .ok_or_else(|| ArmError::parse(format!("Undefined label '{}'", label)))?
+                    .0,
+            ),
+            Expression::Negate(e) => {
+                let value = -e.evaluate_wide(symbol_table, here)?;
+                return fits_word(value).ok_or_else(|| overflow(format!("-({})", hex(-value))));
+            }
+            Expression::Not(e) => i64::from(!e.evaluate(symbol_table, here)?),
+            Expression::Binary(op, lhs, rhs) => {
+                let lhs = lhs.evaluate_wide(symbol_table, here)?;
+                let rhs = rhs.evaluate_wide(symbol_table, here)?;
+                // Division, the right shift and the bitwise operators work on the words
+                let (lhs_word, rhs_word) = (lhs as u32, rhs as u32);
+                let value = match op {
+                    BinaryOp::Add => lhs.checked_add(rhs),
+                    BinaryOp::Sub => lhs.checked_sub(rhs),
+                    BinaryOp::Mul => lhs.checked_mul(rhs),
+                    BinaryOp::Div => {
+                        Some(i64::from(lhs_word.checked_div(rhs_word).ok_or_else(
+                            || ArmError::parse("Division by zero in expression"),
+                        )?))
+                    }
+                    // Shifting a word left by 33 or more can only fit if it is 0
+                    BinaryOp::Shl if lhs == 0 => Some(0),
+                    BinaryOp::Shl => 1i64
+                        .checked_shl(rhs_word)
+                        .filter(|_| rhs_word <= 32)
+                        .and_then(|scale| lhs.checked_mul(scale)),
+                    // Shifting right by 32 or more clears the value
+                    BinaryOp::Shr => Some(i64::from(lhs_word.checked_shr(rhs_word).unwrap_or(0))),
+                    BinaryOp::And => Some(i64::from(lhs_word & rhs_word)),
+                    BinaryOp::Or => Some(i64::from(lhs_word | rhs_word)),
+                    BinaryOp::Xor => Some(i64::from(lhs_word ^ rhs_word)),
+                };
+                return value
+                    .and_then(fits_word)
+                    .ok_or_else(|| overflow(format!("{} {} {}", hex(lhs), op.symbol(), hex(rhs))));
+            }
+        };
+        Ok(value)
+    }
+}
+
+impl BinaryOp {
+    // The operator as it is written in an expression
+    fn symbol(self) -> &'static str {
+        PRECEDENCE
+            .iter()
+            .flat_map(|level| level.iter())
+            .find(|(_, op)| *op == self)
+            .map_or("?", |(symbol, _)| symbol)
+    }
+}
+
+// The value, if it fits in a word as either an unsigned or a signed number
+fn fits_word(value: i64) -> Option<i64> {
+    (i64::from(i32::MIN)..=i64::from(u32::MAX))
+        .contains(&value)
+        .then_some(value)
+}
+
+fn overflow(expression: String) -> ArmError {
+    ArmError::parse(format!("{} overflows a 32 bit word", expression))
+}
+
+// Formats a value in hexadecimal, keeping its sign
+fn hex(value: i64) -> String {
+    if value < 0 {
+        format!("-0x{:x}", value.unsigned_abs())
+    } else {
+        format!("0x{:x}", value)
+    }
+}
+
+// The binary operators, grouped from the loosest binding to the tightest, as they are in C
+const PRECEDENCE: [&[(&str, BinaryOp)]; 6] = [
+    &[("|", BinaryOp::Or)],
+    &[("^", BinaryOp::Xor)],
+    &[("&", BinaryOp::And)],
+    &[("<<", BinaryOp::Shl), (">>", BinaryOp::Shr)],
+    &[("+", BinaryOp::Add), ("-", BinaryOp::Sub)],
+    &[("*", BinaryOp::Mul), ("/", BinaryOp::Div)],
+];
+
+// Parses an expression of numbers, labels, '.', parentheses and the operators in PRECEDENCE,
+// along with unary '-' and '~'. Spaces are allowed around the binary operators.
+pub fn parse_expression(input: &str) -> NomResult<&str, Expression> {
+    context("parsing expression", |i| parse_binary(i, 0))(input)
+}
+
+// Parses a left associative chain of operands joined by the operators at the given level of
+// PRECEDENCE, where each operand is made up of the operators which bind more tightly
+fn parse_binary(input: &str, level: usize) -> NomResult<&str, Expression> {
+    if level == PRECEDENCE.len() {
+        return parse_unary(input);
+    }
+
+    let (mut rest, mut expression) = parse_binary(input, level + 1)?;
+    loop {
+        let (after_space, _) = space0(rest)?;
+        let op = PRECEDENCE[level]
+            .iter()
+            .find(|(symbol, _)| after_space.starts_with(symbol));
+        let (symbol, op) = match op {
+            Some(op) => op,
+            None => return Ok((rest, expression)),
+        };
+        let (after_op, _) = space0(&after_space[symbol.len()..])?;
+        let (after_rhs, rhs) = match parse_binary(after_op, level + 1) {
+            Ok(result) => result,
+            // The operator isn't part of this expression, so leave it for the caller
+            Err(nom::Err::Error(_)) => return Ok((rest, expression)),
+            Err(e) => return Err(e),
+        };
+        expression = Expression::Binary(*op, Box::new(expression), Box::new(rhs));
+        rest = after_rhs;
+    }
+}
+
+fn parse_unary(input: &str) -> NomResult<&str, Expression> {
+    alt((
+        map(preceded(char('-'), parse_unary), |e| {
+            Expression::Negate(Box::new(e))
+        }),
+        map(preceded(char('~'), parse_unary), |e| {
+            Expression::Not(Box::new(e))
+        }),
+        parse_primary,
+    ))(input)
+}
+
+fn parse_primary(input: &str) -> NomResult<&str, Expression> {
+    alt((
+        delimited(
+            pair(char('('), space0),
+            parse_expression,
+            pair(space0, char(')')),
+        ),
+        map(alt((hexedecimal_value, decimal_value)), |(n, _)| {
+            Expression::Number(n)
+        }),
+        map(parse_label, |label: &str| {
+            Expression::Label(label.to_owned())
+        }),
+        value(Expression::Here, char('.')),
+    ))(input)
+}
+
+// Evaluates the expressions in the '#' operands of an instruction, replacing each one with its
+// value so that the instruction can be parsed as usual. Operands which are already a single
+// number are left alone. An expression starting with '-' keeps its sign, which transfer offsets
+// use to subtract from the base register. Here is the address of the instruction.
+// eg: "ldr r0,[r1,#(SIZE*2)]" becomes "ldr r0,[r1,#0x8]" when SIZE is 4
+pub fn substitute_expressions(
+    line: &str,
+    symbol_table: &SymbolTable,
+    here: Address,
+) -> Result<String> {
+    let mut out = String::with_capacity(line.len());
+    let mut rest = line;
+    while let Some(start) = rest.find('#') {
+        out.push_str(&rest[..=start]);
+        rest = &rest[start + 1..];
+
+        // The operand runs until the next ',' or ']' outside of any parentheses
+        let mut depth = 0;
+        let end = rest
+            .char_indices()
+            .find(|&(_, c)| {
+                match c {
+                    '(' => depth += 1,
+                    ')' => depth -= 1,
+                    ',' | ']' if depth == 0 => return true,
+                    _ => (),
+                }
+                false
+            })
+            .map_or(rest.len(), |(i, _)| i);
+        let operand = rest[..end].trim_end();
+
+        if all_consuming(alt((hexedecimal_value, decimal_value)))(operand).is_ok() {
+            out.push_str(operand);
+        } else {
+            let (_, expression) = all_consuming(parse_expression)(operand)
+                .map_err(|_| format!("Invalid expression '{}' in '{}'", operand, line))?;
+            match expression {
+                Expression::Negate(e) => {
+                    out.push_str(&format!("-0x{:x}", e.evaluate(symbol_table, here)?))
+                }
+                e => out.push_str(&format!("0x{:x}", e.evaluate(symbol_table, here)?)),
+            }
+        }
+        rest = &rest[end..];
+    }
+    out.push_str(rest);
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_evaluate_expression() {
+        let symbol_table: SymbolTable = vec![
+            (String::from("LABEL"), Address(0x10)),
+            (String::from("SIZE"), Address(4)),
+        ]
+        .into_iter()
+        .collect();
+        let evaluate = |raw| {
+            all_consuming(parse_expression)(raw)
+                .expect("parse expression failed")
+                .1
+                .evaluate(&symbol_table, Address(0x20))
+                .expect("evaluate expression failed")
+        };
+
+        assert_eq!(evaluate("(LABEL+4)"), 0x14);
+        assert_eq!(evaluate("1<<5"), 32);
+        assert_eq!(evaluate("SIZE*2"), 8);
+        assert_eq!(evaluate("1 + 2 * 3"), 7);
+        assert_eq!(evaluate("(1 + 2) * 3"), 9);
+        assert_eq!(evaluate("0x10 - 1 - 1"), 0xe);
+        assert_eq!(evaluate("1 << 4 | 1"), 0x11);
+        assert_eq!(evaluate("-SIZE"), 0xfffffffc);
+        assert_eq!(evaluate("~0 >> 28"), 0xf);
+        assert_eq!(evaluate(". - 8"), 0x18);
+
+        assert_eq!(evaluate("1 << 31"), 0x80000000);
+        assert_eq!(evaluate("LABEL - 0x20"), 0xfffffff0);
+        assert_eq!(evaluate("-1 << 4"), 0xfffffff0);
+        assert_eq!(evaluate("0 << 40"), 0);
+        assert_eq!(evaluate("1 >> 40"), 0);
+
+        let error = |raw| {
+            all_consuming(parse_expression)(raw)
+                .expect("parse expression failed")
+                .1
+                .evaluate(&symbol_table, Address(0x20))
+                .expect_err(raw)
+                .to_string()
+        };
+        assert!(error("MISSING").contains("Undefined label"));
+        assert!(error("1/0").contains("Division by zero"));
+        // Values which don't fit in a word are an error, rather than wrapping
+        assert!(error("1 << 40").contains("0x1 << 0x28 overflows a 32 bit word"));
+        assert!(error("0x7fffffff*0x7fffffff").contains("overflows a 32 bit word"));
+        assert!(error("0xffffffff + 1").contains("overflows a 32 bit word"));
+        assert!(error("0 - 0xffffffff").contains("overflows a 32 bit word"));
+        assert!(error("-0xffffffff").contains("overflows a 32 bit word"));
+    }
+
+    #[test]
+    fn test_substitute_expressions() {
+        let symbol_table: SymbolTable = vec![(String::from("SIZE"), Address(4))]
+            .into_iter()
+            .collect();
+
+        assert_eq!(
+            substitute_expressions("ldr r0,[r1,#(SIZE*2)]", &symbol_table, Address(0))
+                .expect("substitute failed"),
+            "ldr r0,[r1,#0x8]"
+        );
+        assert_eq!(
+            substitute_expressions("add r0,r1,#1<<5", &symbol_table, Address(0))
+                .expect("substitute failed"),
+            "add r0,r1,#0x20"
+        );
+        assert_eq!(
+            substitute_expressions("str r0,[r1],#-SIZE", &symbol_table, Address(0))
+                .expect("substitute failed"),
+            "str r0,[r1],#-0x4"
+        );
+        assert_eq!(
+            substitute_expressions("mov r0,r1,lsl #0x2", &symbol_table, Address(0))
+                .expect("substitute failed"),
+            "mov r0,r1,lsl #0x2"
+        );
+    }
+}
@@ -0,0 +1,164 @@
+use std::{convert::TryInto, io::Write, str::FromStr};
+
+use arm11_isa::{
+    address::{Address, Word},
+    constants::*,
+    types::*,
+};
+
+// Number of data bytes in each Intel HEX record, and of words in each row of a hex dump
+const IHEX_RECORD_BYTES: usize = 16;
+const HEXDUMP_ROW_WORDS: usize = 4;
+
+// How the assembled image is written. Binaries are the raw bytes, Intel HEX is the text format
+// read by many flashing tools, and a hex dump lists the address and value of each word to read.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum Format {
+    #[default]
+    Binary,
+    Ihex,
+    Hexdump,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "binary" => Ok(Format::Binary),
+            "ihex" => Ok(Format::Ihex),
+            "hexdump" => Ok(Format::Hexdump),
+            _ => Err(format!(
+                "Invalid format '{}', expected binary, ihex or hexdump",
+                s
+            )),
+        }
+    }
+}
+
+impl Format {
+    // Writes the image, which starts at address 0, in this format
+    pub fn write(&self, bytes: &[u8], out: &mut dyn Write) -> Result<()> {
+        match self {
+            Format::Binary => out.write_all(bytes)?,
+            Format::Ihex => write_ihex(bytes, out)?,
+            Format::Hexdump => write_hexdump(bytes, out)?,
+        }
+        Ok(())
+    }
+}
+
+// Writes the image as Intel HEX data records of 16 bytes, followed by an end of file record.
+// Addresses beyond 64KiB are given by extended linear address records, which set the top half
+// of the address of the records after them.
+// eg:
+//
+// :10000000010080E3021081E3002091E50000000080
+// :00000001FF
+//
+fn write_ihex(bytes: &[u8], out: &mut dyn Write) -> std::io::Result<()> {
+    let mut upper = 0;
+    for (i, record) in bytes.chunks(IHEX_RECORD_BYTES).enumerate() {
+        let address = (i * IHEX_RECORD_BYTES) as u32;
+        if address >> 16 != upper {
+            upper = address >> 16;
+            write_ihex_record(out, 0, 0x04, &(upper as u16).to_be_bytes())?;
+        }
+        write_ihex_record(out, address as u16, 0x00, record)?;
+    }
+    write_ihex_record(out, 0, 0x01, &[])
+}
+
+// Writes a record of the given type, ending with the checksum which makes its bytes sum to 0
+fn write_ihex_record(
+    out: &mut dyn Write,
+    address: u16,
+    kind: u8,
+    data: &[u8],
+) -> std::io::Result<()> {
+    let mut record = vec![data.len() as u8];
+    record.extend_from_slice(&address.to_be_bytes());
+    record.push(kind);
+    record.extend_from_slice(data);
+    let sum = record.iter().fold(0u8, |sum, &b| sum.wrapping_add(b));
+    record.push(sum.wrapping_neg());
+
+    write!(out, ":")?;
+    for b in record {
+        write!(out, "{:02X}", b)?;
+    }
+    writeln!(out)
+}
+
+// Writes the image as rows of little endian words, each row starting with the address of its
+// first word. A data image which ends part way through a word ends with the bytes left over, as
+// a shorter little endian value.
+// eg:
+//
+// 0x00000000: 0xe3800001 0xe3811002 0xe5912000 0x00000000
+// 0x00000010: 0x0201
+//
+fn write_hexdump(bytes: &[u8], out: &mut dyn Write) -> std::io::Result<()> {
+    for (i, row) in bytes.chunks(HEXDUMP_ROW_WORDS * BYTES_IN_WORD).enumerate() {
+        let address = Address((i * HEXDUMP_ROW_WORDS * BYTES_IN_WORD) as u32);
+        let words: Vec<String> = row
+            .chunks(BYTES_IN_WORD)
+            .map(|word| match word.try_into() {
+                Ok(word) => Word::from_le_bytes(word).to_string(),
+                Err(_) => {
+                    let value = word
+                        .iter()
+                        .rev()
+                        .fold(0, |value, &b| value << 8 | u32::from(b));
+                    format!("0x{:0>width$x}", value, width = 2 * word.len())
+                }
+            })
+            .collect();
+        writeln!(out, "{}: {}", address, words.join(" "))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn written(format: Format, bytes: &[u8]) -> String {
+        let mut out = Vec::new();
+        format.write(bytes, &mut out).expect("write failed");
+        String::from_utf8(out).expect("invalid utf8")
+    }
+
+    #[test]
+    fn test_ihex() {
+        let bytes: Vec<u8> = (0..20).collect();
+        assert_eq!(
+            written(Format::Ihex, &bytes),
+            ":10000000000102030405060708090A0B0C0D0E0F78\n\
+             :0400100010111213A6\n\
+             :00000001FF\n"
+        );
+
+        // Records past 64KiB are preceded by the top half of their address
+        let bytes = vec![0xff; 0x10001];
+        let ihex = written(Format::Ihex, &bytes);
+        assert!(
+            ihex.contains("\n:020000040001F9\n:01000000FF00\n"),
+            "{}",
+            ihex
+        );
+    }
+
+    #[test]
+    fn test_hexdump() {
+        let bytes = [
+            0x01, 0x00, 0x80, 0xe3, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x01, 0x02,
+        ];
+        assert_eq!(
+            written(Format::Hexdump, &bytes),
+            "0x00000000: 0xe3800001 0x00000000 0x00000000 0x00000000\n0x00000010: 0x0201\n"
+        );
+        assert_eq!("hexdump".parse(), Ok(Format::Hexdump));
+        assert!("srec".parse::<Format>().is_err());
+    }
+}
@@ -0,0 +1,164 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use super::{
+    diagnostic::{Diagnostic, DiagnosticCode},
+    normalize::normalize,
+};
+use arm11_isa::parse::parse_string;
+
+// Where a line of an expanded source was written: the file it is in, as the source is named or
+// as the path of an included file, and its line in that file, counted from 1
+#[derive(Debug, Clone, PartialEq)]
+pub struct Origin {
+    pub file: String,
+    pub line: usize,
+}
+
+// A source with its includes expanded, and the origin of each of its lines
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Expanded {
+    pub raw: String,
+    pub origins: Vec<Origin>,
+}
+
+// Replaces each .include "<file>" line with the contents of the file, before macros are
+// expanded, so that included files can share constants and macros. Files are found relative to
+// the file which includes them, and may include other files, but a file can't include itself,
+// directly or through the files it includes. An .include which fails is reported where it is
+// written, in the source named name or the file including it.
+// eg:
+//
+// .include "gpio.s"
+//
+pub fn expand_includes(
+    raw: &str,
+    name: &str,
+    dir: &Path,
+) -> std::result::Result<Expanded, Diagnostic> {
+    let mut expanded = Expanded::default();
+    expand(raw, name, dir, &mut Vec::new(), &mut expanded)?;
+    Ok(expanded)
+}
+
+// Expands the includes in a source named file into out. The stack holds the files being
+// included, as they were named by .include and as canonical paths, from the outermost in.
+fn expand(
+    raw: &str,
+    file: &str,
+    dir: &Path,
+    stack: &mut Vec<(String, PathBuf)>,
+    out: &mut Expanded,
+) -> std::result::Result<(), Diagnostic> {
+    for (index, line) in raw.lines().enumerate() {
+        let rest = match line.trim().strip_prefix(".include") {
+            Some(rest) if rest.starts_with(char::is_whitespace) => rest.trim(),
+            _ => {
+                out.raw.push_str(line);
+                out.raw.push('\n');
+                out.origins.push(Origin {
+                    file: String::from(file),
+                    line: index + 1,
+                });
+                continue;
+            }
+        };
+        let error = |message: String| {
+            let diagnostic =
+                Diagnostic::for_line(DiagnosticCode::Include, index + 1, line, message);
+            match file {
+                "" => diagnostic,
+                file => diagnostic.in_file(file),
+            }
+        };
+
+        let name = match parse_string(rest) {
+            Ok(("", name)) => name,
+            _ => return Err(error(String::from("expected .include \"<file>\""))),
+        };
+        let path = dir.join(&name);
+        let source = fs::read_to_string(&path)
+            .map_err(|e| error(format!("can't read '{}': {}", path.display(), e)))?;
+        let canonical = path
+            .canonicalize()
+            .map_err(|e| error(format!("can't read '{}': {}", path.display(), e)))?;
+        if let Some(start) = stack.iter().position(|(_, c)| *c == canonical) {
+            let cycle: Vec<String> = stack[start..]
+                .iter()
+                .map(|(named, _)| named.clone())
+                .chain(Some(name))
+                .collect();
+            return Err(error(format!("include cycle {}", cycle.join(" -> "))));
+        }
+
+        stack.push((name, canonical));
+        let parent = path.parent().unwrap_or_else(|| Path::new(""));
+        let included = path.display().to_string();
+        expand(&normalize(&source), &included, parent, stack, out)?;
+        stack.pop();
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_expand_includes() {
+        let dir = std::env::temp_dir().join(format!("arm11-include-{}", std::process::id()));
+        fs::create_dir_all(dir.join("lib")).expect("create dir failed");
+        fs::write(
+            dir.join("lib/pins.s"),
+            ".macro on reg\nmov \\reg,#1\n.endm\n",
+        )
+        .expect("write failed");
+        fs::write(dir.join("lib/all.s"), ".include \"pins.s\"\nmov r2,#2\n").expect("write failed");
+        fs::write(dir.join("a.s"), ".include \"b.s\"\n").expect("write failed");
+        fs::write(dir.join("b.s"), "  .include \"a.s\"\n").expect("write failed");
+
+        let expanded = expand_includes(".include \"lib/all.s\"\non r1\n", "main.s", &dir)
+            .expect("include failed");
+        assert_eq!(
+            expanded.raw,
+            ".macro on reg\nmov \\reg,#1\n.endm\nmov r2,#2\non r1\n"
+        );
+
+        // Each line keeps the file and line it was written on
+        let pins = dir.join("lib/pins.s").display().to_string();
+        let all = dir.join("lib/all.s").display().to_string();
+        let origins: Vec<(&str, usize)> = expanded
+            .origins
+            .iter()
+            .map(|origin| (origin.file.as_str(), origin.line))
+            .collect();
+        assert_eq!(
+            origins,
+            [
+                (pins.as_str(), 1),
+                (pins.as_str(), 2),
+                (pins.as_str(), 3),
+                (all.as_str(), 2),
+                ("main.s", 2)
+            ]
+        );
+
+        // Included files are found relative to the file including them, and a failed include
+        // is reported where it is written
+        let error = expand_includes("mov r0,r0\n.include \"pins.s\"\n", "main.s", &dir)
+            .expect_err("include succeeded");
+        assert_eq!(
+            (error.code, error.file.as_deref(), error.line),
+            (DiagnosticCode::Include, Some("main.s"), 2)
+        );
+
+        let cycle = expand_includes(".include \"a.s\"\n", "", &dir).expect_err("cycle not found");
+        assert_eq!(cycle.file, Some(dir.join("b.s").display().to_string()));
+        assert_eq!(cycle.message, "include cycle a.s -> b.s -> a.s");
+        assert!(expand_includes(".include pins.s\n", "", &dir).is_err());
+
+        fs::remove_dir_all(&dir).expect("remove failed");
+    }
+}
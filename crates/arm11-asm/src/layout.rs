@@ -0,0 +1,135 @@
+use std::fmt;
+
+use super::Assembled;
+use arm11_isa::constants::*;
+
+// A summary of the layout of an assembled binary, similar to `cargo bloat`. Symbols are sized
+// by their span, i.e. the distance from their address to the next symbol or the end of code.
+#[derive(Debug, PartialEq)]
+pub struct SizeReport {
+    pub code_bytes: usize,
+    pub literal_bytes: usize,
+    // (symbol name, address, size in bytes), largest first
+    pub symbols: Vec<(String, u32, usize)>,
+}
+
+impl SizeReport {
+    pub fn new(assembled: &Assembled, max_symbols: usize) -> Self {
+        let code_bytes = assembled.code.len();
+
+        let mut by_address: Vec<(&String, u32)> = assembled
+            .symbol_table
+            .iter()
+            .map(|(name, addr)| (name, addr.0))
+            .collect();
+        by_address.sort_by(|(a_name, a), (b_name, b)| a.cmp(b).then(a_name.cmp(b_name)));
+
+        let mut symbols: Vec<(String, u32, usize)> = by_address
+            .iter()
+            .enumerate()
+            .map(|(index, (name, addr))| {
+                let end = by_address
+                    .iter()
+                    .skip(index + 1)
+                    .map(|(_, next)| *next as usize)
+                    .find(|&next| next > *addr as usize)
+                    .unwrap_or(code_bytes);
+                ((*name).clone(), *addr, end.saturating_sub(*addr as usize))
+            })
+            .collect();
+
+        // Code before the first label isn't covered by any symbol
+        let first = by_address
+            .first()
+            .map_or(code_bytes, |(_, addr)| *addr as usize);
+        if first > 0 {
+            symbols.push((String::from("<start>"), 0, first));
+        }
+
+        symbols.sort_by(|(a_name, a_addr, a), (b_name, b_addr, b)| {
+            b.cmp(a).then(a_addr.cmp(b_addr)).then(a_name.cmp(b_name))
+        });
+        symbols.truncate(max_symbols);
+
+        SizeReport {
+            code_bytes,
+            literal_bytes: assembled.literals.len(),
+            symbols,
+        }
+    }
+}
+
+impl fmt::Display for SizeReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let total = self.code_bytes + self.literal_bytes;
+        let percent = |n: usize| 100.0 * n as f64 / total.max(1) as f64;
+
+        writeln!(
+            f,
+            "{: <10}{: >10} {: >10} {: >6}",
+            "Section", "Start", "Size", "Share"
+        )?;
+        writeln!(
+            f,
+            ".text     0x{:0>8x} {: >10} {: >5.1}%  ({} instructions)",
+            0,
+            self.code_bytes,
+            percent(self.code_bytes),
+            self.code_bytes / BYTES_IN_WORD
+        )?;
+        writeln!(
+            f,
+            ".literals 0x{:0>8x} {: >10} {: >5.1}%  ({} words)",
+            self.code_bytes,
+            self.literal_bytes,
+            percent(self.literal_bytes),
+            self.literal_bytes / BYTES_IN_WORD
+        )?;
+        writeln!(f, "Total               {: >10}", total)?;
+
+        writeln!(f, "Largest symbols:")?;
+        for (name, addr, size) in &self.symbols {
+            writeln!(
+                f,
+                "  0x{:0>8x} {: >10} {: >5.1}%  {}",
+                addr,
+                size,
+                percent(*size),
+                name
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arm11_isa::address::{Address, SymbolTable};
+
+    #[test]
+    fn test_size_report() {
+        let mut symbol_table = SymbolTable::new();
+        symbol_table.insert(String::from("loop"), Address(0x8));
+        symbol_table.insert(String::from("end"), Address(0x10));
+        let assembled = Assembled {
+            code: vec![0; 0x14],
+            literals: vec![0; 0x4],
+            symbol_table,
+            listing: Default::default(),
+        };
+
+        assert_eq!(
+            SizeReport::new(&assembled, 2),
+            SizeReport {
+                code_bytes: 0x14,
+                literal_bytes: 0x4,
+                symbols: vec![
+                    (String::from("<start>"), 0x0, 8),
+                    (String::from("loop"), 0x8, 8),
+                ],
+            }
+        );
+    }
+}
@@ -0,0 +1,234 @@
+use std::ops::Range;
+
+use nom::{
+    branch::alt,
+    bytes::complete::{tag, take_till, take_while1},
+    character::complete::{alphanumeric1, anychar, char, digit1, one_of, space1},
+    combinator::{map, not, opt, peek, recognize, rest, value},
+    sequence::{pair, preceded, terminated, tuple},
+};
+
+use super::parse::{parse_expression, parse_reg, parse_shifttype};
+use arm11_isa::parse::{parse_string, NomResult};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TokenKind {
+    Mnemonic,
+    Register,
+    Immediate,
+    Shift,
+    Label,
+    Comment,
+    Directive,
+    String,
+    Punctuation,
+    Unknown,
+}
+
+// A classified token. The span is a byte range into the whole source, and the line is
+// zero-indexed, so both offset-based (TUI) and line-based (LSP) consumers can use it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Token {
+    pub kind: TokenKind,
+    pub line: usize,
+    pub span: Range<usize>,
+}
+
+// Splits ARM assembly source into a stream of classified tokens. Unlike the parser this never
+// fails: anything that isn't recognised is returned as an Unknown token, so it can be used on
+// incomplete or invalid source.
+//
+// The first word on a line is treated as the mnemonic, unless it is a label definition or a
+// directive. Identifiers after either are operands, so are lexed as labels, eg: start in
+// .word start.
+//
+pub fn lex(source: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut line_start = 0;
+
+    for (line, raw) in source.split_inclusive('\n').enumerate() {
+        let text = raw.trim_end_matches(['\n', '\r']);
+        let mut remaining = text;
+        let mut seen_mnemonic = false;
+
+        while !remaining.is_empty() {
+            let start = line_start + text.len() - remaining.len();
+            let (rest, kind) = lex_token(remaining, seen_mnemonic);
+
+            if let Some(kind) = kind {
+                seen_mnemonic |= kind == TokenKind::Mnemonic || kind == TokenKind::Directive;
+                tokens.push(Token {
+                    kind,
+                    line,
+                    span: start..start + remaining.len() - rest.len(),
+                });
+            }
+            remaining = rest;
+        }
+
+        line_start += raw.len();
+    }
+
+    tokens
+}
+
+// Lexes a single token from the start of the input, returning None for the kind if only
+// whitespace was consumed.
+fn lex_token(input: &str, seen_mnemonic: bool) -> (&str, Option<TokenKind>) {
+    let result: NomResult<&str, Option<TokenKind>> = alt((
+        value(None, space1),
+        map(lex_string, |_| Some(TokenKind::String)),
+        map(lex_comment, |_| Some(TokenKind::Comment)),
+        map(lex_directive, |_| Some(TokenKind::Directive)),
+        map(lex_label_definition, |_| Some(TokenKind::Label)),
+        map(lex_immediate, |_| Some(TokenKind::Immediate)),
+        map(terminated(recognize(parse_reg), word_end), |_| {
+            Some(TokenKind::Register)
+        }),
+        map(terminated(recognize(parse_shifttype), word_end), |_| {
+            Some(TokenKind::Shift)
+        }),
+        map(identifier, move |_| {
+            Some(if seen_mnemonic {
+                TokenKind::Label
+            } else {
+                TokenKind::Mnemonic
+            })
+        }),
+        map(one_of(",[]{}!+-*()<>"), |_| Some(TokenKind::Punctuation)),
+        map(anychar, |_| Some(TokenKind::Unknown)),
+    ))(input);
+
+    // The final anychar alternative always succeeds on non-empty input
+    result.expect("lexing a non-empty line cannot fail")
+}
+
+// Matches a comment, running until the end of the line. Comments start with ';', '@' or '//'.
+fn lex_comment(input: &str) -> NomResult<&str, &str> {
+    recognize(pair(alt((tag(";"), tag("@"), tag("//"))), rest))(input)
+}
+
+// Matches a double quoted string, eg: "Hello\n". A string which isn't closed runs to the end of
+// the line, so the rest of it isn't lexed as code.
+fn lex_string(input: &str) -> NomResult<&str, &str> {
+    alt((recognize(parse_string), recognize(pair(char('"'), rest))))(input)
+}
+
+// Matches an assembler directive, eg: .word
+fn lex_directive(input: &str) -> NomResult<&str, &str> {
+    recognize(preceded(char('.'), identifier))(input)
+}
+
+// Matches a label definition, including its trailing colon, eg: loop:
+fn lex_label_definition(input: &str) -> NomResult<&str, &str> {
+    recognize(pair(identifier, char(':')))(input)
+}
+
+// Matches an immediate value; either a #<expression>, a =<expression> or a bare number.
+// Expressions the parser can't handle are still classified as immediates up to the next
+// delimiter, so highlighting doesn't break on unsupported syntax.
+fn lex_immediate(input: &str) -> NomResult<&str, &str> {
+    alt((
+        recognize(parse_expression),
+        recognize(pair(
+            one_of("#="),
+            take_till(|c: char| c == ',' || c == ']' || c == ' '),
+        )),
+        recognize(tuple((opt(char('-')), digit1, opt(alphanumeric1)))),
+    ))(input)
+}
+
+// Matches an identifier, i.e. a mnemonic or a label name.
+fn identifier(input: &str) -> NomResult<&str, &str> {
+    take_while1(|c: char| c.is_ascii_alphanumeric() || c == '_')(input)
+}
+
+// Succeeds if the input is not in the middle of a word, so that eg: r1abel is not lexed as r1.
+fn word_end(input: &str) -> NomResult<&str, ()> {
+    not(peek(identifier))(input)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kinds(source: &str) -> Vec<(TokenKind, &str)> {
+        lex(source)
+            .into_iter()
+            .map(|t| (t.kind, &source[t.span]))
+            .collect()
+    }
+
+    #[test]
+    fn test_lex_instruction() {
+        use TokenKind::*;
+        assert_eq!(
+            kinds("add r3,r1, r2, lsl #0x2 ; comment"),
+            vec![
+                (Mnemonic, "add"),
+                (Register, "r3"),
+                (Punctuation, ","),
+                (Register, "r1"),
+                (Punctuation, ","),
+                (Register, "r2"),
+                (Punctuation, ","),
+                (Shift, "lsl"),
+                (Immediate, "#0x2"),
+                (Comment, "; comment"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_lex_lines() {
+        use TokenKind::*;
+        let tokens = lex("loop:\r\nldr r0,=0x20200000\nbne loop\n.word 3");
+        let lines: Vec<(TokenKind, usize)> = tokens.iter().map(|t| (t.kind, t.line)).collect();
+        assert_eq!(
+            lines,
+            vec![
+                (Label, 0),
+                (Mnemonic, 1),
+                (Register, 1),
+                (Punctuation, 1),
+                (Immediate, 1),
+                (Mnemonic, 2),
+                (Label, 2),
+                (Directive, 3),
+                (Immediate, 3),
+            ]
+        );
+        assert_eq!(tokens[5].span, 26..29);
+    }
+
+    #[test]
+    fn test_lex_directives() {
+        use TokenKind::*;
+        assert_eq!(
+            kinds(".word start"),
+            vec![(Directive, ".word"), (Label, "start")]
+        );
+        assert_eq!(
+            kinds(".equ size, 4"),
+            vec![
+                (Directive, ".equ"),
+                (Label, "size"),
+                (Punctuation, ","),
+                (Immediate, "4"),
+            ]
+        );
+        assert_eq!(
+            kinds("msg: .ascii \"Hello, ARM!\\0\" ; greeting"),
+            vec![
+                (Label, "msg:"),
+                (Directive, ".ascii"),
+                (String, "\"Hello, ARM!\\0\""),
+                (Comment, "; greeting"),
+            ]
+        );
+        assert_eq!(
+            kinds(".asciz \"unclosed; not a comment"),
+            vec![(Directive, ".asciz"), (String, "\"unclosed; not a comment")]
+        );
+    }
+}
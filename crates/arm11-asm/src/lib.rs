@@ -0,0 +1,1495 @@
+mod alias;
+mod diagnostic;
+mod directive;
+mod expression;
+mod hex;
+mod include;
+mod layout;
+mod lex;
+mod link;
+mod listing;
+mod local;
+mod macros;
+mod normalize;
+mod parse;
+mod stats;
+mod thumb;
+
+use std::{
+    collections::{HashMap, HashSet},
+    convert::TryFrom,
+    fs,
+    io::{self, Read, Write},
+    mem,
+    path::Path,
+    rc::Rc,
+    str::FromStr,
+};
+
+use alias::RegisterAliases;
+use arm11_isa::{
+    address::{Address, SymbolTable},
+    constants::*,
+    encode,
+    parse::parse_number,
+    timing,
+    types::*,
+};
+use directive::Directive;
+use include::Origin;
+use local::LocalLabels;
+
+pub use arm11_isa::parse::parse_string;
+pub use arm11_isa::symbols::{SymbolFile, SYMBOL_FILE_VERSION};
+pub use diagnostic::{Diagnostic, DiagnosticCode, Diagnostics, Severity, DEFAULT_MAX_ERRORS};
+pub use hex::Format;
+pub use layout::SizeReport;
+pub use lex::{lex, Token, TokenKind};
+pub use link::Source;
+pub use listing::{Listing, ListingData, ListingLine};
+pub use stats::{Operand2Forms, Stats};
+
+// Number of symbols listed in the size report
+const SIZE_REPORT_SYMBOLS: usize = 10;
+
+// The largest binary the assembler writes, 16MiB. Statements placed past it, eg: by a mistyped
+// .org, .align or .skip, are an error rather than gigabytes of zeros.
+const MAX_IMAGE_SIZE: u64 = 1 << 24;
+
+// Options for the assembler, set from the command line
+#[derive(Debug, Default, Clone)]
+pub struct Options {
+    pub size_report: bool,
+    // Files to write the listing and the symbol map to
+    pub listing: Option<String>,
+    pub symbol_map: Option<String>,
+    // Annotate the listing with the cycle cost of each instruction
+    pub timing: bool,
+    // Size to pad the binary to, and the byte to pad it with
+    pub pad_to: Option<u32>,
+    pub fill: u8,
+    pub emit: Emit,
+    // How the image is written
+    pub format: Format,
+    // The number of distinct diagnostics reported, or 0 to report them all; DEFAULT_MAX_ERRORS
+    // if not given
+    pub max_errors: Option<usize>,
+}
+
+// What the source describes. Data files are made of labels and data directives only, eg: to
+// generate a lookup table, and are output without the padding which aligns a literal pool.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum Emit {
+    #[default]
+    Code,
+    Data,
+}
+
+impl FromStr for Emit {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "code" => Ok(Emit::Code),
+            "data" => Ok(Emit::Data),
+            _ => Err(format!("Invalid output '{}', expected code or data", s)),
+        }
+    }
+}
+
+// The result of assembling a source file; the encoded instructions, the literal pool data
+// that follows them in the binary, the symbol table used to resolve labels, and a listing of
+// the source with the encoding of each line.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Assembled {
+    pub code: Vec<u8>,
+    pub literals: Vec<u8>,
+    pub symbol_table: SymbolTable,
+    pub listing: Listing,
+}
+
+impl Assembled {
+    // The binary image, with the literal pool placed after all the instructions
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = self.code.clone();
+        bytes.extend_from_slice(&self.literals);
+        bytes
+    }
+
+    // The binary image, padded with the fill byte to exactly size bytes, eg: to match the size
+    // of a ROM. Images larger than size are an error, rather than being truncated.
+    pub fn to_padded_bytes(&self, size: u32, fill: u8) -> Result<Vec<u8>> {
+        let mut bytes = self.to_bytes();
+        if bytes.len() > size as usize {
+            return Err(format!(
+                "Image of {} bytes ({} of code, {} of literals) doesn't fit in 0x{:x} bytes",
+                bytes.len(),
+                self.code.len(),
+                self.literals.len(),
+                size
+            )
+            .into());
+        }
+        bytes.resize(size as usize, fill);
+        Ok(bytes)
+    }
+}
+
+// Parses the byte used to pad the binary, given in decimal or hexadecimal with a 0x prefix
+pub fn parse_fill(s: &str) -> std::result::Result<u8, String> {
+    parse_number(s)
+        .ok()
+        .and_then(|n| u8::try_from(n).ok())
+        .ok_or_else(|| format!("Invalid fill byte '{}', expected 0x00 to 0xff", s))
+}
+
+// Assembles the source files into the output file, linked as described for assemble_sources. An
+// input filename of - reads the only source from stdin, with included files found relative to the
+// current directory, and an output filename of - writes the binary to stdout, with the size report
+// written to stderr so it stays out of the binary.
+pub fn run(input_filenames: &[String], output_filename: &str, options: &Options) -> Result<()> {
+    if input_filenames.len() > 1 && input_filenames.iter().any(|name| name == "-") {
+        return Err("stdin (-) can only be assembled on its own".into());
+    }
+    let sources = input_filenames
+        .iter()
+        .map(|name| Source::read(name).map_err(|e| e.context(name)))
+        .collect::<Result<Vec<Source>>>()?;
+
+    // The output is only written once the sources have assembled, so a failure leaves any
+    // existing output file as it was
+    let mut bytes = Vec::new();
+    let mut report = Vec::new();
+    run_sources(&sources, &mut bytes, &mut report, options)?;
+
+    if output_filename == "-" {
+        io::stdout().write_all(&bytes)?;
+        io::stderr().write_all(&report)?;
+    } else {
+        fs::write(output_filename, bytes)?;
+        io::stdout().write_all(&report)?;
+    }
+    Ok(())
+}
+
+// Assembles the source read from the input, naming it in diagnostics by the given name and finding
+// files it includes relative to dir, and writes the binary to the output and the size report, if
+// one was asked for, to the report. Listings and symbol maps are written to the files given in the
+// options.
+pub fn run_from(
+    mut input: impl Read,
+    input_name: &str,
+    dir: &Path,
+    output: impl Write,
+    report: impl Write,
+    options: &Options,
+) -> Result<()> {
+    let mut raw = String::new();
+    input.read_to_string(&mut raw)?;
+    let source = Source {
+        name: String::from(input_name),
+        raw,
+        dir: dir.to_path_buf(),
+    };
+    run_sources(&[source], output, report, options)
+}
+
+// Assembles the sources into one binary, written to the output, as run_from does for one source
+pub fn run_sources(
+    sources: &[Source],
+    mut output: impl Write,
+    mut report: impl Write,
+    options: &Options,
+) -> Result<()> {
+    let assembled =
+        assemble_sources(sources, options.emit).map_err(|e| match e.downcast::<Diagnostics>() {
+            Ok(diagnostics) => diagnostics
+                .with_limit(options.max_errors.unwrap_or(DEFAULT_MAX_ERRORS))
+                .into(),
+            Err(e) => e,
+        })?;
+    let bytes = match options.pad_to {
+        Some(size) => assembled.to_padded_bytes(size, options.fill)?,
+        None => assembled.to_bytes(),
+    };
+    options.format.write(&bytes, &mut output)?;
+
+    if options.size_report {
+        write!(
+            report,
+            "{}",
+            SizeReport::new(&assembled, SIZE_REPORT_SYMBOLS)
+        )?;
+    }
+    if let Some(listing_filename) = &options.listing {
+        fs::write(listing_filename, assembled.listing.format(options.timing))?;
+    }
+    if let Some(symbol_map_filename) = &options.symbol_map {
+        fs::write(
+            symbol_map_filename,
+            SymbolFile::new(&assembled.symbol_table, &assembled.listing).to_string(),
+        )?;
+    }
+
+    Ok(())
+}
+
+// Assembles a source, with any files it includes found relative to the current directory
+pub fn assemble(raw: String) -> Result<Assembled> {
+    assemble_as(raw, Emit::Code, Path::new(""))
+}
+
+// Assembles a source straight to the binary, eg: for a host with no files to write it to.
+// Sources which .include other files can't be assembled where there are no files to read.
+pub fn assemble_to_bytes(raw: &str) -> Result<Vec<u8>> {
+    Ok(assemble(String::from(raw))?.to_bytes())
+}
+
+// Assembles a data file, which has no instructions
+pub fn assemble_data(raw: String) -> Result<Assembled> {
+    assemble_as(raw, Emit::Data, Path::new(""))
+}
+
+// Assembles sources into one binary, joined in the order given, with diagnostics naming the file
+// they are in. A label defined in one source can be used in the others, unless that source names
+// the labels it shares with .global, when the rest are private to it. Labels defined more than
+// once are reported, along with any other errors.
+// eg:
+//
+// main.s: bl blink        blink.s: .global blink
+//         b .                      blink:
+//                                  wait:
+//                                  subs r0, r0, #1
+//                                  bne wait
+//                                  mov pc, lr
+//
+pub fn assemble_sources(sources: &[Source], emit: Emit) -> Result<Assembled> {
+    assemble_linked(&link::link(sources)?, emit)
+}
+
+// Assembles ARM assembly written as Rust tokens, with statements separated by semicolons and
+// labels before the statement they name. It is meant for tests and fixtures, whose source is
+// fixed, so panics with the diagnostic if the source doesn't assemble.
+//
+// eg: let assembled = asm! { mov r0, #3; loop: subs r0, r0, #1; bne loop; andeq r0, r0, r0 };
+//
+#[macro_export]
+macro_rules! asm {
+    ($($tokens:tt)*) => {
+        $crate::assemble(
+            $crate::tokens_to_source(stringify!($($tokens)*)),
+        )
+        .unwrap_or_else(|e| panic!("asm! failed: {}", e))
+    };
+}
+
+// Turns the tokens given to asm!, as stringified, back into a source with a statement on each
+// line. Line breaks added when the tokens were stringified are removed, and labels are moved to
+// a line of their own.
+pub fn tokens_to_source(tokens: &str) -> String {
+    let mut statements = vec![String::new()];
+    let mut in_string = false;
+    let mut escaped = false;
+    for c in tokens.chars() {
+        let statement = statements.last_mut().expect("no statement");
+        match c {
+            _ if in_string => {
+                statement.push(c);
+                in_string = escaped || c != '"';
+                escaped = !escaped && c == '\\';
+            }
+            '"' => {
+                statement.push(c);
+                in_string = true;
+            }
+            ';' => statements.push(String::new()),
+            _ if c.is_whitespace() => {
+                if !statement.is_empty() && !statement.ends_with(' ') {
+                    statement.push(' ');
+                }
+            }
+            _ => statement.push(c),
+        }
+    }
+
+    let mut source = String::new();
+    for statement in &statements {
+        let statement = statement.trim();
+        let (label, rest) = match statement.split_once(' ') {
+            Some((label, rest)) if label.ends_with(':') => (Some(label), rest),
+            _ if statement.ends_with(':') => (Some(statement), ""),
+            _ => (None, statement),
+        };
+        for line in label
+            .into_iter()
+            .chain(Some(rest))
+            .filter(|l| !l.is_empty())
+        {
+            source.push_str(line);
+            source.push('\n');
+        }
+    }
+    source
+}
+
+fn assemble_as(raw: String, emit: Emit, dir: &Path) -> Result<Assembled> {
+    let source = Source {
+        name: String::new(),
+        raw,
+        dir: dir.to_path_buf(),
+    };
+    assemble_linked(&link::link(&[source])?, emit)
+}
+
+fn assemble_linked(linked: &link::Linked, emit: Emit) -> Result<Assembled> {
+    // Files are included when they are linked, and macros expanded before anything else sees the
+    // source. Each line of the expansion keeps the file and line it was written on, for the
+    // diagnostics and the listing.
+    let (raw, from) = macros::expand_macros(&linked.raw)?;
+    let origins: Vec<Origin> = from
+        .iter()
+        .map(|&line| linked.origins[line - 1].clone())
+        .collect();
+    let dir = linked.dir.as_path();
+
+    // First pass - populate symbol table and statements list. Moving literals to an earlier pool
+    // changes the layout, so this is repeated until no more literals move.
+    let mut backward = BackwardLiterals::default();
+    let (symbol_table, statements, mut diagnostics) = loop {
+        let (symbol_table, statements, diagnostics) =
+            extract_labels_and_statements(&raw, dir, &backward);
+        if !backward.move_closer(&statements) {
+            break (symbol_table, statements, diagnostics);
+        }
+    };
+
+    let rc_symbol_table = Rc::new(symbol_table);
+    let lines: Vec<&str> = raw.lines().collect();
+    let code_size = match emit {
+        Emit::Code => code_size(&statements),
+        Emit::Data => statements.last().map_or(0, |s| s.address + s.size()),
+    };
+    let mut assembled = Vec::with_capacity(code_size);
+    let mut additional = Vec::new();
+    let mut next_free_address = code_size;
+    // The address and size of each pool placed by .ltorg, the index of the next one, and the
+    // number of literals placed in each so far. Literals after the last .ltorg go after all the
+    // code. Pools are filled in once all the code is encoded, as literals can be placed in a
+    // pool before the ldr which loads them.
+    let pools: Vec<(usize, usize)> = statements
+        .iter()
+        .filter(|s| matches!(s.kind, StatementKind::Directive(Directive::Ltorg(_))))
+        .map(|s| (s.address, s.size()))
+        .collect();
+    let mut pool_index: usize = 0;
+    let mut pool_literals = vec![0; pools.len()];
+    let mut pool_data = Vec::new();
+    // The address and encoding of each line placed in the binary, for the listing
+    let mut encoded_lines = HashMap::new();
+    let mut timings = HashMap::new();
+
+    // Second pass, parse the instructions and encode the statements into the binary. A
+    // statement which can't be encoded is reported, and the rest are still encoded so that every
+    // error in the source is found at once.
+    for statement in &statements {
+        let encoded = (|| -> std::result::Result<(), Diagnostic> {
+            // Pad up to the statement's address, if it was aligned
+            assembled.resize(statement.address, 0);
+            let source_line = lines[statement.line - 1];
+
+            match &statement.kind {
+                StatementKind::Instruction(instr) if emit == Emit::Data => {
+                    return Err(Diagnostic::for_line(
+                        DiagnosticCode::InstructionInData,
+                        statement.line,
+                        source_line,
+                        "instructions can't be used in a data file",
+                    ));
+                }
+                StatementKind::Instruction(instr) => {
+                    let st = rc_symbol_table.clone();
+                    let address = Address(statement.address as u32);
+                    let pool = if backward.ldrs.contains(&statement.line) {
+                        pool_index.checked_sub(1)
+                    } else {
+                        Some(pool_index).filter(|&index| index < pools.len())
+                    };
+                    let literal_address = match pool {
+                        Some(index) => pools[index].0 + pool_literals[index] * BYTES_IN_WORD,
+                        None => next_free_address,
+                    };
+                    let substituted =
+                        expression::substitute_expressions(instr, &rc_symbol_table, address)
+                            .map_err(|e| {
+                                Diagnostic::for_line(
+                                    DiagnosticCode::InvalidExpression,
+                                    statement.line,
+                                    source_line,
+                                    e.to_string(),
+                                )
+                            })?;
+                    let opt_data = if statement.thumb {
+                        let (parsed, opt_data) = thumb::parse_thumb(
+                            &substituted,
+                            statement.line,
+                            statement.address,
+                            literal_address,
+                            st,
+                        )
+                        .map_err(|d| d.in_source(source_line))?;
+
+                        // The timing of a bl is that of its suffix, which branches
+                        if let Some(last) = parsed.last() {
+                            timings.insert(statement.line, timing::annotation(last));
+                        }
+                        let encoded = parsed
+                            .into_iter()
+                            .map(thumb::encode)
+                            .collect::<Option<Vec<u16>>>()
+                            .ok_or_else(|| {
+                                Diagnostic::for_line(
+                                    DiagnosticCode::NoThumbEncoding,
+                                    statement.line,
+                                    source_line,
+                                    "instruction has no Thumb encoding; most Thumb instructions \
+                                     only use r0 to r7, and set the flags",
+                                )
+                            })?;
+                        for halfword in &encoded {
+                            assembled.extend_from_slice(&halfword.to_le_bytes());
+                        }
+                        encoded_lines.insert(
+                            statement.line,
+                            (statement.address as u32, ListingData::Halfwords(encoded)),
+                        );
+                        opt_data
+                    } else {
+                        let (parsed, opt_data) = parse::parse_asm(
+                            &substituted,
+                            statement.line,
+                            statement.address,
+                            literal_address,
+                            st,
+                        )
+                        .map_err(|d| d.in_source(source_line))?;
+
+                        timings.insert(statement.line, timing::annotation(&parsed));
+                        let encoded = encode::encode(parsed).map_err(|e| {
+                            Diagnostic::for_line(
+                                DiagnosticCode::NoArmEncoding,
+                                statement.line,
+                                source_line,
+                                e.to_string(),
+                            )
+                        })?;
+                        assembled.extend_from_slice(&encoded.to_le_bytes());
+                        encoded_lines.insert(
+                            statement.line,
+                            (statement.address as u32, ListingData::Word(encoded)),
+                        );
+                        opt_data
+                    };
+
+                    match (opt_data, pool) {
+                        (Some(data), Some(index)) => {
+                            if (pool_literals[index] + 1) * BYTES_IN_WORD > pools[index].1 {
+                                return Err(Diagnostic::for_line(
+                                    DiagnosticCode::LiteralPoolFull,
+                                    statement.line,
+                                    source_line,
+                                    "literal pool has more literals than were reserved for it",
+                                ));
+                            }
+                            pool_literals[index] += 1;
+                            pool_data.push((literal_address, data));
+                        }
+                        (Some(data), None) => {
+                            additional.extend_from_slice(&data.to_le_bytes());
+                            next_free_address += BYTES_IN_WORD;
+                        }
+                        (None, _) => (),
+                    }
+                }
+                StatementKind::Directive(directive @ Directive::Ltorg(_)) => {
+                    assembled.resize(statement.address + directive.size(), 0);
+                    pool_index += 1;
+                }
+                StatementKind::Directive(directive) => {
+                    let bytes = directive
+                        .encode(&rc_symbol_table, Address(statement.address as u32))
+                        .map_err(|e| {
+                            Diagnostic::for_line(
+                                DiagnosticCode::InvalidExpression,
+                                statement.line,
+                                source_line,
+                                e.to_string(),
+                            )
+                        })?;
+                    assembled.extend_from_slice(&bytes);
+                    encoded_lines.insert(
+                        statement.line,
+                        (statement.address as u32, ListingData::Bytes(bytes)),
+                    );
+                }
+            }
+            Ok(())
+        })();
+        if let Err(diagnostic) = encoded {
+            diagnostics.push(diagnostic);
+        }
+    }
+    if !diagnostics.is_empty() || !linked.diagnostics.is_empty() {
+        let diagnostics = diagnostics
+            .into_iter()
+            .map(|d| {
+                let origin = &origins[d.line - 1];
+                d.at(origin)
+            })
+            .chain(linked.diagnostics.iter().cloned())
+            .collect();
+        return Err(Diagnostics::new(diagnostics).into());
+    }
+    assembled.resize(code_size, 0);
+    for (address, data) in pool_data {
+        assembled[address..address + BYTES_IN_WORD].copy_from_slice(&data.to_le_bytes());
+    }
+    for statement in &statements {
+        if let StatementKind::Directive(Directive::Ltorg(_)) = statement.kind {
+            let bytes = assembled[statement.address..statement.address + statement.size()].to_vec();
+            encoded_lines.insert(
+                statement.line,
+                (statement.address as u32, ListingData::Bytes(bytes)),
+            );
+        }
+    }
+
+    let listing = listing::listing(
+        &raw,
+        &origins,
+        &encoded_lines,
+        &timings,
+        code_size as u32,
+        &additional,
+    );
+    Ok(Assembled {
+        code: assembled,
+        literals: additional,
+        symbol_table: Rc::try_unwrap(rc_symbol_table).unwrap_or_else(|rc| (*rc).clone()),
+        listing,
+    })
+}
+
+// A line of source which is placed in the binary, at the given address. The line number is
+// counted from 1, in the source after macro expansion. Instructions after a .thumb directive are
+// Thumb instructions, until a .arm directive.
+#[derive(Debug, Clone, PartialEq)]
+struct Statement {
+    address: usize,
+    line: usize,
+    kind: StatementKind,
+    thumb: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum StatementKind {
+    Instruction(String),
+    Directive(Directive),
+}
+
+impl Statement {
+    fn size(&self) -> usize {
+        match &self.kind {
+            StatementKind::Instruction(instr) if self.thumb => thumb::size(instr),
+            StatementKind::Instruction(_) => BYTES_IN_WORD,
+            StatementKind::Directive(directive) => directive.size(),
+        }
+    }
+}
+
+// The size of the code and data, before the literal pool. This is padded to a whole number of
+// words so that the literal pool is word aligned.
+fn code_size(statements: &[Statement]) -> usize {
+    let end = statements.last().map_or(0, |s| s.address + s.size());
+    align(end, BYTES_IN_WORD)
+}
+
+fn align(address: usize, alignment: usize) -> usize {
+    address.div_ceil(alignment) * alignment
+}
+
+// Whether an instruction is an ldr = whose value might not fit in a mov or mvn, so might need a
+// word in a literal pool. Values which use labels can't be known until all the labels are, so
+// they are assumed to need one. In Thumb state, an ldr = always loads a literal.
+fn may_need_literal(instr: &str, address: usize, thumb: bool) -> bool {
+    let value = match instr
+        .trim_start()
+        .strip_prefix("ldr")
+        .and_then(|rest| rest.split_once('='))
+    {
+        Some(_) if thumb => return true,
+        Some((_, value)) => value.trim(),
+        None => return false,
+    };
+    match expression::parse_expression(value) {
+        Ok((_, expression)) => expression
+            .evaluate(&SymbolTable::new(), Address(address as u32))
+            .map_or(true, |value| {
+                value > mask(IMM_VALUE.size) && !value > mask(IMM_VALUE.size)
+            }),
+        Err(_) => false,
+    }
+}
+
+// The ldr = instructions whose literal is placed in the pool before them, as it is closer than
+// the one after them, by line. Each .ltorg reserves space for the literals placed in it from
+// after it, by line, as well as for those from before it.
+#[derive(Debug, Default, Clone, PartialEq)]
+struct BackwardLiterals {
+    ldrs: HashSet<usize>,
+    reserved: HashMap<usize, usize>,
+}
+
+impl BackwardLiterals {
+    // Moves the literal of each ldr = which might need one to the pool before it, if that is
+    // closer than the pool after it given the current layout. Returns whether any moved.
+    // Literals never move back, so laying out the statements again always finishes. A Thumb
+    // ldr = can only load a literal after it, so its literal never moves.
+    fn move_closer(&mut self, statements: &[Statement]) -> bool {
+        let pools: Vec<&Statement> = statements
+            .iter()
+            .filter(|s| matches!(s.kind, StatementKind::Directive(Directive::Ltorg(_))))
+            .collect();
+        let end = code_size(statements);
+        let mut moved = false;
+        for statement in statements {
+            let instr = match &statement.kind {
+                StatementKind::Instruction(instr) if !statement.thumb => instr,
+                _ => continue,
+            };
+            if self.ldrs.contains(&statement.line)
+                || !may_need_literal(instr, statement.address, false)
+            {
+                continue;
+            }
+            let pc = statement.address + PIPELINE_OFFSET;
+            let next = pools
+                .iter()
+                .position(|pool| pool.address > statement.address);
+            let previous = match next {
+                Some(0) => continue,
+                Some(next) => pools[next - 1],
+                None => match pools.last() {
+                    Some(previous) => previous,
+                    None => continue,
+                },
+            };
+            let ahead = next.map_or(end, |next| pools[next].address);
+            if pc - previous.address < ahead.saturating_sub(pc) {
+                self.ldrs.insert(statement.line);
+                *self.reserved.entry(previous.line).or_insert(0) += 1;
+                moved = true;
+            }
+        }
+        moved
+    }
+}
+
+// Finds the address of every label and statement. Lines which can't be read are reported and
+// left out, so that the rest of the source can still be checked.
+fn extract_labels_and_statements(
+    raw: &str,
+    dir: &Path,
+    backward: &BackwardLiterals,
+) -> (SymbolTable, Vec<Statement>, Vec<Diagnostic>) {
+    let mut symbol_table = HashMap::new();
+    let mut statements = Vec::new();
+    let mut diagnostics = Vec::new();
+
+    // Labels are given the address of the statement that follows them, once it is aligned
+    let mut pending_labels = Vec::new();
+    let mut local_labels = LocalLabels::new(raw);
+    let mut aliases = RegisterAliases::default();
+    let mut address = 0;
+    // The number of ldr = instructions since the last .ltorg which might need a literal in the
+    // next pool
+    let mut literals = 0;
+    let mut thumb = false;
+    for (index, line) in raw.lines().enumerate() {
+        let len = line.len();
+
+        // If the line is empty continue
+        if len == 0 {
+            continue;
+        }
+
+        // If the line ends with ":" it is a label, if it starts with "." it is a directive,
+        // else it is an instruction
+        if let Some(label) = line.strip_suffix(':') {
+            pending_labels.push(local_labels.define(label));
+            continue;
+        }
+        let original = line;
+        match aliases.update(line) {
+            Ok(true) => continue,
+            Ok(false) => (),
+            Err(e) => {
+                diagnostics.push(Diagnostic::for_line(
+                    DiagnosticCode::InvalidRegister,
+                    index + 1,
+                    original,
+                    e,
+                ));
+                continue;
+            }
+        }
+        let aliased = aliases.resolve(line);
+        let line = match local_labels.resolve(&aliased) {
+            Ok(line) => line,
+            Err(e) => {
+                diagnostics.push(Diagnostic::for_line(
+                    DiagnosticCode::InvalidLabel,
+                    index + 1,
+                    original,
+                    e,
+                ));
+                continue;
+            }
+        };
+        let (mut kind, alignment) = if line.trim_start().starts_with('.') {
+            let directive = match directive::parse_directive(&line, index + 1, dir) {
+                Ok(directive) => directive,
+                Err(diagnostic) => {
+                    diagnostics.push(diagnostic.in_source(original));
+                    continue;
+                }
+            };
+            let alignment = directive.alignment();
+            (StatementKind::Directive(directive), alignment)
+        } else if thumb {
+            (StatementKind::Instruction(line.into_owned()), 2)
+        } else {
+            (StatementKind::Instruction(line.into_owned()), BYTES_IN_WORD)
+        };
+
+        // .org moves on to its address, which can't be before the current one
+        let mut start = address;
+        if let StatementKind::Directive(Directive::Org(target)) = kind {
+            if (target as usize) < address {
+                diagnostics.push(Diagnostic::for_line(
+                    DiagnosticCode::InvalidExpression,
+                    index + 1,
+                    original,
+                    format!(
+                        ".org 0x{:x} is before the current address 0x{:x}",
+                        target, address
+                    ),
+                ));
+                continue;
+            }
+            start = target as usize;
+        }
+        start = align(start, alignment);
+        let size = match &kind {
+            StatementKind::Directive(directive) => directive.size(),
+            StatementKind::Instruction(instr) if thumb => thumb::size(instr),
+            StatementKind::Instruction(_) => BYTES_IN_WORD,
+        };
+        let end = start as u64 + size as u64;
+        if end > MAX_IMAGE_SIZE {
+            diagnostics.push(Diagnostic::for_line(
+                DiagnosticCode::InvalidExpression,
+                index + 1,
+                original,
+                format!(
+                    "the binary would end at 0x{:x}, past its maximum size of 0x{:x} bytes",
+                    end, MAX_IMAGE_SIZE
+                ),
+            ));
+            continue;
+        }
+        address = start;
+        match &mut kind {
+            StatementKind::Instruction(_) if backward.ldrs.contains(&(index + 1)) => (),
+            StatementKind::Instruction(instr) if may_need_literal(instr, address, thumb) => {
+                literals += 1
+            }
+            StatementKind::Directive(Directive::Thumb) => thumb = true,
+            StatementKind::Directive(Directive::Arm) => thumb = false,
+            StatementKind::Directive(Directive::Ltorg(reserved)) => {
+                *reserved = mem::take(&mut literals)
+                    + backward.reserved.get(&(index + 1)).copied().unwrap_or(0)
+            }
+            _ => (),
+        }
+        for label in pending_labels.drain(..) {
+            symbol_table.insert(label, Address(address as u32));
+        }
+
+        let statement = Statement {
+            address,
+            line: index + 1,
+            kind,
+            thumb,
+        };
+        address += statement.size();
+        statements.push(statement);
+    }
+
+    // Labels at the end of the source refer to the end of the code
+    for label in pending_labels {
+        symbol_table.insert(label, Address(address as u32));
+    }
+
+    (symbol_table, statements, diagnostics)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_asm_macro() {
+        let assembled = asm! {
+            mov r0, #3;
+            loop: subs r0, r0, #1;
+            bne loop;
+            ldr r1, =msg;
+            andeq r0, r0, r0;
+            msg:
+            .ascii "a; b\"";
+        };
+        assert_eq!(assembled.symbol_table["loop"], Address(4));
+        assert_eq!(assembled.symbol_table["msg"], Address(0x14));
+        assert_eq!(&assembled.code[0x14..0x19], b"a; b\"");
+
+        // The IR built by instr! encodes to the same words as the assembler gives
+        let assembled = asm! { adds r0, r1, #0x100; ldrb r0, [r1, #-4]; mla r0, r1, r2, r3 };
+        let words: Vec<u32> = [
+            arm11_isa::instr!(adds r0, r1, #0x100),
+            arm11_isa::instr!(ldrb r0, [r1, #-4]),
+            arm11_isa::instr!(mla r0, r1, r2, r3),
+        ]
+        .iter()
+        .map(|&instr| encode::encode(instr).expect("encode failed"))
+        .collect();
+        let bytes: Vec<u8> = words.iter().flat_map(|w| w.to_le_bytes()).collect();
+        assert_eq!(assembled.code, bytes);
+
+        assert_eq!(
+            tokens_to_source("mov r0,\n#1; end:; .ascii \"x  ;\""),
+            "mov r0, #1\nend:\n.ascii \"x  ;\"\n"
+        );
+    }
+
+    #[test]
+    fn test_run_from() {
+        let source = "mov r0,#1\nandeq r0,r0,r0\n";
+        let options = Options {
+            size_report: true,
+            ..Options::default()
+        };
+        let mut bytes = Vec::new();
+        let mut report = Vec::new();
+        run_from(
+            source.as_bytes(),
+            "-",
+            Path::new(""),
+            &mut bytes,
+            &mut report,
+            &options,
+        )
+        .expect("run failed");
+        assert_eq!(bytes, [0x01, 0x00, 0xa0, 0xe3, 0x00, 0x00, 0x00, 0x00]);
+        assert!(String::from_utf8(report)
+            .expect("report not utf8")
+            .contains(".text"));
+
+        // Diagnostics name the input
+        let e = run_from(
+            "bad\n".as_bytes(),
+            "<stdin>",
+            Path::new(""),
+            &mut bytes,
+            io::sink(),
+            &options,
+        )
+        .expect_err("run succeeded");
+        assert!(e.to_string().contains("<stdin>"), "{}", e);
+    }
+
+    #[test]
+    fn test_register_aliases() {
+        let aliased = assemble(String::from(
+            "count .req r4\nmov count,#1\nldr r0,[sp,#4]\nstmfd sp!,{fp,ip,lr}\n.unreq count\n\
+             mov pc,lr\n",
+        ))
+        .expect("assemble failed");
+        let named = assemble(String::from(
+            "mov r4,#1\nldr r0,[r13,#4]\nstmfd r13!,{r11,r12,r14}\nmov r15,r14\n",
+        ))
+        .expect("assemble failed");
+        assert_eq!(aliased.code, named.code);
+
+        // A removed alias is an ordinary word again
+        assert!(assemble(String::from("count .req r4\n.unreq count\nmov count,#1\n")).is_err());
+    }
+
+    #[test]
+    fn test_source_format() {
+        let free_form = assemble(String::from(
+            "; count down\n    MOV R0, #10\t@ start\n  Loop:\n\tSUBS\tR0,  R0 , #1 // step\n\
+             BNE Loop\n.WORD 0X10\n",
+        ))
+        .expect("assemble failed");
+        let plain = assemble(String::from(
+            "mov r0,#10\nLoop:\nsubs r0,r0,#1\nbne Loop\n.word 0x10\n",
+        ))
+        .expect("assemble failed");
+        assert_eq!(free_form.code, plain.code);
+        assert_eq!(free_form.symbol_table["Loop"], Address(4));
+
+        // Labels keep their case
+        assert!(assemble(String::from("loop:\nb LOOP\n")).is_err());
+    }
+
+    #[test]
+    fn test_many_diagnostics() {
+        // Every error is found, rather than only the first, and a bad directive or label doesn't
+        // stop the rest of the source from being checked
+        let source = format!(
+            "{}mov r0,#0x101\n.word missing\n.bogus 1\nadd r1,rx,#1\n",
+            "addd r1,r1,#1\n".repeat(42)
+        );
+        let err = assemble(source.clone()).expect_err("assemble succeeded");
+        let diagnostics = err.downcast_ref::<Diagnostics>().expect("not a diagnostic");
+        assert_eq!((diagnostics.errors(), diagnostics.warnings()), (46, 0));
+        let lines: Vec<usize> = diagnostics.iter().map(|d| d.line).skip(41).collect();
+        assert_eq!(lines, [42, 43, 44, 45, 46]);
+
+        // Identical errors are grouped, and the rest are cut off at the limit
+        let mut options = Options {
+            max_errors: Some(2),
+            ..Options::default()
+        };
+        let run = |options: &Options| {
+            run_from(
+                source.as_bytes(),
+                "prog.s",
+                Path::new(""),
+                io::sink(),
+                io::sink(),
+                options,
+            )
+            .expect_err("run succeeded")
+            .to_string()
+        };
+        let shown = run(&options);
+        assert!(shown.starts_with("prog.s:1:1: error[E0001]: unknown mnemonic 'addd' (x42)\n"));
+        assert!(shown.contains("prog.s:43:8: error[E0006]"), "{}", shown);
+        assert!(!shown.contains("prog.s:44"), "{}", shown);
+        assert!(
+            shown.ends_with("46 errors; 3 more not shown, use --max-errors 0 to show them all"),
+            "{}",
+            shown
+        );
+        options.max_errors = Some(0);
+        assert!(run(&options).ends_with("\n\n46 errors"));
+    }
+
+    #[test]
+    fn test_assemble_sources() {
+        let source = |name: &str, raw: &str| Source {
+            name: String::from(name),
+            raw: String::from(raw),
+            dir: PathBuf::new(),
+        };
+        // Labels are shared between sources, except those another .global leaves private
+        let main = source(
+            "main.s",
+            "bl blink
+wait:
+b wait
+",
+        );
+        let blink = source(
+            "blink.s",
+            ".global blink
+blink:
+wait:
+subs r0,r0,#1
+bne wait
+mov pc,lr
+",
+        );
+        let assembled = assemble_sources(&[main.clone(), blink.clone()], Emit::Code)
+            .expect("assemble_sources failed");
+        assert_eq!(assembled.symbol_table["blink"], Address(8));
+        assert_eq!(assembled.symbol_table["wait"], Address(4));
+        assert_eq!(assembled.symbol_table["wait$1"], Address(8));
+        assert_eq!(
+            assembled.code[4..12],
+            [0xfe, 0xff, 0xff, 0xea, 0x01, 0x00, 0x50, 0xe2]
+        );
+
+        // Errors name the file and line they are on
+        let other = source("other.s", "mov r0,r0\nblink:\naddd r0,r0,#1\n");
+        let err = assemble_sources(&[main, blink, other], Emit::Code)
+            .expect_err("assemble_sources succeeded");
+        let diagnostics = err.downcast_ref::<Diagnostics>().expect("not a diagnostic");
+        let found: Vec<(Option<&str>, usize, DiagnosticCode)> = diagnostics
+            .iter()
+            .map(|d| (d.file.as_deref(), d.line, d.code))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                (Some("other.s"), 2, DiagnosticCode::DuplicateLabel),
+                (Some("other.s"), 3, DiagnosticCode::UnknownMnemonic),
+            ]
+        );
+        assert_eq!(
+            diagnostics.first().message,
+            "label 'blink' is already defined in blink.s on line 2"
+        );
+    }
+
+    #[test]
+    fn test_org_align() {
+        let source = "b start\n.align 4\ntable:\n.byte 1\n.org 0x20\nstart:\nmov r0,#1\n\
+                      andeq r0,r0,r0\n";
+        let assembled = assemble(String::from(source)).expect("assemble failed");
+
+        assert_eq!(assembled.symbol_table["table"], Address(0x10));
+        assert_eq!(assembled.symbol_table["start"], Address(0x20));
+        assert_eq!(assembled.code[0..4], 0xea000006u32.to_le_bytes());
+        assert_eq!(
+            assembled.code[0x10..0x20],
+            [1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]
+        );
+        assert_eq!(assembled.code.len(), 0x28);
+
+        // .org can't move backwards
+        let err = assemble(String::from(".skip 8\n.org 4\n")).expect_err("assemble succeeded");
+        let diagnostic = err
+            .downcast_ref::<Diagnostics>()
+            .expect("not a diagnostic")
+            .first();
+        assert_eq!(diagnostic.line, 2);
+        assert_eq!(
+            diagnostic.message,
+            ".org 0x4 is before the current address 0x8"
+        );
+
+        // Nor past the largest binary, which would be padded out with zeros
+        let err = assemble(String::from(
+            "mov r0,#1\n.org 0x80000000\nstart:\nmov r1,#2\n",
+        ))
+        .expect_err("assemble succeeded");
+        let diagnostic = err
+            .downcast_ref::<Diagnostics>()
+            .expect("not a diagnostic")
+            .first();
+        assert_eq!(
+            (diagnostic.line, diagnostic.code),
+            (2, DiagnosticCode::InvalidExpression)
+        );
+        assert_eq!(
+            diagnostic.message,
+            "the binary would end at 0x80000000, past its maximum size of 0x1000000 bytes"
+        );
+        assert!(assemble(String::from(".org 0xfffffc\nmov r0,#1\n")).is_ok());
+    }
+
+    #[test]
+    fn test_assemble_data() {
+        let source = "ldr r0,=msg\nldr r1,=words\nandeq r0,r0,r0\nmsg:\n.ascii \"hi\"\n\
+                      .byte 1\nwords:\n.word 0x20200000, msg\n.skip 2\n";
+        let assembled = assemble(String::from(source)).expect("assemble failed");
+
+        assert_eq!(assembled.symbol_table["msg"], Address(0xc));
+        assert_eq!(assembled.symbol_table["words"], Address(0x10));
+        assert_eq!(
+            assembled.code[0xc..],
+            [
+                b'h', b'i', 0x01, 0x00, 0x00, 0x00, 0x20, 0x20, 0x0c, 0x00, 0x00, 0x00, 0x00, 0x00,
+                0x00, 0x00
+            ]
+        );
+        // mov r0,#0xc; mov r1,#0x10
+        assert_eq!(assembled.code[0..4], 0xe3a0000cu32.to_le_bytes());
+        assert_eq!(assembled.code[4..8], 0xe3a01010u32.to_le_bytes());
+        assert!(assembled.literals.is_empty());
+    }
+
+    #[test]
+    fn test_assemble_expressions() {
+        let source = "mov r0,#(1<<5)\nldr r1,[r2,#(end-start)*2]\nldr r3,=end+4\n\
+                      start:\n.word start+4, ~0 >> 4\nend:\n";
+        let assembled = assemble(String::from(source)).expect("assemble failed");
+
+        // mov r0,#0x20; ldr r1,[r2,#0x10]; mov r3,#0x18
+        assert_eq!(assembled.code[0..4], 0xe3a00020u32.to_le_bytes());
+        assert_eq!(assembled.code[4..8], 0xe5921010u32.to_le_bytes());
+        assert_eq!(assembled.code[8..12], 0xe3a03018u32.to_le_bytes());
+        assert_eq!(assembled.code[12..16], 0x10u32.to_le_bytes());
+        assert_eq!(assembled.code[16..20], 0x0fffffffu32.to_le_bytes());
+
+        assert!(assemble(String::from("mov r0,#missing+1\n")).is_err());
+    }
+
+    #[test]
+    fn test_negative_transfer_offsets() {
+        let source = "a:\nldr r0,[r1,#a-b]\nstr r0,[r1,#-(-4)]\nldrb r0,[r1],-r2\n\
+                      ldr r0,[r1,-r2, lsl #2]!\nldrh r0,[r1,#-0]\nb:\n";
+        let assembled = assemble(String::from(source)).expect("assemble failed");
+
+        // ldr r0,[r1,#-20]; str r0,[r1,#4]; ldrb r0,[r1],-r2; ldr r0,[r1,-r2,lsl #2]!;
+        // ldrh r0,[r1,#-0]
+        let words: Vec<u32> = assembled
+            .code
+            .chunks(4)
+            .map(|w| u32::from_le_bytes([w[0], w[1], w[2], w[3]]))
+            .collect();
+        assert_eq!(
+            words,
+            vec![0xe5110014, 0xe5810004, 0xe6510002, 0xe7310102, 0xe15100b0]
+        );
+
+        assert!(assemble(String::from("a:\nldr r0,[r1,#a-0x1004]\n")).is_err());
+    }
+
+    #[test]
+    fn test_assemble_data_file() {
+        let source = "table:\n.word end-table, table+1\n.byte 1, 2, 3\nend:\n";
+        let assembled = assemble_data(String::from(source)).expect("assemble failed");
+        assert_eq!(assembled.to_bytes(), [11, 0, 0, 0, 1, 0, 0, 0, 1, 2, 3]);
+        assert_eq!(assembled.symbol_table["end"], Address(11));
+
+        let err = assemble_data(String::from(".byte 1\nmov r0,#1\n")).expect_err("assembled");
+        let diagnostics = err.downcast_ref::<Diagnostics>().expect("not a diagnostic");
+        assert_eq!(diagnostics.first().line, 2);
+        assert_eq!("data".parse(), Ok(Emit::Data));
+    }
+
+    #[test]
+    fn test_diagnostics() {
+        let diagnostic = |source: &str| {
+            let err = assemble(String::from(source)).expect_err("assemble succeeded");
+            let diagnostics = err.downcast_ref::<Diagnostics>().expect("not a diagnostic");
+            let diagnostic = diagnostics.first();
+            (
+                diagnostic.line,
+                diagnostic.column,
+                diagnostic.message.clone(),
+            )
+        };
+
+        assert_eq!(
+            diagnostic("mov r1,#1\nadd r1,rx,#1\n"),
+            (2, 8, String::from("unexpected 'rx' while parsing register"))
+        );
+        assert_eq!(
+            diagnostic("movx r0,r1\n"),
+            (1, 1, String::from("unknown mnemonic 'movx'"))
+        );
+        assert_eq!(
+            diagnostic("add r0,r1,#0x101\n"),
+            (
+                1,
+                11,
+                String::from(
+                    "constant 0x101 can't be encoded as an 8 bit rotated immediate \
+                     while parsing operand2 constant"
+                )
+            )
+        );
+        // Values which don't fit in their field are errors, rather than being masked to fit
+        assert_eq!(
+            diagnostic("b 0x2000008\n"),
+            (
+                1,
+                3,
+                String::from(
+                    "branch offset 33554432 can't be encoded, it would be truncated to \
+                     -33554432 while parsing branch target"
+                )
+            )
+        );
+        assert_eq!(
+            diagnostic("b 0x12\n").2,
+            "branch offset 10 can't be encoded, it would be truncated to 8 \
+             while parsing branch target"
+        );
+        assert_eq!(
+            diagnostic("ldr r0,[r1,#0x1000]\n"),
+            (
+                1,
+                12,
+                String::from(
+                    "12 bit transfer offset 4096 can't be encoded, it would be truncated to 0 \
+                     while parsing transfer offset"
+                )
+            )
+        );
+        assert_eq!(
+            diagnostic("ldrh r0,[r1,#-0x104]\n").2,
+            "8 bit halfword transfer offset -260 can't be encoded, it would be truncated to -4 \
+             while parsing transfer offset"
+        );
+        assert_eq!(
+            diagnostic("mov r0,r1,lsl #40\n"),
+            (
+                1,
+                15,
+                String::from(
+                    "5 bit shift amount 40 can't be encoded, it would be truncated to 8 \
+                     while parsing shift"
+                )
+            )
+        );
+        assert_eq!(
+            diagnostic("lsl r2,#33\n").2,
+            "5 bit shift amount 33 can't be encoded, it would be truncated to 1 while parsing shift"
+        );
+        // Columns are in the source as written, before expressions are evaluated
+        assert_eq!(
+            diagnostic("mov r0,#(4+4),r1\n"),
+            (
+                1,
+                15,
+                String::from("unexpected 'r1' while parsing processing instruction")
+            )
+        );
+
+        // The error is the kind of the first diagnostic
+        let kind = |source: &str| assemble(String::from(source)).expect_err("assemble succeeded");
+        assert!(matches!(kind("movx r0,r1\n"), ArmError::Parse(_)));
+        assert!(matches!(kind("add r0,r1,#0x101\n"), ArmError::Immediate(_)));
+        assert!(matches!(
+            kind(".thumb\nadds r8,r9,#1\n"),
+            ArmError::Encode(_)
+        ));
+    }
+
+    #[test]
+    fn test_literal_pools() {
+        let source = "ldr r0,=0x12345678\nldr r1,=label\nb end\n.ltorg\nlabel:\n\
+                      ldr r2,=0xcafe\nend:\nandeq r0,r0,r0\n";
+        let assembled = assemble(String::from(source)).expect("assemble failed");
+
+        // ldr r0,[pc,#4]; mov r1,#0x14, leaving the word reserved for it in the pool unused
+        assert_eq!(assembled.code[0..4], 0xe59f0004u32.to_le_bytes());
+        assert_eq!(assembled.code[4..8], 0xe3a01014u32.to_le_bytes());
+        assert_eq!(assembled.code[0xc..0x10], 0x12345678u32.to_le_bytes());
+        assert_eq!(assembled.code[0x10..0x14], [0; 4]);
+        assert_eq!(assembled.symbol_table["label"], Address(0x14));
+        // ldr r2,[pc,#0], from the pool after the code
+        assert_eq!(assembled.code[0x14..0x18], 0xe59f2000u32.to_le_bytes());
+        assert_eq!(assembled.literals, 0xcafeu32.to_le_bytes());
+
+        let err = assemble(String::from(
+            "ldr r0,=0x12345678\n.skip 4096\nandeq r0,r0,r0\n",
+        ))
+        .expect_err("assemble succeeded");
+        assert_eq!(
+            err.downcast_ref::<Diagnostics>()
+                .expect("not a diagnostic")
+                .first()
+                .message,
+            "literal pool is 4096 bytes after this ldr, out of range of its offset; add a .ltorg \
+             within 4KiB of it"
+        );
+        assert!(assemble(String::from(
+            "ldr r0,=0x12345678\nb end\n.ltorg\n.skip 4096\nend:\nandeq r0,r0,r0\n"
+        ))
+        .is_ok());
+
+        // A pool before the ldr is used when it is closer, here as the one after it is out of
+        // range. ldr r0,[pc,#-12]
+        let assembled = assemble(String::from(
+            "b start\n.ltorg\nstart:\nldr r0,=0x12345678\n.skip 5000\nandeq r0,r0,r0\n",
+        ))
+        .expect("assemble failed");
+        assert_eq!(assembled.symbol_table["start"], Address(0x8));
+        assert_eq!(assembled.code[0x4..0x8], 0x12345678u32.to_le_bytes());
+        assert_eq!(assembled.code[0x8..0xc], 0xe51f000cu32.to_le_bytes());
+        assert!(assembled.literals.is_empty());
+    }
+
+    #[test]
+    fn test_branch_targets() {
+        let source = "start:\nmov r0,#3\n1:\nsubs r0,r0,#1\nbne 1b\nb 1f\nb .\n1:\nb start+8\n";
+        let assembled = assemble(String::from(source)).expect("assemble failed");
+
+        // bne 0x4; b 0x14; b 0x10; b 0x8
+        assert_eq!(assembled.code[8..12], 0x1afffffdu32.to_le_bytes());
+        assert_eq!(assembled.code[12..16], 0xea000000u32.to_le_bytes());
+        assert_eq!(assembled.code[16..20], 0xeafffffeu32.to_le_bytes());
+        assert_eq!(assembled.code[20..24], 0xeafffffbu32.to_le_bytes());
+
+        assert!(assemble(String::from("b 1f\n")).is_err());
+    }
+
+    #[test]
+    fn test_padded_bytes() {
+        // mov r0,#1; ldr r1,=0x12345678; andeq r0,r0,r0, with the literal after the code
+        let source = "mov r0,#1\nldr r1,=0x12345678\nandeq r0,r0,r0\n";
+        let assembled = assemble(String::from(source)).expect("assemble failed");
+
+        let padded = assembled.to_padded_bytes(0x20, 0xff).expect("pad failed");
+        assert_eq!(padded.len(), 0x20);
+        assert_eq!(padded[..0x10], assembled.to_bytes()[..]);
+        assert!(padded[0x10..].iter().all(|&b| b == 0xff));
+
+        assert_eq!(
+            assembled.to_padded_bytes(0x10, 0).expect("pad failed"),
+            assembled.to_bytes()
+        );
+        assert!(assembled.to_padded_bytes(0xc, 0).is_err());
+
+        assert_eq!(parse_fill("0xff"), Ok(0xff));
+        assert!(parse_fill("256").is_err());
+    }
+
+    #[test]
+    fn test_symbol_file() {
+        let assembled = assemble(String::from(
+            "mov r1,#1\nloop:\nsubs r1,r1,#1\nbne loop\n.ascii \"hello\"\n",
+        ))
+        .expect("assemble failed");
+        let symbol_file = SymbolFile::new(&assembled.symbol_table, &assembled.listing);
+        assert_eq!(
+            symbol_file.to_string(),
+            "arm11-symbols 1\nsymbol loop 0x00000004\nline 0x00000000 1\n\
+             line 0x00000004 3\nline 0x00000008 4\nline 0x0000000c 5\n"
+        );
+        assert_eq!(symbol_file.line_at(Address(0xe)), Some(5));
+        assert_eq!(
+            SymbolFile::parse(&symbol_file.to_string()).expect("parse failed"),
+            symbol_file
+        );
+    }
+
+    #[test]
+    fn test_listing() {
+        let source = "mov r1,#1\nloop:\nldr r0,=0x20200000\nbne loop\n.ascii \"hello\"\n";
+        let assembled = assemble(String::from(source)).expect("assemble failed");
+
+        assert_eq!(
+            assembled.listing.to_string(),
+            "   1 00000000 e3a01001     mov r1,#1
+   2                       loop:
+   3 00000004 e59f0008     ldr r0,=0x20200000
+   4 00000008 1afffffd     bne loop
+   5 0000000c 68 65 6c 6c  .ascii \"hello\"
+     00000010 6f
+     00000014 20200000     ; literal pool
+"
+        );
+        let timed = assembled.listing.format(true);
+        let timed: Vec<&str> = timed.lines().collect();
+        assert_eq!(
+            timed[0],
+            "   1 00000000 e3a01001     mov r1,#1                 ; 1 cycle"
+        );
+        assert_eq!(
+            timed[3],
+            "   4 00000008 1afffffd     bne loop                  ; 1 cycle, 1 if skipped, \
+             +2 if it writes pc"
+        );
+        assert_eq!(timed[4], "   5 0000000c 68 65 6c 6c  .ascii \"hello\"");
+
+        // A listing reads back as it was written, apart from its timing
+        let untimed = Listing {
+            lines: assembled
+                .listing
+                .lines
+                .iter()
+                .map(|line| ListingLine {
+                    timing: None,
+                    ..line.clone()
+                })
+                .collect(),
+        };
+        assert_eq!(
+            Listing::parse(&assembled.listing.to_string()).expect("parse failed"),
+            untimed
+        );
+        assert!(Listing::parse("   1 0000000g e3a01001     mov r1,#1").is_err());
+    }
+
+    #[test]
+    fn test_include_locations() {
+        let dir = std::env::temp_dir().join(format!("arm11-locations-{}", std::process::id()));
+        fs::create_dir_all(&dir).expect("create dir failed");
+        fs::write(dir.join("f.s"), "loop:\nadd r0,r0,#1\nadd r1,rx,#1\n").expect("write failed");
+        let source = |raw: &str| Source {
+            name: String::from("g.s"),
+            raw: String::from(raw),
+            dir: dir.clone(),
+        };
+        let f = dir.join("f.s").display().to_string();
+
+        // Errors are on the line of the file they were written in, whether it was included or
+        // used a macro
+        let err = assemble_sources(
+            &[source(
+                ".macro two\nmov r0,r0\nmov r0,r0\n.endm\ntwo\n.include \"f.s\"\nb lop\n",
+            )],
+            Emit::Code,
+        )
+        .expect_err("assemble_sources succeeded");
+        let diagnostics = err.downcast_ref::<Diagnostics>().expect("not a diagnostic");
+        let found: Vec<(Option<&str>, usize, usize)> = diagnostics
+            .iter()
+            .map(|d| (d.file.as_deref(), d.line, d.column))
+            .collect();
+        assert_eq!(found, [(Some(f.as_str()), 3, 8), (Some("g.s"), 7, 3)]);
+        assert!(err
+            .to_string()
+            .starts_with(&format!("{}:3:8: error[E0004]", f)));
+
+        // An include which fails is a diagnostic where it is written
+        let err = assemble_sources(&[source("mov r0,r0\n.include \"missing.s\"\n")], Emit::Code)
+            .expect_err("assemble_sources succeeded");
+        assert!(
+            err.to_string().starts_with("g.s:2:1: error[E0014]"),
+            "{}",
+            err
+        );
+
+        // The listing and line table number lines in their own file, after a comment naming it
+        fs::write(dir.join("f.s"), "loop:\nadd r0,r0,#1\nb loop\n").expect("write failed");
+        let assembled = assemble_sources(
+            &[source("mov r0,#1\n.include \"f.s\"\nandeq r0,r0,r0\n")],
+            Emit::Code,
+        )
+        .expect("assemble_sources failed");
+        let listing = assembled.listing.to_string();
+        let numbers: Vec<(Option<usize>, &str)> = assembled
+            .listing
+            .lines
+            .iter()
+            .map(|line| (line.number, line.source.as_str()))
+            .collect();
+        assert_eq!(
+            numbers[1..5],
+            [
+                (None, format!("; {}", f).as_str()),
+                (Some(1), "loop:"),
+                (Some(2), "add r0,r0,#1"),
+                (Some(3), "b loop")
+            ]
+        );
+        assert_eq!(numbers[5], (None, "; g.s"));
+        let parsed = Listing::parse(&listing).expect("parse failed");
+        assert_eq!(parsed.lines[1].source, numbers[1].1);
+        assert_eq!(
+            SymbolFile::new(&assembled.symbol_table, &assembled.listing).line_at(Address(8)),
+            Some(3)
+        );
+
+        fs::remove_dir_all(&dir).expect("remove failed");
+    }
+}
@@ -0,0 +1,297 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    io::{self, Read},
+    path::{Path, PathBuf},
+};
+
+use super::{
+    diagnostic::{Diagnostic, DiagnosticCode, Diagnostics},
+    include::{self, Origin},
+    local::{is_local, rewrite_words},
+    normalize::normalize,
+};
+use arm11_isa::{parse::parse_string, types::*};
+
+// A source file to assemble, named in diagnostics by its name, with the files it includes found
+// relative to dir
+#[derive(Debug, Clone, PartialEq)]
+pub struct Source {
+    pub name: String,
+    pub raw: String,
+    pub dir: PathBuf,
+}
+
+impl Source {
+    // Reads a source file, or stdin if the filename is -, with included files found relative to
+    // the file, or to the current directory for stdin
+    pub fn read(filename: &str) -> Result<Self> {
+        let mut raw = String::new();
+        if filename == "-" {
+            io::stdin().read_to_string(&mut raw)?;
+            return Ok(Source {
+                name: String::from("<stdin>"),
+                raw,
+                dir: PathBuf::new(),
+            });
+        }
+        fs::File::open(filename)?.read_to_string(&mut raw)?;
+        Ok(Source {
+            name: String::from(filename),
+            raw,
+            dir: Path::new(filename)
+                .parent()
+                .map_or_else(PathBuf::new, Path::to_path_buf),
+        })
+    }
+}
+
+// Sources joined into one, in the order they were given. Origins holds the file and line each
+// line of the joined source was written on, and diagnostics the labels which clash, already
+// placed on the file and line they are on.
+pub(super) struct Linked {
+    pub raw: String,
+    pub dir: PathBuf,
+    pub origins: Vec<Origin>,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+// Joins sources into one, after normalizing them and expanding their includes. A label defined
+// in one source can be used in all of them, unless the source names the labels it shares with
+// .global, when its other labels are renamed so that only it can use them. Each label can only
+// be defined once in a source, and labels which are shared can only be defined once in all of
+// them. The .incbin files of a source are found relative to it, as they are on their own.
+// eg:
+//
+// a.s: .global main   b.s: .global helper
+//      main:               helper:
+//      bl helper           loop:
+//                          b loop
+//
+pub(super) fn link(sources: &[Source]) -> Result<Linked> {
+    let dir = sources
+        .first()
+        .map_or_else(PathBuf::new, |source| source.dir.clone());
+    let mut linked = Linked {
+        raw: String::new(),
+        dir,
+        origins: Vec::new(),
+        diagnostics: Vec::new(),
+    };
+    // The source and place each shared label was first defined
+    let mut shared: HashMap<String, (usize, Origin)> = HashMap::new();
+
+    for (index, source) in sources.iter().enumerate() {
+        let expanded = include::expand_includes(&normalize(&source.raw), &source.name, &source.dir)
+            .map_err(|d| Diagnostics::new(vec![d]))?;
+        let lines: Vec<&str> = expanded.raw.lines().collect();
+        let origins = &expanded.origins;
+        let diagnostic = |code, i: usize, message| {
+            Diagnostic::for_line(code, i + 1, lines[i], message).at(&origins[i])
+        };
+
+        // The line each label is defined on, and the labels named by .global
+        let mut defined: HashMap<&str, usize> = HashMap::new();
+        let mut global = Vec::new();
+        for (i, line) in lines.iter().enumerate() {
+            if let Some(label) = line.strip_suffix(':').filter(|label| !is_local(label)) {
+                if let Some(&first) = defined.get(label) {
+                    linked.diagnostics.push(diagnostic(
+                        DiagnosticCode::DuplicateLabel,
+                        i,
+                        format!(
+                            "label '{}' is already defined {}",
+                            label,
+                            defined_at(&origins[first], &origins[i])
+                        ),
+                    ));
+                } else {
+                    defined.insert(label, i);
+                }
+            } else if let Some(names) = global_names(line) {
+                global.extend(names.into_iter().map(|name| (name, i)));
+            }
+        }
+        for (name, i) in &global {
+            if !defined.contains_key(name.as_str()) {
+                linked.diagnostics.push(diagnostic(
+                    DiagnosticCode::InvalidLabel,
+                    *i,
+                    format!("'{}' is made global but isn't defined in this file", name),
+                ));
+            }
+        }
+
+        // Labels are private once a source makes any of them global
+        let private: HashSet<&str> = match global.is_empty() {
+            true => HashSet::new(),
+            false => defined
+                .keys()
+                .copied()
+                .filter(|label| !global.iter().any(|(name, _)| name == label))
+                .collect(),
+        };
+        let mut labels: Vec<(&str, usize)> = defined
+            .iter()
+            .filter(|(label, _)| !private.contains(*label))
+            .map(|(label, i)| (*label, *i))
+            .collect();
+        labels.sort_by_key(|&(_, i)| i);
+        for (label, i) in labels {
+            match shared.get(label) {
+                Some((other, first)) if *other != index => {
+                    linked.diagnostics.push(diagnostic(
+                        DiagnosticCode::DuplicateLabel,
+                        i,
+                        format!(
+                            "label '{}' is already defined {}",
+                            label,
+                            defined_at(first, &origins[i])
+                        ),
+                    ));
+                }
+                _ => {
+                    shared.insert(String::from(label), (index, origins[i].clone()));
+                }
+            }
+        }
+
+        for line in &lines {
+            if global_names(line).is_some() {
+                linked.raw.push('\n');
+                continue;
+            }
+            let line = rewrite_words(line, |word_index, word| {
+                let is_definition = word_index == 0 && line.ends_with(':');
+                Ok(
+                    (private.contains(word) && (word_index > 0 || is_definition))
+                        .then(|| private_name(word, index)),
+                )
+            })
+            .expect("renaming private labels cannot fail");
+            let line = match incbin_path(&line, &source.dir, &linked.dir) {
+                Some(path) => format!(".incbin {:?}{}", path, incbin_rest(&line)),
+                None => line.into_owned(),
+            };
+            linked.raw.push_str(&line);
+            linked.raw.push('\n');
+        }
+        linked.origins.extend(expanded.origins.iter().cloned());
+    }
+    Ok(linked)
+}
+
+// Where a label was first defined, for an error at a later definition, eg: "in a.s on line 2".
+// The file is left out when it is the same.
+fn defined_at(first: &Origin, here: &Origin) -> String {
+    if first.file == here.file {
+        format!("on line {}", first.line)
+    } else {
+        format!("in {} on line {}", first.file, first.line)
+    }
+}
+
+// The labels named by a .global (or .globl) directive, if the line is one
+fn global_names(line: &str) -> Option<Vec<String>> {
+    let trimmed = line.trim_start();
+    let names = trimmed
+        .strip_prefix(".global")
+        .or_else(|| trimmed.strip_prefix(".globl"))
+        .filter(|names| names.starts_with(char::is_whitespace))?;
+    Some(
+        names
+            .split(',')
+            .map(|name| String::from(name.trim()))
+            .filter(|name| !name.is_empty())
+            .collect(),
+    )
+}
+
+// The name a label private to the source with the given index is renamed to. The '$' keeps it
+// apart from the labels written in sources, as it does for local labels.
+fn private_name(label: &str, index: usize) -> String {
+    format!("{}${}", label, index)
+}
+
+// The path of the file placed by an .incbin, from the directory of the source it is in, if the
+// line is one and that isn't the directory .incbin files are found in
+fn incbin_path(line: &str, dir: &Path, linked_dir: &Path) -> Option<String> {
+    let rest = line.trim_start().strip_prefix(".incbin")?;
+    let (_, name) = parse_string(rest.trim_start()).ok()?;
+    if dir == linked_dir {
+        return None;
+    }
+    let dir = dir.canonicalize().unwrap_or_else(|_| dir.to_path_buf());
+    Some(dir.join(name).display().to_string())
+}
+
+// What follows the file name of an .incbin, ie: its offset and length
+fn incbin_rest(line: &str) -> &str {
+    let rest = line.trim_start()[".incbin".len()..].trim_start();
+    parse_string(rest).map_or("", |(rest, _)| rest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn source(name: &str, raw: &str) -> Source {
+        Source {
+            name: String::from(name),
+            raw: String::from(raw),
+            dir: PathBuf::new(),
+        }
+    }
+
+    #[test]
+    fn test_link() {
+        let linked = link(&[
+            source("a.s", ".global main\nmain:\nbl helper\nloop:\nb loop\n"),
+            source("b.s", ".global helper\nhelper:\nloop:\nb loop\n"),
+            source("c.s", "data:\n.word main\n"),
+        ])
+        .expect("link failed");
+        assert_eq!(
+            linked.raw,
+            "\nmain:\nbl helper\nloop$0:\nb loop$0\n\nhelper:\nloop$1:\nb loop$1\ndata:\n.word main\n"
+        );
+        let origins: Vec<(&str, usize)> = linked
+            .origins
+            .iter()
+            .map(|origin| (origin.file.as_str(), origin.line))
+            .collect();
+        assert_eq!(origins[4..7], [("a.s", 5), ("b.s", 1), ("b.s", 2)]);
+        assert_eq!(origins[9], ("c.s", 1));
+        assert!(linked.diagnostics.is_empty());
+    }
+
+    #[test]
+    fn test_link_errors() {
+        let linked = link(&[
+            source("a.s", "main:\nshared:\nmain:\n"),
+            source("b.s", ".global shared, missing\nshared:\nmain:\n"),
+        ])
+        .expect("link failed");
+        let found: Vec<(DiagnosticCode, Option<&str>, usize)> = linked
+            .diagnostics
+            .iter()
+            .map(|d| (d.code, d.file.as_deref(), d.line))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                (DiagnosticCode::DuplicateLabel, Some("a.s"), 3),
+                (DiagnosticCode::InvalidLabel, Some("b.s"), 1),
+                (DiagnosticCode::DuplicateLabel, Some("b.s"), 2),
+            ]
+        );
+        assert_eq!(
+            linked.diagnostics[0].message,
+            "label 'main' is already defined on line 1"
+        );
+        assert_eq!(
+            linked.diagnostics[2].message,
+            "label 'shared' is already defined in a.s on line 2"
+        );
+    }
+}
@@ -0,0 +1,88 @@
+use std::collections::HashMap;
+
+use super::include::Origin;
+use arm11_isa::constants::*;
+pub use arm11_isa::listing::{Listing, ListingData, ListingLine};
+
+// Bytes of directive data shown on each line of the listing
+const BYTES_PER_LINE: usize = 4;
+
+// Creates a listing of the source, given the file and line each line of it was written on,
+// the address and encoding of each line which was placed in the binary, the timing of each
+// instruction, and the literal pool placed after the code. Lines are looked up by their
+// number in the source, counted from 1.
+pub(crate) fn listing(
+    source: &str,
+    origins: &[Origin],
+    encoded: &HashMap<usize, (u32, ListingData)>,
+    timings: &HashMap<usize, String>,
+    literal_base: u32,
+    literals: &[u8],
+) -> Listing {
+    let mut lines = Vec::new();
+    let mut file = origins.first().map(|origin| origin.file.as_str());
+    for (index, text) in source.lines().enumerate() {
+        let line = index + 1;
+        let origin = origins.get(index);
+        let number = origin.map_or(line, |origin| origin.line);
+        if let Some(origin) = origin.filter(|origin| Some(origin.file.as_str()) != file) {
+            file = Some(&origin.file);
+            lines.push(ListingLine {
+                number: None,
+                address: None,
+                data: None,
+                source: format!("; {}", origin.file),
+                timing: None,
+            });
+        }
+        let (address, data) = match encoded.get(&line) {
+            Some((address, data)) => (Some(*address), Some(data.clone())),
+            None => (None, None),
+        };
+
+        // Directive data is split over several lines, so that the listing stays narrow
+        match data {
+            Some(ListingData::Bytes(bytes)) if bytes.len() > BYTES_PER_LINE => {
+                for (chunk_index, chunk) in bytes.chunks(BYTES_PER_LINE).enumerate() {
+                    let first = chunk_index == 0;
+                    lines.push(ListingLine {
+                        number: first.then_some(number),
+                        address: address.map(|a| a + (chunk_index * BYTES_PER_LINE) as u32),
+                        data: Some(ListingData::Bytes(chunk.to_vec())),
+                        source: if first {
+                            text.to_owned()
+                        } else {
+                            String::new()
+                        },
+                        timing: None,
+                    });
+                }
+            }
+            data => lines.push(ListingLine {
+                number: Some(number),
+                address,
+                data,
+                source: text.to_owned(),
+                timing: timings.get(&line).cloned(),
+            }),
+        }
+    }
+
+    for (index, literal) in literals.chunks(BYTES_IN_WORD).enumerate() {
+        let mut word = [0; BYTES_IN_WORD];
+        word[..literal.len()].copy_from_slice(literal);
+        lines.push(ListingLine {
+            number: None,
+            address: Some(literal_base + (index * BYTES_IN_WORD) as u32),
+            data: Some(ListingData::Word(u32::from_le_bytes(word))),
+            source: if index == 0 {
+                String::from("; literal pool")
+            } else {
+                String::new()
+            },
+            timing: None,
+        });
+    }
+
+    Listing { lines }
+}
@@ -0,0 +1,158 @@
+use std::{borrow::Cow, collections::HashMap};
+
+// Numeric local labels, eg: "1:", which can be defined any number of times. A reference to one
+// gives the direction to look in, so "1b" is the closest "1:" before it and "1f" the closest
+// after it. Each definition is given its own name with a '$' prefix, so that it doesn't clash
+// with ordinary labels, and references are rewritten to the name of the definition they mean.
+//
+// eg: "1:" ... "bne 1b" becomes "$1_0:" ... "bne $1_0"
+//
+pub struct LocalLabels<'a> {
+    // The number of definitions of each label seen so far, and in the whole source
+    defined: HashMap<&'a str, usize>,
+    total: HashMap<&'a str, usize>,
+}
+
+impl<'a> LocalLabels<'a> {
+    pub fn new(raw: &'a str) -> Self {
+        let mut total = HashMap::new();
+        for label in raw.lines().filter_map(|line| line.strip_suffix(':')) {
+            if is_local(label) {
+                *total.entry(label).or_insert(0) += 1;
+            }
+        }
+        LocalLabels {
+            defined: HashMap::new(),
+            total,
+        }
+    }
+
+    // The name of a label which is being defined, which is unique if it is a local label
+    pub fn define(&mut self, label: &'a str) -> String {
+        if !is_local(label) {
+            return String::from(label);
+        }
+        let count = self.defined.entry(label).or_insert(0);
+        *count += 1;
+        local_name(label, *count - 1)
+    }
+
+    // Rewrites the references to local labels in a line, outside of any strings. It's an error
+    // to refer to a label with no definition in the given direction.
+    pub fn resolve<'l>(&self, line: &'l str) -> Result<Cow<'l, str>, String> {
+        rewrite_words(line, |_, word| self.reference(word))
+    }
+
+    // The name of the definition a word refers to, if it is a reference to a local label
+    fn reference(&self, word: &str) -> Result<Option<String>, String> {
+        let (label, forward) = match (word.strip_suffix('b'), word.strip_suffix('f')) {
+            (Some(label), _) if is_local(label) => (label, false),
+            (_, Some(label)) if is_local(label) => (label, true),
+            _ => return Ok(None),
+        };
+
+        let defined = self.defined.get(label).copied().unwrap_or(0);
+        let total = self.total.get(label).copied().unwrap_or(0);
+        match forward {
+            false if defined > 0 => Ok(Some(local_name(label, defined - 1))),
+            true if defined < total => Ok(Some(local_name(label, defined))),
+            false => Err(format!("No local label '{}' before '{}'", label, word)),
+            true => Err(format!("No local label '{}' after '{}'", label, word)),
+        }
+    }
+}
+
+// Rewrites each word in a line outside of any strings, given its index in the line and the word,
+// to the word returned for it, if any. Words are made of alphanumeric characters, underscores
+// and dollar signs, eg: "ldr r0,[r1,#4]" has the words ldr, r0, r1 and 4.
+pub fn rewrite_words<'l>(
+    line: &'l str,
+    mut rewrite: impl FnMut(usize, &str) -> Result<Option<String>, String>,
+) -> Result<Cow<'l, str>, String> {
+    let mut out = String::with_capacity(line.len());
+    let mut in_string = false;
+    let mut escaped = false;
+    let mut word_start = None;
+    let mut changed = false;
+    let mut index = 0;
+
+    // A trailing space ends the last word, and is removed again afterwards
+    for (i, c) in line.char_indices().chain(Some((line.len(), ' '))) {
+        if in_string {
+            in_string = escaped || c != '"';
+            escaped = !escaped && c == '\\';
+        } else if c.is_ascii_alphanumeric() || c == '_' || c == '$' {
+            word_start.get_or_insert(i);
+            continue;
+        } else if c == '"' {
+            in_string = true;
+        }
+
+        if let Some(start) = word_start.take() {
+            let word = &line[start..i];
+            match rewrite(index, word)? {
+                Some(name) => {
+                    out.push_str(&name);
+                    changed = true;
+                }
+                None => out.push_str(word),
+            }
+            index += 1;
+        }
+        if i < line.len() {
+            out.push(c);
+        }
+    }
+
+    Ok(if changed {
+        Cow::Owned(out)
+    } else {
+        Cow::Borrowed(line)
+    })
+}
+
+pub(super) fn is_local(label: &str) -> bool {
+    !label.is_empty() && label.bytes().all(|b| b.is_ascii_digit())
+}
+
+fn local_name(label: &str, index: usize) -> String {
+    format!("${}_{}", label, index)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_local_labels() {
+        let raw = "1:\nb 1f\n1:\nb 1b\n";
+        let mut labels = LocalLabels::new(raw);
+
+        assert_eq!(labels.define("1"), "$1_0");
+        assert_eq!(
+            labels.resolve("b 1f"),
+            Ok(Cow::Owned(String::from("b $1_1")))
+        );
+        assert_eq!(
+            labels.resolve("bne 1b"),
+            Ok(Cow::Owned(String::from("bne $1_0")))
+        );
+        assert_eq!(labels.define("1"), "$1_1");
+        assert_eq!(labels.define("loop"), "loop");
+
+        assert_eq!(
+            labels.resolve("ldr r0,=1b+4"),
+            Ok(Cow::Owned(String::from("ldr r0,=$1_1+4")))
+        );
+        assert_eq!(
+            labels.resolve(".ascii \"1b \\\"2f\""),
+            Ok(Cow::Borrowed(".ascii \"1b \\\"2f\""))
+        );
+        assert_eq!(
+            labels.resolve("mov r0,#0x1f"),
+            Ok(Cow::Borrowed("mov r0,#0x1f"))
+        );
+        assert!(labels.resolve("b 1f").is_err());
+        assert!(labels.resolve("b 2b").is_err());
+    }
+}
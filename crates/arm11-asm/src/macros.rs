@@ -0,0 +1,179 @@
+use std::collections::HashMap;
+
+use arm11_isa::types::*;
+
+// Maximum depth of macros expanding other macros, which stops recursive macros from expanding
+// forever.
+const MAX_EXPANSION_DEPTH: usize = 16;
+
+struct Macro {
+    params: Vec<String>,
+    body: Vec<String>,
+}
+
+// Expands macros in the source, before labels and instructions are extracted. Macros are defined
+// with a .macro/.endm block, and can take parameters which are substituted into the body where
+// they appear prefixed with a backslash.
+// eg:
+//
+// .macro setpin reg, pin
+// mov \reg,#1
+// lsl \reg,#\pin
+// .endm
+// setpin r1, 16
+//
+// Macros must be defined before they are used, and may use other macros in their body. Along
+// with the expanded source, this gives the line of raw each of its lines came from, counted from
+// 1, which for the lines of an expansion is the line using the macro.
+//
+pub fn expand_macros(raw: &str) -> Result<(String, Vec<usize>)> {
+    let mut macros = HashMap::new();
+    let mut out = Vec::new();
+
+    let mut lines = raw.lines().enumerate();
+    while let Some((index, line)) = lines.next() {
+        let trimmed = line.trim();
+        if let Some(header) = trimmed.strip_prefix(".macro") {
+            let (name, params) = parse_header(header).ok_or_else(|| {
+                ArmError::parse(format!("Line {}: expected .macro name [params]", index + 1))
+            })?;
+
+            let mut body = Vec::new();
+            loop {
+                match lines.next() {
+                    Some((_, line)) if line.trim() == ".endm" => break,
+                    Some((_, line)) if line.trim().starts_with(".macro") => {
+                        return Err(ArmError::parse(format!(
+                            "Line {}: macro '{}' contains .macro",
+                            index + 1,
+                            name
+                        )))
+                    }
+                    Some((_, line)) => body.push(line.trim_start().to_owned()),
+                    None => {
+                        return Err(ArmError::parse(format!(
+                            "Line {}: macro '{}' has no .endm",
+                            index + 1,
+                            name
+                        )))
+                    }
+                }
+            }
+            macros.insert(name, Macro { params, body });
+        } else if trimmed == ".endm" {
+            return Err(ArmError::parse(format!(
+                "Line {}: .endm without .macro",
+                index + 1
+            )));
+        } else {
+            expand_line(line, index + 1, &macros, 0, &mut out)
+                .map_err(|e| e.context(format!("Line {}", index + 1)))?;
+        }
+    }
+
+    let (from, lines): (Vec<usize>, Vec<String>) = out.into_iter().unzip();
+    let mut expanded = lines.join("\n");
+    expanded.push('\n');
+    Ok((expanded, from))
+}
+
+// Expands a line into out, along with the line of the source it came from, recursively
+// expanding any macros used in the expansion
+fn expand_line(
+    line: &str,
+    from: usize,
+    macros: &HashMap<String, Macro>,
+    depth: usize,
+    out: &mut Vec<(usize, String)>,
+) -> Result<()> {
+    let trimmed = line.trim();
+    let (name, args) = trimmed
+        .split_once(char::is_whitespace)
+        .unwrap_or((trimmed, ""));
+
+    let m = match macros.get(name) {
+        Some(m) => m,
+        None => {
+            out.push((from, line.to_owned()));
+            return Ok(());
+        }
+    };
+
+    if depth >= MAX_EXPANSION_DEPTH {
+        return Err(ArmError::parse(format!(
+            "macro '{}' exceeds the maximum expansion depth of {}",
+            name, MAX_EXPANSION_DEPTH
+        )));
+    }
+
+    let args: Vec<&str> = if args.trim().is_empty() {
+        Vec::new()
+    } else {
+        args.split(',').map(str::trim).collect()
+    };
+    if args.len() != m.params.len() {
+        return Err(ArmError::parse(format!(
+            "macro '{}' takes {} arguments, but {} were given",
+            name,
+            m.params.len(),
+            args.len()
+        )));
+    }
+
+    // Substitute longer parameter names first, so that eg: \reg1 isn't replaced as \reg
+    let mut substitutions: Vec<(String, &str)> = m
+        .params
+        .iter()
+        .map(|p| format!("\\{}", p))
+        .zip(args)
+        .collect();
+    substitutions.sort_by_key(|(param, _)| std::cmp::Reverse(param.len()));
+
+    for body_line in &m.body {
+        let substituted = substitutions
+            .iter()
+            .fold(body_line.clone(), |l, (param, arg)| l.replace(param, arg));
+        expand_line(&substituted, from, macros, depth + 1, out)?;
+    }
+
+    Ok(())
+}
+
+// Parses the name and parameters of a macro, which are separated by spaces or commas
+fn parse_header(header: &str) -> Option<(String, Vec<String>)> {
+    let mut words = header
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|w| !w.is_empty());
+    let name = words.next()?;
+    Some((name.to_owned(), words.map(String::from).collect()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_expand_macros() {
+        let source = ".macro setpin reg, pin
+    mov \\reg,#1
+    lsl \\reg,#\\pin
+.endm
+.macro blink reg
+setpin \\reg, 16
+str \\reg,[r0,#28]
+.endm
+blink r1
+andeq r0,r0,r0
+";
+        let (expanded, from) = expand_macros(source).expect("expand failed");
+        assert_eq!(
+            expanded,
+            "mov r1,#1\nlsl r1,#16\nstr r1,[r0,#28]\nandeq r0,r0,r0\n"
+        );
+        assert_eq!(from, [9, 9, 9, 10]);
+
+        assert!(expand_macros(".macro m\nm\n.endm\nm\n").is_err());
+        assert!(expand_macros(".macro m a\nmov \\a,#1\n.endm\nm r1, r2\n").is_err());
+        assert!(expand_macros(".macro m\nmov r1,#1\n").is_err());
+    }
+}
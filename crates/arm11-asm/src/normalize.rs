@@ -0,0 +1,106 @@
+use std::borrow::Cow;
+
+use super::local::rewrite_words;
+
+// Words which name registers or shifts in any case, and so are lowercased
+const NAMES: [&str; 12] = [
+    "sp", "lr", "pc", "fp", "ip", "lsl", "lsr", "asr", "ror", "rrx", "cpsr", "spsr",
+];
+
+// Rewrites a source into the form the rest of the assembler expects, line by line so that line
+// numbers still match. Comments starting with ;, @ or // are removed, label definitions lose
+// their indentation, and mnemonics, directives, registers, shifts and hexadecimal prefixes are
+// lowercased, so "  Loop:" and "MOV R0, R1, LSL #0X2 ; comment" are "Loop:" and
+// "mov r0, r1, lsl #0x2". Labels keep their case. Only the end of a line is removed and
+// lowercasing doesn't move anything, so columns in diagnostics still match the source.
+pub fn normalize(raw: &str) -> String {
+    let mut out = String::with_capacity(raw.len());
+    for line in raw.lines() {
+        out.push_str(&normalize_line(line));
+        out.push('\n');
+    }
+    out
+}
+
+fn normalize_line(line: &str) -> Cow<'_, str> {
+    let line = strip_comment(line).trim_end();
+    if line.ends_with(':') && !line.trim_start().contains(char::is_whitespace) {
+        return Cow::Borrowed(line.trim_start());
+    }
+
+    // The mnemonic or directive is the first word, except in "<name> .req <register>". Macros
+    // are used as mnemonics, so their names are lowercased where they are defined too.
+    let mut words = line.split_whitespace();
+    let mnemonics = match (words.next(), words.next()) {
+        (Some(_), Some(req)) if req.eq_ignore_ascii_case(".req") => 1..2,
+        (Some(directive), _) if directive.eq_ignore_ascii_case(".macro") => 0..2,
+        _ => 0..1,
+    };
+    rewrite_words(line, |index, word| {
+        let lower = word.to_ascii_lowercase();
+        Ok(
+            (lower != word && (mnemonics.contains(&index) || is_lowercase_name(&lower)))
+                .then_some(lower),
+        )
+    })
+    .expect("normalizing a line cannot fail")
+}
+
+// Whether a word, once lowercased, names a register, a shift or a status register, or is a
+// hexadecimal number
+fn is_lowercase_name(word: &str) -> bool {
+    let numbered = |prefix: char| {
+        word.strip_prefix(prefix)
+            .filter(|n| !n.is_empty() && n.bytes().all(|b| b.is_ascii_digit()))
+            .is_some()
+    };
+    NAMES.contains(&word)
+        || word.starts_with("cpsr_")
+        || word.starts_with("spsr_")
+        || word.starts_with("0x")
+        || numbered('r')
+        || numbered('s')
+        || numbered('d')
+}
+
+// The line without any comment, which is ignored inside strings
+fn strip_comment(line: &str) -> &str {
+    let mut in_string = false;
+    let mut escaped = false;
+    for (i, c) in line.char_indices() {
+        if in_string {
+            in_string = escaped || c != '"';
+            escaped = !escaped && c == '\\';
+        } else if c == '"' {
+            in_string = true;
+        } else if c == ';' || c == '@' || line[i..].starts_with("//") {
+            return &line[..i];
+        }
+    }
+    line
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize() {
+        assert_eq!(
+            normalize("  Loop:\nMOV R0, R1, LSL #0X2 ; comment\n\tSUBS\tr0,r0,#1 @ tab\nB Loop\n"),
+            "Loop:\nmov r0, r1, lsl #0x2\n\tsubs\tr0,r0,#1\nb Loop\n"
+        );
+        assert_eq!(
+            normalize("// header\nLDR R0, =Table // load\nSTMFD SP!, {R4, LR}\n"),
+            "\nldr r0, =Table\nstmfd sp!, {r4, lr}\n"
+        );
+        assert_eq!(
+            normalize(".ASCII \"A; b @ //\" ; text\nCount .REQ R4\nVMOV S0, D1\nMSR CPSR_F, R0\n"),
+            ".ascii \"A; b @ //\"\nCount .req r4\nvmov s0, d1\nmsr cpsr_f, r0\n"
+        );
+        assert_eq!(
+            normalize(".MACRO SetPin Reg\nMOV \\Reg,#1\n.ENDM\nSETPIN R1\n"),
+            ".macro setpin Reg\nmov \\Reg,#1\n.endm\nsetpin r1\n"
+        );
+    }
+}
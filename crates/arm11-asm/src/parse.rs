@@ -0,0 +1,2363 @@
+use std::{
+    convert::{TryFrom, TryInto},
+    rc::Rc,
+};
+
+use nom::{
+    branch::alt,
+    bytes::complete::{tag, take_while1},
+    character::complete::{char, digit1, hex_digit1, satisfy, space0, space1},
+    combinator::{complete, eof, map, map_opt, not, opt, peek, recognize, success, value, verify},
+    error::{context, ContextError},
+    multi::separated_list1,
+    sequence::{delimited, pair, preceded, separated_pair, terminated, tuple},
+};
+
+use super::{diagnostic::Diagnostic, expression};
+use arm11_isa::{
+    address::{Address, SymbolTable},
+    constants::*,
+    parse::*,
+    types::*,
+};
+
+// Parses an ARM assembly instruction in the form of a string into a ConditionalInstruction. There
+// are 4 main types of instructions:
+// 1. Processing
+// 2. Multiply
+// 3. Transfer
+// 4. Branch
+//
+// There are also 2 special cases; Halt and Lsl instructions.
+//
+// The second field in the return tuple may contain data (usually from Transfer instructions),
+// which are to be added to the assembled binary, in a literal pool.
+//
+pub fn parse_asm(
+    raw: &str,
+    line: usize,
+    current_address: usize,
+    next_free_address: usize,
+    symbol_table: Rc<SymbolTable>,
+) -> std::result::Result<(ConditionalInstruction, Option<u32>), Diagnostic> {
+    let (rest, (instr, opt_data)) = alt((
+        complete(parse_halt),
+        complete(parse_lsl),
+        complete(parse_processing),
+        complete(parse_transfer(
+            current_address,
+            next_free_address,
+            symbol_table.clone(),
+        )),
+        complete(parse_multiply),
+        complete(parse_multiply_long),
+        complete(parse_block_transfer),
+        complete(parse_stack),
+        complete(parse_branch_exchange),
+        complete(parse_coprocessor),
+        complete(parse_vfp),
+        complete(parse_status_transfer),
+        complete(parse_software_interrupt),
+        complete(parse_branch(current_address, symbol_table)),
+    ))(raw.trim())
+    .map_err(|e| Diagnostic::from_nom(line, raw, e))?;
+
+    // Anything left over is a mistake, rather than something which can be ignored
+    if !rest.trim().is_empty() {
+        return Err(Diagnostic::trailing(line, raw, rest, "instruction"));
+    }
+
+    Ok((instr, opt_data))
+}
+
+// Parses a processing instruction. This can either be:
+//
+// 1. Instructions that compute results: and, eor, sub, rsb, add, adc, sbc, rsc, orr, bic
+// eg: <opcode> Rd,Rn,<Operand2>
+//
+// 2. Single operand assignment: mov, mvn
+// eg: <opcode> Rd,<Operand2>
+//
+// 3. Instructions that do not compute results, but do set CPSR flags: tst, teq, cmp, cmn
+// eg: <opcode> Rn,<Operand2>
+//
+// This returns no additional data, so the second field of the return tuple will
+// always be None.
+//
+fn parse_processing(input: &str) -> NomResult<&str, (ConditionalInstruction, Option<u32>)> {
+    let (rest, (opcode, (opt_cond, s_suffix))) = context(
+        "parsing processing opcode",
+        terminated(pair(parse_processing_opcode, parse_suffixes), space1),
+    )(input)?;
+    context(
+        "parsing processing instruction",
+        map(
+            alt((
+                tuple((
+                    // case with two registers
+                    // eg: <opcode> Rd,Rn,<Operand2>
+                    terminated(parse_reg, comma_space),
+                    terminated(parse_reg, comma_space),
+                    terminated(parse_processing_operand2(opcode), eof),
+                    success(false),
+                )),
+                tuple((
+                    // cases with one register
+                    // eg: mov Rd,<Operand2>
+                    // eg: <opcode> Rn,<Operand2>
+                    success(0),
+                    terminated(parse_reg, comma_space),
+                    terminated(parse_processing_operand2(opcode), eof),
+                    success(true),
+                )),
+            )),
+            move |(r1, r2, (opcode, operand2), set_cond)| {
+                // If its a Mov or Mvn instruction, the result is saved to Rd, instead of Rn
+                let (rd, rn, set_cond) = match opcode {
+                    ProcessingOpcode::Mov | ProcessingOpcode::Mvn => (r2, r1, s_suffix),
+                    _ => (r1, r2, set_cond || s_suffix),
+                };
+                (
+                    ConditionalInstruction {
+                        cond: opt_cond.unwrap_or(ConditionCode::Al),
+                        instruction: Instruction::Processing(InstructionProcessing {
+                            opcode,
+                            set_cond,
+                            rn,
+                            rd,
+                            operand2,
+                        }),
+                    },
+                    None,
+                )
+            },
+        ),
+    )(rest)
+}
+
+// Parses a multiply instruction. This can either be a multiply instruction (mul Rd,Rm,Rs)
+// or an multiply with accumulate instruction (mla Rd,Rm,Rs,Rn).
+//
+// This returns no additional data, so the second field of the return tuple will
+// always be None.
+//
+fn parse_multiply(input: &str) -> NomResult<&str, (ConditionalInstruction, Option<u32>)> {
+    context(
+        "parsing multiply instruction",
+        map(
+            tuple((
+                terminated(pair(alt((tag("mul"), tag("mla"))), parse_suffixes), space1),
+                terminated(parse_reg, comma_space),
+                terminated(parse_reg, comma_space),
+                parse_reg,
+                opt(preceded(comma_space, parse_reg)),
+            )),
+            |((opcode, (opt_cond, set_cond)), rd, rm, rs, opt_rn)| {
+                // Mla instructions are accumulate, and have an Rn register specified
+                let (accumulate, rn) = match (opcode, opt_rn) {
+                    ("mla", Some(rn)) => (true, rn),
+                    ("mul", None) => (false, 0),
+                    _ => unreachable!(),
+                };
+
+                (
+                    ConditionalInstruction {
+                        cond: opt_cond.unwrap_or(ConditionCode::Al),
+                        instruction: Instruction::Multiply(InstructionMultiply {
+                            rd,
+                            rm,
+                            rs,
+                            rn,
+                            accumulate,
+                            set_cond,
+                        }),
+                    },
+                    None,
+                )
+            },
+        ),
+    )(input)
+}
+
+// Parses a long multiply instruction, giving a 64 bit result in RdLo and RdHi. This can be
+// unsigned or signed, and can accumulate into the existing value of RdHi:RdLo.
+// eg: umull RdLo,RdHi,Rm,Rs, umlal, smull, smlal
+//
+// RdLo and RdHi must be different registers, as they are both written.
+//
+// This returns no additional data, so the second field of the return tuple will
+// always be None.
+//
+fn parse_multiply_long(input: &str) -> NomResult<&str, (ConditionalInstruction, Option<u32>)> {
+    context(
+        "parsing long multiply instruction",
+        map(
+            verify(
+                tuple((
+                    terminated(
+                        pair(
+                            alt((
+                                value((false, false), tag("umull")),
+                                value((false, true), tag("umlal")),
+                                value((true, false), tag("smull")),
+                                value((true, true), tag("smlal")),
+                            )),
+                            parse_suffixes,
+                        ),
+                        space1,
+                    ),
+                    terminated(parse_reg, comma_space),
+                    terminated(parse_reg, comma_space),
+                    terminated(parse_reg, comma_space),
+                    parse_reg,
+                )),
+                |(_, rd_lo, rd_hi, _, _)| rd_lo != rd_hi,
+            ),
+            |(((signed, accumulate), (opt_cond, set_cond)), rd_lo, rd_hi, rm, rs)| {
+                (
+                    ConditionalInstruction {
+                        cond: opt_cond.unwrap_or(ConditionCode::Al),
+                        instruction: Instruction::MultiplyLong(InstructionMultiplyLong {
+                            signed,
+                            accumulate,
+                            set_cond,
+                            rd_hi,
+                            rd_lo,
+                            rs,
+                            rm,
+                        }),
+                    },
+                    None,
+                )
+            },
+        ),
+    )(input)
+}
+
+// Parses a transfer instruction. This can either be an immediate expression, or an indexed
+// instruction.
+//
+// This may return additional data in the Option<u32>.
+//
+fn parse_transfer(
+    current_address: usize,
+    next_free_address: usize,
+    symbol_table: Rc<SymbolTable>,
+) -> impl Fn(&str) -> NomResult<&str, (ConditionalInstruction, Option<u32>)> {
+    move |input: &str| {
+        context(
+            "parsing transfer instruction",
+            alt((
+                parse_transfer_immediate(current_address, next_free_address, symbol_table.clone()),
+                parse_transfer_indexed,
+            )),
+        )(input)
+    }
+}
+
+// Returns a parser for an immediate transfer instruction, given the address of the current
+// instruction, and the address its literal would be placed at. The expression may refer to
+// labels, in which case their addresses are used.
+//
+// If the immediate expression can fit inside of a mov instruction, this is interpreted as
+// so, and the parser returns a mov instruction with no additional data. Likewise if its inverse
+// fits, eg: ldr r0,=-1 is mvn r0,#0.
+// If the expression cannot fit inside a mov instruction, it is returned by the parser as
+// additional data in the Option<u32>. The instruction is a transfer instruction which
+// contains the offset to the address of this data, which must be within reach of the offset
+// either side of the instruction.
+//
+fn parse_transfer_immediate(
+    current_address: usize,
+    next_free_address: usize,
+    symbol_table: Rc<SymbolTable>,
+) -> impl Fn(&str) -> NomResult<&str, (ConditionalInstruction, Option<u32>)> {
+    move |input: &str| {
+        let (rest, (opt_cond, rd, expression)) = context(
+            "parsing immediate transfer",
+            tuple((
+                delimited(tag("ldr"), opt(parse_condition_code), space1),
+                terminated(parse_reg, comma_space),
+                preceded(
+                    char('='),
+                    map_opt(expression::parse_expression, |e| {
+                        e.evaluate(&symbol_table, Address(current_address as u32))
+                            .ok()
+                    }),
+                ),
+            )),
+        )(input)?;
+
+        let cond = opt_cond.unwrap_or(ConditionCode::Al);
+        let moved = if expression <= mask(IMM_VALUE.size) {
+            Some((ProcessingOpcode::Mov, expression))
+        } else if !expression <= mask(IMM_VALUE.size) {
+            Some((ProcessingOpcode::Mvn, !expression))
+        } else {
+            None
+        };
+        if let Some((opcode, value)) = moved {
+            let instruction = Instruction::Processing(InstructionProcessing {
+                opcode,
+                set_cond: false,
+                rd,
+                rn: 0,
+                operand2: expression_to_operand2(value).unwrap(),
+            });
+            return Ok((rest, (ConditionalInstruction { cond, instruction }, None)));
+        }
+
+        // The literal may be in a pool before the instruction, so the offset is subtracted
+        let offset = next_free_address as i64 - (current_address as i64 + PIPELINE_OFFSET as i64);
+        let magnitude = u16::try_from(offset.abs())
+            .ok()
+            .filter(|&magnitude| u32::from(magnitude) <= mask(OFFSET_TRANSFER.size))
+            .ok_or_else(|| {
+                nom::Err::Failure(ArmNomError::new(ArmNomErrorKind::LiteralOutOfRange(offset)))
+            })?;
+        let instruction = Instruction::Transfer(InstructionTransfer {
+            is_preindexed: true,
+            up_bit: offset >= 0,
+            writeback: false,
+            load: true,
+            size: TransferSize::Word,
+            rn: PC as u8,
+            rd,
+            offset: TransferOffset::Immediate(magnitude),
+        });
+        Ok((
+            rest,
+            (
+                ConditionalInstruction { cond, instruction },
+                Some(expression),
+            ),
+        ))
+    }
+}
+
+// Parses an indexed transfer instruction. This can be without an offset (eg: <opcode> [Rd]), with
+// a pre-indexed offset (eg: <opcode> [Rd, <Operand2>]) or with a post-indexed offset (eg: <opcode>
+// [Rd] <Operand2>). Pre-indexed transfers followed by ! write the address back to the base
+// register, eg: <opcode> [Rd, <Operand2>]!
+//
+// This returns no additional data, so the second field of the return tuple will
+// always be None.
+//
+fn parse_transfer_indexed(input: &str) -> NomResult<&str, (ConditionalInstruction, Option<u32>)> {
+    let (rest, ((load, size, cond), rd)) = context(
+        "parsing indexed transfer",
+        pair(
+            terminated(parse_transfer_opcode, space1),
+            terminated(parse_reg, comma_space),
+        ),
+    )(input)?;
+    context(
+        "parsing indexed transfer",
+        map_opt(
+            alt((
+                // Post-indexed case
+                // eg: <opcode> [Rd], <Operand2>
+                context(
+                    "parsing post-indexed transfer, with offset",
+                    complete(tuple((
+                        delimited(open_bracket, parse_reg, close_bracket),
+                        preceded(comma_space, parse_transfer_offset(size)),
+                        success(false),
+                        success(false),
+                    ))),
+                ),
+                // Pre-indexed case
+                // eg: <opcode> [Rd, <Operand2>]{!}
+                context(
+                    "parsing pre-indexed transfer, with offset",
+                    complete(tuple((
+                        preceded(open_bracket, parse_reg),
+                        preceded(comma_space, parse_transfer_offset(size)),
+                        success(true),
+                        preceded(close_bracket, parse_writeback),
+                    ))),
+                ),
+                // Default case, pre-indexed with no addressing offset
+                // eg: <opcode> [Rd]
+                context(
+                    "parsing pre-indexed transfer, with no offset",
+                    complete(tuple((
+                        delimited(open_bracket, parse_reg, close_bracket),
+                        success((TransferOffset::Immediate(0), false)),
+                        success(true),
+                        parse_writeback,
+                    ))),
+                ),
+            )),
+            move |(rn, (offset, is_signed), is_preindexed, writeback)| {
+                // Halfword and signed transfers only have an 8 bit immediate, or an unshifted
+                // register offset
+                let valid_offset = matches!(
+                    (size, offset),
+                    (TransferSize::Word | TransferSize::Byte, _)
+                        | (_, TransferOffset::Immediate(_))
+                        | (
+                            _,
+                            TransferOffset::ShiftedReg(_, Shift::ConstantShift(ShiftType::Lsl, 0))
+                        )
+                );
+                valid_offset.then_some((
+                    ConditionalInstruction {
+                        cond,
+                        instruction: Instruction::Transfer(InstructionTransfer {
+                            is_preindexed,
+                            up_bit: !is_signed,
+                            writeback,
+                            load,
+                            size,
+                            rd,
+                            rn,
+                            offset,
+                        }),
+                    },
+                    None,
+                ))
+            },
+        ),
+    )(rest)
+}
+
+// Returns a parser for the offset of a single data transfer of the given size, and whether it is
+// subtracted from the base register. Constant offsets are plain values rather than rotated
+// immediates, so must fit in 12 bits, or 8 bits for halfword and signed transfers. They are
+// signed, so an expression which evaluates to a negative value subtracts its magnitude, as does
+// a register offset after a '-'.
+// eg: #-4, #(a-b), -r2 and -r2, lsl #2 are all subtracted
+fn parse_transfer_offset(
+    size: TransferSize,
+) -> impl Fn(&str) -> NomResult<&str, (TransferOffset, bool)> {
+    move |input: &str| {
+        let (field, bits) = match size {
+            TransferSize::Word | TransferSize::Byte => {
+                ("12 bit transfer offset", OFFSET_TRANSFER.size)
+            }
+            _ => (
+                "8 bit halfword transfer offset",
+                OFFSET_HI.size + OFFSET_LO.size,
+            ),
+        };
+        let constant = move |input| {
+            let (rest, (value, is_signed)) =
+                context("parsing transfer offset", parse_expression)(input)?;
+            // Evaluated expressions are two's complement, so a negative one may be negated again
+            let offset = i64::from(value as i32);
+            let offset = if is_signed { -offset } else { offset };
+            let magnitude = offset.unsigned_abs();
+            if magnitude > u64::from(mask(bits)) {
+                return Err(truncated(
+                    input,
+                    "parsing transfer offset",
+                    field,
+                    offset,
+                    offset.signum() * (magnitude & u64::from(mask(bits))) as i64,
+                ));
+            }
+            // #-0 still subtracts, as other assemblers encode it
+            let subtract = offset < 0 || offset == 0 && is_signed;
+            Ok((
+                rest,
+                (TransferOffset::Immediate(magnitude as u16), subtract),
+            ))
+        };
+        context(
+            "parsing transfer offset",
+            alt((
+                constant,
+                map_opt(
+                    pair(opt(char('-')), parse_operand2_shifted),
+                    |(sign, (operand2, _))| match operand2 {
+                        Operand2::ShiftedReg(rm, shift) => {
+                            Some((TransferOffset::ShiftedReg(rm, shift), sign.is_some()))
+                        }
+                        Operand2::ConstantShift(_, _) => None,
+                    },
+                ),
+            )),
+        )(input)
+    }
+}
+
+// Parses a single data transfer opcode into whether it is a load, the size of the transfer and the
+// condition code. The condition may come either after the size (eg: ldrbeq), or before it as in
+// the older syntax (eg: ldreqb). Signed transfers are only available as loads.
+fn parse_transfer_opcode(input: &str) -> NomResult<&str, (bool, TransferSize, ConditionCode)> {
+    context(
+        "parsing transfer opcode",
+        map_opt(
+            pair(
+                alt((value(true, tag("ldr")), value(false, tag("str")))),
+                alt((
+                    map(pair(parse_condition_code, parse_transfer_size), |(c, s)| {
+                        (s, c)
+                    }),
+                    map(
+                        terminated(
+                            pair(opt(parse_transfer_size), opt(parse_condition_code)),
+                            peek(space1),
+                        ),
+                        |(s, c)| {
+                            (
+                                s.unwrap_or(TransferSize::Word),
+                                c.unwrap_or(ConditionCode::Al),
+                            )
+                        },
+                    ),
+                    // A word transfer with a condition which starts like a size, eg: ldrhs
+                    map(parse_condition_code, |c| (TransferSize::Word, c)),
+                )),
+            ),
+            |(load, (size, cond))| {
+                let signed = matches!(
+                    size,
+                    TransferSize::SignedByte | TransferSize::SignedHalfword
+                );
+                (load || !signed).then_some((load, size, cond))
+            },
+        ),
+    )(input)
+}
+
+// Parses the size suffix of a single data transfer opcode
+fn parse_transfer_size(input: &str) -> NomResult<&str, TransferSize> {
+    alt((
+        value(TransferSize::SignedByte, tag("sb")),
+        value(TransferSize::SignedHalfword, tag("sh")),
+        value(TransferSize::Byte, tag("b")),
+        value(TransferSize::Halfword, tag("h")),
+    ))(input)
+}
+
+// Parses a block transfer instruction, i.e. <ldm|stm><mode> Rn{!}, {<register list>}
+// The mode is one of the addressing modes ia/ib/da/db, or one of the stack aliases fd/ed/fa/ea,
+// which map to different addressing modes for loads and stores. If no mode is given, ia is used.
+//
+// This returns no additional data, so the second field of the return tuple will
+// always be None.
+//
+fn parse_block_transfer(input: &str) -> NomResult<&str, (ConditionalInstruction, Option<u32>)> {
+    context(
+        "parsing block transfer instruction",
+        map(
+            tuple((
+                alt((value(true, tag("ldm")), value(false, tag("stm")))),
+                terminated(
+                    alt((
+                        // The condition may also come before the mode, eg: ldmeqfd
+                        map(pair(parse_condition_code, parse_block_mode), |(c, m)| {
+                            (Some(m), Some(c))
+                        }),
+                        pair(opt(parse_block_mode), opt(parse_condition_code)),
+                    )),
+                    space1,
+                ),
+                parse_reg,
+                parse_writeback,
+                preceded(comma_space, parse_register_list),
+            )),
+            |(load, (mode, opt_cond), rn, writeback, register_list)| {
+                // Stack aliases describe the stack, so map to different modes for ldm and stm
+                let (is_preindexed, up_bit) = match (load, mode.unwrap_or("ia")) {
+                    (_, "ia") | (true, "fd") | (false, "ea") => (false, true),
+                    (_, "ib") | (true, "ed") | (false, "fa") => (true, true),
+                    (_, "da") | (true, "fa") | (false, "ed") => (false, false),
+                    (_, "db") | (true, "ea") | (false, "fd") => (true, false),
+                    _ => unreachable!(),
+                };
+
+                (
+                    ConditionalInstruction {
+                        cond: opt_cond.unwrap_or(ConditionCode::Al),
+                        instruction: Instruction::BlockTransfer(InstructionBlockTransfer {
+                            is_preindexed,
+                            up_bit,
+                            writeback,
+                            load,
+                            rn,
+                            register_list,
+                        }),
+                    },
+                    None,
+                )
+            },
+        ),
+    )(input)
+}
+
+// Parses a push or pop instruction, i.e. push{cond} <register list>, which is stmdb r13!,<list>,
+// or pop{cond} <register list>, which is ldmia r13!,<list>. These use a full descending stack,
+// as used by the procedure call standard.
+//
+// This returns no additional data, so the second field of the return tuple will
+// always be None.
+//
+fn parse_stack(input: &str) -> NomResult<&str, (ConditionalInstruction, Option<u32>)> {
+    context(
+        "parsing stack instruction",
+        map(
+            tuple((
+                alt((value(false, tag("push")), value(true, tag("pop")))),
+                terminated(opt(parse_condition_code), space1),
+                parse_register_list,
+            )),
+            |(load, opt_cond, register_list)| {
+                (
+                    ConditionalInstruction {
+                        cond: opt_cond.unwrap_or(ConditionCode::Al),
+                        instruction: Instruction::BlockTransfer(InstructionBlockTransfer {
+                            is_preindexed: !load,
+                            up_bit: load,
+                            writeback: true,
+                            load,
+                            rn: 13,
+                            register_list,
+                        }),
+                    },
+                    None,
+                )
+            },
+        ),
+    )(input)
+}
+
+// Parses the addressing mode or stack alias of a block transfer opcode
+fn parse_block_mode(input: &str) -> NomResult<&str, &str> {
+    alt((
+        tag("ia"),
+        tag("ib"),
+        tag("da"),
+        tag("db"),
+        tag("fd"),
+        tag("ed"),
+        tag("fa"),
+        tag("ea"),
+    ))(input)
+}
+
+// Parses a register list of the form {<register or range>, ...} into a bitmask, where bit n
+// is set if register n is in the list.
+// eg: {r0, r2-r4, r14}
+//
+fn parse_register_list(input: &str) -> NomResult<&str, u16> {
+    context(
+        "parsing register list",
+        map(
+            delimited(
+                terminated(char('{'), space0),
+                separated_list1(
+                    comma_space,
+                    alt((
+                        map_opt(
+                            separated_pair(
+                                parse_reg,
+                                delimited(space0, char('-'), space0),
+                                parse_reg,
+                            ),
+                            |(first, last)| {
+                                (first <= last).then(|| (first..=last).fold(0, |l, r| l | 1 << r))
+                            },
+                        ),
+                        map(parse_reg, |r| 1 << r),
+                    )),
+                ),
+                preceded(space0, char('}')),
+            ),
+            |masks: Vec<u32>| masks.into_iter().fold(0, |list, mask| list | mask as u16),
+        ),
+    )(input)
+}
+
+// Parses a branch and exchange instruction, i.e. bx{cond} Rm, which branches to the address held
+// in a register. This is commonly used to return from subroutines, eg: bx r14
+//
+// This returns no additional data, so the second field of the return tuple will
+// always be None.
+//
+fn parse_branch_exchange(input: &str) -> NomResult<&str, (ConditionalInstruction, Option<u32>)> {
+    context(
+        "parsing branch exchange instruction",
+        map(
+            tuple((
+                delimited(tag("bx"), opt(parse_condition_code), space1),
+                parse_reg,
+            )),
+            |(opt_cond, rm)| {
+                (
+                    ConditionalInstruction {
+                        cond: opt_cond.unwrap_or(ConditionCode::Al),
+                        instruction: Instruction::BranchExchange(InstructionBranchExchange { rm }),
+                    },
+                    None,
+                )
+            },
+        ),
+    )(input)
+}
+
+// Parses a coprocessor register transfer, i.e. <mrc|mcr>{cond} p<cp>, <opcode1>, Rd, c<n>, c<m>
+// {, <opcode2>}, which moves a value from a coprocessor register to Rd (mrc) or from Rd to a
+// coprocessor register (mcr).
+// eg: mrc p15, 0, r0, c0, c0, 0
+//
+// This returns no additional data, so the second field of the return tuple will
+// always be None.
+//
+fn parse_coprocessor(input: &str) -> NomResult<&str, (ConditionalInstruction, Option<u32>)> {
+    context(
+        "parsing coprocessor instruction",
+        map(
+            tuple((
+                terminated(
+                    pair(
+                        alt((value(true, tag("mrc")), value(false, tag("mcr")))),
+                        opt(parse_condition_code),
+                    ),
+                    space1,
+                ),
+                terminated(preceded(char('p'), parse_bounded(15)), comma_space),
+                terminated(parse_bounded(7), comma_space),
+                terminated(parse_reg, comma_space),
+                terminated(preceded(char('c'), parse_bounded(15)), comma_space),
+                preceded(char('c'), parse_bounded(15)),
+                opt(preceded(comma_space, parse_bounded(7))),
+            )),
+            |((load, opt_cond), coprocessor, opcode1, rd, crn, crm, opt_opcode2)| {
+                (
+                    ConditionalInstruction {
+                        cond: opt_cond.unwrap_or(ConditionCode::Al),
+                        instruction: Instruction::Coprocessor(InstructionCoprocessor {
+                            load,
+                            coprocessor,
+                            opcode1,
+                            crn,
+                            rd,
+                            crm,
+                            opcode2: opt_opcode2.unwrap_or(0),
+                        }),
+                    },
+                    None,
+                )
+            },
+        ),
+    )(input)
+}
+
+// Parses a single precision VFP instruction, with the condition after the mnemonic and before
+// any data type. Transfer offsets are multiples of 4 up to 1020.
+// eg: vldr s0,[Rn,#<offset>]
+// eg: vadd.f32 s0,s1,s2
+// eg: vmov r0,s1
+// eg: vcvt.s32.f32 s0,s1
+// eg: vcmp.f32 s0,s1
+// eg: vmrs APSR_nzcv,fpscr
+//
+// This returns no additional data, so the second field of the return tuple will
+// always be None.
+//
+fn parse_vfp(input: &str) -> NomResult<&str, (ConditionalInstruction, Option<u32>)> {
+    let transfer = map(
+        tuple((
+            alt((value(true, tag("vldr")), value(false, tag("vstr")))),
+            terminated(opt(parse_condition_code), space1),
+            terminated(parse_single, comma_space),
+            preceded(open_bracket, parse_reg),
+            terminated(
+                opt(preceded(
+                    comma_space,
+                    verify(parse_expression, |&(offset, _)| {
+                        offset % 4 == 0 && offset / 4 <= mask(VFP_OFFSET.size)
+                    }),
+                )),
+                close_bracket,
+            ),
+        )),
+        |(load, opt_cond, sd, rn, opt_offset)| {
+            let (offset, is_signed) = opt_offset.unwrap_or((0, false));
+            (
+                opt_cond,
+                InstructionVfp::Transfer {
+                    load,
+                    up_bit: !is_signed,
+                    rn,
+                    sd,
+                    offset: (offset / 4) as u8,
+                },
+            )
+        },
+    );
+    let arithmetic = map(
+        tuple((
+            preceded(
+                char('v'),
+                alt((
+                    value(VfpOpcode::Add, tag("add")),
+                    value(VfpOpcode::Sub, tag("sub")),
+                    value(VfpOpcode::Mul, tag("mul")),
+                    value(VfpOpcode::Div, tag("div")),
+                )),
+            ),
+            terminated(opt(parse_condition_code), pair(tag(".f32"), space1)),
+            terminated(parse_single, comma_space),
+            terminated(parse_single, comma_space),
+            parse_single,
+        )),
+        |(opcode, opt_cond, sd, sn, sm)| {
+            (opt_cond, InstructionVfp::Arithmetic { opcode, sd, sn, sm })
+        },
+    );
+    let transfer_register = pair(
+        delimited(tag("vmov"), opt(parse_condition_code), space1),
+        alt((
+            map(
+                separated_pair(parse_reg, comma_space, parse_single),
+                |(rt, sn)| InstructionVfp::Move {
+                    to_arm: true,
+                    sn,
+                    rt,
+                },
+            ),
+            map(
+                separated_pair(parse_single, comma_space, parse_reg),
+                |(sn, rt)| InstructionVfp::Move {
+                    to_arm: false,
+                    sn,
+                    rt,
+                },
+            ),
+        )),
+    );
+    let convert = map(
+        tuple((
+            preceded(tag("vcvt"), opt(parse_condition_code)),
+            terminated(
+                alt((value(true, tag(".s32.f32")), value(false, tag(".f32.s32")))),
+                space1,
+            ),
+            terminated(parse_single, comma_space),
+            parse_single,
+        )),
+        |(opt_cond, to_int, sd, sm)| (opt_cond, InstructionVfp::Convert { to_int, sd, sm }),
+    );
+    let compare = map(
+        tuple((
+            delimited(
+                tag("vcmp"),
+                opt(parse_condition_code),
+                pair(tag(".f32"), space1),
+            ),
+            terminated(parse_single, comma_space),
+            parse_single,
+        )),
+        |(opt_cond, sd, sm)| (opt_cond, InstructionVfp::Compare { sd, sm }),
+    );
+    let move_flags = map(
+        terminated(
+            delimited(tag("vmrs"), opt(parse_condition_code), space1),
+            tuple((
+                alt((tag("APSR_nzcv"), tag("apsr_nzcv"))),
+                comma_space,
+                tag("fpscr"),
+            )),
+        ),
+        |opt_cond| (opt_cond, InstructionVfp::MoveFlags),
+    );
+    context(
+        "parsing VFP instruction",
+        map(
+            alt((
+                transfer,
+                arithmetic,
+                transfer_register,
+                convert,
+                compare,
+                move_flags,
+            )),
+            |(opt_cond, instr)| {
+                (
+                    ConditionalInstruction {
+                        cond: opt_cond.unwrap_or(ConditionCode::Al),
+                        instruction: Instruction::Vfp(instr),
+                    },
+                    None,
+                )
+            },
+        ),
+    )(input)
+}
+
+// Parses a single precision VFP register, s0 to s31
+fn parse_single(input: &str) -> NomResult<&str, u8> {
+    context(
+        "parsing single precision register",
+        preceded(char('s'), parse_bounded(31)),
+    )(input)
+}
+
+// Parses a software interrupt, i.e. swi{cond} #<comment>, which is also written svc. The comment
+// selects the service the program is asking for.
+// eg: swi #0
+//
+// This returns no additional data, so the second field of the return tuple will
+// always be None.
+//
+fn parse_software_interrupt(input: &str) -> NomResult<&str, (ConditionalInstruction, Option<u32>)> {
+    context(
+        "parsing software interrupt instruction",
+        map(
+            pair(
+                delimited(
+                    alt((tag("swi"), tag("svc"))),
+                    opt(parse_condition_code),
+                    space1,
+                ),
+                verify(parse_expression, |&(comment, is_signed)| {
+                    !is_signed && comment <= mask(SWI_COMMENT.size)
+                }),
+            ),
+            |(opt_cond, (comment, _))| {
+                (
+                    ConditionalInstruction {
+                        cond: opt_cond.unwrap_or(ConditionCode::Al),
+                        instruction: Instruction::SoftwareInterrupt(InstructionSoftwareInterrupt {
+                            comment,
+                        }),
+                    },
+                    None,
+                )
+            },
+        ),
+    )(input)
+}
+
+// Parses a status register transfer, which reads the CPSR or SPSR into a register, or writes a
+// register or an immediate to the fields of the status register given by its suffix. The fields
+// are any of f (flags), s (status), x (extension) and c (control), and a plain status register
+// writes the control and flags fields.
+// eg: mrs Rd,cpsr
+// eg: msr spsr_f,Rm
+// eg: msr cpsr_fc,#<imm>
+//
+// This returns no additional data, so the second field of the return tuple will
+// always be None.
+//
+fn parse_status_transfer(input: &str) -> NomResult<&str, (ConditionalInstruction, Option<u32>)> {
+    let parse_read = map(
+        preceded(
+            tag("mrs"),
+            tuple((
+                terminated(opt(parse_condition_code), space1),
+                terminated(parse_reg, comma_space),
+                parse_status_register,
+            )),
+        ),
+        |(opt_cond, rd, spsr)| {
+            (
+                opt_cond,
+                Instruction::StatusRead(InstructionStatusRead { spsr, rd }),
+            )
+        },
+    );
+    let parse_write = map(
+        preceded(
+            tag("msr"),
+            tuple((
+                terminated(opt(parse_condition_code), space1),
+                terminated(pair(parse_status_register, parse_field_mask), comma_space),
+                alt((
+                    map(parse_operand2_constant, |(operand, _)| operand),
+                    map(parse_reg, |rm| {
+                        Operand2::ShiftedReg(rm, Shift::ConstantShift(ShiftType::Lsl, 0))
+                    }),
+                )),
+            )),
+        ),
+        |(opt_cond, (spsr, field_mask), operand)| {
+            (
+                opt_cond,
+                Instruction::StatusWrite(InstructionStatusWrite {
+                    spsr,
+                    field_mask,
+                    operand,
+                }),
+            )
+        },
+    );
+    context(
+        "parsing status register transfer instruction",
+        map(alt((parse_read, parse_write)), |(opt_cond, instruction)| {
+            (
+                ConditionalInstruction {
+                    cond: opt_cond.unwrap_or(ConditionCode::Al),
+                    instruction,
+                },
+                None,
+            )
+        }),
+    )(input)
+}
+
+// Parses the fields of a status register, as a field mask with bit 3 for f, 2 for s, 1 for x
+// and 0 for c. Each field may only be given once, and no fields means the control and flags
+// fields.
+// Parses the status register of a status register transfer, returning whether it is the SPSR
+fn parse_status_register(input: &str) -> NomResult<&str, bool> {
+    alt((value(false, tag("cpsr")), value(true, tag("spsr"))))(input)
+}
+
+fn parse_field_mask(input: &str) -> NomResult<&str, u8> {
+    let fields = |s: &str| {
+        s.chars().try_fold(0, |mask: u8, field| {
+            let bit = 1 << "cxsf".find(field)?;
+            (mask & bit == 0).then_some(mask | bit)
+        })
+    };
+    context(
+        "parsing status register fields",
+        alt((
+            map_opt(
+                preceded(char('_'), take_while1(|c: char| c.is_ascii_alphabetic())),
+                fields,
+            ),
+            success(0x9),
+        )),
+    )(input)
+}
+
+// Returns a parser for a decimal number no larger than max, eg: coprocessor register numbers
+fn parse_bounded(max: u8) -> impl Fn(&str) -> NomResult<&str, u8> {
+    move |input: &str| {
+        verify(map_opt(digit1, |n: &str| n.parse::<u8>().ok()), |&n| {
+            n <= max
+        })(input)
+    }
+}
+
+// Returns a parser for branch instructions, given the address of the current instruction and the
+// symbol table. This also handles branches with link, i.e. bl{cond} <label>, which save the
+// return address in r14.
+//
+// The parser will return no additional data, so the second field of the parser's return tuple will
+// always be None.
+//
+fn parse_branch(
+    current_address: usize,
+    symbol_table: Rc<SymbolTable>,
+) -> impl Fn(&str) -> NomResult<&str, (ConditionalInstruction, Option<u32>)> {
+    move |input: &str| {
+        let (target, (link, opt_cond)) = context(
+            "parsing branch instruction",
+            preceded(
+                char('b'),
+                alt((
+                    // Try a plain branch first, so that eg: ble isn't read as bl
+                    pair(
+                        success(false),
+                        terminated(opt(parse_condition_code), space1),
+                    ),
+                    pair(
+                        value(true, char('l')),
+                        terminated(opt(parse_condition_code), space1),
+                    ),
+                )),
+            ),
+        )(input)?;
+        // The target address, eg: a label, an absolute address, or an expression such as
+        // label+8 or . (this instruction)
+        let (rest, addr) = context(
+            "parsing branch instruction",
+            context(
+                "parsing branch target",
+                map_opt(expression::parse_expression, |e| {
+                    e.evaluate(&symbol_table, Address(current_address as u32))
+                        .ok()
+                        .map(Address)
+                }),
+            ),
+        )(target)?;
+
+        let pc = Address(current_address as u32).wrapping_add(PIPELINE_OFFSET as u32);
+        let offset = branch_offset(target, addr.offset_from(pc))?;
+        let cond = opt_cond.unwrap_or(ConditionCode::Al);
+        Ok((
+            rest,
+            (
+                ConditionalInstruction {
+                    cond,
+                    instruction: Instruction::Branch(InstructionBranch { link, offset }),
+                },
+                None,
+            ),
+        ))
+    }
+}
+
+// The word offset encoded by a branch, given the offset in bytes from the PC to its target.
+// Offsets which aren't whole words, or don't fit in 24 bits, are an error at the target, rather
+// than being masked to fit.
+fn branch_offset(
+    target: &str,
+    offset: i32,
+) -> std::result::Result<i32, nom::Err<ArmNomError<&str>>> {
+    let words = offset >> 2;
+    // Sign extend the offset from the bits which would be encoded
+    let unused = 32 - OFFSET_BRANCH.size;
+    let encoded = ((words as u32) << unused) as i32 >> unused;
+    if encoded == words && offset & 0x3 == 0 {
+        return Ok(words);
+    }
+    Err(truncated(
+        target,
+        "parsing branch target",
+        "branch offset",
+        i64::from(offset),
+        i64::from(encoded) << 2,
+    ))
+}
+
+// An error for a value which doesn't fit in its field, located at the value, with the value it
+// would be truncated to if it was masked to fit. This is a failure, so that no other parser is
+// tried and the error isn't lost.
+pub(super) fn truncated<'a>(
+    input: &'a str,
+    context: &'static str,
+    field: &'static str,
+    value: i64,
+    truncated: i64,
+) -> nom::Err<ArmNomError<&'a str>> {
+    nom::Err::Failure(ArmNomError::add_context(
+        input,
+        context,
+        ArmNomError::new(ArmNomErrorKind::Truncated(field, value, truncated)),
+    ))
+}
+
+// Parses a halt instruction, i.e. andeq r0,r0,r0.
+//
+// This returns no additional data, so the second field of the return tuple will
+// always be None.
+//
+fn parse_halt(input: &str) -> NomResult<&str, (ConditionalInstruction, Option<u32>)> {
+    context(
+        "parsing halt instruction",
+        value(
+            (
+                ConditionalInstruction {
+                    cond: ConditionCode::Eq,
+                    instruction: Instruction::Halt,
+                },
+                None,
+            ),
+            recognize(tuple((
+                tag("andeq"),
+                space1,
+                tag("r0"),
+                comma_space,
+                tag("r0"),
+                comma_space,
+                tag("r0"),
+            ))),
+        ),
+    )(input)
+}
+
+// Parses an lsl instruction. This provides an ARM assembly compatible way of shifting registers,
+// without supporting the full syntax for shift-modified expressions.
+//
+// This returns no additional data, so the second field of the return tuple will
+// always be None.
+//
+fn parse_lsl(input: &str) -> NomResult<&str, (ConditionalInstruction, Option<u32>)> {
+    let (rest, (opt_cond, rn, shift)) = context(
+        "parsing lsl instruction operands",
+        tuple((
+            delimited(tag("lsl"), opt(parse_condition_code), space1),
+            terminated(parse_reg, char(',')),
+            parse_shift_amount(ShiftType::Lsl),
+        )),
+    )(input)?;
+
+    // The lsl instruction is desugared into a mov instruction, i.e. mov Rn,Rn,lsl #<n>
+    let instr = ConditionalInstruction {
+        cond: opt_cond.unwrap_or(ConditionCode::Al),
+        instruction: Instruction::Processing(InstructionProcessing {
+            opcode: ProcessingOpcode::Mov,
+            set_cond: false,
+            rd: rn,
+            rn: 0,
+            operand2: Operand2::ShiftedReg(rn, shift),
+        }),
+    };
+    Ok((rest, (instr, None)))
+}
+
+// Returns a parser for the Operand2 of a processing instruction. Constants which can't be
+// encoded, including negative constants, are encoded by the instruction which does the same
+// with the constant negated or inverted if there is one, so the parser also returns the opcode.
+// eg: mov r0,#-5 is mvn r0,#4, and add r0,r0,#-1 is sub r0,r0,#1
+//
+fn parse_processing_operand2(
+    opcode: ProcessingOpcode,
+) -> impl Fn(&str) -> NomResult<&str, (ProcessingOpcode, Operand2)> {
+    move |input: &str| {
+        let constant = |input| {
+            let (rest, (value, is_signed)) =
+                context("parsing operand2 constant", parse_expression)(input)?;
+            let value = if is_signed {
+                value.wrapping_neg()
+            } else {
+                value
+            };
+            let encoded = processing_constant(opcode, value).ok_or_else(|| {
+                nom::Err::Error(ArmNomError::add_context(
+                    input,
+                    "parsing operand2 constant",
+                    ArmNomError::new(ArmNomErrorKind::Operand2Constant(value)),
+                ))
+            })?;
+            Ok((rest, encoded))
+        };
+        context(
+            "parsing operand2",
+            alt((
+                constant,
+                map(parse_operand2_shifted, |(operand2, _)| (opcode, operand2)),
+            )),
+        )(input)
+    }
+}
+
+// Encodes a constant operand for an opcode, or with the opcode which does the same with the
+// constant negated or inverted, if only that can be encoded
+fn processing_constant(
+    opcode: ProcessingOpcode,
+    value: u32,
+) -> Option<(ProcessingOpcode, Operand2)> {
+    if let Ok(operand2) = expression_to_operand2(value) {
+        return Some((opcode, operand2));
+    }
+    let (opcode, value) = match opcode {
+        ProcessingOpcode::Mov => (ProcessingOpcode::Mvn, !value),
+        ProcessingOpcode::Mvn => (ProcessingOpcode::Mov, !value),
+        ProcessingOpcode::And => (ProcessingOpcode::Bic, !value),
+        ProcessingOpcode::Bic => (ProcessingOpcode::And, !value),
+        ProcessingOpcode::Adc => (ProcessingOpcode::Sbc, !value),
+        ProcessingOpcode::Sbc => (ProcessingOpcode::Adc, !value),
+        ProcessingOpcode::Add => (ProcessingOpcode::Sub, value.wrapping_neg()),
+        ProcessingOpcode::Sub => (ProcessingOpcode::Add, value.wrapping_neg()),
+        ProcessingOpcode::Cmp => (ProcessingOpcode::Cmn, value.wrapping_neg()),
+        ProcessingOpcode::Cmn => (ProcessingOpcode::Cmp, value.wrapping_neg()),
+        _ => return None,
+    };
+    let operand2 = expression_to_operand2(value).ok()?;
+    Some((opcode, operand2))
+}
+
+// Parses an expression from a string, directly to an Operand2.
+fn parse_operand2_constant(input: &str) -> NomResult<&str, (Operand2, bool)> {
+    let (rest, (value, is_signed)) = context("parsing operand2 constant", parse_expression)(input)?;
+    let op2 = expression_to_operand2(value).map_err(|_| {
+        // Located at the constant, so it can be pointed to in the source
+        ArmNomError::add_context(
+            input,
+            "parsing operand2 constant",
+            ArmNomError::new(ArmNomErrorKind::Operand2Constant(value)),
+        )
+    })?;
+
+    Ok((rest, (op2, is_signed)))
+}
+
+// Converts u32 to a constant shifted Operand2.
+//
+// assert_eq!(expression_to_operand2(0x2), Operand2::ConstantShift(0x2, 0));
+// assert_eq!(expression_to_operand2(0x3f0000), Operand2::ConstantShift(0x3f, 6));
+//
+fn expression_to_operand2(mut value: u32) -> Result<Operand2> {
+    let mut rotate_count: u8 = 1 << 4;
+
+    // If the value fits in 8 bits, we don't need to rotate it
+    if value > mask(IMM_VALUE.size) {
+        // While the least significant bits are both zeroes,
+        // shift right and count a rotation.
+        while value & mask(2) == 0 {
+            value = value.overflowing_shr(2).0;
+            rotate_count -= 1;
+        }
+    }
+
+    // If the rotate count was not decremented, we take 0
+    rotate_count &= mask(4) as u8;
+    let to_rotate = value.try_into().map_err(ArmError::immediate)?;
+    Ok(Operand2::ConstantShift(to_rotate, rotate_count))
+}
+
+// Parses a shifted register Operand2, i.e a string of the form: <register>{, <shift>}
+// Curly braces here indicate that the shift is optional. If no shift is given, we use a constant
+// shift of 0 as the shift value.
+//
+fn parse_operand2_shifted(input: &str) -> NomResult<&str, (Operand2, bool)> {
+    context(
+        "parsing operand2 shifted",
+        map(
+            tuple((parse_reg, opt(preceded(comma_space, parse_shift)))),
+            |(reg_to_shift, shift_opt)| {
+                (
+                    shift_opt.map_or(
+                        Operand2::ShiftedReg(reg_to_shift, Shift::ConstantShift(ShiftType::Lsl, 0)),
+                        |shift| Operand2::ShiftedReg(reg_to_shift, shift),
+                    ),
+                    false,
+                )
+            },
+        ),
+    )(input)
+}
+
+// Parses a shift, i.e. an expression which is either a <shifttype> <#expression>, a
+// <shifttype> <register> or rrx. It is preceded by 0 or more spaces. Constant shifts must fit in
+// the 5 bit shift field, i.e. be from 0 to 31, or from 0 to 32 for lsr and asr. rrx, a rotation
+// right by 1 through the carry, is encoded as ror #0.
+//
+// assert_eq!(parse_shift("  lsl r2"), Ok("", Shift::RegisterShift(ShiftType::Lsl, 2)));
+// assert_eq!(parse_shift("ror #2")), Ok("", Shift::ConstantShift(ShiftType::Ror, 2));
+// assert_eq!(parse_shift("rrx")), Ok("", Shift::ConstantShift(ShiftType::Ror, 0));
+//
+fn parse_shift(input: &str) -> NomResult<&str, Shift> {
+    if let Ok((rest, _)) = tag::<_, _, ()>("rrx")(input) {
+        return Ok((rest, Shift::ConstantShift(ShiftType::Ror, 0)));
+    }
+    let (rest, shift_type) = context("parsing shift type", parse_shifttype)(input)?;
+    context(
+        "parsing shift",
+        preceded(
+            space0,
+            alt((
+                parse_shift_amount(shift_type),
+                map(parse_reg, move |reg: u8| {
+                    Shift::RegisterShift(shift_type, reg)
+                }),
+            )),
+        ),
+    )(rest)
+}
+
+// Returns a parser for the amount of a constant shift, eg: #2, which must fit in the 5 bit shift
+// field. An lsr or asr by 32 is encoded as by 0, so an lsr or asr by 0 is encoded as lsl #0.
+fn parse_shift_amount(shift_type: ShiftType) -> impl Fn(&str) -> NomResult<&str, Shift> {
+    move |input: &str| {
+        let (rest, (amount, is_signed)) = parse_expression(input)?;
+        let max = match shift_type {
+            ShiftType::Lsr | ShiftType::Asr => mask(CONST_SHIFT.size) + 1,
+            _ => mask(CONST_SHIFT.size),
+        };
+        if amount > max || is_signed && amount != 0 {
+            let amount = if is_signed {
+                -i64::from(amount)
+            } else {
+                i64::from(amount)
+            };
+            return Err(truncated(
+                input,
+                "parsing shift",
+                "5 bit shift amount",
+                amount,
+                amount & i64::from(mask(CONST_SHIFT.size)),
+            ));
+        }
+        let shift = match (shift_type, amount) {
+            (ShiftType::Lsr, 0) | (ShiftType::Asr, 0) => Shift::ConstantShift(ShiftType::Lsl, 0),
+            _ => Shift::ConstantShift(shift_type, (amount & mask(CONST_SHIFT.size)) as u8),
+        };
+        Ok((rest, shift))
+    }
+}
+
+// Parses a register of the form r<int>, where int is a valid available register, or one of the
+// standard aliases fp (r11), ip (r12), sp (r13), lr (r14) and pc (r15). The name must end
+// there, so "spare" and "r1x" aren't registers.
+// eg: r0, r12, r15, sp
+//
+pub(super) fn parse_reg(input: &str) -> NomResult<&str, u8> {
+    context(
+        "parsing register",
+        terminated(
+            alt((
+                verify(
+                    map_opt(preceded(char('r'), digit1), |r: &str| r.parse::<u8>().ok()),
+                    |&r| r as usize <= PC,
+                ),
+                value(11, tag("fp")),
+                value(12, tag("ip")),
+                value(13, tag("sp")),
+                value(14, tag("lr")),
+                value(PC as u8, tag("pc")),
+            )),
+            not(satisfy(|c| c.is_ascii_alphanumeric() || c == '_')),
+        ),
+    )(input)
+}
+
+// Parses a label name, made up of alphanumeric characters and underscores
+pub(super) fn parse_label(input: &str) -> NomResult<&str, &str> {
+    context(
+        "parsing label",
+        take_while1(|c: char| c.is_ascii_alphanumeric() || c == '_' || c == '$'),
+    )(input)
+}
+
+pub(super) fn parse_expression(input: &str) -> NomResult<&str, (u32, bool)> {
+    context(
+        "parsing expresssion",
+        preceded(char('#'), alt((hexedecimal_value, decimal_value))),
+    )(input)
+}
+
+// Parses a signed hexadecimal value to a (u32, bool), where the boolean is true if the
+// original value is negative.
+// eg:
+//
+// assert_eq!(hexedecimal_value("0x1234"), Ok("", (0x1234, false))
+// assert_eq!(hexedecimal_value("-0x6969"), Ok("", (0x6969, true))
+//
+pub(super) fn hexedecimal_value(input: &str) -> NomResult<&str, (u32, bool)> {
+    let (rest, (opt_sign, out)) = context(
+        "parsing hexedecimal value",
+        tuple((opt(char('-')), preceded(tag("0x"), recognize(hex_digit1)))),
+    )(input)?;
+
+    Ok((
+        rest,
+        (
+            u32::from_str_radix(out, 16)
+                .map_err(|_| ArmNomError::new(ArmNomErrorKind::HexadecimalValue))?,
+            opt_sign.is_some(),
+        ),
+    ))
+}
+
+// Parses a signed decimal value to a (u32, bool), where the boolean is true if the
+// original value is negative.
+//
+// assert_eq!(hexedecimal_value("1234"), Ok("", (1234, false))
+// assert_eq!(hexedecimal_value("-6969"), Ok("", (6969, true))
+//
+pub(super) fn decimal_value(input: &str) -> NomResult<&str, (u32, bool)> {
+    let (rest, (opt_sign, out)) = context(
+        "parsing decimal value",
+        tuple((opt(char('-')), recognize(digit1))),
+    )(input)?;
+
+    Ok((
+        rest,
+        (
+            out.parse::<u32>()
+                .map_err(|_| ArmNomError::new(ArmNomErrorKind::DecimalValue))?,
+            opt_sign.is_some(),
+        ),
+    ))
+}
+
+// Matches a comma, with 0 or more spaces around it.
+pub(super) fn comma_space(input: &str) -> NomResult<&str, char> {
+    delimited(space0, char(','), space0)(input)
+}
+
+// Matches the brackets around a transfer address, with 0 or more spaces inside them.
+// Parses the ! which marks a transfer which writes back to its base register, if there is one
+fn parse_writeback(input: &str) -> NomResult<&str, bool> {
+    map(opt(preceded(space0, char('!'))), |w| w.is_some())(input)
+}
+
+fn open_bracket(input: &str) -> NomResult<&str, char> {
+    terminated(char('['), space0)(input)
+}
+
+fn close_bracket(input: &str) -> NomResult<&str, char> {
+    preceded(space0, char(']'))(input)
+}
+
+// Parses shifttype strings into values of ShiftType.
+pub(super) fn parse_shifttype(input: &str) -> NomResult<&str, ShiftType> {
+    context(
+        "parsing shift type",
+        alt((
+            value(ShiftType::Lsl, tag("lsl")),
+            value(ShiftType::Lsr, tag("lsr")),
+            value(ShiftType::Asr, tag("asr")),
+            value(ShiftType::Ror, tag("ror")),
+        )),
+    )(input)
+}
+
+// Parses processing opcode strings into values of ProcessingOpcode.
+fn parse_processing_opcode(input: &str) -> NomResult<&str, ProcessingOpcode> {
+    context(
+        "parsing processing opcode",
+        alt((
+            value(ProcessingOpcode::And, tag("and")),
+            value(ProcessingOpcode::Eor, tag("eor")),
+            value(ProcessingOpcode::Sub, tag("sub")),
+            value(ProcessingOpcode::Rsb, tag("rsb")),
+            value(ProcessingOpcode::Add, tag("add")),
+            value(ProcessingOpcode::Adc, tag("adc")),
+            value(ProcessingOpcode::Sbc, tag("sbc")),
+            value(ProcessingOpcode::Rsc, tag("rsc")),
+            value(ProcessingOpcode::Tst, tag("tst")),
+            value(ProcessingOpcode::Teq, tag("teq")),
+            value(ProcessingOpcode::Cmp, tag("cmp")),
+            value(ProcessingOpcode::Cmn, tag("cmn")),
+            value(ProcessingOpcode::Orr, tag("orr")),
+            value(ProcessingOpcode::Mov, tag("mov")),
+            value(ProcessingOpcode::Bic, tag("bic")),
+            value(ProcessingOpcode::Mvn, tag("mvn")),
+        )),
+    )(input)
+}
+
+// Parses the optional condition code and 's' suffix which follow a mnemonic, returning whether
+// the instruction sets the CPSR flags. Both the UAL order (addseq) and the older pre-UAL order
+// (addeqs) are accepted.
+fn parse_suffixes(input: &str) -> NomResult<&str, (Option<ConditionCode>, bool)> {
+    context(
+        "parsing mnemonic suffixes",
+        alt((
+            map(pair(parse_condition_code, opt(char('s'))), |(cond, s)| {
+                (Some(cond), s.is_some())
+            }),
+            map(
+                pair(opt(char('s')), opt(parse_condition_code)),
+                |(s, cond)| (cond, s.is_some()),
+            ),
+        )),
+    )(input)
+}
+
+// Parses condition code strings into values of ConditionCode. hs and lo are accepted as aliases
+// of cs and cc, for unsigned comparisons.
+pub(super) fn parse_condition_code(input: &str) -> NomResult<&str, ConditionCode> {
+    context(
+        "parsing condition code",
+        alt((
+            value(ConditionCode::Eq, tag("eq")),
+            value(ConditionCode::Ne, tag("ne")),
+            value(ConditionCode::Cs, alt((tag("cs"), tag("hs")))),
+            value(ConditionCode::Cc, alt((tag("cc"), tag("lo")))),
+            value(ConditionCode::Mi, tag("mi")),
+            value(ConditionCode::Pl, tag("pl")),
+            value(ConditionCode::Vs, tag("vs")),
+            value(ConditionCode::Vc, tag("vc")),
+            value(ConditionCode::Hi, tag("hi")),
+            value(ConditionCode::Ls, tag("ls")),
+            value(ConditionCode::Ge, tag("ge")),
+            value(ConditionCode::Lt, tag("lt")),
+            value(ConditionCode::Gt, tag("gt")),
+            value(ConditionCode::Le, tag("le")),
+            value(ConditionCode::Al, tag("al")),
+        )),
+    )(input)
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// TESTS
+///////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arm11_isa::{decode::decode, disassemble::disassemble_instruction, encode::encode};
+
+    #[test]
+    fn test_parse_reg() {
+        assert_eq!(parse_reg("r12").expect("parse reg failed").1, 12);
+        assert!(parse_reg("r123").is_err());
+
+        // Every register up to the PC can be named by number, so that block transfers can
+        // include sp, lr and pc, but the CPSR can't be used as an operand
+        assert_eq!(parse_reg("r13").expect("parse reg failed").1, 13);
+        assert_eq!(parse_reg("r14").expect("parse reg failed").1, 14);
+        assert_eq!(parse_reg("r15").expect("parse reg failed").1, PC as u8);
+        assert!(parse_reg("r16").is_err());
+    }
+
+    #[test]
+    fn test_parse_shifttype() {
+        assert_eq!(
+            parse_shifttype("lsl").expect("parse shifttype failed").1,
+            ShiftType::Lsl
+        );
+        assert_eq!(
+            parse_shifttype("lsr2341234")
+                .expect("parse shifttype failed")
+                .1,
+            ShiftType::Lsr
+        );
+    }
+
+    #[test]
+    fn test_parse_shift() {
+        assert_eq!(
+            parse_shift("asr #31").expect("parse shift failed").1,
+            Shift::ConstantShift(ShiftType::Asr, 31)
+        );
+        assert_eq!(
+            parse_shift("lsl r2").expect("parse shift failed").1,
+            Shift::RegisterShift(ShiftType::Lsl, 2)
+        );
+        assert_eq!(
+            parse_shift("rrx").expect("parse shift failed").1,
+            Shift::ConstantShift(ShiftType::Ror, 0)
+        );
+        assert_eq!(
+            parse_shift("lsr #32").expect("parse shift failed").1,
+            Shift::ConstantShift(ShiftType::Lsr, 0)
+        );
+        assert_eq!(
+            parse_shift("asr #0").expect("parse shift failed").1,
+            Shift::ConstantShift(ShiftType::Lsl, 0)
+        );
+        assert!(parse_shift("lsl #32").is_err());
+        assert!(parse_shift("asr #33").is_err());
+        assert!(parse_shift("lsl #0x1000").is_err());
+    }
+
+    #[test]
+    fn test_parse_processing_opcode() {
+        assert_eq!(
+            parse_processing_opcode("eor")
+                .expect("parse shifttype failed")
+                .1,
+            ProcessingOpcode::Eor
+        );
+        assert_eq!(
+            parse_processing_opcode("mov2341234")
+                .expect("parse shifttype failed")
+                .1,
+            ProcessingOpcode::Mov
+        );
+    }
+
+    #[test]
+    fn test_parse_expression() {
+        assert_eq!(
+            parse_expression("#123456")
+                .expect("parse expression failed")
+                .1,
+            (123456, false)
+        );
+        assert_eq!(
+            parse_expression("#-123456")
+                .expect("parse expression failed")
+                .1,
+            (123456, true)
+        );
+        assert_eq!(
+            parse_expression("#0x123456")
+                .expect("parse expression failed")
+                .1,
+            (0x123456, false)
+        );
+        assert_eq!(
+            parse_expression("#-0x123456")
+                .expect("parse expression failed")
+                .1,
+            (0x123456, true)
+        );
+    }
+
+    #[test]
+    fn test_parse_operand2_constant() {
+        // Check the case where the constant is less than IMM_VALUE.size
+        assert_eq!(
+            parse_operand2_constant("#0x2")
+                .expect("parse operand 2 constant failed")
+                .1,
+            (Operand2::ConstantShift(0x2, 0), false)
+        );
+
+        assert_eq!(
+            parse_operand2_constant("#0x3f00000")
+                .expect("parse operand 2 constant failed")
+                .1,
+            (Operand2::ConstantShift(0x3f, 6), false)
+        );
+    }
+
+    #[test]
+    fn test_parse_operand2_shifted() {
+        assert_eq!(
+            parse_operand2_shifted("r2,lsr #2")
+                .expect("parse operand 2 shifted failed")
+                .1,
+            (
+                Operand2::ShiftedReg(2, Shift::ConstantShift(ShiftType::Lsr, 2)),
+                false
+            )
+        )
+    }
+
+    #[test]
+    fn test_parse_processing() {
+        assert_eq!(
+            parse_processing("add r3,r1,r2")
+                .expect("parse processing failed")
+                .1,
+            (
+                ConditionalInstruction {
+                    cond: ConditionCode::Al,
+                    instruction: Instruction::Processing(InstructionProcessing {
+                        opcode: ProcessingOpcode::Add,
+                        rd: 3,
+                        rn: 1,
+                        set_cond: false,
+                        operand2: Operand2::ShiftedReg(2, Shift::ConstantShift(ShiftType::Lsl, 0))
+                    })
+                },
+                None
+            )
+        );
+    }
+
+    #[test]
+    fn test_negative_constants() {
+        let parse = |raw| match parse_processing(raw)
+            .expect("parse processing failed")
+            .1
+             .0
+            .instruction
+        {
+            Instruction::Processing(p) => (p.opcode, p.operand2),
+            _ => unreachable!(),
+        };
+        assert_eq!(
+            parse("mov r0,#-5"),
+            (ProcessingOpcode::Mvn, Operand2::ConstantShift(4, 0))
+        );
+        assert_eq!(
+            parse("mvn r0,#0xffffff00"),
+            (ProcessingOpcode::Mov, Operand2::ConstantShift(0xff, 0))
+        );
+        assert_eq!(
+            parse("add r0,r0,#-1"),
+            (ProcessingOpcode::Sub, Operand2::ConstantShift(1, 0))
+        );
+        assert_eq!(
+            parse("sub r0,r0,#-0x100"),
+            (ProcessingOpcode::Add, Operand2::ConstantShift(1, 12))
+        );
+        assert_eq!(
+            parse("cmp r0,#-3"),
+            (ProcessingOpcode::Cmn, Operand2::ConstantShift(3, 0))
+        );
+        assert_eq!(
+            parse("and r0,r0,#0xffffff0f"),
+            (ProcessingOpcode::Bic, Operand2::ConstantShift(0xf0, 0))
+        );
+        // Constants which can be encoded keep their opcode
+        assert_eq!(
+            parse("add r0,r0,#4"),
+            (ProcessingOpcode::Add, Operand2::ConstantShift(4, 0))
+        );
+        assert!(parse_processing("orr r0,r0,#-2").is_err());
+        assert!(parse_processing("mov r0,#0x101").is_err());
+
+        let ldr = |raw| {
+            parse_transfer_immediate(0x0, 0x8, Rc::new(SymbolTable::new()))(raw)
+                .expect("parse transfer immediate failed")
+                .1
+        };
+        assert_eq!(
+            ldr("ldr r0,=-1").0.instruction,
+            Instruction::Processing(InstructionProcessing {
+                opcode: ProcessingOpcode::Mvn,
+                set_cond: false,
+                rd: 0,
+                rn: 0,
+                operand2: Operand2::ConstantShift(0, 0),
+            })
+        );
+        assert_eq!(ldr("ldr r0,=-0x1000").1, Some(0xfffff000));
+    }
+
+    #[test]
+    fn test_parse_multiply_long() {
+        assert_eq!(
+            parse_multiply_long("smlalnes r0,r1,r2,r3")
+                .expect("parse long multiply failed")
+                .1,
+            (
+                ConditionalInstruction {
+                    cond: ConditionCode::Ne,
+                    instruction: Instruction::MultiplyLong(InstructionMultiplyLong {
+                        signed: true,
+                        accumulate: true,
+                        set_cond: true,
+                        rd_hi: 1,
+                        rd_lo: 0,
+                        rs: 3,
+                        rm: 2,
+                    })
+                },
+                None
+            )
+        );
+        assert!(parse_multiply_long("umull r0,r0,r2,r3").is_err());
+    }
+
+    #[test]
+    fn test_parse_status_transfer() {
+        let parse = |raw| {
+            parse_status_transfer(raw)
+                .expect("parse status transfer failed")
+                .1
+                 .0
+                .instruction
+        };
+        assert_eq!(
+            parse("mrs r3,cpsr"),
+            Instruction::StatusRead(InstructionStatusRead { spsr: false, rd: 3 })
+        );
+        assert_eq!(
+            parse("msr cpsr_f,r2"),
+            Instruction::StatusWrite(InstructionStatusWrite {
+                spsr: false,
+                field_mask: 0x8,
+                operand: Operand2::ShiftedReg(2, Shift::ConstantShift(ShiftType::Lsl, 0)),
+            })
+        );
+        assert_eq!(
+            parse("msr cpsr,#0xf0000000"),
+            Instruction::StatusWrite(InstructionStatusWrite {
+                spsr: false,
+                field_mask: 0x9,
+                operand: Operand2::ConstantShift(0xf, 2),
+            })
+        );
+        assert_eq!(
+            parse_field_mask("_fsxc").expect("parse fields failed").1,
+            0xf
+        );
+        assert!(parse_status_transfer("msr cpsr_ff,r2").is_err());
+        assert!(parse_status_transfer("msr cpsr_q,r2").is_err());
+        assert_eq!(
+            parse("msr spsr_fsxc,r0"),
+            Instruction::StatusWrite(InstructionStatusWrite {
+                spsr: true,
+                field_mask: 0xf,
+                operand: Operand2::ShiftedReg(0, Shift::ConstantShift(ShiftType::Lsl, 0)),
+            })
+        );
+        assert_eq!(
+            parse("mrs r0,spsr"),
+            Instruction::StatusRead(InstructionStatusRead { spsr: true, rd: 0 })
+        );
+        assert!(parse_status_transfer("mrs r0,apsr").is_err());
+    }
+
+    #[test]
+    fn test_parse_multiply() {
+        assert_eq!(
+            parse_multiply("mul r3,r1,r2")
+                .expect("parse multiply failed")
+                .1,
+            (
+                ConditionalInstruction {
+                    cond: ConditionCode::Al,
+                    instruction: Instruction::Multiply(InstructionMultiply {
+                        accumulate: false,
+                        set_cond: false,
+                        rd: 3,
+                        rm: 1,
+                        rs: 2,
+                        rn: 0
+                    })
+                },
+                None
+            )
+        );
+
+        assert_eq!(
+            parse_multiply("mla r3,r1,r2,r4")
+                .expect("parse multiply failed")
+                .1,
+            (
+                ConditionalInstruction {
+                    cond: ConditionCode::Al,
+                    instruction: Instruction::Multiply(InstructionMultiply {
+                        accumulate: true,
+                        set_cond: false,
+                        rd: 3,
+                        rm: 1,
+                        rs: 2,
+                        rn: 4
+                    })
+                },
+                None
+            )
+        );
+    }
+
+    #[test]
+    fn test_parse_branch() {
+        let mut symbol_table = SymbolTable::new();
+        symbol_table.insert("foo".to_owned(), Address(0x14));
+        symbol_table.insert("wait".to_owned(), Address(0x4));
+        let rc_symbol_table = Rc::new(symbol_table);
+
+        let st_1 = rc_symbol_table.clone();
+        assert_eq!(
+            parse_branch(0xc, st_1)("beq foo")
+                .expect("parse branch failed")
+                .1,
+            (
+                ConditionalInstruction {
+                    cond: ConditionCode::Eq,
+                    instruction: Instruction::Branch(InstructionBranch {
+                        link: false,
+                        offset: 0
+                    })
+                },
+                None
+            )
+        );
+
+        let st_2 = rc_symbol_table.clone();
+        assert_eq!(
+            parse_branch(0xc, st_2)("bne wait")
+                .expect("parse branch failed")
+                .1,
+            (
+                ConditionalInstruction {
+                    cond: ConditionCode::Ne,
+                    instruction: Instruction::Branch(InstructionBranch {
+                        link: false,
+                        offset: -4
+                    })
+                },
+                None
+            )
+        );
+
+        let st_3 = rc_symbol_table.clone();
+        assert_eq!(
+            parse_branch(0xc, st_3)("bleq foo")
+                .expect("parse branch failed")
+                .1,
+            (
+                ConditionalInstruction {
+                    cond: ConditionCode::Eq,
+                    instruction: Instruction::Branch(InstructionBranch {
+                        link: true,
+                        offset: 0
+                    })
+                },
+                None
+            )
+        );
+
+        assert_eq!(
+            parse_branch(0xc, rc_symbol_table)("ble foo")
+                .expect("parse branch failed")
+                .1,
+            (
+                ConditionalInstruction {
+                    cond: ConditionCode::Le,
+                    instruction: Instruction::Branch(InstructionBranch {
+                        link: false,
+                        offset: 0
+                    })
+                },
+                None
+            )
+        );
+    }
+
+    #[test]
+    fn test_parse_block_transfer() {
+        assert_eq!(
+            parse_block_transfer("stmfd r13!, {r0-r3, r14}")
+                .expect("parse block transfer failed")
+                .1,
+            (
+                ConditionalInstruction {
+                    cond: ConditionCode::Al,
+                    instruction: Instruction::BlockTransfer(InstructionBlockTransfer {
+                        is_preindexed: true,
+                        up_bit: false,
+                        writeback: true,
+                        load: false,
+                        rn: 13,
+                        register_list: 0x400f,
+                    })
+                },
+                None
+            )
+        );
+
+        assert_eq!(
+            parse_block_transfer("ldm r1,{r2}")
+                .expect("parse block transfer failed")
+                .1,
+            (
+                ConditionalInstruction {
+                    cond: ConditionCode::Al,
+                    instruction: Instruction::BlockTransfer(InstructionBlockTransfer {
+                        is_preindexed: false,
+                        up_bit: true,
+                        writeback: false,
+                        load: true,
+                        rn: 1,
+                        register_list: 0x4,
+                    })
+                },
+                None
+            )
+        );
+        assert!(parse_block_transfer("ldmia r1, {r4-r2}").is_err());
+
+        // push and pop use a full descending stack in r13
+        let stack = |load: bool, register_list| {
+            Instruction::BlockTransfer(InstructionBlockTransfer {
+                is_preindexed: !load,
+                up_bit: load,
+                writeback: true,
+                load,
+                rn: 13,
+                register_list,
+            })
+        };
+        assert_eq!(
+            parse_stack("push {r4-r5, r14}").unwrap().1 .0.instruction,
+            stack(false, 0x4030)
+        );
+        assert_eq!(
+            parse_stack("popne {r4, r15}").unwrap().1 .0,
+            ConditionalInstruction {
+                cond: ConditionCode::Ne,
+                instruction: stack(true, 0x8010),
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_transfer_immediate() {
+        // Case where expression <= IMM_VALUE.size
+        assert_eq!(
+            parse_transfer_immediate(0x0, 0xc, Rc::new(SymbolTable::new()))("ldr r0,=0x02")
+                .expect("parse transfer failed")
+                .1,
+            (
+                ConditionalInstruction {
+                    cond: ConditionCode::Al,
+                    instruction: Instruction::Processing(InstructionProcessing {
+                        opcode: ProcessingOpcode::Mov,
+                        set_cond: false,
+                        rn: 0,
+                        rd: 0,
+                        operand2: Operand2::ConstantShift(0x02, 0)
+                    })
+                },
+                None
+            )
+        );
+
+        // Case where expression > IMM_VALUE.size
+        assert_eq!(
+            parse_transfer_immediate(0x0, 0x8, Rc::new(SymbolTable::new()))("ldr r2,=0x20200020")
+                .expect("parse transfer immediate failed")
+                .1,
+            (
+                ConditionalInstruction {
+                    cond: ConditionCode::Al,
+                    instruction: Instruction::Transfer(InstructionTransfer {
+                        is_preindexed: true,
+                        up_bit: true,
+                        writeback: false,
+                        load: true,
+                        size: TransferSize::Word,
+                        rn: PC as u8,
+                        rd: 2,
+                        offset: TransferOffset::Immediate(0x0),
+                    })
+                },
+                Some(0x20200020)
+            )
+        )
+    }
+
+    #[test]
+    fn test_parse_transfer_sizes() {
+        let transfer = |raw| {
+            parse_transfer_indexed(raw)
+                .expect("parse transfer failed")
+                .1
+                 .0
+                .instruction
+        };
+
+        assert_eq!(
+            transfer("ldrsb r0,[r1,#-4]"),
+            Instruction::Transfer(InstructionTransfer {
+                is_preindexed: true,
+                up_bit: false,
+                writeback: false,
+                load: true,
+                size: TransferSize::SignedByte,
+                rn: 1,
+                rd: 0,
+                offset: TransferOffset::Immediate(0x4),
+            })
+        );
+        assert_eq!(
+            transfer("strh r2,[r3],r4"),
+            Instruction::Transfer(InstructionTransfer {
+                is_preindexed: false,
+                up_bit: true,
+                writeback: false,
+                load: false,
+                size: TransferSize::Halfword,
+                rn: 3,
+                rd: 2,
+                offset: TransferOffset::ShiftedReg(4, Shift::ConstantShift(ShiftType::Lsl, 0)),
+            })
+        );
+        assert_eq!(
+            transfer("ldr r0,[r1,#4]!"),
+            Instruction::Transfer(InstructionTransfer {
+                is_preindexed: true,
+                up_bit: true,
+                writeback: true,
+                load: true,
+                size: TransferSize::Word,
+                rn: 1,
+                rd: 0,
+                offset: TransferOffset::Immediate(0x4),
+            })
+        );
+        assert!(parse_transfer_indexed("ldrh r0,[r1,r2, lsl #2]").is_err());
+        assert!(parse_transfer_indexed("ldrh r0,[r1,#0x100]").is_err());
+        assert!(parse_transfer_indexed("strsb r0,[r1]").is_err());
+    }
+
+    #[test]
+    fn test_parse_condition_suffixes() {
+        let parse = |raw| {
+            parse_asm(raw, 1, 0, 0x10, Rc::new(SymbolTable::new()))
+                .expect("parse failed")
+                .0
+        };
+
+        let addne = parse("addne r1,r2,#1");
+        assert_eq!(addne.cond, ConditionCode::Ne);
+        assert_eq!(
+            addne.instruction,
+            Instruction::Processing(InstructionProcessing {
+                opcode: ProcessingOpcode::Add,
+                set_cond: false,
+                rn: 2,
+                rd: 1,
+                operand2: Operand2::ConstantShift(1, 0),
+            })
+        );
+        assert_eq!(parse("moveq r0,#1").cond, ConditionCode::Eq);
+        assert_eq!(parse("mlage r0,r1,r2,r3").cond, ConditionCode::Ge);
+        assert_eq!(parse("lsllt r1,#2").cond, ConditionCode::Lt);
+        assert_eq!(parse("ldrgt r0,=0x20200000").cond, ConditionCode::Gt);
+        assert_eq!(parse("ldmeqfd r13!, {r0}").cond, ConditionCode::Eq);
+        assert_eq!(parse("movle r0,#1").cond, ConditionCode::Le);
+        assert_eq!(parse("moval r0,#1").cond, ConditionCode::Al);
+
+        assert_eq!(parse("movhs r0,#1").cond, ConditionCode::Cs);
+        assert_eq!(parse("movlo r0,#1").cond, ConditionCode::Cc);
+        assert_eq!(parse("bls 0").cond, ConditionCode::Ls);
+        assert_eq!(parse("blhi 0").cond, ConditionCode::Hi);
+
+        // ldrhs is a word transfer with the hs condition, while ldrhhs is a halfword transfer
+        let ldrhs = parse("ldrhs r0,[r1]");
+        assert_eq!(ldrhs.cond, ConditionCode::Cs);
+        assert!(matches!(
+            ldrhs.instruction,
+            Instruction::Transfer(InstructionTransfer {
+                size: TransferSize::Word,
+                ..
+            })
+        ));
+        let ldrhhs = parse("ldrhhs r0,[r1]");
+        assert_eq!(ldrhhs.cond, ConditionCode::Cs);
+        assert!(matches!(
+            ldrhhs.instruction,
+            Instruction::Transfer(InstructionTransfer {
+                size: TransferSize::Halfword,
+                ..
+            })
+        ));
+
+        // The condition may come before or after the size suffix
+        let ldrbeq = parse("ldrbeq r0,[r1]");
+        assert_eq!(ldrbeq, parse("ldreqb r0,[r1]"));
+        assert_eq!(ldrbeq.cond, ConditionCode::Eq);
+        assert!(matches!(
+            ldrbeq.instruction,
+            Instruction::Transfer(InstructionTransfer {
+                size: TransferSize::Byte,
+                ..
+            })
+        ));
+
+        // Halt is still only andeq r0,r0,r0
+        assert_eq!(parse("andeq r0,r0,r0").instruction, Instruction::Halt);
+        assert!(matches!(
+            parse("andeq r0,r0,r1").instruction,
+            Instruction::Processing(_)
+        ));
+    }
+
+    #[test]
+    fn test_parse_s_suffix_and_spacing() {
+        let parse = |raw| {
+            parse_asm(raw, 1, 0, 0x10, Rc::new(SymbolTable::new()))
+                .expect("parse failed")
+                .0
+        };
+
+        // The s suffix may come before or after the condition
+        let addseq = parse("addseq r0,r1,#1");
+        assert_eq!(addseq, parse("addeqs r0,r1,#1"));
+        assert_eq!(addseq.cond, ConditionCode::Eq);
+        assert_eq!(
+            addseq.instruction,
+            Instruction::Processing(InstructionProcessing {
+                opcode: ProcessingOpcode::Add,
+                set_cond: true,
+                rn: 1,
+                rd: 0,
+                operand2: Operand2::ConstantShift(1, 0),
+            })
+        );
+        assert!(matches!(
+            parse("movs r0,#0").instruction,
+            Instruction::Processing(InstructionProcessing { set_cond: true, .. })
+        ));
+        assert!(matches!(
+            parse("mulnes r0,r1,r2").instruction,
+            Instruction::Multiply(InstructionMultiply { set_cond: true, .. })
+        ));
+        assert!(matches!(
+            parse("mov r0,#0").instruction,
+            Instruction::Processing(InstructionProcessing {
+                set_cond: false,
+                ..
+            })
+        ));
+
+        // Spaces are allowed around operands, commas and brackets
+        assert_eq!(parse("  add  r0 , r1 , #1 "), parse("add r0,r1,#1"));
+        assert_eq!(parse("ldr r0, [ r1 , #4 ]"), parse("ldr r0,[r1,#4]"));
+        assert_eq!(parse("stmfd r13 !, {r0}"), parse("stmfd r13!,{r0}"));
+    }
+
+    #[test]
+    fn test_parse_halt() {
+        assert_eq!(
+            parse_halt("andeq r0,r0,r0").expect("parse halt failed").1,
+            (
+                ConditionalInstruction {
+                    cond: ConditionCode::Eq,
+                    instruction: Instruction::Halt
+                },
+                None
+            )
+        );
+    }
+
+    #[test]
+    fn test_parse_software_interrupt() {
+        assert_eq!(
+            parse_software_interrupt("swine #0x10")
+                .expect("parse swi failed")
+                .1,
+            (
+                ConditionalInstruction {
+                    cond: ConditionCode::Ne,
+                    instruction: Instruction::SoftwareInterrupt(InstructionSoftwareInterrupt {
+                        comment: 0x10
+                    })
+                },
+                None
+            )
+        );
+        assert_eq!(
+            parse_software_interrupt("svc #2")
+                .expect("parse svc failed")
+                .1,
+            parse_software_interrupt("swi #2")
+                .expect("parse swi failed")
+                .1
+        );
+        assert!(parse_software_interrupt("swi #0x1000000").is_err());
+    }
+
+    #[test]
+    fn test_set_cond_round_trip() {
+        // The s suffix survives encoding, decoding and disassembly
+        for raw in [
+            "adds r0, r1, #1",
+            "subs r0, r1, r2",
+            "movs r0, #0",
+            "add r0, r1, #1",
+            "muls r0, r1, r2",
+            "mlas r0, r1, r2, r3",
+            "mul r0, r1, r2",
+        ] {
+            let (instr, _) = parse_asm(
+                raw,
+                1,
+                0,
+                0,
+                std::rc::Rc::new(std::collections::HashMap::new()),
+            )
+            .expect("parse failed");
+            let decoded = decode(&encode(instr).expect("encode failed")).expect("decode failed");
+            assert_eq!(decoded, instr);
+            assert_eq!(disassemble_instruction(&decoded, 0), raw);
+        }
+    }
+
+    #[test]
+    fn test_vfp_round_trip() {
+        // VFP instructions survive encoding, decoding and disassembly, with the encodings given
+        // by the ARM ARM
+        for &(raw, encoded) in [
+            ("vldr s0, [r0]", 0xed900a00u32),
+            ("vstr s3, [r1, #-8]", 0xed411a02),
+            ("vadd.f32 s0, s1, s2", 0xee300a81),
+            ("vsubne.f32 s31, s30, s29", 0x1e7ffa6e),
+            ("vmul.f32 s4, s5, s6", 0xee222a83),
+            ("vdiv.f32 s0, s0, s1", 0xee800a20),
+            ("vmov s0, r1", 0xee001a10),
+            ("vmov r2, s3", 0xee112a90),
+            ("vcvt.f32.s32 s0, s1", 0xeeb80ae0),
+            ("vcvt.s32.f32 s2, s0", 0xeebd1ac0),
+            ("vcmp.f32 s0, s1", 0xeeb40a60),
+            ("vmrs APSR_nzcv, fpscr", 0xeef1fa10),
+        ]
+        .iter()
+        {
+            let (instr, _) = parse_asm(
+                raw,
+                1,
+                0,
+                0,
+                std::rc::Rc::new(std::collections::HashMap::new()),
+            )
+            .expect("parse failed");
+            assert_eq!(encode(instr).expect("encode failed"), encoded, "{}", raw);
+            let decoded = decode(&encoded).expect("decode failed");
+            assert_eq!(decoded, instr);
+            assert_eq!(disassemble_instruction(&decoded, 0), raw);
+        }
+    }
+}
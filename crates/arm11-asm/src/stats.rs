@@ -0,0 +1,301 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+    path::Path,
+    rc::Rc,
+};
+
+use super::{
+    extract_labels_and_statements, include, lex, macros, normalize, parse, thumb, StatementKind,
+    TokenKind,
+};
+use arm11_isa::{constants::*, types::*};
+
+// Counts of the forms operand2 (or a transfer offset) takes across instructions.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Operand2Forms {
+    pub immediate: usize,
+    pub register: usize,
+    pub constant_shifted: usize,
+    pub register_shifted: usize,
+}
+
+// Aggregated usage statistics over one or more assembly source files.
+#[derive(Debug, Default)]
+pub struct Stats {
+    pub files: usize,
+    pub lines: usize,
+    pub unparsed_lines: usize,
+    pub labels: usize,
+    pub mnemonics: HashMap<String, usize>,
+    pub operand2: Operand2Forms,
+    // (file name, literal pool size in bytes) for every file with a literal pool
+    pub literal_pools: Vec<(String, usize)>,
+}
+
+impl Stats {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    // Adds the statistics for a single source file. Mnemonics are counted from the instructions
+    // the source is laid out into, so the words of data directives aren't counted. Lines which
+    // fail to parse still have their mnemonics counted, from the lexer, but aren't counted
+    // towards operand2 forms or literal pools.
+    pub fn add_source(&mut self, name: &str, source: &str) {
+        self.files += 1;
+
+        // A malformed include or macro stops the whole file from being laid out, while a
+        // malformed directive or label only leaves out its own line
+        let dir = Path::new(name).parent().unwrap_or_else(|| Path::new(""));
+        let raw = match include::expand_includes(&normalize::normalize(source), name, dir)
+            .map_err(ArmError::from)
+            .and_then(|expanded| macros::expand_macros(&expanded.raw))
+        {
+            Ok((raw, _)) => raw,
+            Err(_) => {
+                self.unparsed_lines += 1;
+                self.add_lexed_mnemonics(source);
+                return;
+            }
+        };
+        let (symbol_table, statements, diagnostics) =
+            extract_labels_and_statements(&raw, dir, &Default::default());
+        self.unparsed_lines += diagnostics.len();
+        self.labels += symbol_table.len();
+        let laid_out: HashSet<usize> = statements.iter().map(|s| s.line).collect();
+        for diagnostic in &diagnostics {
+            if !laid_out.contains(&diagnostic.line) {
+                self.add_lexed_mnemonics(&diagnostic.source_line);
+            }
+        }
+
+        let symbol_table = Rc::new(symbol_table);
+        let mut pool_size = 0;
+        for statement in &statements {
+            let instr = match &statement.kind {
+                StatementKind::Instruction(instr) => instr,
+                StatementKind::Directive(_) => continue,
+            };
+            self.lines += 1;
+            if let Some(mnemonic) = instr.split_whitespace().next() {
+                self.add_mnemonic(mnemonic);
+            }
+            // Where the literals go doesn't matter here, only how many there are, so each is
+            // placed where it can be reached
+            let parsed = if statement.thumb {
+                thumb::parse_thumb(
+                    instr,
+                    statement.line,
+                    statement.address,
+                    statement.address + THUMB_PIPELINE_OFFSET,
+                    symbol_table.clone(),
+                )
+            } else {
+                parse::parse_asm(
+                    instr,
+                    statement.line,
+                    statement.address,
+                    statement.address + PIPELINE_OFFSET,
+                    symbol_table.clone(),
+                )
+                .map(|(parsed, opt_data)| (vec![parsed], opt_data))
+            };
+            match parsed {
+                Ok((parsed, opt_data)) => {
+                    for instr in parsed {
+                        self.add_operand2(instr.instruction);
+                    }
+                    if opt_data.is_some() {
+                        pool_size += BYTES_IN_WORD;
+                    }
+                }
+                Err(_) => self.unparsed_lines += 1,
+            }
+        }
+
+        if pool_size > 0 {
+            self.literal_pools.push((name.to_owned(), pool_size));
+        }
+    }
+
+    fn add_mnemonic(&mut self, mnemonic: &str) {
+        *self
+            .mnemonics
+            .entry(mnemonic.to_ascii_lowercase())
+            .or_insert(0) += 1;
+    }
+
+    // Counts the mnemonic of each line of source the lexer finds one on, for lines which
+    // couldn't be laid out into instructions
+    fn add_lexed_mnemonics(&mut self, source: &str) {
+        let mut line = None;
+        for token in lex(source) {
+            if token.kind == TokenKind::Mnemonic && line != Some(token.line) {
+                line = Some(token.line);
+                self.add_mnemonic(&source[token.span]);
+            }
+        }
+    }
+
+    fn add_operand2(&mut self, instr: Instruction) {
+        // Transfer offsets are counted with the Operand2s they resemble
+        let shift = match instr {
+            Instruction::Processing(p) => match p.operand2 {
+                Operand2::ConstantShift(_, _) => None,
+                Operand2::ShiftedReg(_, shift) => Some(shift),
+            },
+            Instruction::Transfer(t) => match t.offset {
+                TransferOffset::Immediate(_) => None,
+                TransferOffset::ShiftedReg(_, shift) => Some(shift),
+            },
+            _ => return,
+        };
+
+        match shift {
+            None => self.operand2.immediate += 1,
+            // An amount of 0 is only no shift for lsl; it is rrx for ror, and 32 for lsr and asr
+            Some(Shift::ConstantShift(ShiftType::Lsl, 0)) => self.operand2.register += 1,
+            Some(Shift::ConstantShift(_, _)) => self.operand2.constant_shifted += 1,
+            Some(Shift::RegisterShift(_, _)) => self.operand2.register_shifted += 1,
+        }
+    }
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let plural = |n: usize, word: &str| match n {
+            1 => format!("1 {}", word),
+            n => format!("{} {}s", n, word),
+        };
+        writeln!(
+            f,
+            "{}, {} ({} unparsed), {}",
+            plural(self.files, "file"),
+            plural(self.lines, "instruction"),
+            self.unparsed_lines,
+            plural(self.labels, "label")
+        )?;
+
+        writeln!(f, "Mnemonics:")?;
+        let mut mnemonics: Vec<_> = self.mnemonics.iter().collect();
+        mnemonics.sort_by(|(a_name, a), (b_name, b)| b.cmp(a).then(a_name.cmp(b_name)));
+        for (mnemonic, count) in mnemonics {
+            writeln!(f, "  {: <8}{: >8}", mnemonic, count)?;
+        }
+
+        let Operand2Forms {
+            immediate,
+            register,
+            constant_shifted,
+            register_shifted,
+        } = self.operand2;
+        let total = (immediate + register + constant_shifted + register_shifted).max(1);
+        let percent = |n: usize| 100.0 * n as f64 / total as f64;
+        writeln!(f, "Operand2 forms:")?;
+        writeln!(f, "  immediate         {: >5.1}%", percent(immediate))?;
+        writeln!(f, "  register          {: >5.1}%", percent(register))?;
+        writeln!(
+            f,
+            "  constant shifted  {: >5.1}%",
+            percent(constant_shifted)
+        )?;
+        writeln!(
+            f,
+            "  register shifted  {: >5.1}%",
+            percent(register_shifted)
+        )?;
+
+        writeln!(f, "Largest literal pools:")?;
+        let mut pools = self.literal_pools.clone();
+        pools.sort_by(|(a_name, a), (b_name, b)| b.cmp(a).then(a_name.cmp(b_name)));
+        for (name, size) in pools.iter().take(10) {
+            writeln!(f, "  {: >6} bytes  {}", size, name)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_add_source() {
+        let mut stats = Stats::new();
+        stats.add_source(
+            "a.s",
+            "mov r1,#1\nloop:\nldr r2,=0x20200000\nadd r1,r1,r2, lsl #2\nbne loop\nfoo r1\n",
+        );
+
+        assert_eq!(stats.labels, 1);
+        assert_eq!(stats.lines, 5);
+        assert_eq!(stats.unparsed_lines, 1);
+        assert_eq!(stats.mnemonics["mov"], 1);
+        assert_eq!(stats.mnemonics["foo"], 1);
+        assert_eq!(
+            stats.operand2,
+            Operand2Forms {
+                immediate: 2,
+                register: 0,
+                constant_shifted: 1,
+                register_shifted: 0,
+            }
+        );
+        assert_eq!(stats.literal_pools, vec![("a.s".to_owned(), 4)]);
+        assert!(stats
+            .to_string()
+            .starts_with("1 file, 5 instructions (1 unparsed), 1 label\n"));
+
+        // Mnemonics are counted whatever their case, including on lines which don't parse
+        stats.add_source("case.s", "MOV r0,r1\nmov r0,r1\nFOO r1\n");
+        stats.add_source("broken.s", ".endm\nMov r0,#1\n");
+        assert_eq!(stats.mnemonics["mov"], 4);
+        assert_eq!(stats.mnemonics["foo"], 2);
+        assert!(!stats.mnemonics.contains_key("MOV"));
+        assert!(!stats.mnemonics.contains_key("FOO"));
+    }
+
+    #[test]
+    fn test_add_source_shifts() {
+        let mut stats = Stats::new();
+        stats.add_source(
+            "shifts.s",
+            "mov r0,r1\nmov r0,r1,lsl #0\nmov r0,r1,rrx\nmov r0,r1,lsr #32\nmov r0,r1,asr #32\n\
+             mov r0,r1,ror r2\n",
+        );
+        assert_eq!(
+            stats.operand2,
+            Operand2Forms {
+                immediate: 0,
+                register: 2,
+                constant_shifted: 3,
+                register_shifted: 1,
+            }
+        );
+    }
+
+    #[test]
+    fn test_add_source_data() {
+        let mut stats = Stats::new();
+        stats.add_source(
+            "data.s",
+            "start:\nmov r0,#4\nb start\ntable:\n.word start\nmsg:\n.ascii \"Hello, ARM!\\0\"\n\
+             .skip 4\n.equ size, 4\n",
+        );
+
+        let mut mnemonics: Vec<_> = stats.mnemonics.into_iter().collect();
+        mnemonics.sort();
+        assert_eq!(mnemonics, vec![("b".to_owned(), 1), ("mov".to_owned(), 1)]);
+        assert_eq!(stats.lines, 2);
+        // .equ isn't one of the assembler's directives, so only its line is left out
+        assert_eq!(stats.unparsed_lines, 1);
+
+        // A file which can't be laid out still has its mnemonics counted
+        let mut stats = Stats::new();
+        stats.add_source("broken.s", ".endm\nmov r0,#1\n.word start\n");
+        assert_eq!(stats.mnemonics.len(), 1);
+        assert_eq!(stats.mnemonics["mov"], 1);
+    }
+}
@@ -0,0 +1,579 @@
+use std::rc::Rc;
+
+use nom::{
+    branch::alt,
+    bytes::complete::tag,
+    character::complete::{char, space1},
+    combinator::{complete, map_opt, opt, success, value},
+    error::context,
+    sequence::{pair, preceded, terminated},
+};
+
+use super::{
+    diagnostic::{Diagnostic, DiagnosticCode},
+    expression,
+    parse::{self, comma_space, parse_condition_code, parse_reg, truncated},
+};
+use arm11_isa::{
+    address::{Address, SymbolTable},
+    constants::*,
+    parse::*,
+    types::*,
+};
+
+// The stack pointer and link register, which some Thumb instructions use implicitly
+const SP: u8 = 13;
+const LR: u8 = 14;
+
+// The size in bytes of a Thumb instruction. Each is a halfword, except bl which is a pair.
+pub fn size(instr: &str) -> usize {
+    match instr.split_whitespace().next() {
+        Some("bl") => 4,
+        _ => 2,
+    }
+}
+
+// Parses a Thumb instruction into the instructions it is encoded as; one, or two for bl.
+//
+// Thumb instructions are written as they are in ARM state, and parsed by the ARM parser into the
+// ARM instruction which does the same thing. eg: adds r0,r0,#1 or push {r4,r14}. Only branches,
+// whose offsets are in halfwords, and ldr =, which always loads a literal after it, are parsed
+// differently.
+//
+// The second field in the return tuple may contain data for a literal pool, as for parse_asm.
+//
+pub fn parse_thumb(
+    raw: &str,
+    line: usize,
+    current_address: usize,
+    next_free_address: usize,
+    symbol_table: Rc<SymbolTable>,
+) -> std::result::Result<(Vec<ConditionalInstruction>, Option<u32>), Diagnostic> {
+    match complete(parse_branch(current_address, symbol_table.clone()))(raw.trim()) {
+        Ok((rest, branches)) if rest.trim().is_empty() => return Ok((branches, None)),
+        Err(e @ nom::Err::Failure(_)) => return Err(Diagnostic::from_nom(line, raw, e)),
+        _ => (),
+    }
+
+    match complete(parse_literal_load(current_address, symbol_table.clone()))(raw.trim()) {
+        Ok((rest, (rd, value))) if rest.trim().is_empty() => {
+            // The literal is loaded relative to the PC rounded down to a word, and can only be
+            // after it
+            let pc = (current_address + THUMB_PIPELINE_OFFSET) & !(BYTES_IN_WORD - 1);
+            let offset = next_free_address as i64 - pc as i64;
+            if !(0..=1020).contains(&offset) {
+                return Err(Diagnostic::for_line(
+                    DiagnosticCode::LiteralOutOfRange,
+                    line,
+                    raw,
+                    format!(
+                        "literal pool is {} bytes {} this ldr, out of range of its offset; in \
+                         Thumb state add a .ltorg within 1KiB after it",
+                        offset.abs(),
+                        if offset < 0 { "before" } else { "after" }
+                    ),
+                ));
+            }
+            let instruction = Instruction::Transfer(InstructionTransfer {
+                is_preindexed: true,
+                up_bit: true,
+                writeback: false,
+                load: true,
+                size: TransferSize::Word,
+                rn: PC as u8,
+                rd,
+                offset: TransferOffset::Immediate(offset as u16),
+            });
+            let cond = ConditionCode::Al;
+            Ok((
+                vec![ConditionalInstruction { cond, instruction }],
+                Some(value),
+            ))
+        }
+        // Everything else is written as it is in ARM state
+        _ => parse::parse_asm(raw, line, current_address, next_free_address, symbol_table)
+            .map(|(instr, data)| (vec![instr], data)),
+    }
+}
+
+// Returns a parser for a Thumb branch, i.e. b{cond} <target> or bl <target>. Only b has a
+// condition, and b<cond> only reaches 256 bytes, b 2KiB and bl 4MiB either way.
+fn parse_branch(
+    current_address: usize,
+    symbol_table: Rc<SymbolTable>,
+) -> impl Fn(&str) -> NomResult<&str, Vec<ConditionalInstruction>> {
+    move |input: &str| {
+        let (target, (link, opt_cond)) = context(
+            "parsing branch instruction",
+            preceded(
+                char('b'),
+                alt((
+                    // Try a plain branch first, so that eg: ble isn't read as bl
+                    pair(
+                        success(false),
+                        terminated(opt(parse_condition_code), space1),
+                    ),
+                    pair(value(true, char('l')), terminated(success(None), space1)),
+                )),
+            ),
+        )(input)?;
+        let (rest, addr) = context(
+            "parsing branch instruction",
+            context(
+                "parsing branch target",
+                map_opt(expression::parse_expression, |e| {
+                    e.evaluate(&symbol_table, Address(current_address as u32))
+                        .ok()
+                        .map(Address)
+                }),
+            ),
+        )(target)?;
+
+        let pc = Address(current_address as u32).wrapping_add(THUMB_PIPELINE_OFFSET as u32);
+        let offset = addr.offset_from(pc);
+        let branch = |cond, kind, offset| ConditionalInstruction {
+            cond,
+            instruction: Instruction::ThumbBranch(InstructionThumbBranch { kind, offset }),
+        };
+        let branches = match opt_cond.unwrap_or(ConditionCode::Al) {
+            _ if link => {
+                // The suffix adds the bottom 12 bits, so the prefix adds the rest
+                let offset = branch_offset(target, offset, 22)?;
+                let low = offset & 0xfff;
+                vec![
+                    branch(ConditionCode::Al, ThumbBranchKind::LinkPrefix, offset - low),
+                    branch(ConditionCode::Al, ThumbBranchKind::LinkSuffix, low),
+                ]
+            }
+            ConditionCode::Al => vec![branch(
+                ConditionCode::Al,
+                ThumbBranchKind::Branch,
+                branch_offset(target, offset, 11)?,
+            )],
+            cond => vec![branch(
+                cond,
+                ThumbBranchKind::Branch,
+                branch_offset(target, offset, 8)?,
+            )],
+        };
+        Ok((rest, branches))
+    }
+}
+
+// Checks that an offset in bytes from the PC to a branch target is a whole number of halfwords,
+// which fits in the given number of bits
+fn branch_offset(
+    target: &str,
+    offset: i32,
+    bits: u32,
+) -> std::result::Result<i32, nom::Err<ArmNomError<&str>>> {
+    let halfwords = offset >> 1;
+    let unused = 32 - bits;
+    let encoded = ((halfwords as u32) << unused) as i32 >> unused;
+    if encoded == halfwords && offset & 0x1 == 0 {
+        return Ok(offset);
+    }
+    Err(truncated(
+        target,
+        "parsing branch target",
+        "branch offset",
+        i64::from(offset),
+        i64::from(encoded) << 1,
+    ))
+}
+
+// Returns a parser for ldr Rd,=<expression>, which gives the register and the value to load
+fn parse_literal_load(
+    current_address: usize,
+    symbol_table: Rc<SymbolTable>,
+) -> impl Fn(&str) -> NomResult<&str, (u8, u32)> {
+    move |input: &str| {
+        let (rest, rd) = preceded(terminated(tag("ldr"), space1), parse_reg)(input)?;
+        let (rest, value) = context(
+            "parsing immediate transfer",
+            preceded(
+                pair(comma_space, char('=')),
+                map_opt(expression::parse_expression, |e| {
+                    e.evaluate(&symbol_table, Address(current_address as u32))
+                        .ok()
+                }),
+            ),
+        )(rest)?;
+        Ok((rest, (rd, value)))
+    }
+}
+
+// Encodes an instruction as a Thumb instruction, if it has a Thumb encoding. Each ARM instruction
+// is encoded as the Thumb instruction which decodes to it, so only those ARM instructions which
+// do exactly what a Thumb instruction does can be encoded. Most use only r0 to r7, and set the
+// flags.
+pub fn encode(instr: ConditionalInstruction) -> Option<u16> {
+    match instr.instruction {
+        Instruction::Halt => Some(0),
+        Instruction::ThumbBranch(b) => encode_branch(instr.cond, b),
+        // Only branches are conditional
+        _ if instr.cond != ConditionCode::Al => None,
+        Instruction::Processing(p) => encode_processing(p),
+        Instruction::Multiply(m) => encode_multiply(m),
+        Instruction::Transfer(t) => encode_transfer(t),
+        Instruction::BlockTransfer(b) => encode_block_transfer(b),
+        Instruction::BranchExchange(bx) => Some(0x4700 | u16::from(bx.rm) << 3),
+        Instruction::SoftwareInterrupt(swi) if swi.comment <= 0xff => {
+            Some(0xdf00 | swi.comment as u16)
+        }
+        _ => None,
+    }
+}
+
+fn encode_processing(instr: InstructionProcessing) -> Option<u16> {
+    use ProcessingOpcode::*;
+
+    let InstructionProcessing {
+        opcode,
+        set_cond,
+        rn,
+        rd,
+        operand2,
+    } = instr;
+    let low = |r: u8| r < 8;
+    let (imm, rm) = match operand2 {
+        Operand2::ConstantShift(imm, rotate) => (
+            Some(u32::from(imm).rotate_right(2 * u32::from(rotate))),
+            None,
+        ),
+        Operand2::ShiftedReg(rm, Shift::ConstantShift(ShiftType::Lsl, 0)) => (None, Some(rm)),
+        Operand2::ShiftedReg(_, _) => (None, None),
+    };
+    let sub = u16::from(opcode == Sub);
+    let (rn, rd) = (u16::from(rn), u16::from(rd));
+
+    match (opcode, set_cond, operand2) {
+        // 1. lsl, lsr or asr Rd,Rs,#<imm5>
+        (Mov, true, Operand2::ShiftedReg(rs, Shift::ConstantShift(shift_type, amount)))
+            if shift_type != ShiftType::Ror && amount < 32 && low(rs) && rd < 8 =>
+        {
+            Some((shift_type as u16) << 11 | u16::from(amount) << 6 | u16::from(rs) << 3 | rd)
+        }
+        // 4. lsl, lsr, asr or ror Rd,Rs
+        (Mov, true, Operand2::ShiftedReg(rm, Shift::RegisterShift(shift_type, rs)))
+            if u16::from(rm) == rd && low(rs) && rd < 8 =>
+        {
+            let op = match shift_type {
+                ShiftType::Lsl => 0x2,
+                ShiftType::Lsr => 0x3,
+                ShiftType::Asr => 0x4,
+                ShiftType::Ror => 0x7,
+            };
+            Some(alu(op, u16::from(rs), rd))
+        }
+        // 3. mov Rd,#<imm8>
+        (Mov, true, _) => imm
+            .filter(|&imm| imm <= 0xff && rd < 8)
+            .map(|imm| 0x2000 | rd << 8 | imm as u16),
+        // 5. mov Rd,Rs on any registers
+        (Mov, false, _) => rm.map(|rm| hi_register(0x2, rd, u16::from(rm))),
+        (Mvn, true, _) => rm
+            .filter(|&rm| low(rm) && rd < 8)
+            .map(|rm| alu(0xf, u16::from(rm), rd)),
+        (Cmp, _, _) => match (imm, rm) {
+            (Some(imm), _) if imm <= 0xff && rn < 8 => Some(0x2800 | rn << 8 | imm as u16),
+            (_, Some(rm)) if low(rm) && rn < 8 => Some(alu(0xa, u16::from(rm), rn)),
+            (_, Some(rm)) => Some(hi_register(0x1, rn, u16::from(rm))),
+            _ => None,
+        },
+        (Tst | Cmn, _, _) => rm.filter(|&rm| low(rm) && rn < 8).map(|rm| {
+            let op = if opcode == Tst { 0x8 } else { 0xb };
+            alu(op, u16::from(rm), rn)
+        }),
+        (Add | Sub, true, _) => match (imm, rm) {
+            // 2. add or sub Rd,Rs,Rn or Rd,Rs,#<imm3>
+            (_, Some(rm)) if low(rm) && rn < 8 && rd < 8 => {
+                Some(0x1800 | sub << 9 | u16::from(rm) << 6 | rn << 3 | rd)
+            }
+            (Some(imm), _) if imm <= 0x7 && rn < 8 && rd < 8 => {
+                Some(0x1c00 | sub << 9 | (imm as u16) << 6 | rn << 3 | rd)
+            }
+            // 3. add or sub Rd,#<imm8>
+            (Some(imm), _) if imm <= 0xff && rn == rd && rd < 8 => {
+                Some(0x3000 | sub << 11 | rd << 8 | imm as u16)
+            }
+            _ => None,
+        },
+        (Add | Sub, false, _) => match (imm, rm) {
+            // 13. add or sub sp,#<imm7 * 4>
+            (Some(imm), _) if imm.is_multiple_of(4) && imm <= 508 && rn == 13 && rd == 13 => {
+                Some(0xb000 | sub << 7 | (imm / 4) as u16)
+            }
+            // 12. add Rd,pc or sp,#<imm8 * 4>
+            (Some(imm), _) if imm.is_multiple_of(4) && imm <= 1020 && sub == 0 && rd < 8 => {
+                let sp = match rn {
+                    13 => 1,
+                    15 => 0,
+                    _ => return None,
+                };
+                Some(0xa000 | sp << 11 | rd << 8 | (imm / 4) as u16)
+            }
+            // 5. add Rd,Rs on any registers
+            (_, Some(rm)) if sub == 0 && rn == rd => Some(hi_register(0x0, rd, u16::from(rm))),
+            _ => None,
+        },
+        // neg Rd,Rs is rsbs Rd,Rs,#0
+        (Rsb, true, _) if imm == Some(0) && rn < 8 && rd < 8 => Some(alu(0x9, rn, rd)),
+        (And | Eor | Adc | Sbc | Orr | Bic, true, _) if rn == rd && rd < 8 => {
+            let op = match opcode {
+                And => 0x0,
+                Eor => 0x1,
+                Adc => 0x5,
+                Sbc => 0x6,
+                Orr => 0xc,
+                _ => 0xe,
+            };
+            rm.filter(|&rm| low(rm))
+                .map(|rm| alu(op, u16::from(rm), rd))
+        }
+        _ => None,
+    }
+}
+
+// muls Rd,Rm,Rs, where Rd is one of Rm or Rs
+fn encode_multiply(instr: InstructionMultiply) -> Option<u16> {
+    let InstructionMultiply {
+        accumulate,
+        set_cond,
+        rd,
+        rs,
+        rm,
+        ..
+    } = instr;
+    if accumulate || !set_cond || rd > 7 || rs > 7 || rm > 7 {
+        return None;
+    }
+    if rd == rs {
+        Some(alu(0xd, u16::from(rm), u16::from(rd)))
+    } else if rd == rm {
+        Some(alu(0xd, u16::from(rs), u16::from(rd)))
+    } else {
+        None
+    }
+}
+
+fn encode_transfer(instr: InstructionTransfer) -> Option<u16> {
+    let InstructionTransfer {
+        is_preindexed,
+        up_bit,
+        writeback,
+        load,
+        size,
+        rn,
+        rd,
+        offset,
+    } = instr;
+    if !is_preindexed || !up_bit || writeback || rd > 7 {
+        return None;
+    }
+    let l = u16::from(load) << 11;
+    let (rn, rd) = (u16::from(rn), u16::from(rd));
+
+    match offset {
+        TransferOffset::Immediate(imm) => match size {
+            // 6. ldr Rd,[pc,#<imm8 * 4>]
+            TransferSize::Word
+                if rn == PC as u16 && load && imm.is_multiple_of(4) && imm <= 1020 =>
+            {
+                Some(0x4800 | rd << 8 | (imm / 4))
+            }
+            // 11. ldr or str Rd,[sp,#<imm8 * 4>]
+            TransferSize::Word if rn == u16::from(SP) && imm.is_multiple_of(4) && imm <= 1020 => {
+                Some(0x9000 | l | rd << 8 | (imm / 4))
+            }
+            // 9 and 10. Immediate offsets, scaled by the transfer size
+            _ if rn > 7 => None,
+            TransferSize::Word if imm.is_multiple_of(4) && imm <= 124 => {
+                Some(0x6000 | l | (imm / 4) << 6 | rn << 3 | rd)
+            }
+            TransferSize::Byte if imm <= 31 => Some(0x7000 | l | imm << 6 | rn << 3 | rd),
+            TransferSize::Halfword if imm.is_multiple_of(2) && imm <= 62 => {
+                Some(0x8000 | l | (imm / 2) << 6 | rn << 3 | rd)
+            }
+            _ => None,
+        },
+        // 7 and 8. Register offsets
+        TransferOffset::ShiftedReg(rm, Shift::ConstantShift(ShiftType::Lsl, 0))
+            if rm < 8 && rn < 8 =>
+        {
+            let op = match (load, size) {
+                (false, TransferSize::Word) => 0x5000,
+                (false, TransferSize::Halfword) => 0x5200,
+                (false, TransferSize::Byte) => 0x5400,
+                (true, TransferSize::SignedByte) => 0x5600,
+                (true, TransferSize::Word) => 0x5800,
+                (true, TransferSize::Halfword) => 0x5a00,
+                (true, TransferSize::Byte) => 0x5c00,
+                (true, TransferSize::SignedHalfword) => 0x5e00,
+                _ => return None,
+            };
+            Some(op | u16::from(rm) << 6 | rn << 3 | rd)
+        }
+        _ => None,
+    }
+}
+
+fn encode_block_transfer(instr: InstructionBlockTransfer) -> Option<u16> {
+    let InstructionBlockTransfer {
+        is_preindexed,
+        up_bit,
+        writeback,
+        load,
+        rn,
+        register_list,
+    } = instr;
+    let (low, high) = (register_list & 0xff, register_list & !0xff);
+    if !writeback {
+        return None;
+    }
+
+    match (rn, load, is_preindexed, up_bit) {
+        // 14. push {<rlist>{,lr}} and pop {<rlist>{,pc}}
+        (SP, false, true, false) if high & !(1 << LR) == 0 => {
+            Some(0xb400 | u16::from(high != 0) << 8 | low)
+        }
+        (SP, true, false, true) if high & !(1 << PC) == 0 => {
+            Some(0xbc00 | u16::from(high != 0) << 8 | low)
+        }
+        // 15. stmia or ldmia Rb!,{<rlist>}
+        (_, _, false, true) if rn < 8 && high == 0 => {
+            Some(0xc000 | u16::from(load) << 11 | u16::from(rn) << 8 | low)
+        }
+        _ => None,
+    }
+}
+
+fn encode_branch(cond: ConditionCode, instr: InstructionThumbBranch) -> Option<u16> {
+    let InstructionThumbBranch { kind, offset } = instr;
+    match (kind, cond) {
+        (ThumbBranchKind::Branch, ConditionCode::Al) => Some(0xe000 | (offset >> 1) as u16 & 0x7ff),
+        (ThumbBranchKind::Branch, _) => {
+            Some(0xd000 | (cond as u16) << 8 | (offset >> 1) as u16 & 0xff)
+        }
+        (ThumbBranchKind::LinkPrefix, ConditionCode::Al) => {
+            Some(0xf000 | (offset >> 12) as u16 & 0x7ff)
+        }
+        (ThumbBranchKind::LinkSuffix, ConditionCode::Al) => {
+            Some(0xf800 | (offset >> 1) as u16 & 0x7ff)
+        }
+        _ => None,
+    }
+}
+
+// 4. An ALU operation on low registers, i.e. <op> Rd,Rs
+fn alu(op: u16, rs: u16, rd: u16) -> u16 {
+    0x4000 | op << 6 | rs << 3 | rd
+}
+
+// 5. An add, cmp or mov on any registers, whose top bits are in the H1 and H2 bits
+fn hi_register(op: u16, rd: u16, rs: u16) -> u16 {
+    0x4400 | op << 8 | (rd & 0x8) << 4 | rs << 3 | rd & 0x7
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arm11_isa::thumb;
+
+    #[test]
+    fn test_thumb_round_trip() {
+        // Thumb instructions survive encoding and decoding, with the encodings given by the
+        // ARM ARM
+        for &(raw, encoded) in [
+            ("movs r0, #42", 0x202a),
+            ("movs r1, r2, lsl #3", 0x00d1),
+            ("movs r3, r3, ror r4", 0x41e3),
+            ("mov r8, r1", 0x4688),
+            ("mvns r0, r1", 0x43c8),
+            ("cmp r2, #255", 0x2aff),
+            ("cmp r10, r1", 0x458a),
+            ("tst r0, r1", 0x4208),
+            ("adds r0, r1, r2", 0x1888),
+            ("subs r0, r1, #7", 0x1fc8),
+            ("adds r5, r5, #200", 0x35c8),
+            ("add r13, r13, #16", 0xb004),
+            ("sub r13, r13, #508", 0xb0ff),
+            ("add r0, r13, #8", 0xa802),
+            ("add r8, r8, r1", 0x4488),
+            ("rsbs r0, r1, #0", 0x4248),
+            ("bics r2, r2, r3", 0x439a),
+            ("muls r0, r1, r0", 0x4348),
+            ("ldr r2, [r1, #8]", 0x688a),
+            ("strb r0, [r1, #31]", 0x77c8),
+            ("ldrh r0, [r1, #2]", 0x8848),
+            ("ldrsb r0, [r1, r2]", 0x5688),
+            ("str r0, [r13, #4]", 0x9001),
+            ("push {r4, r14}", 0xb510),
+            ("pop {r4, r15}", 0xbd10),
+            ("ldmia r0!, {r1-r3}", 0xc80e),
+            ("bx r14", 0x4770),
+            ("swi #0x11", 0xdf11),
+        ]
+        .iter()
+        {
+            let (parsed, _) =
+                parse_thumb(raw, 1, 0, 0, Rc::new(SymbolTable::new())).expect("parse failed");
+            assert_eq!(encode(parsed[0]), Some(encoded), "{}", raw);
+            assert_eq!(
+                thumb::decode(encoded).expect("decode failed"),
+                parsed[0],
+                "{}",
+                raw
+            );
+        }
+
+        // Instructions which Thumb can't do have no encoding
+        for raw in [
+            "add r0, r1, #1",
+            "movs r8, #1",
+            "addeq r0, r0, #1",
+            "ldr r0, [r1, #-4]",
+            "mrs r0, cpsr",
+            "push {r4, r8}",
+        ] {
+            let (parsed, _) =
+                parse_thumb(raw, 1, 0, 0, Rc::new(SymbolTable::new())).expect("parse failed");
+            assert_eq!(encode(parsed[0]), None, "{}", raw);
+        }
+    }
+
+    #[test]
+    fn test_parse_thumb_branch() {
+        let symbol_table = Rc::new(
+            vec![(String::from("loop"), Address(0x100))]
+                .into_iter()
+                .collect::<SymbolTable>(),
+        );
+        let encoded = |raw, address| {
+            parse_thumb(raw, 1, address, 0, symbol_table.clone()).map(|(parsed, _)| {
+                parsed
+                    .into_iter()
+                    .map(|instr| encode(instr).expect("encode failed"))
+                    .collect::<Vec<_>>()
+            })
+        };
+
+        // Offsets are in halfwords from the address of the branch plus 4
+        assert_eq!(encoded("bne loop", 0x104).unwrap(), vec![0xd1fc]);
+        assert_eq!(encoded("b loop", 0x100).unwrap(), vec![0xe7fe]);
+        assert_eq!(encoded("bl loop", 0x2000).unwrap(), vec![0xf7fe, 0xf87e]);
+        assert_eq!(
+            encoded("beq loop", 0x300).unwrap_err().message,
+            "branch offset -516 can't be encoded, it would be truncated to -4 while parsing \
+             branch target"
+        );
+        assert_eq!(size("bl loop"), 4);
+        assert_eq!(size("ble loop"), 2);
+
+        // ldr = loads a literal after it, relative to the PC rounded down to a word
+        let (parsed, data) =
+            parse_thumb("ldr r1,=0x12345678", 1, 0x102, 0x110, symbol_table).expect("parse failed");
+        assert_eq!(encode(parsed[0]), Some(0x4903));
+        assert_eq!(data, Some(0x12345678));
+    }
+}
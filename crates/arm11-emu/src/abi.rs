@@ -0,0 +1,124 @@
+use std::{
+    io::{self, Write},
+    str::FromStr,
+};
+
+use super::{registers::Register, state::EmulatorState};
+
+// A calling convention, which gives the registers names and groups them by how they are used
+// across calls
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Abi {
+    Aapcs,
+}
+
+impl FromStr for Abi {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "aapcs" => Ok(Abi::Aapcs),
+            _ => Err(format!("Invalid ABI '{}', expected aapcs", s)),
+        }
+    }
+}
+
+// The groups of registers in the AAPCS, each with the registers in it and their names
+const AAPCS_GROUPS: [(&str, &[(&str, Register)]); 3] = [
+    (
+        "Arguments and results",
+        &[
+            ("a1", Register::R0),
+            ("a2", Register::R1),
+            ("a3", Register::R2),
+            ("a4", Register::R3),
+        ],
+    ),
+    (
+        "Callee-saved",
+        &[
+            ("v1", Register::R4),
+            ("v2", Register::R5),
+            ("v3", Register::R6),
+            ("v4", Register::R7),
+            ("v5", Register::R8),
+            ("v6", Register::R9),
+            ("v7", Register::R10),
+            ("v8", Register::R11),
+        ],
+    ),
+    (
+        "Special",
+        &[
+            ("ip", Register::R12),
+            ("sp", Register::Sp),
+            ("lr", Register::Lr),
+            ("pc", Register::Pc),
+        ],
+    ),
+];
+
+impl EmulatorState {
+    // Writes every register grouped and named as in the AAPCS, eg:
+    //
+    // Registers (AAPCS):
+    // Arguments and results:
+    //   a1 (r0) :          1 (0x00000001)
+    //   ...
+    // Special:
+    //   ip (r12):          0 (0x00000000)
+    //   ...
+    // CPSR      : 1610612736 (0x60000000)
+    //
+    pub(super) fn write_aapcs_registers(&self, out: &mut dyn Write) -> io::Result<()> {
+        writeln!(out, "Registers (AAPCS):")?;
+        for (group, registers) in AAPCS_GROUPS.iter() {
+            writeln!(out, "{}:", group)?;
+            for &(name, reg) in registers.iter() {
+                let contents = self.read_reg(reg);
+                writeln!(
+                    out,
+                    "  {: <8}: {: >10} (0x{:0>8x})",
+                    format!("{} (r{})", name, reg as usize),
+                    contents as i32,
+                    contents
+                )?;
+            }
+        }
+        let cpsr = self.read_reg(Register::Cpsr);
+        writeln!(out, "CPSR      : {: >10} (0x{:0>8x})", cpsr as i32, cpsr)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_aapcs_registers() {
+        // mov r0,#1; mov r11,#2; mov sp,#0x100; andeq r0,r0,r0
+        let bytes = [0xe3a00001u32, 0xe3a0b002, 0xe3a0dc01, 0]
+            .iter()
+            .flat_map(|w| w.to_le_bytes())
+            .collect();
+        let mut emulator = EmulatorState::with_memory(bytes).expect("load failed");
+        emulator.run().expect("run failed");
+        emulator.abi = Some("aapcs".parse().expect("parse failed"));
+
+        let mut out = Vec::new();
+        emulator.write_registers(&mut out).expect("write failed");
+        let out = String::from_utf8(out).expect("invalid utf8");
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(lines.len(), 21);
+        assert_eq!(lines[0], "Registers (AAPCS):");
+        assert_eq!(lines[1], "Arguments and results:");
+        assert_eq!(lines[2], "  a1 (r0) :          1 (0x00000001)");
+        assert_eq!(lines[6], "Callee-saved:");
+        assert_eq!(lines[14], "  v8 (r11):          2 (0x00000002)");
+        assert_eq!(lines[15], "Special:");
+        assert_eq!(lines[17], "  sp (r13):        256 (0x00000100)");
+        assert_eq!(lines[20], "CPSR      :          0 (0x00000000)");
+
+        assert!("eabi".parse::<Abi>().is_err());
+    }
+}
@@ -0,0 +1,67 @@
+use super::parse_number;
+
+// The main ID register of the ARM1176JZF-S, as in the Raspberry Pi
+pub const DEFAULT_CPU_ID: u32 = 0x410fb767;
+
+// A minimal system control coprocessor (CP15), enough for the register accesses commonly made by
+// startup code. Only the ID and control registers hold values; every other register reads as
+// zero, and writes to them (eg: cache and TLB maintenance) have no effect.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Cp15 {
+    cpu_id: u32,
+    control: u32,
+}
+
+impl Cp15 {
+    pub fn new(cpu_id: u32) -> Self {
+        Cp15 { cpu_id, control: 0 }
+    }
+
+    // Reads the register selected by the crn, opcode1, crm and opcode2 fields of an mrc
+    pub fn read(&self, crn: u8, opcode1: u8, crm: u8, opcode2: u8) -> u32 {
+        match (crn, opcode1, crm, opcode2) {
+            (0, 0, 0, 0) => self.cpu_id,
+            (1, 0, 0, 0) => self.control,
+            _ => 0,
+        }
+    }
+
+    // Writes the register selected by the crn, opcode1, crm and opcode2 fields of an mcr
+    pub fn write(&mut self, crn: u8, opcode1: u8, crm: u8, opcode2: u8, val: u32) {
+        if let (1, 0, 0, 0) = (crn, opcode1, crm, opcode2) {
+            self.control = val;
+        }
+    }
+}
+
+impl Default for Cp15 {
+    fn default() -> Self {
+        Self::new(DEFAULT_CPU_ID)
+    }
+}
+
+// Parses a CPU ID given as a decimal or 0x prefixed hexadecimal number
+pub fn parse_cpu_id(s: &str) -> std::result::Result<u32, String> {
+    parse_number(s).map_err(|_| format!("Invalid CPU ID '{}'", s))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cp15_registers() {
+        let mut cp15 = Cp15::default();
+        assert_eq!(cp15.read(0, 0, 0, 0), DEFAULT_CPU_ID);
+
+        cp15.write(1, 0, 0, 0, 0x1005);
+        assert_eq!(cp15.read(1, 0, 0, 0), 0x1005);
+
+        // Cache maintenance is a no-op
+        cp15.write(7, 0, 5, 0, 0);
+        assert_eq!(cp15.read(7, 0, 5, 0), 0);
+
+        assert_eq!(parse_cpu_id("0x410fb767"), Ok(0x410fb767));
+        assert!(parse_cpu_id("arm11").is_err());
+    }
+}
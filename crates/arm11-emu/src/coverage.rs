@@ -0,0 +1,153 @@
+use std::{
+    collections::HashMap,
+    io::{self, Write},
+};
+
+use arm11_isa::{
+    address::Address,
+    listing::{Listing, ListingData, ListingLine},
+};
+
+// The addresses of the instructions a run reached, and how many times each was reached, to show
+// which paths through the source were tested. Instructions skipped by their condition are still
+// reached, as they are for the profile.
+#[derive(Debug, Default, Clone)]
+pub struct Coverage {
+    reached: HashMap<Address, u64>,
+}
+
+impl Coverage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Records an instruction reaching the execute stage
+    pub fn record(&mut self, address: Address) {
+        *self.reached.entry(address).or_insert(0) += 1;
+    }
+
+    // The number of times the instruction at an address was reached
+    pub fn times(&self, address: Address) -> u64 {
+        self.reached.get(&address).copied().unwrap_or(0)
+    }
+
+    // Writes the assembler's listing of the program with the number of times each instruction
+    // was reached before it, or ##### if it never was, followed by a summary and the lines of
+    // the instructions which were never reached, eg:
+    //
+    //       1    1 00000000 e3a00001     mov r0,#1
+    //       1    2 00000004 e3500000     cmp r0,#0
+    //   #####    3 00000008 03a01001     moveq r1,#1
+    //                                    ...
+    // Coverage: 2 of 3 instructions reached (66.7%)
+    // Not reached: lines 3
+    //
+    pub fn write_report(&self, listing: &Listing, out: &mut dyn Write) -> io::Result<()> {
+        let mut instructions = 0;
+        let mut unreached = Vec::new();
+        for line in &listing.lines {
+            let count = match instruction(line) {
+                Some((number, address)) => {
+                    instructions += 1;
+                    match self.times(Address(address)) {
+                        0 => {
+                            unreached.push(number);
+                            String::from("#####")
+                        }
+                        times => times.to_string(),
+                    }
+                }
+                None => String::new(),
+            };
+            writeln!(
+                out,
+                "{}",
+                format!("{: >7} {}", count, line.format(false)).trim_end()
+            )?;
+        }
+
+        let reached = instructions - unreached.len();
+        writeln!(
+            out,
+            "Coverage: {} of {} instructions reached ({:.1}%)",
+            reached,
+            instructions,
+            100.0 * reached as f64 / instructions.max(1) as f64
+        )?;
+        if !unreached.is_empty() {
+            writeln!(out, "Not reached: lines {}", line_ranges(&unreached))?;
+        }
+        Ok(())
+    }
+}
+
+// The line number and address of a line of the listing which holds an instruction. Directive
+// data is shown as bytes, and literal pool entries have no line, so neither are instructions.
+fn instruction(line: &ListingLine) -> Option<(usize, u32)> {
+    match line.data {
+        Some(ListingData::Word(_)) | Some(ListingData::Halfwords(_)) => {
+            Some((line.number?, line.address?))
+        }
+        _ => None,
+    }
+}
+
+// Formats sorted line numbers, collapsing runs of consecutive lines into ranges.
+// eg: [3, 4, 5, 9] => 3-5, 9
+fn line_ranges(numbers: &[usize]) -> String {
+    let mut ranges: Vec<(usize, usize)> = Vec::new();
+    for &number in numbers {
+        match ranges.last_mut() {
+            Some((_, last)) if *last + 1 == number => *last = number,
+            _ => ranges.push((number, number)),
+        }
+    }
+    ranges
+        .iter()
+        .map(|&(first, last)| {
+            if first == last {
+                first.to_string()
+            } else {
+                format!("{}-{}", first, last)
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::EmulatorState;
+
+    #[test]
+    fn test_coverage() {
+        let source = "mov r0,#1\ncmp r0,#0\nmoveq r1,#1\nbne done\nmov r2,#2\nmov r3,#3\n\
+                      done:\nandeq r0,r0,r0\n";
+        let assembled = arm11_asm::assemble(String::from(source)).expect("assemble failed");
+        let mut emulator = EmulatorState::with_memory(assembled.to_bytes()).expect("load failed");
+        emulator.coverage = Some(Coverage::new());
+        emulator.run().expect("run failed");
+
+        let mut report = Vec::new();
+        emulator
+            .coverage
+            .expect("no coverage")
+            .write_report(&assembled.listing, &mut report)
+            .expect("write failed");
+        assert_eq!(
+            String::from_utf8(report).expect("report isn't UTF-8"),
+            "      1    1 00000000 e3a00001     mov r0,#1
+      1    2 00000004 e3500000     cmp r0,#0
+      1    3 00000008 03a01001     moveq r1,#1
+      1    4 0000000c 1a000001     bne done
+  #####    5 00000010 e3a02002     mov r2,#2
+  #####    6 00000014 e3a03003     mov r3,#3
+           7                       done:
+      1    8 00000018 00000000     andeq r0,r0,r0
+Coverage: 5 of 7 instructions reached (71.4%)
+Not reached: lines 5-6
+"
+        );
+    }
+}
@@ -0,0 +1,434 @@
+use std::io::{self, BufRead, Read, Write};
+
+use super::{
+    history::History,
+    loops::symbolise,
+    parse_number,
+    registers::Register,
+    search::Pattern,
+    state::{Decoded, EmulatorState},
+    RunResult,
+};
+use arm11_isa::{
+    address::Address, disassemble::disassemble_instruction, parse::parse_string, types::*,
+};
+
+const HELP: &str = "\
+step [n]          run n instructions, 1 by default
+continue          run until the program halts or reaches a watchpoint
+regs              show the registers
+find <pattern>    search memory for a word, eg: find 0xdeadbeef
+find \"string\"     search memory for a string, eg: find \"hello\\n\"
+find --mask m w   search memory for words which equal w in the bits set in m
+set mem a = bytes write bytes to memory at address a, eg: set mem 0x100 = de ad be ef
+set mem a = \"s\"   write a string to memory at address a, eg: set mem 0x200 = \"text\\0\"
+undo              undo the last set mem
+when-written a [@ n]
+                  show the last store to the word at a, within n instructions or so far
+value-at a @ n    show the word at a once n instructions had run
+help              show this message
+quit              stop debugging, and show the final state";
+
+impl EmulatorState {
+    // Runs the program under the control of commands read a line at a time from the input, with
+    // their results written to the output, until the input ends or the quit command. Commands
+    // can be shortened to their first letter, and an empty line repeats the last command. Returns
+    // the run so far. Stores are kept in the history from here on, to answer questions about
+    // the past.
+    pub fn debug(&mut self, input: &mut dyn BufRead, out: &mut dyn Write) -> Result<RunResult> {
+        self.history.get_or_insert_with(History::new);
+        let mut last = String::new();
+        // The bytes each set mem overwrote, so they can be undone from the latest back
+        let mut journal: Vec<(Address, Vec<u8>)> = Vec::new();
+        loop {
+            write!(out, "(arm11) ")?;
+            out.flush()?;
+            let mut line = String::new();
+            if input.read_line(&mut line)? == 0 {
+                writeln!(out)?;
+                break;
+            }
+            let line = match line.trim() {
+                "" => last.clone(),
+                line => String::from(line),
+            };
+            let (command, args) = line.split_once(' ').unwrap_or((&line, ""));
+            let args = args.trim();
+
+            match command {
+                "s" | "step" => {
+                    let count = match args {
+                        "" => 1,
+                        count => match count.parse::<u64>() {
+                            Ok(count) => count,
+                            Err(_) => {
+                                writeln!(out, "Invalid instruction count '{}'", count)?;
+                                continue;
+                            }
+                        },
+                    };
+                    let result = self.run_until(self.instructions.saturating_add(count))?;
+                    self.write_stop(&result, out)?;
+                }
+                "c" | "continue" => {
+                    let result = self.run()?;
+                    self.write_stop(&result, out)?;
+                }
+                "r" | "regs" => self.write_registers(out)?,
+                "f" | "find" => match args.parse::<Pattern>() {
+                    Ok(pattern) => self.write_matches(&pattern, out)?,
+                    Err(e) => writeln!(out, "{}", e)?,
+                },
+                "set" => match parse_set(args) {
+                    Ok((address, bytes)) => {
+                        let old = match self.memory.read(address, bytes.len() as u32) {
+                            Ok(old) => old.to_vec(),
+                            Err(e) => {
+                                writeln!(out, "{}", e)?;
+                                continue;
+                            }
+                        };
+                        self.memory.poke(address, &bytes)?;
+                        journal.push((address, old));
+                        writeln!(out, "Wrote {} bytes at {}", bytes.len(), address)?;
+                    }
+                    Err(e) => writeln!(out, "{}", e)?,
+                },
+                "u" | "undo" => match journal.pop() {
+                    Some((address, old)) => {
+                        self.memory.poke(address, &old)?;
+                        writeln!(out, "Restored {} bytes at {}", old.len(), address)?;
+                    }
+                    None => writeln!(out, "Nothing to undo")?,
+                },
+                "when-written" => match parse_point(args, self.instructions) {
+                    Ok((address, instructions)) => {
+                        let history = self.history.as_ref().expect("no history");
+                        match history.last_write(address, instructions) {
+                            Some(store) => writeln!(out, "{}: {}", address, store)?,
+                            None => writeln!(
+                                out,
+                                "{}: not written within {} instruction{}",
+                                address,
+                                instructions,
+                                if instructions == 1 { "" } else { "s" }
+                            )?,
+                        }
+                    }
+                    Err(e) => writeln!(out, "{}", e)?,
+                },
+                "value-at" => match parse_point(args, self.instructions) {
+                    Ok((address, instructions)) => match self.value_at(address, instructions) {
+                        Some(value) => {
+                            writeln!(out, "{} @ {}: 0x{:0>8x}", address, instructions, value)?
+                        }
+                        None => writeln!(out, "{}: not mapped", address)?,
+                    },
+                    Err(e) => writeln!(out, "{}", e)?,
+                },
+                "h" | "help" => writeln!(out, "{}", HELP)?,
+                "q" | "quit" => break,
+                _ => writeln!(out, "Unknown command '{}', see help", command)?,
+            }
+            last = line;
+        }
+
+        Ok(RunResult {
+            steps: self.steps,
+            instructions: self.instructions,
+            cycles: self.cycles,
+            exit_code: self.exit_code,
+            watchpoint: None,
+        })
+    }
+
+    // Writes why a run stopped, and the instruction it will continue from, if it has decoded it.
+    // With symbols loaded, the address is also given by its label and line of source.
+    fn write_stop(&mut self, result: &RunResult, out: &mut dyn Write) -> Result<()> {
+        if let Some(hit) = result.watchpoint {
+            writeln!(out, "{}", hit)?;
+        }
+        if self.will_halt() {
+            writeln!(out, "Halted after {} instructions", self.instructions)?;
+            return Ok(());
+        }
+        match self.pipeline.decoded {
+            Some(Ok(next)) => {
+                let address = Address(self.read_reg(Register::Pc))
+                    .wrapping_sub(self.instruction_width().pipeline_offset());
+                writeln!(
+                    out,
+                    "{}{}: {}",
+                    address,
+                    self.location(address),
+                    disassemble_instruction(&next.instr, address.0)
+                )?
+            }
+            _ => writeln!(
+                out,
+                "{}: (not yet fetched)",
+                Address(self.read_reg(Register::Pc))
+            )?,
+        }
+        Ok(())
+    }
+
+    // Where an address is in the source, if symbols are loaded, eg: " (loop+0x4, line 3)"
+    fn location(&self, address: Address) -> String {
+        let symbols = match &self.symbols {
+            Some(symbols) => symbols,
+            None => return String::new(),
+        };
+        let line = symbols
+            .line_at(address)
+            .map_or(String::new(), |line| format!(", line {}", line));
+        format!(" ({}{})", symbolise(address, &symbols.symbol_table), line)
+    }
+
+    // Whether the next step would halt, without taking it
+    fn will_halt(&self) -> bool {
+        self.exit_code.is_some()
+            || matches!(
+                self.pipeline.decoded,
+                Some(Ok(Decoded {
+                    instr: ConditionalInstruction {
+                        instruction: Instruction::Halt,
+                        ..
+                    },
+                    ..
+                }))
+            )
+    }
+}
+
+// Parses the arguments of set mem, which are an address and either bytes in hex or a string,
+// eg: mem 0x100 = de ad be ef
+fn parse_set(args: &str) -> std::result::Result<(Address, Vec<u8>), String> {
+    let expected = || {
+        format!(
+            "Invalid set '{}', expected mem <address> = <bytes> or \"<string>\"",
+            args
+        )
+    };
+    let (address, value) = args
+        .strip_prefix("mem ")
+        .and_then(|args| args.split_once('='))
+        .ok_or_else(expected)?;
+    let address = Address(parse_number(address.trim())?);
+
+    let value = value.trim();
+    let bytes = if value.starts_with('"') {
+        match parse_string(value) {
+            Ok(("", string)) => string.into_bytes(),
+            _ => return Err(format!("Invalid string '{}'", value)),
+        }
+    } else {
+        value
+            .split_whitespace()
+            .map(|byte| match byte.len() {
+                2 => u8::from_str_radix(byte, 16).map_err(|_| ()),
+                _ => Err(()),
+            })
+            .collect::<std::result::Result<Vec<u8>, ()>>()
+            .map_err(|_| format!("Invalid bytes '{}', expected hex pairs, eg: de ad", value))?
+    };
+    if bytes.is_empty() {
+        return Err(expected());
+    }
+    Ok((address, bytes))
+}
+
+// Parses the arguments of when-written and value-at, which are an address and optionally the
+// number of instructions to look back to, which can't be more than have run, eg: 0x1040 @ 12000
+fn parse_point(args: &str, now: u64) -> std::result::Result<(Address, u64), String> {
+    let (address, instructions) = match args.split_once('@') {
+        Some((address, instructions)) => (address, Some(instructions.trim())),
+        None => (args, None),
+    };
+    let address = Address(parse_number(address.trim())?);
+    let instructions = match instructions {
+        Some(instructions) => instructions
+            .parse::<u64>()
+            .map_err(|_| format!("Invalid instruction count '{}'", instructions))?,
+        None => now,
+    };
+    if instructions > now {
+        return Err(format!(
+            "Only {} {} run, can't look ahead to {}",
+            now,
+            if now == 1 {
+                "instruction has"
+            } else {
+                "instructions have"
+            },
+            instructions
+        ));
+    }
+    Ok((address, instructions))
+}
+
+// Reads commands a byte at a time, so that nothing after the end of a command is taken from the
+// input. This lets commands and the program's own input share stdin.
+pub(super) struct Unbuffered<R> {
+    inner: R,
+    byte: [u8; 1],
+    filled: bool,
+}
+
+impl<R: Read> Unbuffered<R> {
+    pub(super) fn new(inner: R) -> Self {
+        Unbuffered {
+            inner,
+            byte: [0],
+            filled: false,
+        }
+    }
+}
+
+impl<R: Read> Read for Unbuffered<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.fill_buf()?.len().min(buf.len());
+        buf[..n].copy_from_slice(&self.byte[..n]);
+        self.consume(n);
+        Ok(n)
+    }
+}
+
+impl<R: Read> BufRead for Unbuffered<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        if !self.filled {
+            self.filled = self.inner.read(&mut self.byte)? == 1;
+        }
+        Ok(if self.filled { &self.byte } else { &[] })
+    }
+
+    fn consume(&mut self, amt: usize) {
+        if amt > 0 {
+            self.filled = false;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arm11_asm::{assemble, SymbolFile};
+    use std::io;
+
+    #[test]
+    fn test_debug() {
+        let source = "mov r0,#1\nmov r1,#2\nldr r2,=0xcafe\nandeq r0,r0,r0\n";
+        let bytes = assemble(String::from(source))
+            .expect("assemble failed")
+            .to_bytes();
+        let mut emulator = EmulatorState::with_memory(bytes).expect("load failed");
+        let mut input = io::Cursor::new(&b"step\n\nfind 0xcafe\nbogus\nc\n"[..]);
+        let mut out = Vec::new();
+
+        let result = emulator.debug(&mut input, &mut out).expect("debug failed");
+        assert_eq!(
+            String::from_utf8(out).expect("invalid utf8"),
+            "(arm11) 0x00000004: mov r1, #2\n\
+             (arm11) 0x00000008: ldr r2, [r15] ; 0x00000010\n\
+             (arm11) 0x00000010: 00 20 9f e5 00 00 00 00 [fe ca 00 00] 00 00 00 00 00 00 00 00  \
+             |. ..................|\n1 match\n\
+             (arm11) Unknown command 'bogus', see help\n\
+             (arm11) Halted after 3 instructions\n\
+             (arm11) \n"
+        );
+        assert_eq!(result.instructions, 3);
+        assert_eq!(emulator.read_reg(Register::R2), 0xcafe);
+    }
+
+    #[test]
+    fn test_debug_symbols() {
+        let source = "mov r0,#2\nloop:\nsubs r0,r0,#1\nbne loop\nandeq r0,r0,r0\n";
+        let assembled = assemble(String::from(source)).expect("assemble failed");
+        let mut emulator = EmulatorState::with_memory(assembled.to_bytes()).expect("load failed");
+        emulator.symbols = Some(SymbolFile::new(&assembled.symbol_table, &assembled.listing));
+        let mut input = io::Cursor::new(&b"s\ns\n"[..]);
+        let mut out = Vec::new();
+
+        emulator.debug(&mut input, &mut out).expect("debug failed");
+        assert_eq!(
+            String::from_utf8(out).expect("invalid utf8"),
+            "(arm11) 0x00000004 (loop, line 3): subs r0, r0, #1\n\
+             (arm11) 0x00000008 (loop+0x4, line 4): bne 0x00000004\n\
+             (arm11) \n"
+        );
+    }
+
+    #[test]
+    fn test_time_travel() {
+        let source = "mov r0,#5\nstr r0,[r1,#0x40]\nmov r0,#7\nstr r0,[r1,#0x40]\nandeq r0,r0,r0\n";
+        let bytes = assemble(String::from(source))
+            .expect("assemble failed")
+            .to_bytes();
+        let mut emulator = EmulatorState::with_memory(bytes).expect("load failed");
+        let mut input = io::Cursor::new(
+            &b"c\nwhen-written 0x40\nwhen-written 0x40 @ 3\nwhen-written 0x40 @ 1\n\
+               value-at 0x40 @ 2\nvalue-at 0x40 @ 1\nvalue-at 0x40 @ 9\n"[..],
+        );
+        let mut out = Vec::new();
+
+        emulator.debug(&mut input, &mut out).expect("debug failed");
+        assert_eq!(
+            String::from_utf8(out).expect("invalid utf8"),
+            "(arm11) Halted after 4 instructions\n\
+             (arm11) 0x00000040: instruction 4 at 0x0000000c stored 0x00000007 to 0x00000040\n\
+             (arm11) 0x00000040: instruction 2 at 0x00000004 stored 0x00000005 to 0x00000040\n\
+             (arm11) 0x00000040: not written within 1 instruction\n\
+             (arm11) 0x00000040 @ 2: 0x00000005\n\
+             (arm11) 0x00000040 @ 1: 0x00000000\n\
+             (arm11) Only 4 instructions have run, can't look ahead to 9\n\
+             (arm11) \n"
+        );
+        assert_eq!(
+            parse_point("0x40 @ 2", 1),
+            Err(String::from(
+                "Only 1 instruction has run, can't look ahead to 2"
+            ))
+        );
+    }
+
+    #[test]
+    fn test_set_memory() {
+        let mut emulator = EmulatorState::with_memory(vec![0; 8]).expect("load failed");
+        let mut input = io::Cursor::new(
+            &b"set mem 0x100 = de ad be ef
+set mem 0x102 = \"hi\\0\"
+set mem 0x100 = dead
+\
+               set mem 0xfffffffe = 00 00 00
+undo
+undo
+undo
+"[..],
+        );
+        let mut out = Vec::new();
+
+        emulator.debug(&mut input, &mut out).expect("debug failed");
+        assert_eq!(
+            String::from_utf8(out).expect("invalid utf8"),
+            "(arm11) Wrote 4 bytes at 0x00000100\n\
+             (arm11) Wrote 3 bytes at 0x00000102\n\
+             (arm11) Invalid bytes 'dead', expected hex pairs, eg: de ad\n\
+             (arm11) Out of bounds memory access at address 0xfffffffe\n\
+             (arm11) Restored 3 bytes at 0x00000102\n\
+             (arm11) Restored 4 bytes at 0x00000100\n\
+             (arm11) Nothing to undo\n\
+             (arm11) \n"
+        );
+        assert_eq!(
+            emulator.read_memory(Address(0x100)).expect("read failed").0,
+            0
+        );
+
+        assert_eq!(
+            parse_set("mem 0x102 = \"hi\\0\""),
+            Ok((Address(0x102), vec![b'h', b'i', 0]))
+        );
+        assert!(parse_set("mem 0x100 = ").is_err());
+        assert!(parse_set("reg r0 = 01").is_err());
+    }
+}
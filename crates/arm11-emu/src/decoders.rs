@@ -0,0 +1,322 @@
+use std::{collections::HashMap, fmt, io, io::Write, str::FromStr};
+
+use super::{
+    gpio::{Gpio, NUM_PINS},
+    parse_number,
+};
+
+// A protocol analyser, which interprets the recorded transitions of some GPIO pins as frames of a
+// bit-banged serial protocol. Time is measured in executed instructions, so the UART bit period
+// is given in instructions rather than as a baud rate.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Decoder {
+    // 8N1 frames, LSB first, on an idle high line
+    Uart {
+        pin: usize,
+        ticks_per_bit: u64,
+    },
+    // Bytes sampled MSB first on rising clock edges, while chip select (if any) is low
+    Spi {
+        clk: usize,
+        mosi: usize,
+        cs: Option<usize>,
+    },
+    // Start/stop conditions, and 8 bit bytes followed by an acknowledge bit
+    I2c {
+        scl: usize,
+        sda: usize,
+    },
+}
+
+// A decoded event on the bus
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Frame {
+    Byte(u8),
+    // A UART byte whose stop bit was low
+    FramingError(u8),
+    Start,
+    Stop,
+    // An I2C byte, and whether it was acknowledged
+    I2cByte(u8, bool),
+}
+
+// Parses a decoder of the form <protocol>:<key>=<pin>,..., i.e. one of:
+// uart:pin=14,ticks=8
+// spi:clk=11,mosi=10[,cs=8]
+// i2c:scl=3,sda=2
+impl FromStr for Decoder {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("Invalid decoder '{}'", s);
+        let (protocol, params) = s.split_once(':').ok_or_else(invalid)?;
+
+        let mut values = HashMap::new();
+        for param in params.split(',') {
+            let (key, value) = param.split_once('=').ok_or_else(invalid)?;
+            values.insert(key, parse_number(value)?);
+        }
+        let pin = |key: &str| -> Result<usize, String> {
+            match values.get(key) {
+                Some(&pin) if (pin as usize) < NUM_PINS => Ok(pin as usize),
+                Some(_) => Err(format!("Invalid pin for '{}' in decoder '{}'", key, s)),
+                None => Err(format!("Missing '{}' in decoder '{}'", key, s)),
+            }
+        };
+
+        match protocol {
+            "uart" => Ok(Decoder::Uart {
+                pin: pin("pin")?,
+                ticks_per_bit: match values.get("ticks") {
+                    Some(&ticks) if ticks > 0 => u64::from(ticks),
+                    _ => return Err(format!("Missing or zero 'ticks' in decoder '{}'", s)),
+                },
+            }),
+            "spi" => Ok(Decoder::Spi {
+                clk: pin("clk")?,
+                mosi: pin("mosi")?,
+                cs: values.contains_key("cs").then(|| pin("cs")).transpose()?,
+            }),
+            "i2c" => Ok(Decoder::I2c {
+                scl: pin("scl")?,
+                sda: pin("sda")?,
+            }),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+impl Decoder {
+    pub fn decode(&self, gpio: &Gpio) -> Vec<Frame> {
+        match *self {
+            Decoder::Uart { pin, ticks_per_bit } => decode_uart(gpio, pin, ticks_per_bit),
+            Decoder::Spi { clk, mosi, cs } => decode_spi(gpio, clk, mosi, cs),
+            Decoder::I2c { scl, sda } => decode_i2c(gpio, scl, sda),
+        }
+    }
+
+    // Writes the frames decoded from the GPIO history on one line, eg:
+    // UART (pin 14): 68 69 "hi"
+    pub fn write_decoded(&self, out: &mut dyn Write, gpio: &Gpio) -> io::Result<()> {
+        let frames = self.decode(gpio);
+        write!(out, "{}:", self)?;
+        for frame in &frames {
+            write!(out, " {}", frame)?;
+        }
+
+        let text: String = frames
+            .iter()
+            .filter_map(|frame| match frame {
+                Frame::Byte(b) => Some(*b),
+                _ => None,
+            })
+            .map(|b| {
+                if b.is_ascii_graphic() || b == b' ' {
+                    b as char
+                } else {
+                    '.'
+                }
+            })
+            .collect();
+        if !text.is_empty() {
+            write!(out, " {:?}", text)?;
+        }
+        writeln!(out)
+    }
+}
+
+impl fmt::Display for Decoder {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Decoder::Uart { pin, .. } => write!(f, "UART (pin {})", pin),
+            Decoder::Spi { clk, mosi, .. } => write!(f, "SPI (clk {}, mosi {})", clk, mosi),
+            Decoder::I2c { scl, sda } => write!(f, "I2C (scl {}, sda {})", scl, sda),
+        }
+    }
+}
+
+impl fmt::Display for Frame {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Frame::Byte(b) => write!(f, "{:0>2x}", b),
+            Frame::FramingError(b) => write!(f, "{:0>2x}!", b),
+            Frame::Start => write!(f, "[S]"),
+            Frame::Stop => write!(f, "[P]"),
+            Frame::I2cByte(b, ack) => write!(f, "{:0>2x}{}", b, if *ack { "+" } else { "-" }),
+        }
+    }
+}
+
+fn level(levels: u32, pin: usize) -> bool {
+    levels & (1 << pin) != 0
+}
+
+// Finds the start bit of each frame from a falling edge on an idle line, then samples the data
+// and stop bits in the middle of each bit period.
+fn decode_uart(gpio: &Gpio, pin: usize, ticks_per_bit: u64) -> Vec<Frame> {
+    let mut frames = Vec::new();
+    let mut previous = 0;
+    // The line is busy with a frame until this time
+    let mut busy_until = 0;
+    for &(time, levels) in gpio.history() {
+        let falling = level(previous, pin) && !level(levels, pin);
+        previous = levels;
+        if !falling || time < busy_until {
+            continue;
+        }
+
+        let sample = |bit: u64| {
+            level(
+                gpio.levels_at(time + ticks_per_bit * bit + ticks_per_bit / 2),
+                pin,
+            )
+        };
+        let byte = (0..8).fold(0u8, |byte, bit| byte | (sample(bit + 1) as u8) << bit);
+        frames.push(if sample(9) {
+            Frame::Byte(byte)
+        } else {
+            Frame::FramingError(byte)
+        });
+        busy_until = time + ticks_per_bit * 9 + ticks_per_bit / 2;
+    }
+    frames
+}
+
+fn decode_spi(gpio: &Gpio, clk: usize, mosi: usize, cs: Option<usize>) -> Vec<Frame> {
+    let mut frames = Vec::new();
+    let mut previous = 0;
+    let (mut byte, mut bits) = (0u8, 0);
+    for &(_, levels) in gpio.history() {
+        let selected = cs.is_none_or(|cs| !level(levels, cs));
+        if !selected {
+            // Deselecting the device abandons any partial byte
+            bits = 0;
+        } else if !level(previous, clk) && level(levels, clk) {
+            byte = byte << 1 | level(levels, mosi) as u8;
+            bits += 1;
+            if bits == 8 {
+                frames.push(Frame::Byte(byte));
+                bits = 0;
+            }
+        }
+        previous = levels;
+    }
+    frames
+}
+
+fn decode_i2c(gpio: &Gpio, scl: usize, sda: usize) -> Vec<Frame> {
+    let mut frames = Vec::new();
+    let mut previous = 0;
+    let (mut byte, mut bits) = (0u8, 0);
+    for &(_, levels) in gpio.history() {
+        let clock_high = level(previous, scl) && level(levels, scl);
+        if clock_high && level(previous, sda) != level(levels, sda) {
+            // A data change while the clock is high is a start or stop condition
+            frames.push(if level(levels, sda) {
+                Frame::Stop
+            } else {
+                Frame::Start
+            });
+            bits = 0;
+        } else if !level(previous, scl) && level(levels, scl) {
+            if bits < 8 {
+                byte = byte << 1 | level(levels, sda) as u8;
+                bits += 1;
+            } else {
+                // The ninth bit is the acknowledge, which is sent by pulling data low
+                frames.push(Frame::I2cByte(byte, !level(levels, sda)));
+                bits = 0;
+            }
+        }
+        previous = levels;
+    }
+    frames
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arm11_isa::address::Address;
+
+    const SET: Address = Address(0x2020001c);
+    const CLR: Address = Address(0x20200028);
+
+    fn drive(gpio: &mut Gpio, time: u64, pin: usize, high: bool) {
+        gpio.write(if high { SET } else { CLR }, 1 << pin, time);
+    }
+
+    #[test]
+    fn test_parse_decoder() {
+        assert_eq!(
+            "uart:pin=14,ticks=8".parse::<Decoder>(),
+            Ok(Decoder::Uart {
+                pin: 14,
+                ticks_per_bit: 8
+            })
+        );
+        assert_eq!(
+            "spi:clk=11,mosi=10".parse::<Decoder>(),
+            Ok(Decoder::Spi {
+                clk: 11,
+                mosi: 10,
+                cs: None
+            })
+        );
+        assert!("i2c:scl=3".parse::<Decoder>().is_err());
+        assert!("uart:pin=40,ticks=8".parse::<Decoder>().is_err());
+    }
+
+    #[test]
+    fn test_decode_uart() {
+        // Idle high, then 'h' (0x68) as start bit, LSB first data, and stop bit
+        let mut gpio = Gpio::new();
+        drive(&mut gpio, 0, 14, true);
+        let bits = [
+            false, false, false, false, true, false, true, true, false, true,
+        ];
+        for (index, bit) in bits.iter().enumerate() {
+            drive(&mut gpio, 10 + index as u64 * 8, 14, *bit);
+        }
+
+        let decoder: Decoder = "uart:pin=14,ticks=8".parse().expect("parse failed");
+        assert_eq!(decoder.decode(&gpio), vec![Frame::Byte(0x68)]);
+
+        let mut out = Vec::new();
+        decoder
+            .write_decoded(&mut out, &gpio)
+            .expect("write failed");
+        assert_eq!(
+            String::from_utf8(out).expect("invalid output"),
+            "UART (pin 14): 68 \"h\"\n"
+        );
+    }
+
+    #[test]
+    fn test_decode_i2c() {
+        // Start, 0xa1 with acknowledge, stop
+        let (scl, sda) = (3, 2);
+        let mut gpio = Gpio::new();
+        let mut time = 0;
+        let mut step = |gpio: &mut Gpio, pin, high| {
+            time += 1;
+            drive(gpio, time, pin, high);
+        };
+        step(&mut gpio, sda, true);
+        step(&mut gpio, scl, true);
+        step(&mut gpio, sda, false);
+        for bit in (0..8).map(|i| 0xa1u8 >> (7 - i) & 1 == 1).chain([false]) {
+            step(&mut gpio, scl, false);
+            step(&mut gpio, sda, bit);
+            step(&mut gpio, scl, true);
+        }
+        step(&mut gpio, scl, false);
+        step(&mut gpio, sda, false);
+        step(&mut gpio, scl, true);
+        step(&mut gpio, sda, true);
+
+        assert_eq!(
+            Decoder::I2c { scl, sda }.decode(&gpio),
+            vec![Frame::Start, Frame::I2cByte(0xa1, true), Frame::Stop]
+        );
+    }
+}
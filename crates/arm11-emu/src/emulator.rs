@@ -0,0 +1,184 @@
+use std::{
+    cell::RefCell,
+    collections::VecDeque,
+    io::{self, Read},
+    rc::Rc,
+};
+
+use super::{
+    harness::Capture,
+    memory::MemoryMap,
+    registers::Register,
+    state::EmulatorState,
+    uart::{Uart, DEFAULT_UART_BASE},
+    Status,
+};
+use arm11_isa::{address::Address, types::*};
+
+// A program in the emulator, driven a step at a time by a host with no files or stdout, eg: an
+// in-browser playground built for wasm32-unknown-unknown. Everything the program writes, through
+// syscalls or the UART at the default base address, is kept until the host takes it, and both
+// read the characters the host has pushed, seeing the input as closed when there are none left.
+// The values passed in and out are plain numbers and strings, so that the methods can be bound
+// to JavaScript as they are.
+// eg:
+//
+// let mut emulator = Emulator::new(assemble_to_bytes("mov r0,#42\nswi #1\nandeq r0,r0,r0\n")?)?;
+// emulator.run(1000)?;
+// emulator.take_output() == "42"
+//
+pub struct Emulator {
+    state: EmulatorState,
+    output: Capture,
+    input: Input,
+}
+
+// The characters pushed by the host, which the program reads in the order they were pushed
+#[derive(Debug, Clone, Default)]
+struct Input(Rc<RefCell<VecDeque<u8>>>);
+
+impl Read for Input {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut pushed = self.0.borrow_mut();
+        let len = buf.len().min(pushed.len());
+        for (byte, pushed) in buf.iter_mut().zip(pushed.drain(..len)) {
+            *byte = pushed;
+        }
+        Ok(len)
+    }
+}
+
+impl Emulator {
+    // Loads a binary, which starts executing from its first instruction. Binaries too large for
+    // the default memory are an error.
+    pub fn new(bytes: Vec<u8>) -> Result<Self> {
+        let mut state = EmulatorState::with_memory_map(bytes, &MemoryMap::default())?;
+        let output = Capture::new();
+        let input = Input::default();
+        state.set_output(Box::new(output.clone()));
+        state.set_input(Box::new(input.clone()));
+        state.uart = Some(Uart::new(
+            DEFAULT_UART_BASE,
+            Box::new(input.clone()),
+            Box::new(output.clone()),
+        ));
+        Ok(Emulator {
+            state,
+            output,
+            input,
+        })
+    }
+
+    pub fn state(&self) -> &EmulatorState {
+        &self.state
+    }
+
+    pub fn state_mut(&mut self) -> &mut EmulatorState {
+        &mut self.state
+    }
+
+    // Advances the pipeline by one step, see EmulatorState::step
+    pub fn step(&mut self) -> Result<Status> {
+        self.state.step()
+    }
+
+    // Runs until the program halts or reaches a watchpoint, or has executed max_instructions
+    // more instructions, when it is still Running, so that a host can keep drawing while a long
+    // program runs, or stop one stuck in a loop
+    pub fn run(&mut self, max_instructions: u64) -> Result<Status> {
+        let limit = self.state.instructions.saturating_add(max_instructions);
+        while self.state.instructions < limit {
+            match self.step()? {
+                Status::Running => (),
+                status => return Ok(status),
+            }
+        }
+        Ok(Status::Running)
+    }
+
+    // The registers, r0 to r12, sp, lr, pc and cpsr, in that order. The pc is 8 ahead of the
+    // instruction being executed, as the program reads it.
+    pub fn registers(&self) -> Vec<u32> {
+        Register::all()
+            .map(|reg| self.state.read_reg(reg))
+            .collect()
+    }
+
+    // The address of the next instruction to be executed, eg: to highlight its line
+    pub fn address(&self) -> u32 {
+        self.state
+            .read_reg(Register::Pc)
+            .wrapping_sub(self.state.instruction_width().pipeline_offset())
+    }
+
+    // The word at an aligned address, or None if it isn't in memory
+    pub fn read_word(&self, address: u32) -> Option<u32> {
+        self.state
+            .read_memory(Address(address))
+            .ok()
+            .map(|word| word.0)
+    }
+
+    pub fn instructions(&self) -> u64 {
+        self.state.instructions
+    }
+
+    pub fn cycles(&self) -> u64 {
+        self.state.cycles()
+    }
+
+    // Queues characters for the program to read
+    pub fn push_input(&mut self, input: &str) {
+        self.input.0.borrow_mut().extend(input.bytes());
+    }
+
+    // Everything the program has written since the output was last taken
+    pub fn take_output(&mut self) -> String {
+        self.output.take()
+    }
+
+    // The registers and non-zero memory, as they are printed at the end of a run
+    pub fn final_state(&self) -> String {
+        let mut out = Vec::new();
+        self.state
+            .write_state(&mut out)
+            .expect("writing to a Vec failed");
+        String::from_utf8_lossy(&out).into_owned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arm11_asm::assemble_to_bytes;
+
+    #[test]
+    fn test_emulator() {
+        let source = "mov r0,#42\nswi #1\nswi #3\nmov r1,r0\nloop:\nb loop\n";
+        let bytes = assemble_to_bytes(source).expect("assemble failed");
+        let mut emulator = Emulator::new(bytes).expect("load failed");
+        emulator.push_input("a");
+        assert_eq!(emulator.run(4).expect("run failed"), Status::Running);
+        assert_eq!(emulator.take_output(), "42");
+        assert_eq!(emulator.take_output(), "");
+        assert_eq!(emulator.registers()[1], u32::from(b'a'));
+        assert_eq!(emulator.address(), 0x10);
+        assert_eq!(emulator.instructions(), 4);
+
+        // the loop never halts, so each run stops at its limit
+        assert_eq!(emulator.run(100).expect("run failed"), Status::Running);
+        assert_eq!(emulator.instructions(), 104);
+        assert_eq!(emulator.read_word(0), Some(0xe3a0002a));
+        assert_eq!(emulator.read_word(0x1000_0000), None);
+
+        let bytes = assemble_to_bytes("mov r2,#7\nandeq r0,r0,r0\n").expect("assemble failed");
+        let mut emulator = Emulator::new(bytes).expect("load failed");
+        assert_eq!(emulator.run(100).expect("run failed"), Status::Halted);
+        assert!(emulator
+            .final_state()
+            .contains("$2  :          7 (0x00000007)"));
+
+        let image = vec![0; MemoryMap::default().ram.size as usize + 4];
+        assert!(Emulator::new(image).is_err());
+    }
+}
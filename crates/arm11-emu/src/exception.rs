@@ -0,0 +1,282 @@
+use std::fmt;
+
+use super::{registers::Register, state::EmulatorState};
+use arm11_isa::types::*;
+
+// The mode bits of the CPSR
+const MODE_MASK: u32 = 0x1f;
+// Set in the control register of CP15 to move the vector table to the top of memory
+const HIGH_VECTORS: u32 = 1 << 13;
+const HIGH_VECTOR_BASE: u32 = 0xffff0000;
+// The bits of the interrupt lines in the interrupt history
+pub const IRQ_LINE: u32 = 1 << 0;
+pub const FIQ_LINE: u32 = 1 << 1;
+
+// The processor modes, as stored in the mode bits of the CPSR. The emulator starts with a CPSR
+// of zero, which isn't a mode, so that programs which never use exceptions start from the same
+// state as on a machine without them. It behaves as System mode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    User = 0x10,
+    Fiq = 0x11,
+    Irq = 0x12,
+    Supervisor = 0x13,
+    Abort = 0x17,
+    Undefined = 0x1b,
+    System = 0x1f,
+}
+
+impl Mode {
+    pub fn from_cpsr(cpsr: u32) -> Self {
+        match cpsr & MODE_MASK {
+            0x10 => Mode::User,
+            0x11 => Mode::Fiq,
+            0x12 => Mode::Irq,
+            0x13 => Mode::Supervisor,
+            0x17 => Mode::Abort,
+            0x1b => Mode::Undefined,
+            _ => Mode::System,
+        }
+    }
+
+    // The set of banked registers the mode uses. User and System mode share theirs.
+    fn bank(self) -> usize {
+        match self {
+            Mode::User | Mode::System => 0,
+            Mode::Fiq => 1,
+            Mode::Irq => 2,
+            Mode::Supervisor => 3,
+            Mode::Abort => 4,
+            Mode::Undefined => 5,
+        }
+    }
+
+    // Every mode except User mode can write the control byte of the CPSR, and so change mode
+    pub fn is_privileged(self) -> bool {
+        self != Mode::User
+    }
+}
+
+impl fmt::Display for Mode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Mode::User => "User",
+            Mode::Fiq => "FIQ",
+            Mode::Irq => "IRQ",
+            Mode::Supervisor => "Supervisor",
+            Mode::Abort => "Abort",
+            Mode::Undefined => "Undefined",
+            Mode::System => "System",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+// The registers which are only visible in some modes; r13, r14 and the SPSR of each exception
+// mode, and r8 to r12 in FIQ mode, so that FIQ handlers don't need to save them. The registers of
+// the current mode are in the register file, and the rest are kept here until the mode changes.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct BankedRegisters {
+    // r8 to r12 of FIQ mode, and of every other mode
+    high: [[u32; 5]; 2],
+    // r13 and r14, and the SPSR, of each bank of Mode::bank. User and System mode have no SPSR.
+    stack_link: [[u32; 2]; 6],
+    spsr: [u32; 6],
+}
+
+impl BankedRegisters {
+    // Every banked register, in the order they are stored in snapshots
+    pub fn words(&self) -> impl Iterator<Item = u32> + '_ {
+        self.high
+            .iter()
+            .flatten()
+            .chain(self.stack_link.iter().flatten())
+            .chain(self.spsr.iter())
+            .copied()
+    }
+
+    pub fn words_mut(&mut self) -> impl Iterator<Item = &mut u32> + '_ {
+        self.high
+            .iter_mut()
+            .flatten()
+            .chain(self.stack_link.iter_mut().flatten())
+            .chain(self.spsr.iter_mut())
+    }
+}
+
+// The exceptions raised by the interrupt lines. FIQs take priority, and mask IRQs until they
+// return.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Exception {
+    Irq,
+    Fiq,
+}
+
+impl Exception {
+    fn mode(self) -> Mode {
+        match self {
+            Exception::Irq => Mode::Irq,
+            Exception::Fiq => Mode::Fiq,
+        }
+    }
+
+    // The offset of the exception's entry in the vector table
+    fn vector(self) -> u32 {
+        match self {
+            Exception::Irq => 0x18,
+            Exception::Fiq => 0x1c,
+        }
+    }
+}
+
+impl EmulatorState {
+    // The mode the processor is in
+    pub fn mode(&self) -> Mode {
+        Mode::from_cpsr(self.regs().cpsr())
+    }
+
+    // Writes the CPSR, swapping the banked registers in and out if the mode changes. Writes
+    // which only change the flags can use write_reg instead.
+    pub fn set_cpsr(&mut self, cpsr: u32) {
+        let (from, to) = (self.mode(), Mode::from_cpsr(cpsr));
+        self.switch_bank(from.bank(), to.bank());
+        self.write_reg(Register::Cpsr, cpsr);
+    }
+
+    // The SPSR of the current mode, or None in User and System mode, which have none
+    pub fn spsr(&self) -> Option<u32> {
+        match self.mode().bank() {
+            0 => None,
+            bank => Some(self.banked.spsr[bank]),
+        }
+    }
+
+    pub(super) fn set_spsr(&mut self, val: u32) -> Result<()> {
+        match self.mode().bank() {
+            0 => Err(format!("There is no SPSR in {} mode", self.mode()).into()),
+            bank => {
+                self.banked.spsr[bank] = val;
+                Ok(())
+            }
+        }
+    }
+
+    // The interrupt which is raised and not masked by the CPSR, if any. Polling the UART can
+    // fail if its input can't be read.
+    pub fn pending_interrupt(&mut self) -> Result<Option<Exception>> {
+        let cpsr = self.regs().status();
+        let uart = match &mut self.uart {
+            Some(uart) => uart.interrupt(self.cycles)?,
+            None => false,
+        };
+        let irq = self.irq || uart;
+        let irq_level = if irq { IRQ_LINE } else { 0 };
+        let fiq_level = if self.fiq { FIQ_LINE } else { 0 };
+        self.record_interrupt_lines(irq_level | fiq_level);
+        Ok(if self.fiq && !cpsr.flag(CpsrFlag::F) {
+            Some(Exception::Fiq)
+        } else if irq && !cpsr.flag(CpsrFlag::I) {
+            Some(Exception::Irq)
+        } else {
+            None
+        })
+    }
+
+    // The levels of the interrupt lines after each change, with the instruction count at the
+    // time, as IRQ_LINE and FIQ_LINE bits. Both lines start low.
+    pub fn interrupt_history(&self) -> &[(u64, u32)] {
+        &self.interrupt_history
+    }
+
+    fn record_interrupt_lines(&mut self, levels: u32) {
+        let previous = self
+            .interrupt_history
+            .last()
+            .map_or(0, |&(_, levels)| levels);
+        if levels != previous {
+            self.interrupt_history.push((self.instructions, levels));
+        }
+    }
+
+    // Takes an exception before the instruction at return_address is executed. The CPSR is
+    // saved to the SPSR of the exception's mode, and r14 of that mode is set so that
+    // subs pc,lr,#4 returns to the instruction. Execution continues from the vector, in ARM
+    // state, with IRQs (and for FIQs, FIQs) masked.
+    pub(super) fn take_exception(&mut self, exception: Exception, return_address: u32) {
+        let cpsr = self.regs().cpsr();
+        let mode = exception.mode();
+        let mut new_cpsr =
+            cpsr & !(MODE_MASK | 1 << CpsrFlag::T as u32) | mode as u32 | 1 << CpsrFlag::I as u32;
+        if exception == Exception::Fiq {
+            new_cpsr |= 1 << CpsrFlag::F as u32;
+        }
+        self.set_cpsr(new_cpsr);
+        self.banked.spsr[mode.bank()] = cpsr;
+        self.write_reg(Register::Lr, return_address.wrapping_add(4));
+
+        let high = self
+            .cp15
+            .is_some_and(|cp15| cp15.read(1, 0, 0, 0) & HIGH_VECTORS != 0);
+        let base = if high { HIGH_VECTOR_BASE } else { 0 };
+        self.write_reg(Register::Pc, base + exception.vector());
+        self.pipeline.flush();
+        self.interrupts += 1;
+    }
+
+    // Saves r8 to r14 to the bank being left, and loads them from the bank being entered
+    fn switch_bank(&mut self, from: usize, to: usize) {
+        if from == to {
+            return;
+        }
+        let high = |bank: usize| (bank == Mode::Fiq.bank()) as usize;
+        let (high_from, high_to) = (high(from), high(to));
+        if high_from != high_to {
+            for (i, reg) in (8..=12).map(Register::from_field).enumerate() {
+                self.banked.high[high_from][i] = self.read_reg(reg);
+                self.write_reg(reg, self.banked.high[high_to][i]);
+            }
+        }
+        for (i, &reg) in [Register::Sp, Register::Lr].iter().enumerate() {
+            self.banked.stack_link[from][i] = self.read_reg(reg);
+            self.write_reg(reg, self.banked.stack_link[to][i]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_banked_registers() {
+        let mut emulator = EmulatorState::new();
+        emulator.write_reg(Register::R8, 8);
+        emulator.write_reg(Register::Sp, 0x1000);
+        assert_eq!(emulator.mode(), Mode::System);
+        assert_eq!(emulator.spsr(), None);
+
+        // FIQ mode has its own r8 to r14, while IRQ mode only has its own r13 and r14
+        emulator.set_cpsr(Mode::Fiq as u32);
+        assert_eq!(emulator.read_reg(Register::R8), 0);
+        assert_eq!(emulator.read_reg(Register::Sp), 0);
+        emulator.write_reg(Register::Sp, 0x2000);
+        emulator.set_cpsr(Mode::Irq as u32);
+        assert_eq!(emulator.read_reg(Register::R8), 8);
+        assert_eq!(emulator.read_reg(Register::Sp), 0);
+        emulator.set_spsr(0x10).expect("set spsr failed");
+        assert_eq!(emulator.spsr(), Some(0x10));
+
+        emulator.set_cpsr(Mode::User as u32);
+        assert_eq!(emulator.read_reg(Register::Sp), 0x1000);
+        assert!(emulator.set_spsr(0).is_err());
+        emulator.set_cpsr(Mode::Fiq as u32);
+        assert_eq!(emulator.read_reg(Register::Sp), 0x2000);
+
+        let mut banked = BankedRegisters::default();
+        for (slot, val) in banked.words_mut().zip(emulator.banked.words()) {
+            *slot = val;
+        }
+        assert_eq!(banked, emulator.banked);
+        assert_eq!(banked.words().count(), 28);
+    }
+}
@@ -0,0 +1,730 @@
+use arm11_isa::{
+    address::{Address, Word},
+    constants::*,
+    decode::signed_24_to_32,
+    encode,
+    types::{Instruction::*, *},
+};
+
+use super::{
+    led::write_indicator,
+    registers::{Register, RegisterFile},
+    state::*,
+    syscall, vfp,
+};
+
+pub fn execute(state: &mut EmulatorState, instr: ConditionalInstruction) -> Result<()> {
+    if !instr.satisfies(state.regs().status()) {
+        return Ok(());
+    }
+
+    match instr.instruction {
+        Processing(processing) => execute_processing(state, processing),
+        Multiply(multiply) => execute_multiply(state, multiply),
+        MultiplyLong(multiply) => execute_multiply_long(state, multiply),
+        StatusRead(status) => execute_status_read(state, status),
+        StatusWrite(status) => execute_status_write(state, status),
+        Transfer(transfer) => execute_transfer(state, transfer),
+        BlockTransfer(block_transfer) => execute_block_transfer(state, block_transfer),
+        Branch(branch) => execute_branch(state, branch),
+        ThumbBranch(branch) => execute_thumb_branch(state, branch),
+        BranchExchange(branch_exchange) => execute_branch_exchange(state, branch_exchange),
+        Coprocessor(coprocessor) => execute_coprocessor(state, coprocessor, instr),
+        Vfp(vfp) => vfp::execute(state, vfp, instr),
+        SoftwareInterrupt(swi) => syscall::call(state, swi.comment, instr),
+        Halt => Err("Can't execute halt".into()),
+    }
+}
+
+// Raised for an instruction the emulated machine doesn't have, with its ARM encoding. These are
+// coprocessor, VFP and swi instructions, which always have one.
+pub(super) fn undefined(
+    instr: ConditionalInstruction,
+    kind: &'static str,
+    reason: impl Into<String>,
+) -> ArmError {
+    ArmError::BadOpcode {
+        opcode: encode::encode(instr).unwrap_or_default(),
+        kind,
+        reason: Some(reason.into()),
+    }
+}
+
+fn execute_processing(state: &mut EmulatorState, instr: InstructionProcessing) -> Result<()> {
+    let InstructionProcessing {
+        opcode,
+        set_cond,
+        rn,
+        rd,
+        operand2,
+    } = instr;
+    let (rn, rd) = (Register::from_field(rn), Register::from_field(rd));
+
+    // Get operands
+    let op1 = read_base(state, rn);
+    let (op2, bs_carry_out) = barrel_shifter(operand2, state.regs());
+    // Perform process
+    let carry_in = state.regs().status().flag(CpsrFlag::C);
+    let (result, carry_out, overflow) =
+        perform_processing_operation(op1 as i32, op2 as i32, carry_in, opcode);
+
+    // Save result
+    match opcode {
+        ProcessingOpcode::Cmp
+        | ProcessingOpcode::Cmn
+        | ProcessingOpcode::Teq
+        | ProcessingOpcode::Tst => (),
+        _ => {
+            // Writing to the PC is a branch, so flush the pipeline. In Thumb state, the bottom
+            // bit is ignored rather than changing state.
+            if rd == Register::Pc {
+                let thumb = state.instruction_width() == InstructionWidth::Halfword;
+                state.write_reg(rd, result as u32 & !(thumb as u32));
+                state.pipeline.flush();
+            } else {
+                state.write_reg(rd, result as u32);
+            }
+        }
+    }
+
+    // Setting the flags while writing the PC returns from an exception, restoring the CPSR from
+    // the SPSR. User and System mode have no SPSR, so set the flags as usual.
+    if set_cond && rd == Register::Pc {
+        if let Some(spsr) = state.spsr() {
+            state.set_cpsr(spsr);
+            return Ok(());
+        }
+    }
+
+    // Set flags
+    if set_cond {
+        // Logical operations have no overflow, and take their carry from the barrel shifter
+        let c_flag = if overflow.is_some() {
+            carry_out
+        } else {
+            bs_carry_out
+        };
+        state.set_flags(CpsrFlag::C, c_flag);
+        state.set_flags(
+            CpsrFlag::N,
+            extract_bit(&(result as u32), CpsrFlag::N as u8),
+        );
+        state.set_flags(CpsrFlag::Z, result == 0);
+        if let Some(overflow) = overflow {
+            state.set_flags(CpsrFlag::V, overflow);
+        }
+    }
+
+    Ok(())
+}
+
+fn execute_multiply(state: &mut EmulatorState, instr: InstructionMultiply) -> Result<()> {
+    let InstructionMultiply {
+        accumulate,
+        set_cond,
+        rd,
+        rn,
+        rs,
+        rm,
+    } = instr;
+    let (rd, rn, rs, rm) = (
+        Register::from_field(rd),
+        Register::from_field(rn),
+        Register::from_field(rs),
+        Register::from_field(rm),
+    );
+
+    // Perform multiplication, keeping the bottom 32 bits of the result
+    let mut result: u32 = state.read_reg(rm).wrapping_mul(state.read_reg(rs));
+
+    if accumulate {
+        result = result.wrapping_add(state.read_reg(rn));
+    }
+
+    // Save result
+    state.write_reg(rd, result);
+
+    // Set flags
+    if set_cond {
+        state.set_flags(CpsrFlag::N, extract_bit(&result, CpsrFlag::N as u8));
+        state.set_flags(CpsrFlag::Z, result == 0);
+    }
+
+    Ok(())
+}
+
+fn execute_multiply_long(state: &mut EmulatorState, instr: InstructionMultiplyLong) -> Result<()> {
+    let InstructionMultiplyLong {
+        signed,
+        accumulate,
+        set_cond,
+        rd_hi,
+        rd_lo,
+        rs,
+        rm,
+    } = instr;
+    let (rd_hi, rd_lo, rs, rm) = (
+        Register::from_field(rd_hi),
+        Register::from_field(rd_lo),
+        Register::from_field(rs),
+        Register::from_field(rm),
+    );
+    let (rm, rs) = (state.read_reg(rm), state.read_reg(rs));
+
+    // Perform multiplication, on the operands sign extended to 64 bits if signed
+    let mut result: u64 = if signed {
+        (i64::from(rm as i32) * i64::from(rs as i32)) as u64
+    } else {
+        u64::from(rm) * u64::from(rs)
+    };
+
+    if accumulate {
+        let acc = u64::from(state.read_reg(rd_hi)) << 32 | u64::from(state.read_reg(rd_lo));
+        result = result.wrapping_add(acc);
+    }
+
+    // Save result
+    state.write_reg(rd_lo, result as u32);
+    state.write_reg(rd_hi, (result >> 32) as u32);
+
+    // Set flags from the whole 64 bit result
+    if set_cond {
+        state.set_flags(CpsrFlag::N, result >> 63 != 0);
+        state.set_flags(CpsrFlag::Z, result == 0);
+    }
+
+    Ok(())
+}
+
+fn execute_status_read(state: &mut EmulatorState, instr: InstructionStatusRead) -> Result<()> {
+    let InstructionStatusRead { spsr, rd } = instr;
+    let val = if spsr {
+        state
+            .spsr()
+            .ok_or_else(|| format!("There is no SPSR in {} mode", state.mode()))?
+    } else {
+        state.regs().cpsr()
+    };
+    state.write_reg(Register::from_field(rd), val);
+    Ok(())
+}
+
+fn execute_status_write(state: &mut EmulatorState, instr: InstructionStatusWrite) -> Result<()> {
+    let InstructionStatusWrite {
+        spsr,
+        field_mask,
+        operand,
+    } = instr;
+    let val = barrel_shifter(operand, state.regs()).0;
+
+    // Each bit of the field mask selects a byte of the status register
+    let mut mask = (0..4)
+        .filter(|field| field_mask & 1 << field != 0)
+        .fold(0, |mask, field| mask | 0xff << (8 * field));
+    if spsr {
+        let old = state
+            .spsr()
+            .ok_or_else(|| format!("There is no SPSR in {} mode", state.mode()))?;
+        return state.set_spsr(old & !mask | val & mask);
+    }
+
+    // User mode can only write the flags, and the T bit can only be changed by bx
+    if !state.mode().is_privileged() {
+        mask &= 0xff << 24;
+    }
+    mask &= !(1 << CpsrFlag::T as u32);
+    state.set_cpsr(state.regs().cpsr() & !mask | val & mask);
+
+    Ok(())
+}
+
+fn execute_transfer(state: &mut EmulatorState, instr: InstructionTransfer) -> Result<()> {
+    let InstructionTransfer {
+        is_preindexed,
+        up_bit,
+        writeback,
+        load,
+        size,
+        rn,
+        rd,
+        offset,
+    } = instr;
+    let (rn, rd) = (Register::from_field(rn), Register::from_field(rd));
+
+    // Calculate the offset address, adding or subtracting the offset's magnitude as the U bit
+    // says. Addresses wrap around the 32 bit address space, as on the ARM11.
+    let magnitude = match offset {
+        TransferOffset::Immediate(imm) => u32::from(imm),
+        TransferOffset::ShiftedReg(rm, shift) => {
+            barrel_shifter(Operand2::ShiftedReg(rm, shift), state.regs()).0
+        }
+    };
+    let base = Address(read_base(state, rn));
+    let offset_address = if up_bit {
+        base.wrapping_add(magnitude)
+    } else {
+        base.wrapping_sub(magnitude)
+    };
+
+    // Calculate memory address, handling pre-indexing
+    let mem_address = if is_preindexed { offset_address } else { base };
+
+    // Perform transfer
+    match mem_address {
+        _ if state
+            .uart
+            .as_ref()
+            .is_some_and(|uart| uart.contains(mem_address)) =>
+        {
+            let val = state.read_reg(rd);
+            let uart = state.uart.as_mut().expect("UART not present");
+            if load {
+                let received = uart.read(mem_address, state.cycles)?;
+                state.write_reg(rd, received);
+            } else {
+                uart.write(mem_address, val)?;
+            }
+        }
+        _ if state.is_mapped(mem_address, size.bytes()) => {
+            if load {
+                // Load the memory to R[rd], zero or sign extending bytes and halfwords
+                let val = match size {
+                    TransferSize::Word => state.read_memory(mem_address)?.into(),
+                    TransferSize::Byte => u32::from(state.read_byte(mem_address)?),
+                    TransferSize::Halfword => u32::from(state.read_halfword(mem_address)?),
+                    TransferSize::SignedByte => state.read_byte(mem_address)? as i8 as u32,
+                    TransferSize::SignedHalfword => state.read_halfword(mem_address)? as i16 as u32,
+                };
+                state.check_watchpoints(mem_address, size.bytes(), true, val);
+                if rd == Register::Pc {
+                    branch_exchange(state, val);
+                } else {
+                    state.write_reg(rd, val);
+                }
+            } else {
+                // Stores the value at Mem[rd], truncated to the transfer size
+                let val = state.read_reg(rd);
+                state.check_watchpoints(mem_address, size.bytes(), false, val);
+                match size {
+                    TransferSize::Word => state.write_memory(mem_address, Word(val))?,
+                    TransferSize::Byte | TransferSize::SignedByte => {
+                        state.write_byte(mem_address, val as u8)?
+                    }
+                    TransferSize::Halfword | TransferSize::SignedHalfword => {
+                        state.write_halfword(mem_address, val as u16)?
+                    }
+                }
+            }
+        }
+        _ if state.gpio.contains(mem_address) => {
+            let message = state.gpio.message(mem_address);
+            writeln!(state.output(), "{}", message)?;
+            if load {
+                let val = state.gpio.read(mem_address);
+                state.write_reg(rd, val);
+            } else {
+                let val = state.read_reg(rd);
+                let changed = state.gpio.write(mem_address, val, state.instructions);
+                for pin in state.leds.clone() {
+                    if changed & (1 << pin) != 0 {
+                        let level = state.gpio.level(pin);
+                        write_indicator(state.output(), pin, level)?;
+                    }
+                }
+            }
+        }
+        _ => state.unmapped_access(mem_address)?,
+    }
+
+    // Handle post-indexing, and writeback of pre-indexed addresses
+    if !is_preindexed || writeback {
+        state.write_reg(rn, offset_address.0);
+    }
+
+    Ok(())
+}
+
+fn execute_block_transfer(
+    state: &mut EmulatorState,
+    instr: InstructionBlockTransfer,
+) -> Result<()> {
+    let InstructionBlockTransfer {
+        is_preindexed,
+        up_bit,
+        writeback,
+        load,
+        rn,
+        register_list,
+    } = instr;
+
+    let base = Address(state.read_reg(Register::from_field(rn)));
+    let transfer_size = register_list.count_ones() * BYTES_IN_WORD as u32;
+
+    // Registers are always transferred lowest first, to the lowest address
+    let mut mem_address = match (is_preindexed, up_bit) {
+        (false, true) => base,
+        (true, true) => base.wrapping_add(BYTES_IN_WORD as u32),
+        (false, false) => base
+            .wrapping_sub(transfer_size)
+            .wrapping_add(BYTES_IN_WORD as u32),
+        (true, false) => base.wrapping_sub(transfer_size),
+    };
+
+    // Perform transfers
+    for reg in Register::all().filter(|&r| u32::from(register_list) & (1 << r as u32) != 0) {
+        if !state.is_mapped(mem_address, BYTES_IN_WORD as u32) {
+            state.unmapped_access(mem_address)?;
+        } else if load {
+            let val = state.read_memory(mem_address)?.into();
+            state.check_watchpoints(mem_address, BYTES_IN_WORD as u32, true, val);
+            if reg == Register::Pc {
+                branch_exchange(state, val);
+            } else {
+                state.write_reg(reg, val);
+            }
+        } else {
+            let val = state.read_reg(reg);
+            state.check_watchpoints(mem_address, BYTES_IN_WORD as u32, false, val);
+            state.write_memory(mem_address, Word(val))?;
+        }
+        mem_address = mem_address.wrapping_add(BYTES_IN_WORD as u32);
+    }
+
+    // Handle writeback, unless the base register was loaded
+    if writeback && !(load && register_list & (1 << rn) != 0) {
+        let new_base = if up_bit {
+            base.wrapping_add(transfer_size)
+        } else {
+            base.wrapping_sub(transfer_size)
+        };
+        state.write_reg(Register::from_field(rn), new_base.0);
+    }
+
+    Ok(())
+}
+
+fn execute_branch(state: &mut EmulatorState, instr: InstructionBranch) -> Result<()> {
+    let InstructionBranch { link, offset } = instr;
+
+    // Save the return address, i.e. the address of the next instruction. Both it and the target
+    // wrap around the address space, as a branch near either end of it does on the hardware.
+    let pc = Address(state.read_reg(Register::Pc));
+    if link {
+        state.write_reg(Register::Lr, pc.wrapping_sub(BYTES_IN_WORD as u32).0);
+    }
+
+    // Update the PC
+    state.write_reg(Register::Pc, pc.offset(signed_24_to_32(offset << 2)).0);
+
+    // Flush the pipeline
+    state.pipeline.flush();
+
+    Ok(())
+}
+
+fn execute_thumb_branch(state: &mut EmulatorState, instr: InstructionThumbBranch) -> Result<()> {
+    let InstructionThumbBranch { kind, offset } = instr;
+    let pc = Address(state.read_reg(Register::Pc));
+
+    match kind {
+        ThumbBranchKind::Branch => {
+            state.write_reg(Register::Pc, pc.offset(offset).0);
+            state.pipeline.flush();
+        }
+        // The prefix of a bl leaves the top half of the target in LR
+        ThumbBranchKind::LinkPrefix => state.write_reg(Register::Lr, pc.offset(offset).0),
+        // The suffix branches, leaving the address of the next instruction in LR with the bottom
+        // bit set, so that returning with bx stays in Thumb state
+        ThumbBranchKind::LinkSuffix => {
+            let target = Address(state.read_reg(Register::Lr)).offset(offset);
+            state.write_reg(Register::Lr, pc.wrapping_sub(2).0 | 1);
+            state.write_reg(Register::Pc, target.0);
+            state.pipeline.flush();
+        }
+    }
+
+    Ok(())
+}
+
+fn execute_branch_exchange(
+    state: &mut EmulatorState,
+    instr: InstructionBranchExchange,
+) -> Result<()> {
+    let InstructionBranchExchange { rm } = instr;
+    let target = state.read_reg(Register::from_field(rm));
+    branch_exchange(state, target);
+    Ok(())
+}
+
+// Branches to an address, entering Thumb state if its bottom bit is set and ARM state otherwise.
+// This is used by bx, and by loads to the PC, which also change state on ARMv5 and later.
+fn branch_exchange(state: &mut EmulatorState, target: u32) {
+    state.set_flags(CpsrFlag::T, target & 1 != 0);
+    state.write_reg(Register::Pc, target & !1);
+    state.pipeline.flush();
+}
+
+// Reads a base register of an address or a sum. In Thumb state the PC may only be halfword
+// aligned, so PC relative addresses are calculated from it rounded down to a word. The PC is
+// always word aligned in ARM state, so this has no effect there.
+fn read_base(state: &EmulatorState, rn: Register) -> u32 {
+    match rn {
+        Register::Pc => state.read_reg(rn) & !3,
+        _ => state.read_reg(rn),
+    }
+}
+
+fn execute_coprocessor(
+    state: &mut EmulatorState,
+    instr: InstructionCoprocessor,
+    encoded: ConditionalInstruction,
+) -> Result<()> {
+    let InstructionCoprocessor {
+        load,
+        coprocessor,
+        opcode1,
+        crn,
+        rd,
+        crm,
+        opcode2,
+    } = instr;
+    let rd = Register::from_field(rd);
+
+    // Only CP15 is present, and only with the extended ISA
+    if coprocessor != 15 {
+        return Err(undefined(
+            encoded,
+            "coprocessor",
+            format!("coprocessor p{} is not present", coprocessor),
+        ));
+    }
+    let mut cp15 = state.cp15.ok_or_else(|| {
+        undefined(
+            encoded,
+            "coprocessor",
+            "coprocessor p15 needs the extended ISA",
+        )
+    })?;
+
+    if load {
+        let val = cp15.read(crn, opcode1, crm, opcode2);
+        if rd == Register::Pc {
+            // Reading into r15 sets the condition flags from the top 4 bits instead
+            let cpsr = state.read_reg(Register::Cpsr) & mask(28) | val & !mask(28);
+            state.write_reg(Register::Cpsr, cpsr);
+        } else {
+            state.write_reg(rd, val);
+        }
+    } else {
+        let val = state.read_reg(rd);
+        cp15.write(crn, opcode1, crm, opcode2, val);
+        state.cp15 = Some(cp15);
+    }
+
+    Ok(())
+}
+
+// Helper Functions and Impls
+
+// Computes an Operand2, returning its value and the shifter's carry out, which logical
+// instructions which set the flags write to the C flag
+pub fn barrel_shifter(op2: Operand2, register_file: &RegisterFile) -> (u32, bool) {
+    let carry_in = register_file.status().flag(CpsrFlag::C);
+    match op2 {
+        // A rotated constant carries out bit 31 of the result, unless it isn't rotated
+        Operand2::ConstantShift(to_shift, shift_amt) => shift(
+            u32::from(to_shift),
+            2 * u32::from(shift_amt),
+            ShiftType::Ror,
+            carry_in,
+        ),
+        // A constant rotation by 0 is rrx, which shifts the carry flag in at the top, and the
+        // bottom bit out as the carry
+        Operand2::ShiftedReg(reg_to_shift, Shift::ConstantShift(ShiftType::Ror, 0)) => {
+            let to_shift = register_file[Register::from_field(reg_to_shift)];
+            (
+                (u32::from(carry_in) << 31) | (to_shift >> 1),
+                extract_bit(&to_shift, 0),
+            )
+        }
+        // A constant lsr or asr by 0 is by 32, as lsl #0 already leaves the value alone
+        Operand2::ShiftedReg(reg_to_shift, Shift::ConstantShift(shift_type, constant_shift)) => {
+            let shift_amt = match (shift_type, constant_shift) {
+                (ShiftType::Lsr, 0) | (ShiftType::Asr, 0) => 32,
+                _ => u32::from(constant_shift),
+            };
+            shift(
+                register_file[Register::from_field(reg_to_shift)],
+                shift_amt,
+                shift_type,
+                carry_in,
+            )
+        }
+        Operand2::ShiftedReg(reg_to_shift, Shift::RegisterShift(shift_type, shift_reg)) => shift(
+            register_file[Register::from_field(reg_to_shift)],
+            register_file[Register::from_field(shift_reg)] & mask(8),
+            shift_type,
+            carry_in,
+        ),
+    }
+}
+
+// Shifts a value by any amount, as a shift by a register does, returning the result and the
+// carry out, which is the last bit shifted out. A shift by 0 leaves both the value and the carry
+// alone. Shifting by 32 or more shifts every bit out, except that asr fills the result with the
+// sign bit, and a rotation by a multiple of 32 carries out bit 31.
+// eg: shift(0x80000001, 1, ShiftType::Lsl, false) == (0x2, true)
+pub fn shift(to_shift: u32, shift_amt: u32, shift_type: ShiftType, carry_in: bool) -> (u32, bool) {
+    if shift_amt == 0 {
+        return (to_shift, carry_in);
+    }
+    let bit = |index: u32| extract_bit(&to_shift, index as u8);
+    match shift_type {
+        ShiftType::Lsl => match shift_amt {
+            1..=31 => (to_shift << shift_amt, bit(32 - shift_amt)),
+            32 => (0, bit(0)),
+            _ => (0, false),
+        },
+        ShiftType::Lsr => match shift_amt {
+            1..=31 => (to_shift >> shift_amt, bit(shift_amt - 1)),
+            32 => (0, bit(31)),
+            _ => (0, false),
+        },
+        ShiftType::Asr => match shift_amt {
+            1..=31 => (((to_shift as i32) >> shift_amt) as u32, bit(shift_amt - 1)),
+            _ => (((to_shift as i32) >> 31) as u32, bit(31)),
+        },
+        // A rotation by a register can be by up to 255, which is the same as by that mod 32
+        ShiftType::Ror => match shift_amt % 32 {
+            0 => (to_shift, bit(31)),
+            shift_amt => (to_shift.rotate_right(shift_amt), bit(shift_amt - 1)),
+        },
+    }
+}
+
+// Performs a processing operation, returning the result, the carry out and the signed overflow.
+// The carry is unsigned, i.e. for additions it is set if the result overflowed 32 bits, and for
+// subtractions it is set if there was no borrow. The carry in is the C flag, which adc adds, and
+// sbc and rsc subtract the inverse of. Logical operations leave the V flag alone, so have no
+// overflow.
+pub fn perform_processing_operation(
+    op1: i32,
+    op2: i32,
+    carry_in: bool,
+    opcode: ProcessingOpcode,
+) -> (i32, bool, Option<bool>) {
+    // Computes a + b + carry, with the carry out of bit 31. The result overflows if both inputs
+    // have the same sign, and the result has a different one.
+    let add_with_carry = |a: i32, b: i32, carry: bool| {
+        let wide = u64::from(a as u32) + u64::from(b as u32) + u64::from(carry);
+        let result = wide as u32 as i32;
+        let overflow = (a ^ result) & (b ^ result) < 0;
+        (result, wide > u64::from(u32::MAX), Some(overflow))
+    };
+    // Subtraction is addition of the inverse, where a carry means there was no borrow
+    let sub_with_carry = |a: i32, b: i32, carry: bool| add_with_carry(a, !b, carry);
+    match opcode {
+        ProcessingOpcode::And | ProcessingOpcode::Tst => (op1 & op2, false, None),
+        ProcessingOpcode::Eor | ProcessingOpcode::Teq => (op1 ^ op2, false, None),
+        ProcessingOpcode::Sub | ProcessingOpcode::Cmp => sub_with_carry(op1, op2, true),
+        ProcessingOpcode::Rsb => sub_with_carry(op2, op1, true),
+        ProcessingOpcode::Add | ProcessingOpcode::Cmn => add_with_carry(op1, op2, false),
+        ProcessingOpcode::Adc => add_with_carry(op1, op2, carry_in),
+        ProcessingOpcode::Sbc => sub_with_carry(op1, op2, carry_in),
+        ProcessingOpcode::Rsc => sub_with_carry(op2, op1, carry_in),
+        ProcessingOpcode::Orr => (op1 | op2, false, None),
+        ProcessingOpcode::Mov => (op2, false, None),
+        ProcessingOpcode::Bic => (op1 & !op2, false, None),
+        ProcessingOpcode::Mvn => (!op2, false, None),
+    }
+}
+
+pub fn extract_bit(word: &u32, index: u8) -> bool {
+    ((word >> index) & 1) == 1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_shift() {
+        // Examples of each shift from the ARM ARM, as (value, amount, type, carry in) => (result,
+        // carry out)
+        let shifts = [
+            ((0x8000_0001, 0, ShiftType::Lsl, true), (0x8000_0001, true)),
+            ((0x8000_0001, 1, ShiftType::Lsl, false), (0x2, true)),
+            (
+                (0x4000_0001, 31, ShiftType::Lsl, false),
+                (0x8000_0000, false),
+            ),
+            ((0x1, 32, ShiftType::Lsl, false), (0x0, true)),
+            ((0xffff_ffff, 33, ShiftType::Lsl, true), (0x0, false)),
+            ((0x0000_0003, 1, ShiftType::Lsr, false), (0x1, true)),
+            ((0x8000_0000, 32, ShiftType::Lsr, false), (0x0, true)),
+            ((0x8000_0000, 40, ShiftType::Lsr, true), (0x0, false)),
+            (
+                (0x8000_0010, 4, ShiftType::Asr, false),
+                (0xf800_0001, false),
+            ),
+            ((0x8000_0008, 4, ShiftType::Asr, false), (0xf800_0000, true)),
+            (
+                (0x8000_0000, 32, ShiftType::Asr, false),
+                (0xffff_ffff, true),
+            ),
+            ((0x7fff_ffff, 255, ShiftType::Asr, true), (0x0, false)),
+            (
+                (0x0000_00f1, 4, ShiftType::Ror, false),
+                (0x1000_000f, false),
+            ),
+            (
+                (0x8000_0001, 32, ShiftType::Ror, false),
+                (0x8000_0001, true),
+            ),
+            ((0x0000_0002, 33, ShiftType::Ror, true), (0x1, false)),
+            (
+                (0x1234_5678, 0, ShiftType::Ror, false),
+                (0x1234_5678, false),
+            ),
+        ];
+        for ((value, amount, shift_type, carry_in), expected) in shifts.iter() {
+            assert_eq!(
+                shift(*value, *amount, *shift_type, *carry_in),
+                *expected,
+                "{:?} #{} of 0x{:08x}",
+                shift_type,
+                amount,
+                value
+            );
+        }
+
+        // An lsr or asr by a constant 0 is by 32, a ror by a constant 0 is rrx, and a rotated
+        // constant carries out bit 31 if it is rotated, and the carry in otherwise
+        let mut register_file = RegisterFile::default();
+        register_file[Register::R1] = 0x8000_0001;
+        let operands = [
+            (
+                Operand2::ShiftedReg(1, Shift::ConstantShift(ShiftType::Lsr, 0)),
+                (0x0, true),
+            ),
+            (
+                Operand2::ShiftedReg(1, Shift::ConstantShift(ShiftType::Asr, 0)),
+                (0xffff_ffff, true),
+            ),
+            (
+                Operand2::ShiftedReg(1, Shift::ConstantShift(ShiftType::Ror, 0)),
+                (0x4000_0000, true),
+            ),
+            (Operand2::ConstantShift(0x2, 1), (0x8000_0000, true)),
+            (Operand2::ConstantShift(0xff, 0), (0xff, false)),
+        ];
+        for (operand, expected) in operands.iter() {
+            assert_eq!(
+                barrel_shifter(*operand, &register_file),
+                *expected,
+                "{:?}",
+                operand
+            );
+        }
+    }
+}
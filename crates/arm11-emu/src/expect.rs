@@ -0,0 +1,308 @@
+use std::{convert::TryFrom, fmt, str::FromStr};
+
+use nom::{
+    branch::alt,
+    bytes::complete::tag,
+    character::complete::{char, digit1, multispace0},
+    combinator::{all_consuming, map, map_opt, opt, recognize, value},
+    multi::separated_list0,
+    sequence::{delimited, pair, separated_pair},
+};
+
+use super::{json::json_name, registers::Register, state::EmulatorState};
+use arm11_isa::{
+    address::Address,
+    parse::{parse_string, NomResult},
+    types::*,
+};
+
+// The values a run is expected to finish with, read from JSON in the same form as the output of
+// --output json. Only what is listed is checked, so memory words which are listed are checked
+// even if they are zero, and words which aren't are ignored.
+//
+// eg: {
+//   "registers": {"r0": 55, "sp": 65536},
+//   "flags": {"z": true},
+//   "memory": [{"address": 256, "value": 0}],
+//   "instructions": 30
+// }
+//
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ExpectedState {
+    pub registers: Vec<(Register, u32)>,
+    pub flags: Vec<(CpsrFlag, bool)>,
+    pub memory: Vec<(Address, u32)>,
+    pub cycles: Option<u64>,
+    pub instructions: Option<u64>,
+}
+
+// A value which didn't match the expected state, eg: r0: expected 55 (0x00000037), got 0
+#[derive(Debug, Clone, PartialEq)]
+pub struct Mismatch {
+    pub what: String,
+    pub expected: String,
+    pub actual: String,
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}: expected {}, got {}",
+            self.what, self.expected, self.actual
+        )
+    }
+}
+
+impl FromStr for ExpectedState {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let json = parse_document(s).ok_or("Invalid expected state, expected JSON")?;
+        ExpectedState::from_json(&json)
+    }
+}
+
+impl ExpectedState {
+    pub(super) fn from_json(json: &Json) -> std::result::Result<Self, String> {
+        let mut expected = ExpectedState::default();
+        for (key, value) in json.members("the expected state")? {
+            match key.as_str() {
+                "registers" => {
+                    for (name, value) in value.members("registers")? {
+                        let reg = Register::from_name(name)
+                            .ok_or_else(|| format!("Unknown register '{}'", name))?;
+                        expected.registers.push((reg, value.word(name)?));
+                    }
+                }
+                "flags" => {
+                    for (name, value) in value.members("flags")? {
+                        let flag = match name.as_str() {
+                            "n" => CpsrFlag::N,
+                            "z" => CpsrFlag::Z,
+                            "c" => CpsrFlag::C,
+                            "v" => CpsrFlag::V,
+                            _ => return Err(format!("Unknown flag '{}'", name)),
+                        };
+                        match value {
+                            Json::Bool(set) => expected.flags.push((flag, *set)),
+                            _ => return Err(format!("Expected true or false for flag '{}'", name)),
+                        }
+                    }
+                }
+                "memory" => {
+                    let words = match value {
+                        Json::Array(words) => words,
+                        _ => return Err(String::from("Expected an array of words for memory")),
+                    };
+                    for word in words {
+                        let (mut address, mut value) = (None, None);
+                        for (key, v) in word.members("a memory word")? {
+                            match key.as_str() {
+                                "address" => address = Some(v.word(key)?),
+                                "value" => value = Some(v.word(key)?),
+                                _ => return Err(format!("Unknown key '{}' in memory word", key)),
+                            }
+                        }
+                        match (address, value) {
+                            (Some(address), Some(value)) => {
+                                expected.memory.push((Address(address), value))
+                            }
+                            _ => {
+                                return Err(String::from("Memory words need an address and value"))
+                            }
+                        }
+                    }
+                }
+                "cycles" => expected.cycles = Some(value.count(key)?),
+                "instructions" => expected.instructions = Some(value.count(key)?),
+                _ => return Err(format!("Unknown key '{}' in the expected state", key)),
+            }
+        }
+        Ok(expected)
+    }
+}
+
+impl EmulatorState {
+    // Compares the state with the expected state, returning everything which differs in the
+    // order it is listed
+    pub fn compare_state(&self, expected: &ExpectedState) -> Vec<Mismatch> {
+        let word = |value: u32| format!("{} (0x{:0>8x})", value, value);
+        let mut mismatches = Vec::new();
+
+        for &(reg, value) in &expected.registers {
+            let actual = self.read_reg(reg);
+            if actual != value {
+                mismatches.push(Mismatch {
+                    what: json_name(reg),
+                    expected: word(value),
+                    actual: word(actual),
+                });
+            }
+        }
+        for &(flag, set) in &expected.flags {
+            let actual = self.regs().status().flag(flag);
+            if actual != set {
+                mismatches.push(Mismatch {
+                    what: format!("flag {:?}", flag),
+                    expected: set.to_string(),
+                    actual: actual.to_string(),
+                });
+            }
+        }
+        for &(address, value) in &expected.memory {
+            let actual = match self.read_memory(address) {
+                Ok(actual) if actual.0 == value => continue,
+                Ok(actual) => word(actual.0),
+                Err(_) => String::from("unmapped memory"),
+            };
+            mismatches.push(Mismatch {
+                what: format!("memory {}", address),
+                expected: word(value),
+                actual,
+            });
+        }
+        let counts = [
+            ("cycles", expected.cycles, self.cycles),
+            ("instructions", expected.instructions, self.instructions),
+        ];
+        for &(what, expected, actual) in counts.iter() {
+            match expected {
+                Some(expected) if expected != actual => mismatches.push(Mismatch {
+                    what: String::from(what),
+                    expected: expected.to_string(),
+                    actual: actual.to_string(),
+                }),
+                _ => (),
+            }
+        }
+        mismatches
+    }
+}
+
+// A JSON value. Numbers are integers, as every value in the state is.
+#[derive(Debug, Clone, PartialEq)]
+pub(super) enum Json {
+    Null,
+    Bool(bool),
+    Number(i64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    pub(super) fn members(&self, what: &str) -> std::result::Result<&[(String, Json)], String> {
+        match self {
+            Json::Object(members) => Ok(members),
+            _ => Err(format!("Expected an object for {}", what)),
+        }
+    }
+
+    // A 32 bit word, which may be written as a negative number
+    pub(super) fn word(&self, what: &str) -> std::result::Result<u32, String> {
+        match *self {
+            Json::Number(n) if n >= i64::from(i32::MIN) && n <= i64::from(u32::MAX) => Ok(n as u32),
+            _ => Err(format!("Expected a 32 bit number for '{}'", what)),
+        }
+    }
+
+    fn count(&self, what: &str) -> std::result::Result<u64, String> {
+        match *self {
+            Json::Number(n) => u64::try_from(n).map_err(|_| format!("Negative '{}'", what)),
+            _ => Err(format!("Expected a number for '{}'", what)),
+        }
+    }
+}
+
+// Parses a whole document of JSON, which may be surrounded by whitespace
+pub(super) fn parse_document(s: &str) -> Option<Json> {
+    all_consuming(delimited(multispace0, parse_json, multispace0))(s)
+        .ok()
+        .map(|(_, json)| json)
+}
+
+fn parse_json(input: &str) -> NomResult<&str, Json> {
+    let list_separator = || delimited(multispace0, char(','), multispace0);
+    alt((
+        value(Json::Null, tag("null")),
+        value(Json::Bool(true), tag("true")),
+        value(Json::Bool(false), tag("false")),
+        map_opt(recognize(pair(opt(char('-')), digit1)), |n: &str| {
+            n.parse().ok().map(Json::Number)
+        }),
+        map(parse_string, Json::String),
+        map(
+            delimited(
+                pair(char('['), multispace0),
+                separated_list0(list_separator(), parse_json),
+                pair(multispace0, char(']')),
+            ),
+            Json::Array,
+        ),
+        map(
+            delimited(
+                pair(char('{'), multispace0),
+                separated_list0(
+                    list_separator(),
+                    separated_pair(
+                        parse_string,
+                        delimited(multispace0, char(':'), multispace0),
+                        parse_json,
+                    ),
+                ),
+                pair(multispace0, char('}')),
+            ),
+            Json::Object,
+        ),
+    ))(input)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compare_state() {
+        // mov r0,#1; cmp r0,#1; str r0,[r1,#0x40]; andeq r0,r0,r0
+        let bytes = [0xe3a00001u32, 0xe3500001, 0xe5810040, 0]
+            .iter()
+            .flat_map(|w| w.to_le_bytes())
+            .collect();
+        let mut emulator = EmulatorState::with_memory(bytes).expect("load failed");
+        emulator.run().expect("run failed");
+
+        // The output of --output json matches itself
+        let expected: ExpectedState = emulator.state_to_json().parse().expect("parse failed");
+        assert_eq!(expected.registers.len(), 17);
+        assert_eq!(emulator.compare_state(&expected), vec![]);
+
+        let expected: ExpectedState = "{\"registers\": {\"r0\": 2, \"r1\": 0}, \
+                                       \"flags\": {\"z\": false},\n\
+                                       \"memory\": [{\"address\": 64, \"value\": 1}, \
+                                       {\"address\": 68, \"value\": -1}], \"instructions\": 3}"
+            .parse()
+            .expect("parse failed");
+        let mismatches: Vec<String> = emulator
+            .compare_state(&expected)
+            .iter()
+            .map(Mismatch::to_string)
+            .collect();
+        assert_eq!(
+            mismatches,
+            vec![
+                "r0: expected 2 (0x00000002), got 1 (0x00000001)",
+                "flag Z: expected false, got true",
+                "memory 0x00000044: expected 4294967295 (0xffffffff), got 0 (0x00000000)",
+            ]
+        );
+
+        assert!("{\"registers\": {\"r16\": 0}}"
+            .parse::<ExpectedState>()
+            .is_err());
+        assert!("{\"memory\": [{\"address\": 0}]}"
+            .parse::<ExpectedState>()
+            .is_err());
+        assert!("{\"registers\": ".parse::<ExpectedState>().is_err());
+    }
+}
@@ -0,0 +1,22 @@
+use super::{
+    registers::Register,
+    state::{EmulatorState, Fetched, PrefetchAbort},
+};
+use arm11_isa::{address::Address, types::InstructionWidth};
+
+// Fetches the instruction at PC, and advances PC to the next instruction. Instructions are a word
+// wide in ARM state, and a halfword in Thumb state. Fetching from an unmapped or peripheral
+// address aborts, rather than reading a value.
+pub fn fetch(state: &mut EmulatorState) -> Fetched<u32> {
+    let pc = Address(state.read_reg(Register::Pc));
+    let width = state.instruction_width();
+    state.write_reg(Register::Pc, pc.wrapping_add(width.bytes()).0);
+    match width {
+        InstructionWidth::Word => state.read_memory(pc).map(u32::from),
+        InstructionWidth::Halfword => state.read_halfword(pc).map(u32::from),
+    }
+    .map_err(|_| PrefetchAbort {
+        address: pc,
+        peripheral: state.gpio.contains(pc),
+    })
+}
@@ -0,0 +1,114 @@
+use std::{io::Write, str::FromStr};
+
+use super::{memory::Region, parse_number, state::EmulatorState};
+use arm11_isa::{address::Address, types::*};
+
+// Bytes in each RGB565 pixel
+const BYTES_PER_PIXEL: u32 = 2;
+
+// A framebuffer of RGB565 pixels, stored row by row from the top left as little endian
+// halfwords in memory of its own. Programs draw by writing to it as they would to RAM, and the
+// image can be written out as a PPM once the run stops.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Framebuffer {
+    pub base: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Framebuffer {
+    // The memory the pixels are stored in
+    pub fn region(&self) -> Region {
+        Region::new(self.base, self.width * self.height * BYTES_PER_PIXEL)
+    }
+}
+
+// Parses a framebuffer of the form base:widthxheight, where the base is halfword aligned, the
+// width and height are decimal, and the pixels fit in the address space. eg: 0x40000000:320x240
+impl FromStr for Framebuffer {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let invalid = || format!("Invalid framebuffer '{}', expected base:widthxheight", s);
+        let (base, size) = s.split_once(':').ok_or_else(invalid)?;
+        let (width, height) = size.split_once('x').ok_or_else(invalid)?;
+        let framebuffer = Framebuffer {
+            base: parse_number(base)?,
+            width: width.parse().map_err(|_| invalid())?,
+            height: height.parse().map_err(|_| invalid())?,
+        };
+        let size = u64::from(framebuffer.width)
+            * u64::from(framebuffer.height)
+            * u64::from(BYTES_PER_PIXEL);
+        if !framebuffer.base.is_multiple_of(BYTES_PER_PIXEL)
+            || size == 0
+            || u64::from(framebuffer.base) + size > 1 << 32
+        {
+            return Err(invalid());
+        }
+        Ok(framebuffer)
+    }
+}
+
+impl EmulatorState {
+    // Writes the framebuffer as a binary PPM, widening each RGB565 pixel to 8 bits a channel
+    pub fn write_ppm(&self, framebuffer: &Framebuffer, out: &mut dyn Write) -> Result<()> {
+        let region = framebuffer.region();
+        let bytes = self.memory.read(Address(region.base), region.size)?;
+        writeln!(out, "P6\n{} {}\n255", framebuffer.width, framebuffer.height)?;
+        let pixels: Vec<u8> = bytes
+            .chunks_exact(BYTES_PER_PIXEL as usize)
+            .flat_map(|pixel| {
+                let pixel = u16::from_le_bytes([pixel[0], pixel[1]]);
+                let (r, g, b) = (pixel >> 11, pixel >> 5 & 0x3f, pixel & 0x1f);
+                [
+                    (r << 3 | r >> 2) as u8,
+                    (g << 2 | g >> 4) as u8,
+                    (b << 3 | b >> 2) as u8,
+                ]
+            })
+            .collect();
+        out.write_all(&pixels)?;
+        Ok(out.flush()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Config, MemoryMap};
+
+    #[test]
+    fn test_framebuffer() {
+        // Draws a red pixel at (1, 0) and a white pixel at (0, 1) of a 2x2 framebuffer
+        let source = "ldr r0,=0x40000000\nmov r1,#0xf800\nstrh r1,[r0,#2]\nmvn r1,#0\n\
+                      strh r1,[r0,#4]\nandeq r0,r0,r0\n";
+        let assembled = arm11_asm::assemble(String::from(source)).expect("assemble failed");
+        let framebuffer: Framebuffer = "0x40000000:2x2".parse().expect("parse failed");
+        let config = Config {
+            memory_map: MemoryMap {
+                framebuffer: Some(framebuffer),
+                ..Default::default()
+            },
+            cpu_id: None,
+            vfp: false,
+        };
+        let mut emulator =
+            EmulatorState::with_config(assembled.to_bytes(), &config).expect("emulator failed");
+        emulator.run().expect("run failed");
+
+        let mut out = Vec::new();
+        emulator
+            .write_ppm(&framebuffer, &mut out)
+            .expect("write failed");
+        let mut expected = b"P6\n2 2\n255\n".to_vec();
+        expected.extend_from_slice(&[0, 0, 0, 0xff, 0, 0, 0xff, 0xff, 0xff, 0, 0, 0]);
+        assert_eq!(out, expected);
+
+        assert!("0x40000001:2x2".parse::<Framebuffer>().is_err());
+        assert!("0x40000000:0x140x0xf0".parse::<Framebuffer>().is_err());
+        assert!("0x40000000:0x2".parse::<Framebuffer>().is_err());
+        assert!("0xffff0000:1024x1024".parse::<Framebuffer>().is_err());
+        assert!("0x40000000:320".parse::<Framebuffer>().is_err());
+    }
+}
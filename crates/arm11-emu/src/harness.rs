@@ -0,0 +1,88 @@
+use std::{
+    cell::RefCell,
+    io::{self, Write},
+    rc::Rc,
+};
+
+use super::{
+    memory::MemoryMap,
+    replay::RecordingReader,
+    state::EmulatorState,
+    uart::{Uart, DEFAULT_UART_BASE},
+};
+use arm11_isa::types::*;
+
+// An output which can still be read after it is given to the emulator, eg: to compare what a
+// program wrote against what was expected
+#[derive(Debug, Clone, Default)]
+pub struct Capture(Rc<RefCell<Vec<u8>>>);
+
+impl Capture {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn contents(&self) -> String {
+        String::from_utf8_lossy(&self.0.borrow()).into_owned()
+    }
+
+    // Removes and returns what has been written so far, eg: to show output as it arrives
+    pub fn take(&self) -> String {
+        String::from_utf8_lossy(&self.0.take()).into_owned()
+    }
+}
+
+impl Write for Capture {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+// Runs a binary in-process, with a UART at the default base address and syscalls
+// both reading from the given input. Returns everything the run wrote; the messages from the
+// emulator and the characters sent to the UART in the order they were written, followed by the
+// final state.
+// Programs which haven't halted after max_instructions are an error, so that a program stuck in
+// a loop can't hang its caller, as are images too large for the default memory.
+pub fn run_program(bytes: Vec<u8>, input: &[u8], max_instructions: u64) -> Result<String> {
+    let mut emulator = EmulatorState::with_memory_map(bytes, &MemoryMap::default())?;
+    let mut output = Capture::new();
+    emulator.set_output(Box::new(output.clone()));
+    let input = RecordingReader::new(Box::new(io::Cursor::new(input.to_vec())), Rc::default());
+    emulator.set_input(Box::new(input.clone()));
+    emulator.uart = Some(Uart::new(
+        DEFAULT_UART_BASE,
+        Box::new(input),
+        Box::new(output.clone()),
+    ));
+
+    emulator.run_guarded(Some(max_instructions), None)?;
+    emulator.write_state(&mut output)?;
+    Ok(output.contents())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arm11_asm::assemble_to_bytes;
+
+    #[test]
+    fn test_run_program() {
+        let bytes = assemble_to_bytes("mov r0,#3\nandeq r0,r0,r0\n").expect("assemble failed");
+        let output = run_program(bytes, &[], 100).expect("run failed");
+        assert!(output.starts_with("Registers:\n$0  :          3 (0x00000003)\n"));
+        assert!(output.ends_with("Cycles: 1 (1 instructions)\n"));
+
+        let bytes = assemble_to_bytes("loop:\nb loop\n").expect("assemble failed");
+        let err = run_program(bytes, &[], 100).expect_err("loop didn't stop");
+        assert!(err.is::<crate::RunawayError>());
+
+        let image = vec![0; MemoryMap::default().ram.size as usize + 4];
+        assert!(run_program(image, &[], 100).is_err());
+    }
+}
@@ -0,0 +1,166 @@
+use std::fmt;
+
+use super::{registers::Register, state::EmulatorState};
+use arm11_isa::address::Address;
+
+// A store made by the program; the instruction which made it, counted from 1, its address,
+// the bytes it wrote and the bytes they overwrote, both little endian
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Store {
+    pub instruction: u64,
+    pub pc: Address,
+    pub address: Address,
+    pub len: u32,
+    pub value: u32,
+    pub old: u32,
+}
+
+// A journal of every store the program makes, so that questions about the past can be answered,
+// eg: which instruction last wrote an address, or what it held at an earlier point. Each store
+// keeps the bytes it overwrote, so memory can be wound back from its current state. A point in
+// the run is given as a number of instructions, and is the state once they have run.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct History {
+    stores: Vec<Store>,
+}
+
+impl Store {
+    // Whether the store wrote the byte at the address
+    fn touches(&self, address: Address) -> bool {
+        address.0.wrapping_sub(self.address.0) < self.len
+    }
+
+    // The byte the store overwrote at the address, which it must touch
+    fn old_byte(&self, address: Address) -> u8 {
+        (self.old >> (8 * address.0.wrapping_sub(self.address.0))) as u8
+    }
+}
+
+impl History {
+    pub fn new() -> Self {
+        History::default()
+    }
+
+    pub fn stores(&self) -> &[Store] {
+        &self.stores
+    }
+
+    // The last store to any byte of the word at the address, made within the given number of
+    // instructions
+    pub fn last_write(&self, address: Address, instructions: u64) -> Option<&Store> {
+        self.stores
+            .iter()
+            .rev()
+            .filter(|store| store.instruction <= instructions)
+            .find(|store| (0..4).any(|i| store.touches(address.wrapping_add(i))))
+    }
+}
+
+impl fmt::Display for Store {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "instruction {} at {} stored 0x{:0>width$x} to {}",
+            self.instruction,
+            self.pc,
+            self.value,
+            self.address,
+            width = 2 * self.len as usize
+        )
+    }
+}
+
+impl EmulatorState {
+    // Adds a store to the history, if it is being kept. This must be called before the store is
+    // made, so that the bytes it overwrites can be kept, and before the instruction writes any
+    // registers, so that the PC is still that of the instruction.
+    pub(super) fn record_store(&mut self, address: Address, len: u32, value: u32) {
+        if self.history.is_none() {
+            return;
+        }
+        let old = match self.memory.read(address, len) {
+            Ok(bytes) => bytes
+                .iter()
+                .rev()
+                .fold(0, |word, &byte| word << 8 | u32::from(byte)),
+            Err(_) => return,
+        };
+        let store = Store {
+            instruction: self.instructions + 1,
+            pc: Address(self.read_reg(Register::Pc))
+                .wrapping_sub(self.instruction_width().pipeline_offset()),
+            address,
+            len,
+            value: value & (u32::MAX >> (32 - 8 * len)),
+            old,
+        };
+        if let Some(history) = &mut self.history {
+            history.stores.push(store);
+        }
+    }
+
+    // The word at the address as it was once the given number of instructions had run, found by
+    // undoing the stores made since, latest first. None if the address isn't mapped, or no
+    // history is being kept.
+    pub fn value_at(&self, address: Address, instructions: u64) -> Option<u32> {
+        let history = self.history.as_ref()?;
+        let mut bytes = [0; 4];
+        bytes.copy_from_slice(self.memory.read(address, 4).ok()?);
+        for store in history
+            .stores
+            .iter()
+            .rev()
+            .take_while(|store| store.instruction > instructions)
+        {
+            for (i, byte) in bytes.iter_mut().enumerate() {
+                let at = address.wrapping_add(i as u32);
+                if store.touches(at) {
+                    *byte = store.old_byte(at);
+                }
+            }
+        }
+        Some(u32::from_le_bytes(bytes))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arm11_asm::assemble;
+
+    #[test]
+    fn test_history() {
+        let source = "mov r0,#5\nstr r0,[r1,#0x40]\nmov r0,#7\nstrb r0,[r1,#0x41]\n\
+                      str r0,[r1,#0x44]\nandeq r0,r0,r0\n";
+        let bytes = assemble(String::from(source))
+            .expect("assemble failed")
+            .to_bytes();
+        let mut emulator = EmulatorState::with_memory(bytes).expect("load failed");
+        emulator.history = Some(History::new());
+        emulator.run().expect("run failed");
+
+        let history = emulator.history.as_ref().expect("no history");
+        assert_eq!(history.stores().len(), 3);
+        let store = history
+            .last_write(Address(0x40), u64::MAX)
+            .expect("no store");
+        assert_eq!(
+            store.to_string(),
+            "instruction 4 at 0x0000000c stored 0x07 to 0x00000041"
+        );
+        assert_eq!(
+            history.last_write(Address(0x40), 3).map(|s| s.instruction),
+            Some(2)
+        );
+        assert_eq!(history.last_write(Address(0x40), 1), None);
+
+        // Memory is wound back to each point
+        assert_eq!(emulator.value_at(Address(0x40), 1), Some(0));
+        assert_eq!(emulator.value_at(Address(0x40), 2), Some(5));
+        assert_eq!(emulator.value_at(Address(0x40), 4), Some(0x705));
+        assert_eq!(emulator.value_at(Address(0x42), 4), Some(0));
+        assert_eq!(emulator.value_at(Address(0x42), 5), Some(0x70000));
+        assert_eq!(emulator.value_at(Address(0x44), 4), Some(0));
+        assert_eq!(emulator.value_at(Address(0xfffffffe), 4), None);
+    }
+}
@@ -0,0 +1,57 @@
+use std::{error::Error, fmt};
+
+use arm11_isa::{constants::BYTES_IN_WORD, ArmError};
+
+// A binary which can't be run, because it doesn't hold whole instructions. The assembler pads
+// code to a whole number of words, including Thumb code, so any other length means the binary
+// was cut short or isn't a binary. The codes follow on from the assembler's DiagnosticCode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageError {
+    // A length which isn't even a whole number of halfwords
+    OddLength(usize),
+    // A whole number of halfwords, but not of words, so the last instruction is cut short
+    Truncated(usize),
+}
+
+impl ImageError {
+    // Checks the image is a whole number of words
+    pub fn check(image: &[u8]) -> std::result::Result<(), ImageError> {
+        match image.len() {
+            len if len % 2 != 0 => Err(ImageError::OddLength(len)),
+            len if len % BYTES_IN_WORD != 0 => Err(ImageError::Truncated(len)),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn code(&self) -> &'static str {
+        match self {
+            ImageError::OddLength(_) => "E0101",
+            ImageError::Truncated(_) => "E0102",
+        }
+    }
+}
+
+impl fmt::Display for ImageError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "error[{}]: ", self.code())?;
+        match self {
+            ImageError::OddLength(len) => {
+                write!(f, "binary of {} bytes has an odd length", len)
+            }
+            ImageError::Truncated(len) => write!(
+                f,
+                "binary of {} bytes ends part way through an instruction, {} bytes are missing",
+                len,
+                BYTES_IN_WORD - len % BYTES_IN_WORD
+            ),
+        }
+    }
+}
+
+impl Error for ImageError {}
+
+impl From<ImageError> for ArmError {
+    fn from(error: ImageError) -> Self {
+        ArmError::Parse(Box::new(error))
+    }
+}
@@ -0,0 +1,136 @@
+use std::str::FromStr;
+
+use super::{registers::Register, state::EmulatorState};
+use arm11_isa::{address::Address, types::*};
+
+// How the final state is written when the emulator stops
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum OutputFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(OutputFormat::Text),
+            "json" => Ok(OutputFormat::Json),
+            _ => Err(format!(
+                "Invalid output format '{}', expected text or json",
+                s
+            )),
+        }
+    }
+}
+
+impl EmulatorState {
+    // The final state as a JSON object, for tools which compare results rather than read them.
+    // Registers and memory words are numbers, and memory lists every non-zero word in address
+    // order with its value as stored, i.e. little endian. Keys are always written in the same
+    // order, so identical runs give identical output.
+    //
+    // eg: {
+    //   "registers": {"r0": 1, ..., "r12": 0, "sp": 0, "lr": 0, "pc": 12, "cpsr": 1610612736},
+    //   "flags": {"n": false, "z": true, "c": true, "v": false},
+    //   "memory": [{"address": 0, "value": 3818913793}, ...],
+    //   "cycles": 2,
+    //   "instructions": 2
+    // }
+    //
+    pub fn state_to_json(&self) -> String {
+        let cpsr = self.regs().status();
+        let flags: Vec<String> = [
+            ("n", CpsrFlag::N),
+            ("z", CpsrFlag::Z),
+            ("c", CpsrFlag::C),
+            ("v", CpsrFlag::V),
+        ]
+        .iter()
+        .map(|&(name, flag)| format!("\"{}\": {}", name, cpsr.flag(flag)))
+        .collect();
+
+        let memory: Vec<String> = self
+            .non_zero_words()
+            .into_iter()
+            .map(|(address, word)| memory_json(address, word.0))
+            .collect();
+
+        format!(
+            "{{\n  \"registers\": {{{}}},\n  \"flags\": {{{}}},\n  \"memory\": {},\n  \
+             \"cycles\": {},\n  \"instructions\": {}\n}}\n",
+            self.registers_json(),
+            flags.join(", "),
+            memory_array_json(&memory),
+            self.cycles,
+            self.instructions
+        )
+    }
+}
+
+impl EmulatorState {
+    // The registers as the members of a JSON object, eg: "r0": 1, ..., "cpsr": 0
+    pub(super) fn registers_json(&self) -> String {
+        let registers: Vec<String> = self
+            .regs()
+            .iter()
+            .map(|(reg, val)| format!("\"{}\": {}", json_name(reg), val))
+            .collect();
+        registers.join(", ")
+    }
+}
+
+// A word of memory as a JSON object, indented to be an element of memory_array_json
+pub(super) fn memory_json(address: Address, value: u32) -> String {
+    format!("    {{\"address\": {}, \"value\": {}}}", address.0, value)
+}
+
+pub(super) fn memory_array_json(memory: &[String]) -> String {
+    if memory.is_empty() {
+        String::from("[]")
+    } else {
+        format!("[\n{}\n  ]", memory.join(",\n"))
+    }
+}
+
+pub(super) fn json_name(reg: Register) -> String {
+    match reg {
+        Register::Sp => String::from("sp"),
+        Register::Lr => String::from("lr"),
+        Register::Pc => String::from("pc"),
+        Register::Cpsr => String::from("cpsr"),
+        _ => format!("r{}", reg as usize),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_state_to_json() {
+        // mov r0,#1; cmp r0,#1; andeq r0,r0,r0
+        let bytes = [0xe3a00001u32, 0xe3500001, 0]
+            .iter()
+            .flat_map(|w| w.to_le_bytes())
+            .collect();
+        let mut emulator = EmulatorState::with_memory(bytes).expect("load failed");
+        emulator.run().expect("run failed");
+
+        let json = emulator.state_to_json();
+        assert_eq!(
+            json,
+            "{\n  \"registers\": {\"r0\": 1, \"r1\": 0, \"r2\": 0, \"r3\": 0, \"r4\": 0, \
+             \"r5\": 0, \"r6\": 0, \"r7\": 0, \"r8\": 0, \"r9\": 0, \"r10\": 0, \"r11\": 0, \
+             \"r12\": 0, \"sp\": 0, \"lr\": 0, \"pc\": 16, \"cpsr\": 1610612736},\n  \
+             \"flags\": {\"n\": false, \"z\": true, \"c\": true, \"v\": false},\n  \
+             \"memory\": [\n    {\"address\": 0, \"value\": 3818913793},\n    \
+             {\"address\": 4, \"value\": 3813670913}\n  ],\n  \
+             \"cycles\": 2,\n  \"instructions\": 2\n}\n"
+        );
+        assert_eq!("json".parse(), Ok(OutputFormat::Json));
+        assert!("yaml".parse::<OutputFormat>().is_err());
+    }
+}
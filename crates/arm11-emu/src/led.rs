@@ -0,0 +1,75 @@
+use std::{io, io::Write};
+
+use super::gpio::{Gpio, NUM_PINS};
+
+// Width of the timeline printed for each LED, in characters
+const TIMELINE_WIDTH: u64 = 64;
+
+// Parses an LED given as pin=<number>, eg: pin=16
+pub fn parse_led_pin(s: &str) -> std::result::Result<usize, String> {
+    s.strip_prefix("pin=")
+        .and_then(|pin| pin.parse().ok())
+        .filter(|&pin| pin < NUM_PINS)
+        .ok_or_else(|| format!("Invalid LED '{}', expected pin=<0 to {}>", s, NUM_PINS - 1))
+}
+
+// Writes an on/off indicator for an LED whose pin just changed level
+pub fn write_indicator(out: &mut dyn Write, pin: usize, level: bool) -> io::Result<()> {
+    writeln!(
+        out,
+        "LED {: <2} {}",
+        pin,
+        if level { "(*) on" } else { "( ) off" }
+    )
+}
+
+// Writes a timeline of an LED over a run of the given number of instructions. Each character
+// covers an equal share of the run, and is '#' if the LED was on at any point during it, or '_'
+// if it stayed off. Runs shorter than the timeline get one character per instruction.
+//
+// eg: LED 16 |____####____####| 4 transitions over 16 instructions
+//
+pub fn write_timeline(out: &mut dyn Write, gpio: &Gpio, pin: usize, end: u64) -> io::Result<()> {
+    let width = TIMELINE_WIDTH.min(end.max(1));
+    let timeline: String = (0..width)
+        .map(|col| {
+            let start = col * end / width;
+            let stop = ((col + 1) * end / width).max(start + 1);
+            if gpio.high_during(pin, start, stop) {
+                '#'
+            } else {
+                '_'
+            }
+        })
+        .collect();
+
+    writeln!(
+        out,
+        "LED {: <2} |{}| {} transitions over {} instructions",
+        pin,
+        timeline,
+        gpio.transitions(pin),
+        end
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arm11_isa::address::Address;
+
+    #[test]
+    fn test_write_timeline() {
+        let mut gpio = Gpio::new();
+        gpio.write(Address(0x2020001c), 1 << 16, 4);
+        gpio.write(Address(0x20200028), 1 << 16, 8);
+        gpio.write(Address(0x2020001c), 1 << 16, 12);
+
+        let mut out = Vec::new();
+        write_timeline(&mut out, &gpio, 16, 16).expect("timeline failed");
+        assert_eq!(
+            String::from_utf8(out).expect("invalid timeline"),
+            "LED 16 |____####____####| 3 transitions over 16 instructions\n"
+        );
+    }
+}
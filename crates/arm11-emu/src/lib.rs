@@ -0,0 +1,1349 @@
+mod abi;
+mod coprocessor;
+mod coverage;
+mod debugger;
+mod decoders;
+mod dump;
+mod emulator;
+mod exception;
+mod execute;
+mod expect;
+mod fetch;
+mod framebuffer;
+mod gpio;
+mod harness;
+mod history;
+mod image;
+mod json;
+mod led;
+mod loops;
+mod memory;
+mod preload;
+mod profile;
+mod registers;
+mod replay;
+mod search;
+mod serialize;
+mod snapshot;
+mod state;
+mod syscall;
+mod trace;
+mod uart;
+mod vcd;
+mod vectors;
+mod vfp;
+mod watch;
+
+use std::{
+    cell::RefCell,
+    error::Error,
+    fmt, fs,
+    io::{self, Read},
+    rc::Rc,
+    time::{Duration, Instant},
+};
+
+use arm11_isa::{address::Address, listing::Listing, symbols::SymbolFile, timing, types::*};
+use debugger::Unbuffered;
+use state::Decoded;
+
+pub use abi::Abi;
+pub use arm11_isa::parse::parse_number;
+pub use coprocessor::{parse_cpu_id, Cp15, DEFAULT_CPU_ID};
+pub use coverage::Coverage;
+pub use decoders::{Decoder, Frame};
+pub use dump::Dump;
+pub use emulator::Emulator;
+pub use exception::{Exception, Mode, FIQ_LINE, IRQ_LINE};
+pub use expect::{ExpectedState, Mismatch};
+pub use framebuffer::Framebuffer;
+pub use gpio::{parse_gpio_base, Gpio, DEFAULT_GPIO_BASE, PI2_GPIO_BASE};
+pub use harness::{run_program, Capture};
+pub use history::{History, Store};
+pub use image::ImageError;
+pub use json::OutputFormat;
+pub use led::parse_led_pin;
+pub use loops::{Loop, LoopProfile};
+pub use memory::{parse_size, MemoryError, MemoryMap, Mirror, Region};
+pub use preload::{parse_register_value, Load};
+pub use profile::Profile;
+pub use registers::{Register, RegisterFile};
+pub use replay::{Recording, RecordingReader};
+pub use search::Pattern;
+pub use snapshot::Snapshot;
+pub use state::{Config, EmulatorState, PrefetchAbort};
+pub use syscall::Syscall;
+pub use uart::{parse_uart_base, Uart, DEFAULT_UART_BASE};
+pub use vectors::{TestVector, VectorReport};
+pub use vfp::Vfp;
+pub use watch::{Access, WatchHit, Watchpoint};
+
+// Options for the emulator, set from the command line
+#[derive(Debug, Default, Clone)]
+pub struct Options {
+    pub memory_map: MemoryMap,
+    pub peripheral_summary: bool,
+    pub trace: bool,
+    // GPIO pins with an LED attached
+    pub leds: Vec<usize>,
+    // File to write a waveform of the GPIO pins to
+    pub vcd: Option<String>,
+    // File to write the framebuffer to as a PPM image at exit, if the memory map has one
+    pub ppm: Option<String>,
+    // Protocol analysers to run over the GPIO pins at exit
+    pub decoders: Vec<Decoder>,
+    // Whether instructions beyond the base ISA (eg: CP15 accesses) are available, and the CPU ID
+    // they report if so
+    pub extended_isa: bool,
+    pub cpu_id: Option<u32>,
+    // Whether the single precision VFP instructions are available
+    pub vfp: bool,
+    // Base address of a UART connected to stdin and stdout, if any, and the baud rate it
+    // receives at
+    pub uart: Option<u32>,
+    pub uart_baud: Option<u32>,
+    // Whether to report the loops found at exit, and the symbol map used to name addresses in the
+    // loop and profile reports and the debugger
+    pub loops: bool,
+    pub symbols: Option<String>,
+    // How to write the execution profile at exit, if the run is being profiled
+    pub profile: Option<OutputFormat>,
+    // The assembler's listing of the program, to annotate with the instructions reached at
+    // exit, if coverage is being reported
+    pub coverage: Option<String>,
+    // File to record the run to, so that it can be replayed
+    pub record: Option<String>,
+    // Memory ranges to report each load and store of
+    pub watchpoints: Vec<Watchpoint>,
+    // Files to load into memory, and values to set registers to, before the program runs
+    pub loads: Vec<Load>,
+    pub registers: Vec<(Register, u32)>,
+    // Whether to run under the debugger, with commands read from stdin
+    pub debug: bool,
+    // Number of instructions to stop after, rather than running until halt
+    pub run_until: Option<u64>,
+    // Number of instructions, and time, a program may run for before it's stopped as a runaway
+    pub max_steps: Option<u64>,
+    pub timeout: Option<Duration>,
+    // Snapshot files to start the run from, and to save the state to when the run stops
+    pub restore_state: Option<String>,
+    pub save_state: Option<String>,
+    // How the final state is written, and the parts of it to write in place of the full summary
+    pub output: OutputFormat,
+    pub on_halt: Option<Dump>,
+    // Whether the text summary shows consecutive non-zero words of memory as ranges
+    pub memory_ranges: bool,
+    // The calling convention to group the registers by when they are written, if any
+    pub abi: Option<Abi>,
+    // Whether unmapped and unaligned accesses stop the run with a MemoryError
+    pub strict_memory: bool,
+    // JSON file of the values the run must finish with, if they are being checked
+    pub expect_state: Option<String>,
+}
+
+// Whether the emulator can keep running after a step, or has stopped at a watchpoint. Running
+// again after a watchpoint continues from the next instruction.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Status {
+    Running,
+    Halted,
+    Watchpoint(WatchHit),
+}
+
+// Raised when a program doesn't halt within the limits it was run with, eg: when a bad branch
+// sends it round a loop forever. The emulator is left where it stopped, so its state can still be
+// inspected.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RunawayError {
+    MaxSteps(u64),
+    Timeout(Duration),
+}
+
+impl fmt::Display for RunawayError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RunawayError::MaxSteps(steps) => {
+                write!(f, "Program didn't halt within {} instructions", steps)
+            }
+            RunawayError::Timeout(timeout) => {
+                write!(f, "Program didn't halt within {:?}", timeout)
+            }
+        }
+    }
+}
+
+impl Error for RunawayError {}
+
+impl From<RunawayError> for ArmError {
+    fn from(error: RunawayError) -> Self {
+        ArmError::Other(Box::new(error))
+    }
+}
+
+// The number of pipeline steps between checks of the clock when a run has a timeout
+const TIMEOUT_CHECK_STEPS: u64 = 0x10000;
+
+// Summary of a run of the emulator, from loading the binary until it halted, the code it exited
+// with if it stopped with the exit syscall, and the access which stopped it if it stopped at a
+// watchpoint
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RunResult {
+    pub steps: u64,
+    pub instructions: u64,
+    pub cycles: u64,
+    pub exit_code: Option<u32>,
+    pub watchpoint: Option<WatchHit>,
+}
+
+// Runs the binary in the file, or read from stdin if the filename is -
+pub fn run(filename: &str, options: &Options) -> Result<RunResult> {
+    if filename == "-" {
+        // The debugger reads its commands from stdin, which the binary has used up
+        if options.debug {
+            return Err("The binary can't be read from stdin when debugging".into());
+        }
+        run_from(io::stdin(), options)
+    } else {
+        run_from(fs::File::open(filename)?, options)
+    }
+}
+
+// Continues the run saved in the snapshot given by --restore-state, which holds the whole of
+// memory, so no binary is needed
+pub fn resume(options: &Options) -> Result<RunResult> {
+    if options.restore_state.is_none() {
+        return Err("No binary to run, give one or a snapshot with --restore-state".into());
+    }
+    run_from(io::empty(), options)
+}
+
+// Runs the binary read from the input. A program reading from stdin, through the UART or a
+// syscall, still reads from stdin, so finds nothing left when the binary was read from it.
+// Binaries which aren't a whole number of words are an ImageError.
+pub fn run_from(mut input: impl Read, options: &Options) -> Result<RunResult> {
+    let mut bytes = Vec::new();
+    input.read_to_end(&mut bytes)?;
+    ImageError::check(&bytes)?;
+
+    let recording = Recording {
+        memory_map: options.memory_map.clone(),
+        cpu_id: options
+            .extended_isa
+            .then(|| options.cpu_id.unwrap_or(DEFAULT_CPU_ID)),
+        vfp: options.vfp,
+        uart: options.uart,
+        uart_baud: options.uart_baud,
+        image: bytes,
+        loads: options
+            .loads
+            .iter()
+            .map(Load::read)
+            .collect::<Result<Vec<_>>>()?,
+        registers: options.registers.clone(),
+        input: Vec::new(),
+    };
+    run_recording(recording, Box::new(io::stdin()), options)
+}
+
+// Replays a recorded run, using the configuration and UART input from the recording in place of
+// the options given
+pub fn replay(filename: &str, options: &Options) -> Result<RunResult> {
+    let recording = Recording::read(&mut io::BufReader::new(fs::File::open(filename)?))?;
+    let input = io::Cursor::new(recording.input.clone());
+    run_recording(recording, Box::new(input), options)
+}
+
+// Runs the image from the recording, with its UART reading from the given input. If the run is
+// being recorded, the recording is written even when the run fails, so the failure can be
+// replayed.
+fn run_recording(
+    mut recording: Recording,
+    input: Box<dyn Read>,
+    options: &Options,
+) -> Result<RunResult> {
+    // Create emulator and load binary
+    let config = Config {
+        memory_map: recording.memory_map.clone(),
+        cpu_id: recording.cpu_id,
+        vfp: recording.vfp,
+    };
+    let mut emulator = EmulatorState::with_config(recording.image.clone(), &config)?;
+    for (address, bytes) in &recording.loads {
+        emulator.preload(*address, bytes)?;
+    }
+    for &(reg, value) in &recording.registers {
+        emulator.write_reg(reg, value);
+    }
+    emulator.set_output(Box::new(io::stdout()));
+    emulator.leds = options.leds.clone();
+    emulator.abi = options.abi;
+    emulator.strict_memory = options.strict_memory;
+    if let Some(symbols_filename) = &options.symbols {
+        emulator.symbols = Some(SymbolFile::read(symbols_filename)?);
+    }
+    let input_log = Rc::new(RefCell::new(Vec::new()));
+    let input = RecordingReader::new(input, input_log.clone());
+    emulator.set_input(Box::new(input.clone()));
+    if let Some(base) = recording.uart {
+        let mut uart = Uart::new(base, Box::new(input), Box::new(io::stdout()));
+        if let Some(baud) = recording.uart_baud {
+            uart.set_baud(baud);
+        }
+        emulator.uart = Some(uart);
+    }
+    if options.trace {
+        emulator.set_trace(Box::new(io::stdout()));
+    }
+    if options.loops {
+        emulator.loops = Some(LoopProfile::new());
+    }
+    if options.profile.is_some() {
+        emulator.profile = Some(Profile::new());
+    }
+    let listing = options.coverage.as_deref().map(Listing::read).transpose()?;
+    if listing.is_some() {
+        emulator.coverage = Some(Coverage::new());
+    }
+    for &watchpoint in &options.watchpoints {
+        emulator.add_watchpoint(watchpoint);
+    }
+
+    if let Some(snapshot_filename) = &options.restore_state {
+        let mut file = io::BufReader::new(fs::File::open(snapshot_filename)?);
+        emulator.restore(Snapshot::read(&mut file)?);
+    }
+
+    // Run emulator, reporting each access to a watchpoint and carrying on
+    let result = if options.debug {
+        emulator.debug(&mut Unbuffered::new(io::stdin()), &mut io::stdout())
+    } else {
+        loop {
+            let result = match options.run_until {
+                Some(instructions) => emulator.run_until(instructions),
+                None => emulator.run_guarded(options.max_steps, options.timeout),
+            };
+            match result {
+                Ok(RunResult {
+                    watchpoint: Some(hit),
+                    ..
+                }) => println!("{}", hit),
+                _ => break result,
+            }
+        }
+    };
+    if let Some(record_filename) = &options.record {
+        recording.input = input_log.borrow().clone();
+        let mut file = io::BufWriter::new(fs::File::create(record_filename)?);
+        recording.write(&mut file)?;
+    }
+    // A runaway program still has its state written, so it can be seen where it got stuck
+    let (result, runaway) = match result {
+        Err(e) if e.is::<RunawayError>() => (emulator.result(None), Some(e)),
+        result => (result?, None),
+    };
+    if let Some(snapshot_filename) = &options.save_state {
+        let mut file = io::BufWriter::new(fs::File::create(snapshot_filename)?);
+        emulator.save().write(&mut file)?;
+    }
+    match (options.output, &options.on_halt) {
+        (OutputFormat::Text, None) if options.memory_ranges => {
+            emulator.write_state_with_ranges(&mut io::stdout())?
+        }
+        (OutputFormat::Text, None) => emulator.print_state(),
+        (OutputFormat::Json, None) => print!("{}", emulator.state_to_json()),
+        (OutputFormat::Text, Some(dump)) => emulator.write_dump(dump, &mut io::stdout())?,
+        (OutputFormat::Json, Some(dump)) => print!("{}", emulator.dump_to_json(dump)?),
+    }
+    emulator.print_led_timelines();
+    if options.peripheral_summary {
+        emulator.print_peripheral_summary();
+    }
+    let symbols = emulator
+        .symbols
+        .as_ref()
+        .map(|s| s.symbol_table.clone())
+        .unwrap_or_default();
+    if let Some(profile) = &emulator.loops {
+        profile.write_report(&mut io::stdout(), result.cycles, &symbols)?;
+    }
+    match (&emulator.profile, options.profile) {
+        (Some(profile), Some(OutputFormat::Text)) => {
+            profile.write_report(&mut io::stdout(), &symbols)?
+        }
+        (Some(profile), Some(OutputFormat::Json)) => print!("{}", profile.to_json()),
+        _ => (),
+    }
+    if let (Some(coverage), Some(listing)) = (&emulator.coverage, &listing) {
+        coverage.write_report(listing, &mut io::stdout())?;
+    }
+    for decoder in &options.decoders {
+        decoder.write_decoded(&mut io::stdout(), &emulator.gpio)?;
+    }
+    if let Some(vcd_filename) = &options.vcd {
+        let mut file = io::BufWriter::new(fs::File::create(vcd_filename)?);
+        vcd::write_vcd(
+            &mut file,
+            &emulator.gpio,
+            emulator.interrupt_history(),
+            result.instructions,
+        )?;
+    }
+    if let Some(ppm_filename) = &options.ppm {
+        let framebuffer = recording
+            .memory_map
+            .framebuffer
+            .ok_or("--ppm needs a framebuffer, added with --framebuffer")?;
+        let mut file = io::BufWriter::new(fs::File::create(ppm_filename)?);
+        emulator.write_ppm(&framebuffer, &mut file)?;
+    }
+    if let Some(expected_filename) = &options.expect_state {
+        let expected: ExpectedState = fs::read_to_string(expected_filename)?
+            .parse()
+            .map_err(ArmError::parse)?;
+        let mismatches = emulator.compare_state(&expected);
+        for mismatch in &mismatches {
+            println!("Mismatch: {}", mismatch);
+        }
+        if !mismatches.is_empty() {
+            return Err(format!(
+                "{} value{} didn't match the expected state",
+                mismatches.len(),
+                if mismatches.len() == 1 { "" } else { "s" }
+            )
+            .into());
+        }
+    }
+
+    match runaway {
+        Some(e) => Err(e),
+        None => Ok(result),
+    }
+}
+
+impl EmulatorState {
+    // Runs the emulator until it reaches a halt instruction or a watchpoint
+    pub fn run(&mut self) -> Result<RunResult> {
+        self.run_until(u64::MAX)
+    }
+
+    // Runs the emulator until it reaches a halt instruction or a watchpoint, or has executed the
+    // given number of instructions in total
+    pub fn run_until(&mut self, instructions: u64) -> Result<RunResult> {
+        let mut watchpoint = None;
+        while self.instructions < instructions {
+            match self.step()? {
+                Status::Running => (),
+                Status::Halted => break,
+                Status::Watchpoint(hit) => {
+                    watchpoint = Some(hit);
+                    break;
+                }
+            }
+        }
+        Ok(self.result(watchpoint))
+    }
+
+    // Runs the emulator until it reaches a halt instruction, calling on_step after each
+    // instruction is executed with the state and the address of the instruction, eg: to compare
+    // each step with another emulator. The run stops early once on_step returns false.
+    pub fn run_with(
+        &mut self,
+        mut on_step: impl FnMut(&EmulatorState, Address) -> bool,
+    ) -> Result<RunResult> {
+        loop {
+            let address = Address(self.read_reg(Register::Pc))
+                .wrapping_sub(self.instruction_width().pipeline_offset());
+            let instructions = self.instructions;
+            let status = self.step()?;
+            if self.instructions != instructions && !on_step(self, address) {
+                break;
+            }
+            if status == Status::Halted {
+                break;
+            }
+        }
+        Ok(self.result(None))
+    }
+
+    // Runs the emulator as run does, but stops with a RunawayError if the program hasn't halted
+    // once it has executed max_instructions in total, or has run for the timeout
+    pub fn run_guarded(
+        &mut self,
+        max_instructions: Option<u64>,
+        timeout: Option<Duration>,
+    ) -> Result<RunResult> {
+        // the clock is only read with a timeout, as there isn't one on wasm32-unknown-unknown
+        let start = timeout.map(|_| Instant::now());
+        loop {
+            if let Some(max_instructions) = max_instructions {
+                if self.instructions >= max_instructions {
+                    return Err(RunawayError::MaxSteps(max_instructions).into());
+                }
+            }
+            if let (Some(timeout), Some(start)) = (timeout, start) {
+                if self.steps.is_multiple_of(TIMEOUT_CHECK_STEPS) && start.elapsed() >= timeout {
+                    return Err(RunawayError::Timeout(timeout).into());
+                }
+            }
+            match self.step()? {
+                Status::Running => (),
+                Status::Halted => return Ok(self.result(None)),
+                Status::Watchpoint(hit) => return Ok(self.result(Some(hit))),
+            }
+        }
+    }
+
+    // The summary of the run so far
+    fn result(&self, watchpoint: Option<WatchHit>) -> RunResult {
+        RunResult {
+            steps: self.steps,
+            instructions: self.instructions,
+            cycles: self.cycles,
+            exit_code: self.exit_code,
+            watchpoint,
+        }
+    }
+
+    // Advances the pipeline by one step; executing the decoded instruction, decoding the
+    // fetched instruction, and fetching the next instruction. Once the halt instruction
+    // reaches the execute stage, or the program exits with a syscall, this has no effect.
+    pub fn step(&mut self) -> Result<Status> {
+        if self.exit_code.is_some() {
+            return Ok(Status::Halted);
+        }
+
+        // interrupt: taken before the next instruction, once there is one to return to
+        if self.pipeline.decoded.is_some() {
+            if let Some(exception) = self.pending_interrupt()? {
+                let address = Address(self.read_reg(Register::Pc))
+                    .wrapping_sub(self.instruction_width().pipeline_offset());
+                self.take_exception(exception, address.0);
+            }
+        }
+
+        // execute
+        if let Some(fetched) = self.pipeline.decoded {
+            // check: was the fetch aborted?
+            let to_execute = fetched?.instr;
+            let address = Address(self.read_reg(Register::Pc))
+                .wrapping_sub(self.instruction_width().pipeline_offset());
+            // the halt instruction is reached too, so a run which halts covers it
+            if let Some(coverage) = &mut self.coverage {
+                coverage.record(address);
+            }
+            // check: is halt?
+            if let Instruction::Halt = to_execute.instruction {
+                return Ok(Status::Halted);
+            }
+            // execute otherwise, tracing the registers changed
+            let before = *self.regs();
+            let executed = to_execute.satisfies(before.status());
+            execute::execute(self, to_execute)?;
+            let after = *self.regs();
+            if let Some(out) = self.trace() {
+                trace::write_trace(out, address, &to_execute, executed, &before, &after)?;
+            }
+            self.instructions += 1;
+
+            // count the cycles taken, including refilling the pipeline if it was flushed
+            let mut cycles = timing::cycles(&to_execute, executed);
+            let flushed = self.pipeline.decoded.is_none();
+            if flushed {
+                cycles += timing::FLUSH_PENALTY;
+            }
+            self.cycles += cycles;
+            let target = flushed.then(|| Address(self.read_reg(Register::Pc)));
+            if let Some(profile) = &mut self.loops {
+                profile.record(address, cycles, target);
+            }
+            if let Some(profile) = &mut self.profile {
+                profile.record(address, &to_execute, cycles, target);
+            }
+            if self.exit_code.is_some() {
+                return Ok(Status::Halted);
+            }
+        }
+
+        // decode
+        if let Some(fetched) = self.pipeline.fetched {
+            self.pipeline.decoded = Some(match fetched {
+                Ok(raw) => Ok(Decoded::new(raw, self.instruction_width())?),
+                Err(abort) => Err(abort),
+            });
+        }
+
+        // fetch
+        self.pipeline.fetched = Some(fetch::fetch(self));
+        self.steps += 1;
+
+        match self.watch_hit.take() {
+            Some(hit) => Ok(Status::Watchpoint(hit)),
+            None => Ok(Status::Running),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arm11_isa::address::Word;
+
+    #[test]
+    fn test_run_binary() {
+        // mov r1,#1; add r1,r1,r1; andeq r0,r0,r0
+        let bytes: Vec<u8> = [0xe3a01001u32, 0xe0811001, 0x0]
+            .iter()
+            .flat_map(|w| w.to_le_bytes())
+            .collect();
+
+        let mut emulator = EmulatorState::new();
+        emulator.load_binary(&bytes).expect("load failed");
+        let result = emulator.run().expect("run failed");
+
+        assert_eq!(emulator.read_reg(Register::R1), 2);
+        assert_eq!(emulator.regs().pc(), 0x10);
+        assert_eq!(
+            result,
+            RunResult {
+                steps: 4,
+                instructions: 2,
+                cycles: 2,
+                exit_code: None,
+                watchpoint: None,
+            }
+        );
+        assert_eq!(emulator.step().expect("step failed"), Status::Halted);
+
+        // An image too large for the default memory is an error rather than a panic
+        let image = vec![0; MemoryMap::default().ram.size as usize + 4];
+        assert!(EmulatorState::with_memory(image).is_err());
+    }
+
+    #[test]
+    fn test_run_with() {
+        let source = "mov r0,#1\nloop:\nsubs r0,r0,#1\nbne loop\nandeq r0,r0,r0\n";
+        let bytes = arm11_asm::assemble(String::from(source))
+            .expect("assemble failed")
+            .to_bytes();
+
+        // Each executed instruction is passed on, including one skipped by its condition
+        let mut emulator = EmulatorState::with_memory(bytes.clone()).expect("load failed");
+        let mut steps = Vec::new();
+        emulator
+            .run_with(|state, address| {
+                steps.push((address.0, state.read_reg(Register::R0)));
+                true
+            })
+            .expect("run failed");
+        assert_eq!(steps, [(0, 1), (4, 0), (8, 0)]);
+
+        let mut emulator = EmulatorState::with_memory(bytes).expect("load failed");
+        let result = emulator.run_with(|_, _| false).expect("run failed");
+        assert_eq!(result.instructions, 1);
+    }
+
+    #[test]
+    fn test_memory_report() {
+        // mov r0,#1; str r0,[r1,#8]; str r0,[r1,#0xc]; andeq r0,r0,r0, with the last word of a
+        // 16 byte memory written
+        let bytes: Vec<u8> = [0xe3a00001u32, 0xe5810008, 0xe581000c, 0x0]
+            .iter()
+            .flat_map(|w| w.to_le_bytes())
+            .collect();
+        let map = MemoryMap {
+            ram: Region {
+                base: 0,
+                size: 0x10,
+            },
+            ..Default::default()
+        };
+        let mut emulator = EmulatorState::with_memory_map(bytes, &map).expect("create failed");
+        emulator.run().expect("run failed");
+
+        let mut out = Vec::new();
+        emulator.write_state(&mut out).expect("write failed");
+        let out = String::from_utf8(out).expect("invalid utf8");
+        assert!(out.contains(
+            "Non-zero memory:\n0x00000000: 0xe3a00001\n0x00000004: 0xe5810008\n\
+             0x00000008: 0x00000001\n0x0000000c: 0x00000001\n"
+        ));
+
+        let mut out = Vec::new();
+        emulator
+            .write_state_with_ranges(&mut out)
+            .expect("write failed");
+        let out = String::from_utf8(out).expect("invalid utf8");
+        assert!(out.contains(
+            "Non-zero memory:\n0x00000000..0x00000010:\n    \
+             0xe3a00001 0xe5810008 0x00000001 0x00000001\nCycles"
+        ));
+    }
+
+    #[test]
+    fn test_byte_and_halfword_transfers() {
+        let source = "ldr r0,=0x1ff\nstrh r0,[r1,#0x20]\nstrb r0,[r1,#0x23]\nldrsb r2,[r1,#0x20]\n\
+                      ldrh r3,[r1,#0x20]\nldr r4,[r1,#0x20]\nandeq r0,r0,r0\n";
+        let assembled = arm11_asm::assemble(String::from(source)).expect("assemble failed");
+
+        let mut emulator = EmulatorState::new();
+        emulator
+            .load_binary(&assembled.to_bytes())
+            .expect("load failed");
+        emulator.run().expect("run failed");
+
+        assert_eq!(emulator.read_reg(Register::R2), 0xffffffff);
+        assert_eq!(emulator.read_reg(Register::R3), 0x1ff);
+        assert_eq!(emulator.read_reg(Register::R4), 0xff0001ff);
+    }
+
+    #[test]
+    fn test_unsigned_conditions() {
+        // 1 is below 0xffffffff when unsigned, but above -1 when signed
+        let source = "mov r0,#1\nldr r1,=0xffffffff\ncmp r0,r1\nmovlo r2,#1\nmovhi r3,#1\n\
+                      movgt r4,#1\nmovmi r5,#1\ncmp r0,#1\nmovls r6,#1\nmovcs r7,#1\n\
+                      andeq r0,r0,r0\n";
+        let assembled = arm11_asm::assemble(String::from(source)).expect("assemble failed");
+
+        let mut emulator = EmulatorState::new();
+        emulator
+            .load_binary(&assembled.to_bytes())
+            .expect("load failed");
+        emulator.run().expect("run failed");
+
+        let regs: Vec<u32> = (2..=7)
+            .map(|r| emulator.read_reg(Register::from_field(r)))
+            .collect();
+        assert_eq!(regs, vec![1, 0, 1, 0, 1, 1]);
+    }
+
+    #[test]
+    fn test_carry_and_extra_opcodes() {
+        let source = "ldr r0,=0xffffffff\nmov r1,#0\nadds r2,r0,#1\nadc r3,r1,#0\n\
+                      subs r4,r1,#1\nsbc r5,r1,#0\nrsc r6,r1,#2\nmov r7,#0xff\n\
+                      bic r7,r7,#0xf\nmvn r8,#0\ncmn r0,#1\nmoveq r9,#1\nandeq r0,r0,r0\n";
+        let assembled = arm11_asm::assemble(String::from(source)).expect("assemble failed");
+
+        let mut emulator = EmulatorState::new();
+        emulator
+            .load_binary(&assembled.to_bytes())
+            .expect("load failed");
+        emulator.run().expect("run failed");
+
+        let regs: Vec<u32> = (2..=9)
+            .map(|r| emulator.read_reg(Register::from_field(r)))
+            .collect();
+        // adds carries into adc, and subs borrows from sbc and rsc
+        assert_eq!(
+            regs,
+            vec![0, 1, 0xffffffff, 0xffffffff, 1, 0xf0, 0xffffffff, 1]
+        );
+    }
+
+    #[test]
+    fn test_rrx() {
+        // rrx shifts the carry in at the top, and movs sets the carry to the bit shifted out
+        let source = "mov r0,#3\nmovs r1,r0,rrx\nmov r2,r0,rrx\nmovs r3,r1,rrx\n\
+                      mov r4,r0,ror #0\nandeq r0,r0,r0\n";
+        let bytes = arm11_asm::assemble(String::from(source))
+            .expect("assemble failed")
+            .to_bytes();
+        let mut emulator = EmulatorState::with_memory(bytes).expect("load failed");
+        emulator.run().expect("run failed");
+
+        let regs: Vec<u32> = (1..=4)
+            .map(|r| emulator.read_reg(Register::from_field(r)))
+            .collect();
+        assert_eq!(regs, vec![1, 0x80000001, 0x80000000, 0x80000001]);
+        assert!(emulator.regs().status().flag(CpsrFlag::C));
+    }
+
+    #[test]
+    fn test_pc_operands() {
+        // The pc reads 8 bytes ahead of the instruction in ARM state, including straight after a
+        // branch, and 4 bytes ahead in Thumb state, where it is word aligned for addressing
+        let source = "b l\nmov r7,#1\nl:\nmov r0,pc\nbl f\nmov r2,pc\nmov r9,#0x100\n\
+                      stmia r9,{r8,pc}\nldr r4,=t+1\nbx r4\nf:\nmov r1,pc\nmov r3,lr\n\
+                      mov pc,lr\nt:\n.thumb\nmov r5,pc\nadd r6,pc,#4\nandeq r0,r0,r0\n";
+        let bytes = arm11_asm::assemble(String::from(source))
+            .expect("assemble failed")
+            .to_bytes();
+        let mut emulator = EmulatorState::with_memory(bytes).expect("load failed");
+        emulator.run().expect("run failed");
+
+        let regs: Vec<u32> = [0, 1, 2, 3, 5, 6, 7]
+            .iter()
+            .map(|&r| emulator.read_reg(Register::from_field(r)))
+            .collect();
+        assert_eq!(regs, vec![0x10, 0x2c, 0x18, 0x10, 0x34, 0x38, 0]);
+        assert_eq!(
+            emulator.read_memory(Address(0x104)).expect("read failed"),
+            Word(0x20)
+        );
+    }
+
+    #[test]
+    fn test_multiply_long() {
+        let source = "ldr r0,=0xffffffff\nmov r1,#2\numull r2,r3,r0,r1\nsmull r4,r5,r0,r1\n\
+                      mov r6,#1\nmov r7,#0\numlal r6,r7,r0,r1\nmov r8,#0\nmov r9,#0\n\
+                      smlals r8,r9,r0,r1\nandeq r0,r0,r0\n";
+        let assembled = arm11_asm::assemble(String::from(source)).expect("assemble failed");
+
+        let mut emulator = EmulatorState::new();
+        emulator
+            .load_binary(&assembled.to_bytes())
+            .expect("load failed");
+        emulator.run().expect("run failed");
+
+        let regs: Vec<u32> = (2..=9)
+            .map(|r| emulator.read_reg(Register::from_field(r)))
+            .collect();
+        // 0xffffffff * 2 is 0x1_fffffffe unsigned, and -2 signed
+        assert_eq!(
+            regs,
+            vec![0xfffffffe, 1, 0xfffffffe, 0xffffffff, 0xffffffff, 1, 0xfffffffe, 0xffffffff]
+        );
+        // The flags come from the whole 64 bit result
+        assert!(emulator.regs().status().flag(CpsrFlag::N));
+    }
+
+    #[test]
+    fn test_status_transfers() {
+        let source = "mov r0,#1\ncmp r0,#1\nmrs r1,cpsr\nmsr cpsr_f,#0x80000000\nmrs r2,cpsr\n\
+                      msr cpsr_f,r1\nmoveq r3,#1\nmsr cpsr_fc,#0xff\nmrs r4,cpsr\n\
+                      andeq r0,r0,r0\n";
+        let assembled = arm11_asm::assemble(String::from(source)).expect("assemble failed");
+
+        let mut emulator = EmulatorState::new();
+        emulator
+            .load_binary(&assembled.to_bytes())
+            .expect("load failed");
+        emulator.run().expect("run failed");
+
+        let regs: Vec<u32> = (1..=4)
+            .map(|r| emulator.read_reg(Register::from_field(r)))
+            .collect();
+        // The flags can be saved and restored, but the T bit can't be set
+        assert_eq!(regs, vec![0x60000000, 0x80000000, 1, 0xdf]);
+    }
+
+    #[test]
+    fn test_loops() {
+        // An outer loop run twice, around an inner loop run 3 times
+        let source = "mov r0,#2\nouter:\nmov r1,#3\ninner:\nsubs r1,r1,#1\nbne inner\n\
+                      subs r0,r0,#1\nbne outer\nandeq r0,r0,r0\n";
+        let assembled = arm11_asm::assemble(String::from(source)).expect("assemble failed");
+
+        let mut emulator = EmulatorState::with_memory(assembled.to_bytes()).expect("load failed");
+        emulator.loops = Some(LoopProfile::new());
+        emulator.run().expect("run failed");
+
+        let loops = emulator.loops.expect("no loop profile").loops();
+        let summary: Vec<(u32, u32, u64)> = loops
+            .iter()
+            .map(|l| (l.head.0, l.branch.0, l.iterations))
+            .collect();
+        assert_eq!(summary, vec![(0x4, 0x14, 2), (0x8, 0xc, 6)]);
+        assert!(loops[0].cycles > loops[1].cycles);
+    }
+
+    #[test]
+    fn test_transfer_offsets() {
+        // Immediate offsets are plain 12 bit values, so a literal 0x12c bytes away and offsets
+        // which aren't rotated immediates can be reached
+        let source = "ldr r0,=0x12345678\nmov r1,#0x1000\nmov r2,#7\nstr r2,[r1,#0x101]\n\
+                      ldrb r3,[r1,#257]\nstrb r2,[r1,#-4095]\nldrb r4,[r1,#-0xfff]\n\
+                      b end\n.skip 0x110\nend:\nandeq r0,r0,r0\n";
+        let assembled = arm11_asm::assemble(String::from(source)).expect("assemble failed");
+        // ldr r0,[pc,#0x12c]; str r2,[r1,#0x101]
+        assert_eq!(assembled.code[0..4], 0xe59f012cu32.to_le_bytes());
+        assert_eq!(assembled.code[0xc..0x10], 0xe5812101u32.to_le_bytes());
+
+        let mut emulator = EmulatorState::with_memory(assembled.to_bytes()).expect("load failed");
+        emulator.run().expect("run failed");
+        let regs: Vec<u32> = [Register::R0, Register::R3, Register::R4]
+            .iter()
+            .map(|&reg| emulator.read_reg(reg))
+            .collect();
+        assert_eq!(regs, vec![0x12345678, 7, 7]);
+    }
+
+    #[test]
+    fn test_block_transfers() {
+        // Registers are stored lowest first to the lowest address, whatever the mode, and a
+        // written back base moves past the whole list
+        let source = "mov r13,#0x100\nmov r0,#1\nmov r1,#2\nmov r2,#3\npush {r0-r2}\n\
+                      mov r4,#0x200\nstmia r4!,{r0,r2}\nldmdb r4,{r5,r6}\npop {r7-r9}\n\
+                      andeq r0,r0,r0\n";
+        let assembled = arm11_asm::assemble(String::from(source)).expect("assemble failed");
+
+        let mut emulator = EmulatorState::with_memory(assembled.to_bytes()).expect("load failed");
+        emulator.run().expect("run failed");
+        let regs: Vec<u32> = [
+            Register::R4,
+            Register::R5,
+            Register::R6,
+            Register::R7,
+            Register::R8,
+            Register::R9,
+            Register::Sp,
+        ]
+        .iter()
+        .map(|&reg| emulator.read_reg(reg))
+        .collect();
+        assert_eq!(regs, vec![0x208, 1, 3, 1, 2, 3, 0x100]);
+        assert_eq!(
+            emulator.read_memory(Address(0xf4)).expect("read failed"),
+            Word(1)
+        );
+    }
+
+    #[test]
+    fn test_transfer_writeback() {
+        // A pre-indexed transfer with ! leaves its address in the base register, so a loop can
+        // walk a pointer through an array
+        let source = "ldr r1,=data-4\nmov r4,#0x200\nmov r2,#3\nloop:\nldr r3,[r1,#4]!\n\
+                      add r0,r0,r3\nstrh r3,[r4,#-2]!\nsubs r2,r2,#1\nbne loop\n\
+                      andeq r0,r0,r0\ndata:\n.word 1,2,3\n";
+        let assembled = arm11_asm::assemble(String::from(source)).expect("assemble failed");
+        let data = assembled.symbol_table["data"].0;
+
+        let mut emulator = EmulatorState::with_memory(assembled.to_bytes()).expect("load failed");
+        emulator.run().expect("run failed");
+        assert_eq!(emulator.read_reg(Register::R0), 6);
+        assert_eq!(emulator.read_reg(Register::R1), data + 8);
+        assert_eq!(emulator.read_reg(Register::R4), 0x1fa);
+        assert_eq!(
+            emulator.read_memory(Address(0x1fc)).expect("read failed"),
+            Word(0x0001_0002)
+        );
+    }
+
+    #[test]
+    fn test_fixed_point_library() {
+        // 3.25 * -0.5, 7 / -2, 10 / 4 and 3.75 rounded down, in 16.16 fixed point
+        let source = format!(
+            "ldr r4,=0x00034000\nmov r0,r4\nldr r1,=0xffff8000\nbl fix_mul\nmov r5,r0\n\
+             mov r0,#7\nbl fix_from_int\nmov r6,r0\nmvn r0,#1\nbl fix_from_int\nmov r1,r0\n\
+             mov r0,r6\nbl fix_div\nmov r6,r0\nldr r0,=ten\nldr r0,[r0]\n\
+             ldr r1,=four\nldr r1,[r1]\nbl fix_div\nmov r7,r0\nldr r0,=0x0003c000\nbl fix_to_int\nmov r8,r0\nandeq r0,r0,r0\n\
+             ten:\n.float16.16 10\nfour:\n.float16.16 4\n.include \"{}/../../lib/fixed.s\"\n",
+            env!("CARGO_MANIFEST_DIR")
+        );
+        let assembled = arm11_asm::assemble(source).expect("assemble failed");
+
+        let mut emulator = EmulatorState::with_memory(assembled.to_bytes()).expect("load failed");
+        emulator.run().expect("run failed");
+        let regs: Vec<u32> = (5..=8)
+            .map(|r| emulator.read_reg(Register::from_field(r)))
+            .collect();
+        assert_eq!(regs, vec![0xfffe6000, 0xfffc8000, 0x00028000, 3]);
+    }
+
+    #[test]
+    fn test_signed_overflow() {
+        // 0x7fffffff + 1 overflows to a negative number, but is still greater than -1 when signed,
+        // and 0x80000000 - 1 overflows to a positive number, but is still less than 1
+        let source = "ldr r0,=0x7fffffff\ncmn r0,#1\nmovvs r1,#1\nmovgt r2,#1\nmovlt r3,#1\n\
+                      ldr r4,=0x80000000\ncmp r4,#1\nmovvs r5,#1\nmovlt r6,#1\n\
+                      cmp r0,#1\nmovvc r7,#1\nmovgt r8,#1\nandeq r0,r0,r0\n";
+        let assembled = arm11_asm::assemble(String::from(source)).expect("assemble failed");
+
+        let mut emulator = EmulatorState::with_memory(assembled.to_bytes()).expect("load failed");
+        emulator.run().expect("run failed");
+
+        let regs: Vec<u32> = [1, 2, 3, 5, 6, 7, 8]
+            .iter()
+            .map(|&r| emulator.read_reg(Register::from_field(r)))
+            .collect();
+        assert_eq!(regs, vec![1, 1, 0, 1, 1, 1, 1]);
+    }
+
+    #[test]
+    fn test_coprocessor() {
+        // mrc p15, 0, r0, c0, c0, 0; mcr p15, 0, r0, c7, c5, 0; andeq r0,r0,r0
+        let source = "mrc p15, 0, r0, c0, c0, 0\nmcr p15, 0, r0, c7, c5, 0\nandeq r0,r0,r0\n";
+        let bytes = arm11_asm::assemble(String::from(source))
+            .expect("assemble failed")
+            .to_bytes();
+
+        let mut emulator = EmulatorState::with_memory(bytes.clone()).expect("load failed");
+        assert!(emulator.run().is_err());
+
+        let mut emulator = EmulatorState::with_memory(bytes).expect("load failed");
+        emulator.cp15 = Some(Cp15::new(0x1234));
+        emulator.run().expect("run failed");
+        assert_eq!(emulator.read_reg(Register::R0), 0x1234);
+    }
+
+    #[test]
+    fn test_uart() {
+        // Reads characters from the UART, counting them until the input is empty
+        let source = "ldr r0,=0x20201000\nloop:\nldr r1,[r0,#0x18]\ntst r1,#0x10\nbne end\n\
+                      ldr r3,[r0]\nadd r2,r2,#1\nb loop\nend:\nandeq r0,r0,r0\n";
+        let bytes = arm11_asm::assemble(String::from(source))
+            .expect("assemble failed")
+            .to_bytes();
+
+        let mut emulator = EmulatorState::with_memory(bytes).expect("load failed");
+        emulator.uart = Some(Uart::new(
+            DEFAULT_UART_BASE,
+            Box::new(io::Cursor::new(b"abc".to_vec())),
+            Box::new(io::sink()),
+        ));
+        emulator.run().expect("run failed");
+        assert_eq!(emulator.read_reg(Register::R3), u32::from(b'c'));
+        assert_eq!(emulator.read_reg(Register::R2), 3);
+    }
+
+    #[test]
+    fn test_irq() {
+        // Counts the characters received by the UART in its IRQ handler, while the main program
+        // waits in System mode
+        let source = "b start\nb start\nb start\nb start\nb start\nb start\nb irq\n\
+                      start:\nmov r0,#0x12\nmsr cpsr_c,r0\nmov r13,#0x1000\nmov r0,#0x1f\n\
+                      msr cpsr_c,r0\nldr r0,=0x20201000\nmov r1,#0x10\nstr r1,[r0,#0x38]\n\
+                      wait:\ncmp r2,#3\nbne wait\nandeq r0,r0,r0\n\
+                      irq:\nldr r4,=0x20201000\nldr r3,[r4]\nadd r2,r2,#1\nmrs r5,spsr\n\
+                      subs r15,r14,#4\n";
+        let bytes = arm11_asm::assemble(String::from(source))
+            .expect("assemble failed")
+            .to_bytes();
+
+        let mut emulator = EmulatorState::with_memory(bytes).expect("load failed");
+        emulator.uart = Some(Uart::new(
+            DEFAULT_UART_BASE,
+            Box::new(io::Cursor::new(b"abc".to_vec())),
+            Box::new(io::sink()),
+        ));
+        emulator.run().expect("run failed");
+        assert_eq!(emulator.read_reg(Register::R2), 3);
+        assert_eq!(emulator.read_reg(Register::R3), u32::from(b'c'));
+        assert_eq!(emulator.read_reg(Register::R5), 0x1f);
+        // The handler's stack pointer is banked, and the program finishes in System mode
+        assert_eq!(emulator.read_reg(Register::Sp), 0);
+        assert_eq!(emulator.regs().cpsr(), 0x6000001f);
+        assert_eq!(emulator.mode(), Mode::System);
+        let mut summary = Vec::new();
+        emulator
+            .write_peripheral_summary(&mut summary)
+            .expect("write failed");
+        assert_eq!(
+            String::from_utf8_lossy(&summary),
+            "Peripheral activity:\nGPIO: no pins changed level\n\
+             UART: 0 characters sent, 3 received\nInterrupts taken: 3\n"
+        );
+        // Without a baud rate each character arrives as the last is read, so the UART holds the
+        // IRQ line raised until the input runs out
+        let levels: Vec<u32> = emulator
+            .interrupt_history()
+            .iter()
+            .map(|&(_, l)| l)
+            .collect();
+        assert_eq!(levels, [IRQ_LINE, 0]);
+
+        // Masked interrupts aren't taken
+        let mut emulator = EmulatorState::with_memory(vec![0; 8]).expect("load failed");
+        emulator.set_cpsr(0xd3);
+        emulator.irq = true;
+        emulator.fiq = true;
+        assert_eq!(emulator.pending_interrupt().expect("poll failed"), None);
+        emulator.set_cpsr(0x93);
+        assert_eq!(
+            emulator.pending_interrupt().expect("poll failed"),
+            Some(Exception::Fiq)
+        );
+        emulator.step().expect("step failed");
+        emulator.step().expect("step failed");
+        emulator.step().expect("step failed");
+        assert_eq!(emulator.mode(), Mode::Fiq);
+        assert_eq!(emulator.read_reg(Register::Lr), 4);
+        assert_eq!(emulator.spsr(), Some(0x93));
+        assert_eq!(emulator.regs().pc(), 0x1c + 4);
+        assert_eq!(emulator.interrupt_history(), [(0, IRQ_LINE | FIQ_LINE)]);
+
+        // An input which can't be read stops the run, rather than never raising the interrupt
+        struct Broken;
+        impl io::Read for Broken {
+            fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+                Err(io::Error::other("input closed unexpectedly"))
+            }
+        }
+        let mut uart = Uart::new(DEFAULT_UART_BASE, Box::new(Broken), Box::new(io::sink()));
+        uart.write(Address(DEFAULT_UART_BASE + 0x38), 1 << 4)
+            .expect("write failed");
+        let mut emulator = EmulatorState::with_memory(vec![0; 8]).expect("load failed");
+        emulator.uart = Some(uart);
+        assert!(emulator.pending_interrupt().is_err());
+    }
+
+    #[test]
+    fn test_cycles() {
+        // A loop of 3 iterations, where the taken branches each flush the pipeline
+        let source = "mov r0,#3\nloop:\nsub r0,r0,#1\ncmp r0,#0\nbne loop\nandeq r0,r0,r0\n";
+        let bytes = arm11_asm::assemble(String::from(source))
+            .expect("assemble failed")
+            .to_bytes();
+
+        let mut emulator = EmulatorState::with_memory(bytes).expect("load failed");
+        let result = emulator.run().expect("run failed");
+        assert_eq!(result.instructions, 10);
+        assert_eq!(result.cycles, 10 + 2 * timing::FLUSH_PENALTY);
+        assert_eq!(emulator.cycles(), result.cycles);
+    }
+
+    #[test]
+    fn test_thumb_fetch() {
+        // Thumb instructions are fetched a halfword at a time, i.e. movs r0,#1 then a zero
+        // halfword, which halts
+        let mut emulator =
+            EmulatorState::with_memory(vec![0x01, 0x20, 0, 0, 0, 0, 0, 0]).expect("load failed");
+        emulator.set_flags(CpsrFlag::T, true);
+        emulator.step().expect("step failed");
+        assert_eq!(emulator.regs().pc(), 2);
+        let result = emulator.run().expect("run failed");
+        assert_eq!(result.instructions, 1);
+        assert_eq!(emulator.read_reg(Register::R0), 1);
+    }
+
+    #[test]
+    fn test_with_config() {
+        // 1MiB of RAM high in the address space, with the program and its data both in it
+        let source = "mrc p15, 0, r0, c0, c0, 0\nldr r1,=0x100ffffc\nstr r0,[r1]\nldr r2,[r1]\n\
+                      andeq r0,r0,r0\n";
+        let assembled = arm11_asm::assemble(String::from(source)).expect("assemble failed");
+        let config = Config {
+            memory_map: MemoryMap {
+                rom: None,
+                ram: Region::new(0x10000000, 1 << 20),
+                mirrors: Vec::new(),
+                framebuffer: None,
+                gpio_base: DEFAULT_GPIO_BASE,
+            },
+            cpu_id: Some(0x1234),
+            vfp: false,
+        };
+
+        // The literal pool is addressed relative to pc, so the program runs from any address
+        let mut emulator =
+            EmulatorState::with_config(assembled.to_bytes(), &config).expect("emulator failed");
+        emulator.run().expect("run failed");
+        assert_eq!(emulator.read_reg(Register::R2), 0x1234);
+        assert_eq!(
+            emulator
+                .read_memory(Address(0x100ffffc))
+                .expect("read failed"),
+            Word(0x1234)
+        );
+        assert!(emulator.read_memory(Address(0)).is_err());
+    }
+
+    #[test]
+    fn test_gpio_base() {
+        // Turns pin 16 on through the Pi 2's GPIO registers, after reading a function select
+        // register, which gives its own address
+        let source = "ldr r0,=0x3f200000\nldr r1,[r0,#4]\nmov r2,#0x10000\nstr r2,[r0,#0x1c]\n\
+                      ldr r3,=0x20200004\nldr r4,[r3]\nandeq r0,r0,r0\n";
+        let assembled = arm11_asm::assemble(String::from(source)).expect("assemble failed");
+        let config = Config {
+            memory_map: MemoryMap {
+                gpio_base: PI2_GPIO_BASE,
+                ..Default::default()
+            },
+            cpu_id: None,
+            vfp: false,
+        };
+        let mut emulator =
+            EmulatorState::with_config(assembled.to_bytes(), &config).expect("emulator failed");
+        let output = Capture::new();
+        emulator.set_output(Box::new(output.clone()));
+        emulator.run().expect("run failed");
+        assert_eq!(emulator.read_reg(Register::R1), 0x3f200004);
+        assert!(emulator.gpio.level(16));
+
+        // The Pi 1's registers are ordinary, unmapped, memory
+        assert_eq!(emulator.read_reg(Register::R4), 0);
+        let output = output.contents();
+        assert!(output.starts_with("One GPIO pin from 10 to 19 has been accessed\nPIN ON\n"));
+        assert!(output.contains("Out of bounds memory access at address 0x20200004"));
+    }
+
+    #[test]
+    fn test_transfer_address_wrapping() {
+        // Negative offsets from addresses in the top half of the address space
+        let source = "ldr r1,=0x80000100\nmov r0,#7\nstr r0,[r1,#-4]\nldr r2,[r1],#-4\n\
+                      ldr r3,[r1]\nandeq r0,r0,r0\n";
+        let assembled = arm11_asm::assemble(String::from(source)).expect("assemble failed");
+        let config = Config {
+            memory_map: MemoryMap {
+                rom: None,
+                ram: Region::new(0x80000000, 0x1000),
+                mirrors: Vec::new(),
+                framebuffer: None,
+                gpio_base: DEFAULT_GPIO_BASE,
+            },
+            cpu_id: None,
+            vfp: false,
+        };
+
+        let mut emulator =
+            EmulatorState::with_config(assembled.to_bytes(), &config).expect("emulator failed");
+        emulator.run().expect("run failed");
+        assert_eq!(emulator.read_reg(Register::R1), 0x800000fc);
+        assert_eq!(emulator.read_reg(Register::R3), 7);
+    }
+
+    #[test]
+    fn test_prefetch_abort() {
+        let to_bytes =
+            |words: &[u32]| -> Vec<u8> { words.iter().flat_map(|w| w.to_le_bytes()).collect() };
+
+        // Prefetching past the end of memory doesn't abort, as the halt is executed first
+        let map = MemoryMap {
+            rom: None,
+            ram: Region::new(0x0, 0x8),
+            mirrors: Vec::new(),
+            framebuffer: None,
+            gpio_base: DEFAULT_GPIO_BASE,
+        };
+        let mut emulator = EmulatorState::with_memory_map(to_bytes(&[0xe3a01001, 0x0]), &map)
+            .expect("emulator failed");
+        assert!(emulator.run().is_ok());
+
+        // ldr r0,=0x20200000; bx r0
+        let mut emulator = EmulatorState::new();
+        emulator
+            .load_binary(&to_bytes(&[0xe59f0004, 0xe12fff10, 0x0, 0x20200000]))
+            .expect("load failed");
+        let err = emulator
+            .run()
+            .expect_err("branch into peripheral space didn't abort");
+        assert!(matches!(err, ArmError::Memory(_)));
+        assert_eq!(
+            err.downcast_ref::<PrefetchAbort>(),
+            Some(&PrefetchAbort {
+                address: Address(0x20200000),
+                peripheral: true
+            })
+        );
+    }
+
+    #[test]
+    fn test_strict_memory() {
+        let run = |source: &str, strict: bool| {
+            let assembled = arm11_asm::assemble(String::from(source)).expect("assemble failed");
+            let mut emulator =
+                EmulatorState::with_memory(assembled.to_bytes()).expect("load failed");
+            let output = Capture::new();
+            emulator.set_output(Box::new(output.clone()));
+            emulator.strict_memory = strict;
+            emulator.run().map(|_| output.contents())
+        };
+
+        // Unmapped stores are reported and skipped, unless memory is strict
+        let unmapped = "ldr r1,=0x20000\nmov r0,#1\nstr r0,[r1]\nandeq r0,r0,r0\n";
+        let output = run(unmapped, false).expect("run failed");
+        assert!(output.contains("Error: Out of bounds memory access at address 0x00020000"));
+        let err = run(unmapped, true).expect_err("unmapped store didn't stop");
+        assert!(matches!(err, ArmError::Memory(_)));
+        assert_eq!(
+            err.downcast_ref::<MemoryError>(),
+            Some(&MemoryError::OutOfBounds(Address(0x20000)))
+        );
+
+        // Unaligned loads read the bytes at the address, unless memory is strict
+        let unaligned = "mov r0,#1\nldr r2,[r0]\nldrh r3,[r0]\nandeq r0,r0,r0\n";
+        assert!(run(unaligned, false).is_ok());
+        let err = run(unaligned, true).expect_err("unaligned load didn't stop");
+        assert_eq!(
+            err.downcast_ref::<MemoryError>(),
+            Some(&MemoryError::Unaligned(Address(1), 4))
+        );
+    }
+
+    #[test]
+    fn test_run_guarded() {
+        let bytes = arm11_asm::assemble(String::from("mov r0,#1\nloop:\nb loop\n"))
+            .expect("assemble failed")
+            .to_bytes();
+
+        // A runaway program is stopped, leaving its state as it was
+        let mut emulator = EmulatorState::with_memory(bytes.clone()).expect("load failed");
+        let err = emulator
+            .run_guarded(Some(100), None)
+            .expect_err("loop didn't stop");
+        assert_eq!(
+            err.downcast_ref::<RunawayError>(),
+            Some(&RunawayError::MaxSteps(100))
+        );
+        assert_eq!(emulator.instructions, 100);
+        assert_eq!(emulator.read_reg(Register::R0), 1);
+
+        let mut emulator = EmulatorState::with_memory(bytes).expect("load failed");
+        let timeout = Duration::from_millis(10);
+        let err = emulator
+            .run_guarded(None, Some(timeout))
+            .expect_err("loop didn't time out");
+        assert_eq!(
+            err.downcast_ref::<RunawayError>(),
+            Some(&RunawayError::Timeout(timeout))
+        );
+
+        // Programs which halt within the limits run as normal
+        let mut emulator = EmulatorState::with_memory(vec![0; 4]).expect("load failed");
+        let result = emulator
+            .run_guarded(Some(100), Some(timeout))
+            .expect("run failed");
+        assert_eq!(result.instructions, 0);
+    }
+
+    #[test]
+    fn test_undefined_instructions() {
+        // Runs an instruction on the default machine, returning its encoding and the error
+        let run = |source: &str| {
+            let bytes = arm11_asm::assemble(String::from(source))
+                .expect("assemble failed")
+                .to_bytes();
+            let opcode = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+            let err = EmulatorState::with_memory(bytes)
+                .expect("load failed")
+                .run()
+                .expect_err("undefined instruction ran");
+            (opcode, err)
+        };
+
+        for (source, expected_kind, expected_reason) in [
+            (
+                "mrc p14, 0, r0, c0, c0, 0",
+                "coprocessor",
+                "coprocessor p14 is not present",
+            ),
+            (
+                "mrc p15, 0, r0, c0, c0, 0",
+                "coprocessor",
+                "coprocessor p15 needs the extended ISA",
+            ),
+            ("vadd.f32 s0,s1,s2", "VFP", "VFP instructions need --vfp"),
+            ("swi #9", "swi", "there is no service swi #0x9"),
+        ] {
+            match run(source) {
+                (
+                    expected_opcode,
+                    ArmError::BadOpcode {
+                        opcode,
+                        kind,
+                        reason,
+                    },
+                ) => {
+                    assert_eq!(opcode, expected_opcode, "{}", source);
+                    assert_eq!(kind, expected_kind, "{}", source);
+                    assert_eq!(reason.as_deref(), Some(expected_reason), "{}", source);
+                }
+                (_, err) => panic!("{}: expected a bad opcode, got {:?}", source, err),
+            }
+        }
+        assert_eq!(
+            run("swi #9").1.to_string(),
+            "Unsupported swi instruction 0xef000009, there is no service swi #0x9"
+        );
+    }
+}
@@ -0,0 +1,154 @@
+use std::{
+    collections::HashMap,
+    io::{self, Write},
+};
+
+use arm11_isa::address::{Address, SymbolTable};
+
+// Counts how often each instruction is reached and the cycles it takes, along with the backward
+// branches taken, so that the loops in a program can be found after it has run. A loop is the
+// range of instructions from the target of a backward branch to the branch itself.
+#[derive(Debug, Default, Clone)]
+pub struct LoopProfile {
+    // Times reached and cycles taken, by instruction address
+    addresses: HashMap<Address, (u64, u64)>,
+    // Times each backward branch was taken, by branch address and target
+    back_edges: HashMap<(Address, Address), u64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Loop {
+    // The first instruction of the loop, and the backward branch which ends it
+    pub head: Address,
+    pub branch: Address,
+    // The number of times the head of the loop was reached
+    pub iterations: u64,
+    // Cycles spent in the loop, including any loops nested inside it
+    pub cycles: u64,
+}
+
+impl LoopProfile {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Records an instruction reaching the execute stage, and the address it branched to if it
+    // flushed the pipeline
+    pub fn record(&mut self, address: Address, cycles: u64, branch_target: Option<Address>) {
+        let entry = self.addresses.entry(address).or_insert((0, 0));
+        entry.0 += 1;
+        entry.1 += cycles;
+
+        if let Some(target) = branch_target.filter(|&target| target <= address) {
+            *self.back_edges.entry((address, target)).or_insert(0) += 1;
+        }
+    }
+
+    // The loops found, with the most cycles first
+    pub fn loops(&self) -> Vec<Loop> {
+        let mut loops: Vec<Loop> = self
+            .back_edges
+            .keys()
+            .map(|&(branch, head)| Loop {
+                head,
+                branch,
+                iterations: self.addresses.get(&head).map_or(0, |&(count, _)| count),
+                cycles: self
+                    .addresses
+                    .iter()
+                    .filter(|(&address, _)| head <= address && address <= branch)
+                    .map(|(_, &(_, cycles))| cycles)
+                    .sum(),
+            })
+            .collect();
+        loops.sort_by(|a, b| b.cycles.cmp(&a.cycles).then(a.head.cmp(&b.head)));
+        loops
+    }
+
+    // Writes a line for each loop, naming addresses by the nearest symbol before them.
+    // eg: loop+0x4 (0x00000008-0x00000014): 10 iterations, 40 cycles (80.0%)
+    pub fn write_report(
+        &self,
+        out: &mut dyn Write,
+        total_cycles: u64,
+        symbols: &SymbolTable,
+    ) -> io::Result<()> {
+        let loops = self.loops();
+        if loops.is_empty() {
+            return writeln!(out, "Loops: none found");
+        }
+        writeln!(out, "Loops:")?;
+        for l in loops {
+            writeln!(
+                out,
+                "{} ({}-{}): {} iterations, {} cycles ({:.1}%)",
+                symbolise(l.head, symbols),
+                l.head,
+                l.branch,
+                l.iterations,
+                l.cycles,
+                100.0 * l.cycles as f64 / total_cycles.max(1) as f64
+            )?;
+        }
+        Ok(())
+    }
+}
+
+// Names an address by the closest symbol at or before it, eg: loop+0x4. Addresses with no
+// symbol before them are written in hex.
+pub(super) fn symbolise(address: Address, symbols: &SymbolTable) -> String {
+    symbols
+        .iter()
+        .filter(|(_, &symbol)| symbol <= address)
+        .max_by(|(a_name, a), (b_name, b)| a.cmp(b).then(b_name.cmp(a_name)))
+        .map_or(address.to_string(), |(name, &symbol)| {
+            if symbol == address {
+                name.clone()
+            } else {
+                format!("{}+0x{:x}", name, address.offset_from(symbol))
+            }
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_loops() {
+        // A loop from 0x4 to 0xc, run 3 times
+        let mut profile = LoopProfile::new();
+        profile.record(Address(0x0), 1, None);
+        for i in 0..3 {
+            profile.record(Address(0x4), 1, None);
+            profile.record(Address(0x8), 2, None);
+            profile.record(Address(0xc), 1, (i < 2).then_some(Address(0x4)));
+        }
+
+        assert_eq!(
+            profile.loops(),
+            vec![Loop {
+                head: Address(0x4),
+                branch: Address(0xc),
+                iterations: 3,
+                cycles: 12,
+            }]
+        );
+
+        let symbols = vec![
+            (String::from("start"), Address(0x0)),
+            (String::from("loop"), Address(0x4)),
+        ]
+        .into_iter()
+        .collect();
+        let mut out = Vec::new();
+        profile
+            .write_report(&mut out, 16, &symbols)
+            .expect("write report failed");
+        assert_eq!(
+            String::from_utf8(out).expect("report not utf-8"),
+            "Loops:\nloop (0x00000004-0x0000000c): 3 iterations, 12 cycles (75.0%)\n"
+        );
+        assert_eq!(symbolise(Address(0x8), &symbols), "loop+0x4");
+    }
+}
@@ -0,0 +1,370 @@
+use std::{convert::TryInto, error::Error, fmt, str::FromStr};
+
+use super::{framebuffer::Framebuffer, gpio::DEFAULT_GPIO_BASE, parse_number};
+use arm11_isa::{
+    address::{Address, Word},
+    constants::*,
+    types::*,
+};
+
+// A contiguous range of the address space, given as a base address and a size in bytes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Region {
+    pub base: u32,
+    pub size: u32,
+}
+
+impl Region {
+    pub fn new(base: u32, size: u32) -> Self {
+        Region { base, size }
+    }
+
+    // Checks if the len bytes starting at address are all inside the region
+    pub fn contains(&self, address: u32, len: u32) -> bool {
+        address >= self.base
+            && u64::from(address) + u64::from(len) <= u64::from(self.base) + u64::from(self.size)
+    }
+
+    pub fn overlaps(&self, other: &Region) -> bool {
+        u64::from(self.base) < u64::from(other.base) + u64::from(other.size)
+            && u64::from(other.base) < u64::from(self.base) + u64::from(self.size)
+    }
+}
+
+// Parses a region of the form base:size, where both values are either decimal or hexadecimal
+// with a 0x prefix. eg: 0x8000:0x1000
+impl FromStr for Region {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let (base, size) = s
+            .split_once(':')
+            .ok_or_else(|| format!("Invalid region '{}', expected base:size", s))?;
+        Ok(Region::new(parse_number(base)?, parse_number(size)?))
+    }
+}
+
+// A window of the address space which aliases another part of it, so that an access at
+// region.base + n is redirected to target + n. eg: with a mirror of 0x8000:0x8000 to 0x0,
+// 0x8004 and 0x4 refer to the same word.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Mirror {
+    pub region: Region,
+    pub target: u32,
+}
+
+// Parses a mirror of the form base:size=target, eg: 0x8000:0x8000=0x0
+impl FromStr for Mirror {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let (region, target) = s
+            .split_once('=')
+            .ok_or_else(|| format!("Invalid mirror '{}', expected base:size=target", s))?;
+        Ok(Mirror {
+            region: region.parse()?,
+            target: parse_number(target)?,
+        })
+    }
+}
+
+// Parses a memory size, given as a number of bytes with an optional K or M suffix for KiB or
+// MiB. eg: 0x10000, 64K, 1M
+pub fn parse_size(s: &str) -> std::result::Result<u32, String> {
+    let (number, scale) = match s.strip_suffix('K') {
+        Some(number) => (number, 1 << 10),
+        None => match s.strip_suffix('M') {
+            Some(number) => (number, 1 << 20),
+            None => (s, 1),
+        },
+    };
+    parse_number(number)
+        .ok()
+        .and_then(|n| n.checked_mul(scale))
+        .filter(|&size| size > 0)
+        .ok_or_else(|| format!("Invalid memory size '{}'", s))
+}
+
+// The layout of the emulator's memory. The loaded image is placed in ROM if there is one,
+// otherwise at the start of RAM. By default there is no ROM, and the whole of memory is RAM.
+// Mirrors are checked before ROM and RAM, and can alias either of them. A framebuffer, if there
+// is one, has memory of its own alongside RAM. The GPIO registers are at gpio_base, which isn't
+// memory.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MemoryMap {
+    pub rom: Option<Region>,
+    pub ram: Region,
+    pub mirrors: Vec<Mirror>,
+    pub framebuffer: Option<Framebuffer>,
+    pub gpio_base: u32,
+}
+
+impl Default for MemoryMap {
+    fn default() -> Self {
+        MemoryMap {
+            rom: None,
+            ram: Region::new(0, MEMORY_SIZE as u32),
+            mirrors: Vec::new(),
+            framebuffer: None,
+            gpio_base: DEFAULT_GPIO_BASE,
+        }
+    }
+}
+
+// An access which memory can't carry out. Stores to unmapped memory are only errors in strict
+// mode, as is an access which isn't aligned to its size, which is otherwise carried out as an
+// unaligned access, as an ARM11 does.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MemoryError {
+    OutOfBounds(Address),
+    ReadOnly(Address),
+    Unaligned(Address, u32),
+}
+
+impl fmt::Display for MemoryError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MemoryError::OutOfBounds(address) => {
+                write!(f, "Out of bounds memory access at address {}", address)
+            }
+            MemoryError::ReadOnly(address) => {
+                write!(f, "Write to read-only memory at address {}", address)
+            }
+            MemoryError::Unaligned(address, len) => write!(
+                f,
+                "Unaligned {} access at address {}",
+                if *len == 2 { "halfword" } else { "word" },
+                address
+            ),
+        }
+    }
+}
+
+impl Error for MemoryError {}
+
+impl From<MemoryError> for ArmError {
+    fn from(error: MemoryError) -> Self {
+        ArmError::Memory(Box::new(error))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(super) struct Bank {
+    pub(super) region: Region,
+    pub(super) writable: bool,
+    pub(super) bytes: Vec<u8>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Memory {
+    pub(super) banks: Vec<Bank>,
+    pub(super) mirrors: Vec<Mirror>,
+}
+
+impl Memory {
+    // Creates the memory for the given map, and loads the image into it
+    pub fn new(map: &MemoryMap, image: &[u8]) -> Result<Self> {
+        let mut banks = Vec::new();
+        if let Some(rom) = map.rom {
+            if rom.overlaps(&map.ram) {
+                return Err(format!("ROM {:x?} overlaps RAM {:x?}", rom, map.ram).into());
+            }
+            banks.push(Bank::new(rom, false));
+        }
+        banks.push(Bank::new(map.ram, true));
+        if let Some(framebuffer) = map.framebuffer {
+            let region = framebuffer.region();
+            if let Some(bank) = banks.iter().find(|b| b.region.overlaps(&region)) {
+                return Err(
+                    format!("Framebuffer {:x?} overlaps {:x?}", region, bank.region).into(),
+                );
+            }
+            banks.push(Bank::new(region, true));
+        }
+
+        let mut memory = Memory {
+            banks,
+            mirrors: map.mirrors.clone(),
+        };
+        memory.load(image)?;
+        Ok(memory)
+    }
+
+    // Loads an image into the first bank, which is ROM if there is one. This ignores write
+    // protection, and leaves the rest of memory untouched.
+    pub fn load(&mut self, image: &[u8]) -> Result<()> {
+        let image_bank = &mut self.banks[0];
+        if image.len() > image_bank.bytes.len() {
+            return Err(format!(
+                "Image of {} bytes doesn't fit in {} bytes of {}",
+                image.len(),
+                image_bank.bytes.len(),
+                if image_bank.writable { "RAM" } else { "ROM" }
+            )
+            .into());
+        }
+        image_bank.bytes[..image.len()].copy_from_slice(image);
+        Ok(())
+    }
+
+    // The address the image was loaded at, which is where execution starts
+    pub fn image_base(&self) -> Address {
+        Address(self.banks[0].region.base)
+    }
+
+    pub fn is_mapped(&self, address: Address, len: u32) -> bool {
+        self.locate(address, len).is_some()
+    }
+
+    pub fn read_word(&self, address: Address) -> std::result::Result<Word, MemoryError> {
+        let bytes = self.read(address, BYTES_IN_WORD as u32)?;
+        Ok(Word::from_le_bytes(
+            bytes.try_into().expect("read a word of the wrong size"),
+        ))
+    }
+
+    pub fn write_word(
+        &mut self,
+        address: Address,
+        val: Word,
+    ) -> std::result::Result<(), MemoryError> {
+        self.write(address, &val.to_le_bytes())
+    }
+
+    pub fn read(&self, address: Address, len: u32) -> std::result::Result<&[u8], MemoryError> {
+        let (index, offset) = self
+            .locate(address, len)
+            .ok_or(MemoryError::OutOfBounds(address))?;
+        Ok(&self.banks[index].bytes[offset..offset + len as usize])
+    }
+
+    pub fn write(
+        &mut self,
+        address: Address,
+        bytes: &[u8],
+    ) -> std::result::Result<(), MemoryError> {
+        let (index, offset) = self
+            .locate(address, bytes.len() as u32)
+            .ok_or(MemoryError::OutOfBounds(address))?;
+        let bank = &mut self.banks[index];
+        if !bank.writable {
+            return Err(MemoryError::ReadOnly(address));
+        }
+        bank.bytes[offset..offset + bytes.len()].copy_from_slice(bytes);
+        Ok(())
+    }
+
+    // Writes bytes as write does, but ignoring write protection, as a debugger would
+    pub fn poke(&mut self, address: Address, bytes: &[u8]) -> std::result::Result<(), MemoryError> {
+        let (index, offset) = self
+            .locate(address, bytes.len() as u32)
+            .ok_or(MemoryError::OutOfBounds(address))?;
+        self.banks[index].bytes[offset..offset + bytes.len()].copy_from_slice(bytes);
+        Ok(())
+    }
+
+    // The contents of each bank in address order, with its base address
+    pub fn banks(&self) -> impl Iterator<Item = (Address, &[u8])> {
+        let mut banks: Vec<&Bank> = self.banks.iter().collect();
+        banks.sort_by_key(|b| b.region.base);
+        banks
+            .into_iter()
+            .map(|b| (Address(b.region.base), &b.bytes[..]))
+    }
+
+    // Finds the bank containing an access, and the offset of the access into that bank.
+    // Accesses are first redirected through the first mirror containing them, if any.
+    fn locate(&self, Address(address): Address, len: u32) -> Option<(usize, usize)> {
+        let address = self
+            .mirrors
+            .iter()
+            .find(|m| m.region.contains(address, len))
+            .map_or(address, |m| m.target.wrapping_add(address - m.region.base));
+
+        self.banks
+            .iter()
+            .position(|b| b.region.contains(address, len))
+            .map(|index| (index, (address - self.banks[index].region.base) as usize))
+    }
+}
+
+impl Bank {
+    fn new(region: Region, writable: bool) -> Self {
+        Bank {
+            region,
+            writable,
+            bytes: vec![0; region.size as usize],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(parse_size("0x10000"), Ok(0x10000));
+        assert_eq!(parse_size("64K"), Ok(0x10000));
+        assert_eq!(parse_size("2M"), Ok(0x200000));
+        assert!(parse_size("0").is_err());
+        assert!(parse_size("8192M").is_err());
+        assert!(parse_size("1G").is_err());
+    }
+
+    #[test]
+    fn test_parse_region() {
+        assert_eq!(
+            "0x8000:4096".parse::<Region>(),
+            Ok(Region::new(0x8000, 0x1000))
+        );
+        assert!("0x8000".parse::<Region>().is_err());
+    }
+
+    #[test]
+    fn test_rom_ram_split() {
+        let map = MemoryMap {
+            rom: Some(Region::new(0x0, 0x100)),
+            ram: Region::new(0x1000, 0x100),
+            mirrors: Vec::new(),
+            framebuffer: None,
+            gpio_base: DEFAULT_GPIO_BASE,
+        };
+        let mut memory = Memory::new(&map, &[0x01, 0x02, 0x03, 0x04]).expect("memory failed");
+
+        assert_eq!(
+            memory.read_word(Address(0x0)).expect("read failed"),
+            Word(0x04030201)
+        );
+        assert!(memory.write_word(Address(0x0), Word(0)).is_err());
+        assert!(memory.write_word(Address(0x1000), Word(0xdeadbeef)).is_ok());
+        assert_eq!(
+            memory.read_word(Address(0x1000)).expect("read failed"),
+            Word(0xdeadbeef)
+        );
+        assert!(memory.read_word(Address(0x200)).is_err());
+        assert!(memory.read_word(Address(0x10fe)).is_err());
+    }
+
+    #[test]
+    fn test_mirror() {
+        let map = MemoryMap {
+            rom: None,
+            ram: Region::new(0x0, 0x8000),
+            mirrors: vec!["0x8000:0x8000=0x0".parse().expect("parse mirror failed")],
+            framebuffer: None,
+            gpio_base: DEFAULT_GPIO_BASE,
+        };
+        let mut memory = Memory::new(&map, &[]).expect("memory failed");
+
+        memory
+            .write_word(Address(0x8004), Word(0x12345678))
+            .expect("write failed");
+        assert_eq!(
+            memory.read_word(Address(0x4)).expect("read failed"),
+            Word(0x12345678)
+        );
+        assert!(memory.is_mapped(Address(0xfffc), 4));
+        assert!(!memory.is_mapped(Address(0x10000), 4));
+    }
+}
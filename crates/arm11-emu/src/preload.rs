@@ -0,0 +1,96 @@
+use std::{fs, io, str::FromStr};
+
+use super::{parse_number, registers::Register, state::EmulatorState};
+use arm11_isa::{address::Address, types::*};
+
+// A file to load into memory at an address before the program runs, eg: data.bin@0x8000 gives
+// the program a buffer it expects to be filled
+#[derive(Debug, Clone, PartialEq)]
+pub struct Load {
+    pub path: String,
+    pub address: u32,
+}
+
+impl Load {
+    // The address and contents of the file
+    pub fn read(&self) -> Result<(u32, Vec<u8>)> {
+        let bytes = fs::read(&self.path)
+            .map_err(|e| io::Error::new(e.kind(), format!("Can't read '{}': {}", self.path, e)))?;
+        Ok((self.address, bytes))
+    }
+}
+
+// Parses a load of the form file@address, splitting at the last @ so the file can contain one
+impl FromStr for Load {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let (path, address) = s
+            .rsplit_once('@')
+            .filter(|(path, _)| !path.is_empty())
+            .ok_or_else(|| format!("Invalid load '{}', expected file@address", s))?;
+        Ok(Load {
+            path: String::from(path),
+            address: parse_number(address)?,
+        })
+    }
+}
+
+// Parses the value a register starts with, of the form register=value, eg: r0=0x42 or sp=0xff00
+pub fn parse_register_value(s: &str) -> std::result::Result<(Register, u32), String> {
+    let (name, value) = s
+        .split_once('=')
+        .ok_or_else(|| format!("Invalid register value '{}', expected register=value", s))?;
+    let reg = Register::from_name(name).ok_or_else(|| format!("Unknown register '{}'", name))?;
+    Ok((reg, parse_number(value)?))
+}
+
+impl EmulatorState {
+    // Places bytes in memory before the program runs, as a loader would, so ROM can be
+    // preloaded too. The bytes must all be mapped.
+    pub fn preload(&mut self, address: u32, bytes: &[u8]) -> Result<()> {
+        self.memory.poke(Address(address), bytes).map_err(|_| {
+            format!(
+                "Can't load {} bytes at 0x{:08x}, which isn't all memory",
+                bytes.len(),
+                address
+            )
+        })?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_preload() {
+        assert_eq!(
+            "data.bin@0x8000".parse(),
+            Ok(Load {
+                path: String::from("data.bin"),
+                address: 0x8000
+            })
+        );
+        assert!("data.bin".parse::<Load>().is_err());
+        assert!("@0x8000".parse::<Load>().is_err());
+        assert_eq!(
+            parse_register_value("sp=0xff00"),
+            Ok((Register::Sp, 0xff00))
+        );
+        assert_eq!(parse_register_value("r13=4"), Ok((Register::Sp, 4)));
+        assert!(parse_register_value("r16=1").is_err());
+        assert!(parse_register_value("r0").is_err());
+
+        let mut emulator = EmulatorState::with_memory(vec![0; 4]).expect("load failed");
+        emulator
+            .preload(0x100, &[1, 2, 3, 4])
+            .expect("preload failed");
+        assert_eq!(
+            emulator.read_memory(Address(0x100)).expect("read failed").0,
+            0x04030201
+        );
+        assert!(emulator.preload(0xfffe, &[1, 2, 3, 4]).is_err());
+    }
+}
@@ -0,0 +1,274 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    io::{self, Write},
+};
+
+use super::loops::{symbolise, LoopProfile};
+use arm11_isa::{
+    address::{Address, SymbolTable},
+    disassemble::disassemble_instruction,
+    types::*,
+};
+
+// The number of instructions listed as the hottest in the text report
+const HOT_INSTRUCTIONS: usize = 10;
+
+// Execution statistics for a whole run: how often each instruction was reached and the cycles
+// it took, the mix of instruction types, how often each branch was taken, and the loops found.
+// Instructions skipped by their condition are still counted, as they take a cycle.
+#[derive(Debug, Default, Clone)]
+pub struct Profile {
+    // Times reached, cycles taken and the instruction, by instruction address
+    addresses: HashMap<Address, (u64, u64, ConditionalInstruction)>,
+    // Times reached, by instruction type
+    types: BTreeMap<&'static str, u64>,
+    // Times taken and not taken, by branch address
+    branches: BTreeMap<Address, (u64, u64)>,
+    loops: LoopProfile,
+    instructions: u64,
+    cycles: u64,
+}
+
+impl Profile {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Records an instruction reaching the execute stage, and the address it branched to if it
+    // flushed the pipeline. Branches which didn't flush the pipeline weren't taken.
+    pub fn record(
+        &mut self,
+        address: Address,
+        instr: &ConditionalInstruction,
+        cycles: u64,
+        branch_target: Option<Address>,
+    ) {
+        let entry = self.addresses.entry(address).or_insert((0, 0, *instr));
+        entry.0 += 1;
+        entry.1 += cycles;
+        *self.types.entry(instruction_type(instr)).or_insert(0) += 1;
+
+        if let Instruction::Branch(_) | Instruction::BranchExchange(_) = instr.instruction {
+            let entry = self.branches.entry(address).or_insert((0, 0));
+            if branch_target.is_some() {
+                entry.0 += 1;
+            } else {
+                entry.1 += 1;
+            }
+        }
+        self.loops.record(address, cycles, branch_target);
+        self.instructions += 1;
+        self.cycles += cycles;
+    }
+
+    // Writes the report, with the hottest instructions by cycles first, eg:
+    //
+    // Profile: 31 instructions, 47 cycles
+    // Instruction types:
+    //   branch      : 10 (32.3%)
+    //   ...
+    // Hottest instructions:
+    //   loop (0x00000004): 10 times, 10 cycles (21.3%)  add r0, r0, r1
+    //   ...
+    // Branches:
+    //   loop+0xc (0x00000010): taken 9, not taken 1 (90.0% taken)  bne 0x00000004
+    // Loops:
+    // loop (0x00000004-0x00000010): 10 iterations, 46 cycles (97.9%)
+    //
+    pub fn write_report(&self, out: &mut dyn Write, symbols: &SymbolTable) -> io::Result<()> {
+        let percent = |n: u64, total: u64| 100.0 * n as f64 / total.max(1) as f64;
+        writeln!(
+            out,
+            "Profile: {} instructions, {} cycles",
+            self.instructions, self.cycles
+        )?;
+
+        writeln!(out, "Instruction types:")?;
+        for (name, &count) in &self.types {
+            writeln!(
+                out,
+                "  {: <12}: {} ({:.1}%)",
+                name,
+                count,
+                percent(count, self.instructions)
+            )?;
+        }
+
+        writeln!(out, "Hottest instructions:")?;
+        for (address, (count, cycles, instr)) in self.hottest().take(HOT_INSTRUCTIONS) {
+            writeln!(
+                out,
+                "  {} ({}): {} times, {} cycles ({:.1}%)  {}",
+                symbolise(address, symbols),
+                address,
+                count,
+                cycles,
+                percent(cycles, self.cycles),
+                disassemble_instruction(&instr, address.0)
+            )?;
+        }
+
+        if !self.branches.is_empty() {
+            writeln!(out, "Branches:")?;
+        }
+        for (&address, &(taken, not_taken)) in &self.branches {
+            writeln!(
+                out,
+                "  {} ({}): taken {}, not taken {} ({:.1}% taken)  {}",
+                symbolise(address, symbols),
+                address,
+                taken,
+                not_taken,
+                percent(taken, taken + not_taken),
+                disassemble_instruction(&self.addresses[&address].2, address.0)
+            )?;
+        }
+
+        self.loops.write_report(out, self.cycles, symbols)
+    }
+
+    // The profile as a JSON object, with instructions and branches in address order.
+    //
+    // eg: {
+    //   "instructions": 31,
+    //   "cycles": 47,
+    //   "types": {"branch": 10, "processing": 21},
+    //   "addresses": [{"address": 0, "count": 1, "cycles": 1}, ...],
+    //   "branches": [{"address": 16, "taken": 9, "not_taken": 1}],
+    //   "loops": [{"head": 4, "branch": 16, "iterations": 10, "cycles": 46}]
+    // }
+    //
+    pub fn to_json(&self) -> String {
+        let types: Vec<String> = self
+            .types
+            .iter()
+            .map(|(name, count)| format!("\"{}\": {}", name, count))
+            .collect();
+        let mut addresses: Vec<_> = self.addresses.iter().collect();
+        addresses.sort_by_key(|(&address, _)| address);
+        let addresses: Vec<String> = addresses
+            .iter()
+            .map(|(address, (count, cycles, _))| {
+                format!(
+                    "{{\"address\": {}, \"count\": {}, \"cycles\": {}}}",
+                    address.0, count, cycles
+                )
+            })
+            .collect();
+        let branches: Vec<String> = self
+            .branches
+            .iter()
+            .map(|(address, (taken, not_taken))| {
+                format!(
+                    "{{\"address\": {}, \"taken\": {}, \"not_taken\": {}}}",
+                    address.0, taken, not_taken
+                )
+            })
+            .collect();
+        let loops: Vec<String> = self
+            .loops
+            .loops()
+            .iter()
+            .map(|l| {
+                format!(
+                    "{{\"head\": {}, \"branch\": {}, \"iterations\": {}, \"cycles\": {}}}",
+                    l.head.0, l.branch.0, l.iterations, l.cycles
+                )
+            })
+            .collect();
+
+        format!(
+            "{{\n  \"instructions\": {},\n  \"cycles\": {},\n  \"types\": {{{}}},\n  \
+             \"addresses\": [{}],\n  \"branches\": [{}],\n  \"loops\": [{}]\n}}\n",
+            self.instructions,
+            self.cycles,
+            types.join(", "),
+            addresses.join(", "),
+            branches.join(", "),
+            loops.join(", ")
+        )
+    }
+
+    // Every instruction reached, with the most cycles first
+    fn hottest(&self) -> impl Iterator<Item = (Address, (u64, u64, ConditionalInstruction))> {
+        let mut hottest: Vec<_> = self
+            .addresses
+            .iter()
+            .map(|(&address, &entry)| (address, entry))
+            .collect();
+        hottest.sort_by(|(a, (_, a_cycles, _)), (b, (_, b_cycles, _))| {
+            b_cycles.cmp(a_cycles).then(a.cmp(b))
+        });
+        hottest.into_iter()
+    }
+}
+
+// The name an instruction is counted under in the instruction mix
+fn instruction_type(instr: &ConditionalInstruction) -> &'static str {
+    match instr.instruction {
+        Instruction::Processing(_) => "processing",
+        Instruction::Multiply(_) | Instruction::MultiplyLong(_) => "multiply",
+        Instruction::Branch(_) | Instruction::ThumbBranch(_) | Instruction::BranchExchange(_) => {
+            "branch"
+        }
+        Instruction::Transfer(_) => "transfer",
+        Instruction::BlockTransfer(_) => "block",
+        Instruction::Coprocessor(_) => "coprocessor",
+        Instruction::Vfp(_) => "vfp",
+        Instruction::StatusRead(_) | Instruction::StatusWrite(_) => "status",
+        Instruction::SoftwareInterrupt(_) => "swi",
+        Instruction::Halt => "halt",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::EmulatorState;
+
+    #[test]
+    fn test_profile() {
+        // A loop run 3 times, whose branch is taken twice
+        let source = "mov r0,#3\nloop:\nsubs r0,r0,#1\nbne loop\nldr r1,[r0]\nandeq r0,r0,r0\n";
+        let assembled = arm11_asm::assemble(String::from(source)).expect("assemble failed");
+
+        let mut emulator = EmulatorState::with_memory(assembled.to_bytes()).expect("load failed");
+        emulator.profile = Some(Profile::new());
+        let result = emulator.run().expect("run failed");
+        let profile = emulator.profile.expect("no profile");
+        assert_eq!(profile.instructions, result.instructions);
+        assert_eq!(profile.cycles, result.cycles);
+        assert_eq!(profile.branches[&Address(0x8)], (2, 1));
+
+        let symbols = vec![(String::from("loop"), Address(0x4))]
+            .into_iter()
+            .collect();
+        let mut out = Vec::new();
+        profile
+            .write_report(&mut out, &symbols)
+            .expect("write report failed");
+        let out = String::from_utf8(out).expect("report not utf-8");
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(lines[0], "Profile: 8 instructions, 14 cycles");
+        assert_eq!(
+            &lines[1..5],
+            &[
+                "Instruction types:",
+                "  branch      : 3 (37.5%)",
+                "  processing  : 4 (50.0%)",
+                "  transfer    : 1 (12.5%)",
+            ]
+        );
+        assert!(lines.contains(
+            &"  loop+0x4 (0x00000008): taken 2, not taken 1 (66.7% taken)  bne 0x00000004"
+        ));
+        assert_eq!(
+            lines.last(),
+            Some(&"loop (0x00000004-0x00000008): 3 iterations, 10 cycles (71.4%)")
+        );
+
+        let json = profile.to_json();
+        assert!(json.contains("\"types\": {\"branch\": 3, \"processing\": 4, \"transfer\": 1}"));
+        assert!(json.contains("\"branches\": [{\"address\": 8, \"taken\": 2, \"not_taken\": 1}]"));
+    }
+}
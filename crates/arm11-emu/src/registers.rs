@@ -0,0 +1,146 @@
+use std::ops::{Index, IndexMut};
+
+use enum_primitive_derive::Primitive;
+use num_traits::FromPrimitive;
+
+use arm11_isa::{
+    constants::*,
+    types::{CpsrFlag, InstructionWidth, StatusRegister},
+};
+
+// A register in the register file. Instructions can name r0 to r15, while the CPSR is only
+// accessed by the emulator itself.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Primitive)]
+pub enum Register {
+    R0 = 0,
+    R1 = 1,
+    R2 = 2,
+    R3 = 3,
+    R4 = 4,
+    R5 = 5,
+    R6 = 6,
+    R7 = 7,
+    R8 = 8,
+    R9 = 9,
+    R10 = 10,
+    R11 = 11,
+    R12 = 12,
+    Sp = 13,
+    Lr = 14,
+    Pc = 15,
+    Cpsr = 16,
+}
+
+impl Register {
+    // The register named by a 4 bit register field of an instruction, eg: Rd or Rn
+    pub fn from_field(field: u8) -> Self {
+        Register::from_u8(field & mask(RD.size) as u8).expect("4 bit register field")
+    }
+
+    // All registers, in the order they are numbered
+    pub fn all() -> impl Iterator<Item = Register> {
+        (0..NUM_REGS as u8).filter_map(Register::from_u8)
+    }
+
+    // The register with the given name; r0 to r15, sp, lr, pc or cpsr
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "sp" => Some(Register::Sp),
+            "lr" => Some(Register::Lr),
+            "pc" => Some(Register::Pc),
+            "cpsr" => Some(Register::Cpsr),
+            _ => name
+                .strip_prefix('r')
+                .filter(|n| !n.starts_with('0') || *n == "0")
+                .and_then(|n| n.parse::<u8>().ok())
+                .filter(|&n| n < 16)
+                .and_then(Register::from_u8),
+        }
+    }
+}
+
+// The contents of the registers. Registers can be accessed by indexing with a Register, or with
+// the named accessors for the special registers.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct RegisterFile([u32; NUM_REGS]);
+
+impl RegisterFile {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn r(&self, reg: Register) -> u32 {
+        self[reg]
+    }
+
+    pub fn set(&mut self, reg: Register, val: u32) {
+        self[reg] = val;
+    }
+
+    pub fn sp(&self) -> u32 {
+        self[Register::Sp]
+    }
+
+    pub fn lr(&self) -> u32 {
+        self[Register::Lr]
+    }
+
+    pub fn pc(&self) -> u32 {
+        self[Register::Pc]
+    }
+
+    pub fn cpsr(&self) -> u32 {
+        self[Register::Cpsr]
+    }
+
+    // The CPSR, with its flags readable by name
+    pub fn status(&self) -> StatusRegister {
+        StatusRegister(self.cpsr())
+    }
+
+    // The width of the instructions being fetched, i.e. a halfword in Thumb state
+    pub fn instruction_width(&self) -> InstructionWidth {
+        if self.status().flag(CpsrFlag::T) {
+            InstructionWidth::Halfword
+        } else {
+            InstructionWidth::Word
+        }
+    }
+
+    // The contents of every register, with the register holding them
+    pub fn iter(&self) -> impl Iterator<Item = (Register, u32)> + '_ {
+        Register::all().map(move |reg| (reg, self[reg]))
+    }
+}
+
+impl Index<Register> for RegisterFile {
+    type Output = u32;
+
+    fn index(&self, reg: Register) -> &u32 {
+        &self.0[reg as usize]
+    }
+}
+
+impl IndexMut<Register> for RegisterFile {
+    fn index_mut(&mut self, reg: Register) -> &mut u32 {
+        &mut self.0[reg as usize]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_register_file() {
+        let mut regs = RegisterFile::new();
+        regs.set(Register::from_field(15), 0x8);
+        regs[Register::Sp] = 0x1000;
+
+        assert_eq!(regs.pc(), 0x8);
+        assert_eq!(regs.sp(), 0x1000);
+        assert_eq!(regs.r(Register::from_field(13)), 0x1000);
+        assert_eq!(Register::from_field(0x1f), Register::Pc);
+        assert_eq!(regs.iter().count(), NUM_REGS);
+    }
+}
@@ -0,0 +1,248 @@
+use std::{
+    cell::RefCell,
+    io::{self, Read, Write},
+    rc::Rc,
+};
+
+use num_traits::FromPrimitive;
+
+use super::{
+    framebuffer::Framebuffer,
+    gpio::DEFAULT_GPIO_BASE,
+    memory::{MemoryMap, Mirror},
+    registers::Register,
+    serialize::*,
+};
+use arm11_isa::types::*;
+
+// Identifies a recording file, followed by the version of the format
+const MAGIC: &[u8; 4] = b"A11R";
+const VERSION: u32 = 6;
+
+// Everything needed to reproduce a run exactly; the configuration of the emulator, the image it
+// ran with the files loaded alongside it and the registers it started with, and the characters it
+// read from the UART and syscalls, which are its only nondeterministic input.
+//
+// Recordings are stored in a .rr file, with every number a little endian u32:
+//
+// "A11R" version
+// rom?  ram  mirror_count mirror*  cpu_id?  uart?  uart_baud?  framebuffer?  vfp  gpio_base
+// load_count load*  register_count register*  image_len image_bytes  input_len input_bytes
+//
+// where an optional value x? is a flag (0 or 1) followed by the value if the flag is 1, a region
+// is its base and size, a mirror is its region and target, a framebuffer is its base, width and
+// height, vfp is 0 or 1, a load is its address and then its length and bytes, and a register is
+// its number and value. Version 1 recordings, which don't have uart_baud, version 2 recordings,
+// which don't have a framebuffer, version 3 recordings, which don't have vfp, version 4
+// recordings, which don't have gpio_base and so used the Pi 1's, and version 5 recordings, which
+// don't have loads or registers, can still be read.
+//
+#[derive(Debug, Clone, PartialEq)]
+pub struct Recording {
+    pub memory_map: MemoryMap,
+    // The CPU ID reported by CP15, which is only present with the extended ISA
+    pub cpu_id: Option<u32>,
+    pub vfp: bool,
+    pub uart: Option<u32>,
+    pub uart_baud: Option<u32>,
+    pub image: Vec<u8>,
+    // Files loaded into memory at their address, and registers set, before the run started
+    pub loads: Vec<(u32, Vec<u8>)>,
+    pub registers: Vec<(Register, u32)>,
+    pub input: Vec<u8>,
+}
+
+impl Recording {
+    pub fn write(&self, out: &mut dyn Write) -> io::Result<()> {
+        out.write_all(MAGIC)?;
+        write_u32(out, VERSION)?;
+
+        write_optional(out, self.memory_map.rom, write_region)?;
+        write_region(out, self.memory_map.ram)?;
+        write_u32(out, self.memory_map.mirrors.len() as u32)?;
+        for mirror in &self.memory_map.mirrors {
+            write_region(out, mirror.region)?;
+            write_u32(out, mirror.target)?;
+        }
+        write_optional(out, self.cpu_id, write_u32)?;
+        write_optional(out, self.uart, write_u32)?;
+        write_optional(out, self.uart_baud, write_u32)?;
+        write_optional(out, self.memory_map.framebuffer, write_framebuffer)?;
+        write_bool(out, self.vfp)?;
+        write_u32(out, self.memory_map.gpio_base)?;
+        write_u32(out, self.loads.len() as u32)?;
+        for (address, bytes) in &self.loads {
+            write_u32(out, *address)?;
+            write_bytes(out, bytes)?;
+        }
+        write_u32(out, self.registers.len() as u32)?;
+        for &(reg, value) in &self.registers {
+            write_u32(out, reg as u32)?;
+            write_u32(out, value)?;
+        }
+
+        write_bytes(out, &self.image)?;
+        write_bytes(out, &self.input)
+    }
+
+    pub fn read(input: &mut dyn Read) -> Result<Self> {
+        let mut magic = [0; 4];
+        input.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(ArmError::parse(
+                "Not a recording, the file doesn't start with A11R",
+            ));
+        }
+        let version = read_u32(input)?;
+        if !(1..=VERSION).contains(&version) {
+            return Err(ArmError::parse(format!(
+                "Unsupported recording version {}, expected {}",
+                version, VERSION
+            )));
+        }
+
+        let rom = read_optional(input, read_region)?;
+        let ram = read_region(input)?;
+        let mut mirrors = Vec::new();
+        for _ in 0..read_u32(input)? {
+            mirrors.push(Mirror {
+                region: read_region(input)?,
+                target: read_u32(input)?,
+            });
+        }
+        let cpu_id = read_optional(input, read_u32)?;
+        let uart = read_optional(input, read_u32)?;
+        let uart_baud = match version {
+            1 => None,
+            _ => read_optional(input, read_u32)?,
+        };
+        let framebuffer = match version {
+            1 | 2 => None,
+            _ => read_optional(input, read_framebuffer)?,
+        };
+        let vfp = match version {
+            1..=3 => false,
+            _ => read_bool(input)?,
+        };
+        let gpio_base = match version {
+            1..=4 => DEFAULT_GPIO_BASE,
+            _ => read_u32(input)?,
+        };
+        let mut loads = Vec::new();
+        let mut registers = Vec::new();
+        if version >= 6 {
+            for _ in 0..read_u32(input)? {
+                loads.push((read_u32(input)?, read_bytes(input)?));
+            }
+            for _ in 0..read_u32(input)? {
+                let number = read_u32(input)?;
+                let reg = Register::from_u32(number).ok_or_else(|| {
+                    ArmError::parse(format!("Invalid register {} in recording", number))
+                })?;
+                registers.push((reg, read_u32(input)?));
+            }
+        }
+        Ok(Recording {
+            memory_map: MemoryMap {
+                rom,
+                ram,
+                mirrors,
+                framebuffer,
+                gpio_base,
+            },
+            cpu_id,
+            vfp,
+            uart,
+            uart_baud,
+            image: read_bytes(input)?,
+            loads,
+            registers,
+            input: read_bytes(input)?,
+        })
+    }
+}
+
+fn write_framebuffer(out: &mut dyn Write, framebuffer: Framebuffer) -> io::Result<()> {
+    write_u32(out, framebuffer.base)?;
+    write_u32(out, framebuffer.width)?;
+    write_u32(out, framebuffer.height)
+}
+
+fn read_framebuffer(input: &mut dyn Read) -> Result<Framebuffer> {
+    Ok(Framebuffer {
+        base: read_u32(input)?,
+        width: read_u32(input)?,
+        height: read_u32(input)?,
+    })
+}
+
+// Wraps the program's input, keeping a copy of every character read so it can be recorded.
+// Clones read from the same input, so the UART and syscalls can share it.
+#[derive(Clone)]
+pub struct RecordingReader {
+    inner: Rc<RefCell<Box<dyn Read>>>,
+    log: Rc<RefCell<Vec<u8>>>,
+}
+
+impl RecordingReader {
+    pub fn new(inner: Box<dyn Read>, log: Rc<RefCell<Vec<u8>>>) -> Self {
+        RecordingReader {
+            inner: Rc::new(RefCell::new(inner)),
+            log,
+        }
+    }
+}
+
+impl Read for RecordingReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.borrow_mut().read(buf)?;
+        self.log.borrow_mut().extend_from_slice(&buf[..read]);
+        Ok(read)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Region;
+
+    #[test]
+    fn test_recording_round_trip() {
+        let recording = Recording {
+            memory_map: MemoryMap {
+                rom: Some(Region::new(0x8000, 0x1000)),
+                ram: Region::new(0, 0x8000),
+                mirrors: vec![Mirror {
+                    region: Region::new(0x10000, 0x1000),
+                    target: 0x8000,
+                }],
+                framebuffer: Some(Framebuffer {
+                    base: 0x40000000,
+                    width: 320,
+                    height: 240,
+                }),
+                gpio_base: 0x3f200000,
+            },
+            cpu_id: None,
+            vfp: true,
+            uart: Some(0x20201000),
+            uart_baud: Some(115200),
+            image: vec![1, 2, 3],
+            loads: vec![(0x100, vec![4, 5])],
+            registers: vec![(Register::Sp, 0xff00)],
+            input: b"hello".to_vec(),
+        };
+
+        let mut bytes = Vec::new();
+        recording.write(&mut bytes).expect("write failed");
+        assert_eq!(&bytes[..4], b"A11R");
+        assert_eq!(
+            Recording::read(&mut bytes.as_slice()).expect("read failed"),
+            recording
+        );
+
+        bytes[4] = 7;
+        assert!(Recording::read(&mut bytes.as_slice()).is_err());
+        assert!(Recording::read(&mut &b"A11R"[..]).is_err());
+    }
+}
@@ -0,0 +1,167 @@
+use std::{io::Write, str::FromStr};
+
+use super::{parse_number, state::EmulatorState};
+use arm11_isa::{address::Address, constants::*, parse::parse_string, types::*};
+
+// The number of bytes shown either side of a match
+const CONTEXT_BYTES: u32 = 8;
+
+// What to search memory for. Words are compared as they are read by ldr, at word aligned
+// addresses, and strings as bytes at any address.
+//
+// eg: 0xdeadbeef
+//     "hello\n"
+//     --mask 0xffff0000 0x12340000  words whose top halfword is 0x1234
+//
+#[derive(Debug, Clone, PartialEq)]
+pub enum Pattern {
+    Word { value: u32, mask: u32 },
+    Bytes(Vec<u8>),
+}
+
+impl FromStr for Pattern {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let s = s.trim();
+        if s.starts_with('"') {
+            return match parse_string(s) {
+                Ok(("", string)) if !string.is_empty() => Ok(Pattern::Bytes(string.into_bytes())),
+                _ => Err(format!("Invalid string '{}'", s)),
+            };
+        }
+
+        let words: Vec<&str> = s.split_whitespace().collect();
+        match words[..] {
+            [value] => Ok(Pattern::Word {
+                value: parse_number(value)?,
+                mask: u32::MAX,
+            }),
+            ["--mask", mask, value] => {
+                let mask = parse_number(mask)?;
+                Ok(Pattern::Word {
+                    value: parse_number(value)? & mask,
+                    mask,
+                })
+            }
+            _ => Err(format!(
+                "Invalid pattern '{}', expected a word, a \"string\" or --mask mask word",
+                s
+            )),
+        }
+    }
+}
+
+impl EmulatorState {
+    // The addresses in memory which match a pattern, in address order
+    pub fn find(&self, pattern: &Pattern) -> Vec<Address> {
+        let mut matches = Vec::new();
+        for (base, bytes) in self.memory.banks() {
+            let at = |i: usize| base.wrapping_add(i as u32);
+            match pattern {
+                Pattern::Word { value, mask } => {
+                    for (i, word) in bytes.chunks_exact(BYTES_IN_WORD).enumerate() {
+                        let mut le = [0; BYTES_IN_WORD];
+                        le.copy_from_slice(word);
+                        if u32::from_le_bytes(le) & mask == *value {
+                            matches.push(at(i * BYTES_IN_WORD));
+                        }
+                    }
+                }
+                Pattern::Bytes(needle) => {
+                    for (i, window) in bytes.windows(needle.len()).enumerate() {
+                        if window == &needle[..] {
+                            matches.push(at(i));
+                        }
+                    }
+                }
+            }
+        }
+        matches
+    }
+
+    // Writes the address of each match of a pattern, with the memory either side of it as bytes
+    // followed by the same bytes as ASCII. The matched bytes are in brackets, and unmapped bytes
+    // are shown as --, eg:
+    //
+    // 0x00000010: 00 00 00 00 73 61 79 20 [68 65 6c 6c 6f] 00 00 00 78 56 34 12 00  |....say ...
+    //
+    pub fn write_matches(&self, pattern: &Pattern, out: &mut dyn Write) -> Result<()> {
+        let len = match pattern {
+            Pattern::Word { .. } => BYTES_IN_WORD,
+            Pattern::Bytes(needle) => needle.len(),
+        };
+        let matches = self.find(pattern);
+        for &address in &matches {
+            let start = address.wrapping_sub(CONTEXT_BYTES);
+            let end = address.wrapping_add(len as u32 - 1);
+
+            write!(out, "{}:", address)?;
+            let mut ascii = String::new();
+            for i in 0..len as u32 + 2 * CONTEXT_BYTES {
+                let at = start.wrapping_add(i);
+                let byte = self.read_byte(at).ok();
+                let opening = at == address;
+                let closing = at == end;
+                write!(
+                    out,
+                    "{}{}{}",
+                    if opening { " [" } else { " " },
+                    byte.map_or(String::from("--"), |b| format!("{:02x}", b)),
+                    if closing { "]" } else { "" }
+                )?;
+                ascii.push(match byte {
+                    Some(b) if b.is_ascii_graphic() || b == b' ' => char::from(b),
+                    _ => '.',
+                });
+            }
+            writeln!(out, "  |{}|", ascii)?;
+        }
+        writeln!(
+            out,
+            "{} match{}",
+            matches.len(),
+            if matches.len() == 1 { "" } else { "es" }
+        )?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arm11_asm::assemble;
+
+    #[test]
+    fn test_find() {
+        let source = "ldr r0,=0x12345678\nandeq r0,r0,r0\n.skip 4\nmsg:\n.ascii \"say hello\"\n";
+        let bytes = assemble(String::from(source))
+            .expect("assemble failed")
+            .to_bytes();
+        let emulator = EmulatorState::with_memory(bytes).expect("load failed");
+
+        let word = "0x12345678".parse().expect("parse failed");
+        let matches = emulator.find(&word);
+        assert_eq!(matches.len(), 1);
+        let masked = "--mask 0xffff0000 0x12340000"
+            .parse()
+            .expect("parse failed");
+        assert_eq!(emulator.find(&masked), matches);
+
+        let hello: Pattern = "\"hello\"".parse().expect("parse failed");
+        assert_eq!(emulator.find(&hello), vec![Address(0x10)]);
+        let mut out = Vec::new();
+        emulator
+            .write_matches(&hello, &mut out)
+            .expect("write failed");
+        assert_eq!(
+            String::from_utf8(out).expect("invalid utf8"),
+            "0x00000010: 00 00 00 00 73 61 79 20 [68 65 6c 6c 6f] 00 00 00 78 56 34 12 00  \
+             |....say hello...xV4..|\n1 match\n"
+        );
+
+        assert!("\"\"".parse::<Pattern>().is_err());
+        assert!("--mask 0xff".parse::<Pattern>().is_err());
+        assert!("hello".parse::<Pattern>().is_err());
+    }
+}
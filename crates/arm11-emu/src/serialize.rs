@@ -0,0 +1,112 @@
+use std::io::{self, Read, Write};
+
+use super::memory::Region;
+use arm11_isa::types::*;
+
+// Helpers for the binary files the emulator saves its state to, where every value is little
+// endian. Flags are stored as a u32 of 0 or 1, an optional value is a flag followed by the value
+// if it is present, and a byte string is its length followed by the bytes.
+
+pub fn write_u32(out: &mut dyn Write, value: u32) -> io::Result<()> {
+    out.write_all(&value.to_le_bytes())
+}
+
+pub fn write_u64(out: &mut dyn Write, value: u64) -> io::Result<()> {
+    out.write_all(&value.to_le_bytes())
+}
+
+pub fn write_bool(out: &mut dyn Write, value: bool) -> io::Result<()> {
+    write_u32(out, u32::from(value))
+}
+
+pub fn write_region(out: &mut dyn Write, region: Region) -> io::Result<()> {
+    write_u32(out, region.base)?;
+    write_u32(out, region.size)
+}
+
+pub fn write_optional<T>(
+    out: &mut dyn Write,
+    value: Option<T>,
+    write: fn(&mut dyn Write, T) -> io::Result<()>,
+) -> io::Result<()> {
+    write_bool(out, value.is_some())?;
+    match value {
+        Some(value) => write(out, value),
+        None => Ok(()),
+    }
+}
+
+pub fn write_bytes(out: &mut dyn Write, bytes: &[u8]) -> io::Result<()> {
+    write_u32(out, bytes.len() as u32)?;
+    out.write_all(bytes)
+}
+
+pub fn read_u32(input: &mut dyn Read) -> Result<u32> {
+    let mut bytes = [0; 4];
+    input.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+pub fn read_u64(input: &mut dyn Read) -> Result<u64> {
+    let mut bytes = [0; 8];
+    input.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+pub fn read_bool(input: &mut dyn Read) -> Result<bool> {
+    match read_u32(input)? {
+        0 => Ok(false),
+        1 => Ok(true),
+        flag => Err(ArmError::parse(format!(
+            "Invalid flag {}, expected 0 or 1",
+            flag
+        ))),
+    }
+}
+
+pub fn read_region(input: &mut dyn Read) -> Result<Region> {
+    Ok(Region::new(read_u32(input)?, read_u32(input)?))
+}
+
+pub fn read_optional<T>(
+    input: &mut dyn Read,
+    read: fn(&mut dyn Read) -> Result<T>,
+) -> Result<Option<T>> {
+    match read_bool(input)? {
+        false => Ok(None),
+        true => Ok(Some(read(input)?)),
+    }
+}
+
+// The bytes are read as they arrive, rather than allocating the length first, so that a corrupt
+// length can't ask for gigabytes of memory
+pub fn read_bytes(input: &mut dyn Read) -> Result<Vec<u8>> {
+    let len = read_u32(input)?;
+    let mut bytes = Vec::new();
+    Read::take(&mut *input, u64::from(len)).read_to_end(&mut bytes)?;
+    if bytes.len() as u64 != u64::from(len) {
+        return Err(ArmError::parse(format!(
+            "Expected {} bytes, but only {} were left",
+            len,
+            bytes.len()
+        )));
+    }
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_bytes() {
+        let mut out = Vec::new();
+        write_bytes(&mut out, b"hi").expect("write failed");
+        assert_eq!(read_bytes(&mut out.as_slice()).expect("read failed"), b"hi");
+
+        // A length past the end of the file is an error, without allocating it
+        let mut truncated = u32::MAX.to_le_bytes().to_vec();
+        truncated.extend_from_slice(b"hi");
+        assert!(read_bytes(&mut truncated.as_slice()).is_err());
+    }
+}
@@ -0,0 +1,353 @@
+use std::io::{self, Read, Write};
+
+use super::{
+    coprocessor::Cp15,
+    exception::BankedRegisters,
+    gpio::{Gpio, NUM_PINS},
+    memory::{Bank, Memory, Mirror},
+    registers::{Register, RegisterFile},
+    serialize::*,
+    state::{Decoded, EmulatorState, Fetched, Pipeline, PrefetchAbort},
+    vfp::{Vfp, NUM_SINGLE_REGS},
+};
+use arm11_isa::{address::Address, types::*};
+
+// Identifies a snapshot file, followed by the version of the format
+const MAGIC: &[u8; 4] = b"A11S";
+const VERSION: u32 = 4;
+
+// The complete state of the emulator at a point in a run, which can be restored to continue the
+// run from that point. The UART and the outputs aren't included, as they are connected to the
+// outside world rather than being part of the emulated machine.
+//
+// Snapshots are stored with the helpers in serialize, as:
+//
+// "A11S" version
+// registers  banked_registers  fetched  decoded  cp15?  vfp?  steps instructions cycles (u64)
+// levels transitions history_len (time (u64), levels)*
+// bank_count (region writable bytes)*  mirror_count (region target)*
+//
+// where a pipeline stage is 0 if it is empty, 1 followed by the word or halfword fetched, or 2
+// followed by the address and peripheral flag of a prefetch abort, and the VFP is its registers
+// and FPSCR. The decoded stage is decoded again when it is read, as Thumb if the CPSR is in
+// Thumb state. Version 1 snapshots, which don't have the banked registers, and version 2
+// snapshots, which don't have the VFP, can still be read, as can version 3 snapshots, whose
+// decoded stage is the ARM encoding of the instruction, unless they were taken in Thumb state.
+//
+#[derive(Debug, Clone, PartialEq)]
+pub struct Snapshot {
+    registers: RegisterFile,
+    banked: BankedRegisters,
+    memory: Memory,
+    pipeline: Pipeline,
+    gpio: Gpio,
+    cp15: Option<Cp15>,
+    vfp: Option<Vfp>,
+    steps: u64,
+    instructions: u64,
+    cycles: u64,
+}
+
+impl EmulatorState {
+    // Saves the state of the emulator, so that it can be restored later
+    pub fn save(&self) -> Snapshot {
+        Snapshot {
+            registers: *self.regs(),
+            banked: self.banked,
+            memory: self.memory.clone(),
+            pipeline: self.pipeline.clone(),
+            gpio: self.gpio.clone(),
+            cp15: self.cp15,
+            vfp: self.vfp,
+            steps: self.steps,
+            instructions: self.instructions,
+            cycles: self.cycles,
+        }
+    }
+
+    // Restores a saved state, keeping the current UART, GPIO base address, outputs and trace
+    pub fn restore(&mut self, snapshot: Snapshot) {
+        for (reg, val) in snapshot.registers.iter() {
+            self.write_reg(reg, val);
+        }
+        self.banked = snapshot.banked;
+        self.memory = snapshot.memory;
+        self.pipeline = snapshot.pipeline;
+        self.gpio = Gpio {
+            base: self.gpio.base,
+            ..snapshot.gpio
+        };
+        self.cp15 = snapshot.cp15;
+        self.vfp = snapshot.vfp;
+        self.steps = snapshot.steps;
+        self.instructions = snapshot.instructions;
+        self.cycles = snapshot.cycles;
+    }
+}
+
+impl Snapshot {
+    // The number of instructions executed when the snapshot was taken
+    pub fn instructions(&self) -> u64 {
+        self.instructions
+    }
+
+    pub fn write(&self, out: &mut dyn Write) -> io::Result<()> {
+        out.write_all(MAGIC)?;
+        write_u32(out, VERSION)?;
+
+        for (_, val) in self.registers.iter() {
+            write_u32(out, val)?;
+        }
+        for val in self.banked.words() {
+            write_u32(out, val)?;
+        }
+        write_stage(out, self.pipeline.fetched)?;
+        write_stage(out, self.pipeline.decoded.map(|d| d.map(|d| d.raw)))?;
+        write_optional(out, self.cp15, |out, cp15| {
+            write_u32(out, cp15.read(0, 0, 0, 0))?;
+            write_u32(out, cp15.read(1, 0, 0, 0))
+        })?;
+        write_optional(out, self.vfp, |out, vfp| {
+            for s in 0..NUM_SINGLE_REGS as u8 {
+                write_u32(out, vfp.read(s))?;
+            }
+            write_u32(out, vfp.fpscr)
+        })?;
+        write_u64(out, self.steps)?;
+        write_u64(out, self.instructions)?;
+        write_u64(out, self.cycles)?;
+
+        write_u32(out, self.gpio.levels)?;
+        for &transitions in &self.gpio.transitions {
+            write_u32(out, transitions)?;
+        }
+        write_u32(out, self.gpio.history.len() as u32)?;
+        for &(time, levels) in &self.gpio.history {
+            write_u64(out, time)?;
+            write_u32(out, levels)?;
+        }
+
+        write_u32(out, self.memory.banks.len() as u32)?;
+        for bank in &self.memory.banks {
+            write_region(out, bank.region)?;
+            write_bool(out, bank.writable)?;
+            write_bytes(out, &bank.bytes)?;
+        }
+        write_u32(out, self.memory.mirrors.len() as u32)?;
+        for mirror in &self.memory.mirrors {
+            write_region(out, mirror.region)?;
+            write_u32(out, mirror.target)?;
+        }
+        Ok(())
+    }
+
+    pub fn read(input: &mut dyn Read) -> Result<Self> {
+        let mut magic = [0; 4];
+        input.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(ArmError::parse(
+                "Not a snapshot, the file doesn't start with A11S",
+            ));
+        }
+        let version = read_u32(input)?;
+        if version == 0 || version > VERSION {
+            return Err(ArmError::parse(format!(
+                "Unsupported snapshot version {}, expected {}",
+                version, VERSION
+            )));
+        }
+
+        let mut registers = RegisterFile::new();
+        for reg in Register::all() {
+            registers[reg] = read_u32(input)?;
+        }
+        let mut banked = BankedRegisters::default();
+        if version >= 2 {
+            for val in banked.words_mut() {
+                *val = read_u32(input)?;
+            }
+        }
+        let fetched = read_stage(input)?;
+        let width = registers.instruction_width();
+        let decoded = match read_stage(input)? {
+            Some(Ok(_)) if version <= 3 && width == InstructionWidth::Halfword => {
+                return Err(ArmError::parse(
+                    "Version 3 snapshots taken in Thumb state can't be restored",
+                ));
+            }
+            Some(Ok(raw)) => Some(Ok(Decoded::new(raw, width)?)),
+            Some(Err(abort)) => Some(Err(abort)),
+            None => None,
+        };
+        let cp15 = read_optional(input, |input| {
+            let mut cp15 = Cp15::new(read_u32(input)?);
+            cp15.write(1, 0, 0, 0, read_u32(input)?);
+            Ok(cp15)
+        })?;
+        let vfp = match version {
+            1 | 2 => None,
+            _ => read_optional(input, |input| {
+                let mut vfp = Vfp::new();
+                for s in 0..NUM_SINGLE_REGS as u8 {
+                    vfp.write(s, read_u32(input)?);
+                }
+                vfp.fpscr = read_u32(input)?;
+                Ok(vfp)
+            })?,
+        };
+        let steps = read_u64(input)?;
+        let instructions = read_u64(input)?;
+        let cycles = read_u64(input)?;
+
+        let mut gpio = Gpio::new();
+        gpio.levels = read_u32(input)?;
+        for pin in 0..NUM_PINS {
+            gpio.transitions[pin] = read_u32(input)?;
+        }
+        for _ in 0..read_u32(input)? {
+            gpio.history.push((read_u64(input)?, read_u32(input)?));
+        }
+
+        let mut banks = Vec::new();
+        for _ in 0..read_u32(input)? {
+            let region = read_region(input)?;
+            let writable = read_bool(input)?;
+            let bytes = read_bytes(input)?;
+            if bytes.len() != region.size as usize {
+                return Err(ArmError::parse(format!(
+                    "Memory bank {:x?} has {} bytes",
+                    region,
+                    bytes.len()
+                )));
+            }
+            banks.push(Bank {
+                region,
+                writable,
+                bytes,
+            });
+        }
+        let mut mirrors = Vec::new();
+        for _ in 0..read_u32(input)? {
+            mirrors.push(Mirror {
+                region: read_region(input)?,
+                target: read_u32(input)?,
+            });
+        }
+        if banks.is_empty() {
+            return Err(ArmError::parse("Snapshot has no memory"));
+        }
+
+        Ok(Snapshot {
+            registers,
+            banked,
+            memory: Memory { banks, mirrors },
+            pipeline: Pipeline { fetched, decoded },
+            gpio,
+            cp15,
+            vfp,
+            steps,
+            instructions,
+            cycles,
+        })
+    }
+}
+
+fn write_stage(out: &mut dyn Write, stage: Option<Fetched<u32>>) -> io::Result<()> {
+    match stage {
+        None => write_u32(out, 0),
+        Some(Ok(word)) => {
+            write_u32(out, 1)?;
+            write_u32(out, word)
+        }
+        Some(Err(abort)) => {
+            write_u32(out, 2)?;
+            write_u32(out, abort.address.0)?;
+            write_bool(out, abort.peripheral)
+        }
+    }
+}
+
+fn read_stage(input: &mut dyn Read) -> Result<Option<Fetched<u32>>> {
+    match read_u32(input)? {
+        0 => Ok(None),
+        1 => Ok(Some(Ok(read_u32(input)?))),
+        2 => Ok(Some(Err(PrefetchAbort {
+            address: Address(read_u32(input)?),
+            peripheral: read_bool(input)?,
+        }))),
+        tag => Err(ArmError::parse(format!(
+            "Invalid pipeline stage {} in snapshot",
+            tag
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_snapshot() {
+        // Counts r0 up to 10, toggling a GPIO pin on the way
+        let source = "ldr r2,=0x2020001c\nmov r3,#1\nstr r3,[r2]\nloop:\nadd r0,r0,#1\n\
+                      cmp r0,#10\nbne loop\nandeq r0,r0,r0\n";
+        let bytes = arm11_asm::assemble(String::from(source))
+            .expect("assemble failed")
+            .to_bytes();
+
+        let mut emulator = EmulatorState::with_memory(bytes).expect("load failed");
+        emulator.cp15 = Some(Cp15::new(0x1234));
+        emulator.vfp = Some(Vfp::new());
+        emulator.run_until(8).expect("run failed");
+        let snapshot = emulator.save();
+        let r0 = emulator.read_reg(Register::R0);
+
+        emulator.run().expect("run failed");
+        let finished = emulator.save();
+        assert_eq!(emulator.read_reg(Register::R0), 10);
+
+        // Restoring goes back to the middle of the loop, and running again finishes the same way
+        emulator.restore(snapshot.clone());
+        assert_eq!(emulator.read_reg(Register::R0), r0);
+        emulator.run().expect("run failed");
+        assert_eq!(emulator.save(), finished);
+
+        // Snapshots survive being written to a file
+        let mut file = Vec::new();
+        snapshot.write(&mut file).expect("write failed");
+        assert_eq!(
+            Snapshot::read(&mut file.as_slice()).expect("read failed"),
+            snapshot
+        );
+        assert!(Snapshot::read(&mut &file[..file.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn test_thumb_snapshot() {
+        // Sums an array in Thumb code, which a snapshot can be taken in the middle of wherever
+        // the pipeline is, including when a bl is being decoded
+        let source = "mov r13,#0x1000\nldr r0,=main+1\nbx r0\n.thumb\nmain:\nmovs r2,#3\n\
+                      movs r0,#0\nloop:\nbl add\nsubs r2,r2,#1\nbne loop\nldr r3,=done\n\
+                      bx r3\nadd:\nadds r0,r0,r2\nbx lr\n.arm\ndone:\nandeq r0,r0,r0\n";
+        let bytes = arm11_asm::assemble(String::from(source))
+            .expect("assemble failed")
+            .to_bytes();
+
+        let mut emulator = EmulatorState::with_memory(bytes.clone()).expect("load failed");
+        emulator.run().expect("run failed");
+        let finished = emulator.save();
+        assert_eq!(emulator.read_reg(Register::R0), 6);
+
+        for n in 1..finished.instructions() {
+            let mut emulator = EmulatorState::with_memory(bytes.clone()).expect("load failed");
+            emulator.run_until(n).expect("run failed");
+            let mut file = Vec::new();
+            emulator.save().write(&mut file).expect("write failed");
+
+            let mut restored = EmulatorState::new();
+            restored.restore(Snapshot::read(&mut file.as_slice()).expect("read failed"));
+            restored.run().expect("run failed");
+            assert_eq!(restored.save(), finished, "{}", n);
+        }
+    }
+}
@@ -0,0 +1,517 @@
+use std::{
+    convert::TryInto,
+    error::Error,
+    fmt, io,
+    io::{Read, Write},
+};
+
+use super::{
+    abi::Abi,
+    coprocessor::Cp15,
+    coverage::Coverage,
+    exception::BankedRegisters,
+    gpio::Gpio,
+    history::History,
+    led,
+    loops::LoopProfile,
+    memory::{Memory, MemoryError, MemoryMap},
+    profile::Profile,
+    registers::{Register, RegisterFile},
+    uart::Uart,
+    vfp::Vfp,
+    watch::{WatchHit, Watchpoint},
+};
+use arm11_isa::address::{Address, Word};
+use arm11_isa::constants::*;
+use arm11_isa::symbols::SymbolFile;
+use arm11_isa::types::*;
+use arm11_isa::{decode, thumb};
+
+// The number of words on each line of a range in the memory report
+const WORDS_PER_ROW: usize = 4;
+
+pub struct EmulatorState {
+    pub(super) memory: Memory,
+    register_file: RegisterFile,
+    // The registers of the modes other than the current one
+    pub(super) banked: BankedRegisters,
+    pub pipeline: Pipeline,
+    pub gpio: Gpio,
+    // GPIO pins with an LED attached, which are shown when they change level
+    pub leds: Vec<usize>,
+    // The system control coprocessor, which is only present with the extended ISA
+    pub cp15: Option<Cp15>,
+    // The single precision VFP, if it is enabled
+    pub vfp: Option<Vfp>,
+    // A memory-mapped UART, if one is attached
+    pub uart: Option<Uart>,
+    // The interrupt lines, which library users can raise to interrupt the program. They are
+    // level sensitive, so stay raised until they are cleared.
+    pub irq: bool,
+    pub fiq: bool,
+    // Counts of the instructions executed and branches taken, if loops are being reported
+    pub loops: Option<LoopProfile>,
+    // Execution statistics for the whole run, if it is being profiled
+    pub profile: Option<Profile>,
+    // The instructions reached, if coverage is being reported
+    pub coverage: Option<Coverage>,
+    // The calling convention to name and group the registers by when they are written, if any
+    pub abi: Option<Abi>,
+    // Every store the program has made, if they are being kept for the debugger
+    pub history: Option<History>,
+    // The symbols and line table of the program, if they were loaded, to name addresses by
+    pub symbols: Option<SymbolFile>,
+    // Whether accesses to unmapped memory and unaligned accesses stop the emulator with a
+    // MemoryError, rather than being reported and skipped or carried out unaligned
+    pub strict_memory: bool,
+    // Where messages from the emulated program (eg: GPIO accesses) are written, and where its
+    // syscalls read from
+    output: Box<dyn Write>,
+    input: Box<dyn Read>,
+    // The code the program exited with, once it has asked to exit with a syscall
+    pub(super) exit_code: Option<u32>,
+    // The memory ranges which stop execution when accessed, and the access which last did so
+    pub(super) watchpoints: Vec<Watchpoint>,
+    pub(super) watch_hit: Option<WatchHit>,
+    // Where the execution trace is written, if tracing is enabled
+    trace: Option<Box<dyn Write>>,
+    // Number of pipeline steps taken, instructions executed, and cycles they took
+    pub(super) steps: u64,
+    pub(super) instructions: u64,
+    pub(super) cycles: u64,
+    // Number of IRQs and FIQs taken, and the levels of their lines over the run
+    pub(super) interrupts: u64,
+    pub(super) interrupt_history: Vec<(u64, u32)>,
+}
+
+// The emulated machine; the layout of its memory, the CPU ID reported by CP15 if it has the
+// extended ISA, and whether it has a VFP. Only the memory in the map is allocated, so RAM and
+// ROM can be placed anywhere in the address space.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Config {
+    pub memory_map: MemoryMap,
+    pub cpu_id: Option<u32>,
+    pub vfp: bool,
+}
+
+// An instruction moving through the pipeline, or the abort raised when it was fetched. Aborts
+// only become errors once they reach the execute stage, as the instruction may be flushed from
+// the pipeline before then, eg: when the last instruction in memory is a branch or halt.
+pub type Fetched<T> = std::result::Result<T, PrefetchAbort>;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pipeline {
+    pub fetched: Option<Fetched<u32>>,
+    pub decoded: Option<Fetched<Decoded>>,
+}
+
+// A decoded instruction, and the word or halfword it was decoded from, which is kept so that the
+// stage can be saved and decoded again, whichever state it was fetched in
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Decoded {
+    pub raw: u32,
+    pub instr: ConditionalInstruction,
+}
+
+// Raised when an instruction is fetched from an address with no memory behind it. Only
+// interrupts are taken as exceptions, so executing an aborted instruction stops the emulator
+// with this error.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PrefetchAbort {
+    pub address: Address,
+    pub peripheral: bool,
+}
+
+impl fmt::Display for PrefetchAbort {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Prefetch abort: instruction fetched from {} address {}",
+            if self.peripheral {
+                "peripheral"
+            } else {
+                "unmapped"
+            },
+            self.address
+        )
+    }
+}
+
+impl Error for PrefetchAbort {}
+
+impl From<PrefetchAbort> for ArmError {
+    fn from(abort: PrefetchAbort) -> Self {
+        ArmError::Memory(Box::new(abort))
+    }
+}
+
+impl Decoded {
+    // Decodes a fetched instruction, as a Thumb halfword or an ARM word depending on the width
+    // of the instructions it was fetched with
+    pub fn new(raw: u32, width: InstructionWidth) -> Result<Self> {
+        let instr = match width {
+            InstructionWidth::Halfword => thumb::decode(raw as u16)?,
+            InstructionWidth::Word => decode::decode(&raw)?,
+        };
+        Ok(Decoded { raw, instr })
+    }
+}
+
+impl Pipeline {
+    pub fn new() -> Self {
+        Pipeline {
+            fetched: None,
+            decoded: None,
+        }
+    }
+
+    pub fn flush(&mut self) {
+        self.fetched = None;
+        self.decoded = None;
+    }
+}
+
+impl Default for Pipeline {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl EmulatorState {
+    pub fn new() -> Self {
+        Self::with_memory(Vec::new()).expect("an empty image always fits in memory")
+    }
+
+    // Creates an emulator with the default memory layout. Images too large for it are an error.
+    pub fn with_memory(bytes: Vec<u8>) -> Result<Self> {
+        Self::with_memory_map(bytes, &MemoryMap::default())
+    }
+
+    // Creates an emulator with the given memory layout, loading the image into ROM if the map
+    // has one, or RAM otherwise. Execution starts from the start of the image.
+    pub fn with_memory_map(bytes: Vec<u8>, map: &MemoryMap) -> Result<Self> {
+        Self::with_config(
+            bytes,
+            &Config {
+                memory_map: map.clone(),
+                ..Default::default()
+            },
+        )
+    }
+
+    // Creates an emulator for the configured machine, loading the image as with_memory_map
+    pub fn with_config(bytes: Vec<u8>, config: &Config) -> Result<Self> {
+        let memory = Memory::new(&config.memory_map, &bytes)?;
+        let mut register_file = RegisterFile::new();
+        register_file[Register::Pc] = memory.image_base().0;
+        Ok(EmulatorState {
+            memory,
+            register_file,
+            banked: BankedRegisters::default(),
+            pipeline: Pipeline::new(),
+            gpio: Gpio::with_base(config.memory_map.gpio_base),
+            leds: Vec::new(),
+            cp15: config.cpu_id.map(Cp15::new),
+            vfp: config.vfp.then(Vfp::new),
+            uart: None,
+            irq: false,
+            fiq: false,
+            loops: None,
+            profile: None,
+            coverage: None,
+            abi: None,
+            history: None,
+            symbols: None,
+            strict_memory: false,
+            output: Box::new(io::sink()),
+            input: Box::new(io::empty()),
+            exit_code: None,
+            watchpoints: Vec::new(),
+            watch_hit: None,
+            trace: None,
+            steps: 0,
+            instructions: 0,
+            cycles: 0,
+            interrupts: 0,
+            interrupt_history: Vec::new(),
+        })
+    }
+
+    // Loads a binary image into memory, and resets the registers and pipeline so that
+    // execution starts from the start of the image.
+    pub fn load_binary(&mut self, bytes: &[u8]) -> Result<()> {
+        self.memory.load(bytes)?;
+        self.register_file = RegisterFile::new();
+        self.register_file[Register::Pc] = self.memory.image_base().0;
+        self.banked = BankedRegisters::default();
+        self.pipeline.flush();
+        self.gpio = Gpio::with_base(self.gpio.base.0);
+        self.exit_code = None;
+        self.steps = 0;
+        self.instructions = 0;
+        self.cycles = 0;
+        self.interrupts = 0;
+        self.interrupt_history.clear();
+        Ok(())
+    }
+
+    // Sets where messages from the emulated program are written. By default they are discarded.
+    pub fn set_output(&mut self, output: Box<dyn Write>) {
+        self.output = output;
+    }
+
+    pub fn output(&mut self) -> &mut dyn Write {
+        &mut self.output
+    }
+
+    // Sets where the program's syscalls read characters from. By default there is no input.
+    pub fn set_input(&mut self, input: Box<dyn Read>) {
+        self.input = input;
+    }
+
+    pub fn input(&mut self) -> &mut dyn Read {
+        &mut self.input
+    }
+
+    // The code the program passed to the exit syscall, if it has exited
+    pub fn exit_code(&self) -> Option<u32> {
+        self.exit_code
+    }
+
+    // Enables tracing, writing a line for each executed instruction to the given output
+    pub fn set_trace(&mut self, trace: Box<dyn Write>) {
+        self.trace = Some(trace);
+    }
+
+    pub fn trace(&mut self) -> Option<&mut dyn Write> {
+        match &mut self.trace {
+            Some(trace) => Some(trace),
+            None => None,
+        }
+    }
+
+    // The number of cycles taken by the instructions executed so far, see timing::cycles
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    pub fn regs(&self) -> &RegisterFile {
+        &self.register_file
+    }
+
+    pub fn read_reg(&self, reg: Register) -> u32 {
+        self.register_file[reg]
+    }
+
+    pub fn write_reg(&mut self, reg: Register, val: u32) {
+        self.register_file[reg] = val;
+    }
+
+    // Checks if the len bytes starting at address are all backed by memory
+    pub fn is_mapped(&self, address: Address, len: u32) -> bool {
+        self.memory.is_mapped(address, len)
+    }
+
+    // Every word of memory which isn't zero, with its address, in address order
+    pub fn non_zero_words(&self) -> Vec<(Address, Word)> {
+        let mut words = Vec::new();
+        for (base, bytes) in self.memory.banks() {
+            for (i, word) in bytes.chunks_exact(BYTES_IN_WORD).enumerate() {
+                let word =
+                    Word::from_le_bytes(word.try_into().expect("slice with incorrect length"));
+                if word.0 != 0 {
+                    words.push((base.wrapping_add((i * BYTES_IN_WORD) as u32), word));
+                }
+            }
+        }
+        words
+    }
+
+    pub fn read_memory(&self, address: Address) -> std::result::Result<Word, MemoryError> {
+        self.check_alignment(address, BYTES_IN_WORD as u32)?;
+        self.memory.read_word(address)
+    }
+
+    pub fn write_memory(
+        &mut self,
+        address: Address,
+        val: Word,
+    ) -> std::result::Result<(), MemoryError> {
+        self.check_alignment(address, BYTES_IN_WORD as u32)?;
+        self.memory.write_word(address, val)
+    }
+
+    pub fn read_halfword(&self, address: Address) -> std::result::Result<u16, MemoryError> {
+        self.check_alignment(address, 2)?;
+        let bytes = self.memory.read(address, 2)?;
+        Ok(u16::from_le_bytes(
+            bytes.try_into().expect("read a halfword of the wrong size"),
+        ))
+    }
+
+    pub fn write_halfword(
+        &mut self,
+        address: Address,
+        val: u16,
+    ) -> std::result::Result<(), MemoryError> {
+        self.check_alignment(address, 2)?;
+        self.memory.write(address, &val.to_le_bytes())
+    }
+
+    pub fn read_byte(&self, address: Address) -> std::result::Result<u8, MemoryError> {
+        Ok(self.memory.read(address, 1)?[0])
+    }
+
+    pub fn write_byte(
+        &mut self,
+        address: Address,
+        val: u8,
+    ) -> std::result::Result<(), MemoryError> {
+        self.memory.write(address, &[val])
+    }
+
+    // In strict mode, accesses must be aligned to their size
+    fn check_alignment(&self, address: Address, len: u32) -> std::result::Result<(), MemoryError> {
+        if self.strict_memory && !address.0.is_multiple_of(len) {
+            return Err(MemoryError::Unaligned(address, len));
+        }
+        Ok(())
+    }
+
+    // Reports a load or store to an address with no memory behind it. In strict mode this stops
+    // the emulator with the error, otherwise the error is written to the output and the access
+    // is skipped, as the reference emulator does.
+    pub fn unmapped_access(&mut self, address: Address) -> Result<()> {
+        let error = MemoryError::OutOfBounds(address);
+        if self.strict_memory {
+            return Err(error.into());
+        }
+        writeln!(self.output(), "Error: {}", error)?;
+        Ok(())
+    }
+
+    // The width of the instructions being fetched, i.e. a halfword in Thumb state
+    pub fn instruction_width(&self) -> InstructionWidth {
+        self.register_file.instruction_width()
+    }
+
+    pub fn set_flags(&mut self, flag: CpsrFlag, set: bool) {
+        self.register_file[Register::Cpsr] = self.register_file.status().with_flag(flag, set).0;
+    }
+
+    pub fn print_state(&self) {
+        self.write_state(&mut io::stdout())
+            .expect("failed to write to stdout");
+    }
+
+    // Writes the registers, the non-zero words of memory, and the cycles taken. Words are
+    // written as they are read by ldr, i.e. little endian.
+    pub fn write_state(&self, out: &mut dyn Write) -> io::Result<()> {
+        self.write_state_as(out, false)
+    }
+
+    // Writes the final state as write_state, but with each run of consecutive non-zero words as
+    // one range, four words to a line, eg:
+    //
+    // 0x00000000..0x00000014:
+    //     0xe3a00001 0xe3a01002 0xe0802001 0xe5802040
+    //     0xeafffffe
+    //
+    pub fn write_state_with_ranges(&self, out: &mut dyn Write) -> io::Result<()> {
+        self.write_state_as(out, true)
+    }
+
+    fn write_state_as(&self, out: &mut dyn Write, ranges: bool) -> io::Result<()> {
+        self.write_registers(out)?;
+        writeln!(out, "Non-zero memory:")?;
+        for (base, bytes) in self.memory.banks() {
+            let words: Vec<Word> = bytes
+                .chunks_exact(BYTES_IN_WORD)
+                .map(|word| {
+                    Word::from_le_bytes(word.try_into().expect("slice with incorrect length"))
+                })
+                .collect();
+            let address = |i: usize| base.wrapping_add((i * BYTES_IN_WORD) as u32);
+
+            let mut i = 0;
+            while i < words.len() {
+                if words[i].0 == 0 {
+                    i += 1;
+                    continue;
+                }
+                if !ranges {
+                    writeln!(out, "{}: {}", address(i), words[i])?;
+                    i += 1;
+                    continue;
+                }
+                let len = words[i..].iter().take_while(|word| word.0 != 0).count();
+                writeln!(out, "{}..{}:", address(i), address(i + len))?;
+                for row in words[i..i + len].chunks(WORDS_PER_ROW) {
+                    let row: Vec<String> = row.iter().map(Word::to_string).collect();
+                    writeln!(out, "    {}", row.join(" "))?;
+                }
+                i += len;
+            }
+        }
+        writeln!(
+            out,
+            "Cycles: {} ({} instructions)",
+            self.cycles, self.instructions
+        )
+    }
+
+    // Writes r0 to r12, the PC and the CPSR, or every register as the ABI groups them if one is
+    // set
+    pub fn write_registers(&self, out: &mut dyn Write) -> io::Result<()> {
+        if let Some(Abi::Aapcs) = self.abi {
+            return self.write_aapcs_registers(out);
+        }
+        writeln!(out, "Registers:")?;
+        for (reg, contents) in self.register_file.iter() {
+            match reg {
+                Register::Pc => {
+                    writeln!(out, "PC  : {: >10} (0x{:0>8x})", contents as i32, contents)?
+                }
+                Register::Cpsr => {
+                    writeln!(out, "CPSR: {: >10} (0x{:0>8x})", contents as i32, contents)?
+                }
+                Register::Sp | Register::Lr => (),
+                _ => writeln!(
+                    out,
+                    "${: <3}: {: >10} (0x{:0>8x})",
+                    reg as usize, contents as i32, contents
+                )?,
+            }
+        }
+        Ok(())
+    }
+
+    pub fn print_led_timelines(&self) {
+        for &pin in &self.leds {
+            led::write_timeline(&mut io::stdout(), &self.gpio, pin, self.instructions)
+                .expect("failed to write to stdout");
+        }
+    }
+
+    pub fn print_peripheral_summary(&self) {
+        self.write_peripheral_summary(&mut io::stdout())
+            .expect("failed to write to stdout");
+    }
+
+    // Writes the final level of each GPIO pin which changed, the characters the UART sent and
+    // received, and the interrupts taken
+    pub fn write_peripheral_summary(&self, out: &mut dyn Write) -> io::Result<()> {
+        writeln!(out, "Peripheral activity:")?;
+        self.gpio.write_summary(out)?;
+        if let Some(uart) = &self.uart {
+            uart.write_summary(out)?;
+        }
+        writeln!(out, "Interrupts taken: {}", self.interrupts)
+    }
+}
+
+impl Default for EmulatorState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,99 @@
+use enum_primitive_derive::Primitive;
+use num_traits::FromPrimitive;
+
+use super::{execute::undefined, registers::Register, state::EmulatorState};
+use arm11_isa::{address::Address, types::*};
+
+// The services a program can ask for with swi, selected by its comment field. The argument and
+// the result are passed in r0.
+//
+// swi #0  exit           stops the emulator, with r0 as the exit code
+// swi #1  print integer  writes r0 to the output as a signed decimal number
+// swi #2  print string   writes the null terminated string at the address in r0
+// swi #3  read char      reads a character from the input into r0, or -1 once it is closed
+//
+#[derive(Debug, Clone, Copy, PartialEq, Primitive)]
+pub enum Syscall {
+    Exit = 0,
+    PrintInteger = 1,
+    PrintString = 2,
+    ReadChar = 3,
+}
+
+// Performs the service numbered by a swi's comment field. Unknown services are an error, as
+// there is no handler for the exception to be taken to.
+pub fn call(state: &mut EmulatorState, number: u32, encoded: ConditionalInstruction) -> Result<()> {
+    let syscall = Syscall::from_u32(number).ok_or_else(|| {
+        undefined(
+            encoded,
+            "swi",
+            format!("there is no service swi #0x{:x}", number),
+        )
+    })?;
+    let arg = state.read_reg(Register::R0);
+
+    match syscall {
+        Syscall::Exit => state.exit_code = Some(arg),
+        Syscall::PrintInteger => {
+            write!(state.output(), "{}", arg as i32)?;
+            state.output().flush()?;
+        }
+        Syscall::PrintString => {
+            let mut string = Vec::new();
+            let mut address = Address(arg);
+            loop {
+                match state.read_byte(address)? {
+                    0 => break,
+                    byte => string.push(byte),
+                }
+                address = address.wrapping_add(1);
+            }
+            state.output().write_all(&string)?;
+            state.output().flush()?;
+        }
+        Syscall::ReadChar => {
+            let mut byte = [0];
+            let val = match state.input().read(&mut byte)? {
+                0 => u32::MAX,
+                _ => u32::from(byte[0]),
+            };
+            state.write_reg(Register::R0, val);
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Capture;
+    use arm11_asm::assemble;
+    use std::io;
+
+    #[test]
+    fn test_syscalls() {
+        let source = "mov r0,#42\nswi #1\nldr r0,=msg\nswi #2\nswi #3\nmov r1,r0\nswi #3\n\
+                      mov r2,r0\nmov r0,#3\nswi #0\nmov r3,#1\nandeq r0,r0,r0\nmsg:\n\
+                      .ascii \"hi\\n\"\n.byte 0\n";
+        let bytes = assemble(String::from(source))
+            .expect("assemble failed")
+            .to_bytes();
+        let mut emulator = EmulatorState::with_memory(bytes).expect("load failed");
+        let output = Capture::new();
+        emulator.set_output(Box::new(output.clone()));
+        emulator.set_input(Box::new(io::Cursor::new(b"x".to_vec())));
+
+        let result = emulator.run().expect("run failed");
+        assert_eq!(output.contents(), "42hi\n");
+        assert_eq!(emulator.read_reg(Register::R1), u32::from(b'x'));
+        assert_eq!(emulator.read_reg(Register::R2), u32::MAX);
+        // Nothing runs after the exit
+        assert_eq!(emulator.read_reg(Register::R3), 0);
+        assert_eq!(result.exit_code, Some(3));
+
+        let mut emulator =
+            EmulatorState::with_memory(0xef000009u32.to_le_bytes().to_vec()).expect("load failed");
+        assert!(emulator.run().is_err());
+    }
+}
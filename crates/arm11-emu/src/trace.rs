@@ -0,0 +1,82 @@
+use std::{io, io::Write};
+
+use super::registers::{Register, RegisterFile};
+use arm11_isa::{address::Address, disassemble::disassemble_instruction, types::*};
+
+// Writes a line of the execution trace for an instruction; its address, disassembly, and the
+// registers it changed. Instructions which failed their condition are marked as skipped.
+//
+// eg: 0x00000008: subs r1, r1, #1            r1=0x00000000 cpsr=0x40000000
+//     0x0000000c: bne 0x00000008              skipped
+//
+pub fn write_trace(
+    out: &mut dyn Write,
+    address: Address,
+    instr: &ConditionalInstruction,
+    executed: bool,
+    before: &RegisterFile,
+    after: &RegisterFile,
+) -> io::Result<()> {
+    let deltas: Vec<String> = Register::all()
+        .filter(|&reg| before[reg] != after[reg])
+        .map(|reg| format!("{}=0x{:0>8x}", reg_name(reg), after[reg]))
+        .collect();
+
+    let effect = if !executed {
+        String::from("skipped")
+    } else if deltas.is_empty() {
+        String::from("-")
+    } else {
+        deltas.join(" ")
+    };
+    writeln!(
+        out,
+        "{}: {: <28}{}",
+        address,
+        disassemble_instruction(instr, address.0),
+        effect
+    )
+}
+
+fn reg_name(reg: Register) -> String {
+    match reg {
+        Register::Pc => String::from("pc"),
+        Register::Cpsr => String::from("cpsr"),
+        _ => format!("r{}", reg as usize),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_trace() {
+        let instr = ConditionalInstruction {
+            cond: ConditionCode::Al,
+            instruction: Instruction::Processing(InstructionProcessing {
+                opcode: ProcessingOpcode::Add,
+                set_cond: false,
+                rn: 1,
+                rd: 1,
+                operand2: Operand2::ShiftedReg(2, Shift::ConstantShift(ShiftType::Lsl, 0)),
+            }),
+        };
+        let before = RegisterFile::new();
+        let mut after = before;
+        after[Register::R1] = 3;
+
+        let mut out = Vec::new();
+        write_trace(&mut out, Address(0x8), &instr, true, &before, &after).expect("trace failed");
+        let skipped = ConditionalInstruction {
+            cond: ConditionCode::Ne,
+            ..instr
+        };
+        write_trace(&mut out, Address(0xc), &skipped, false, &after, &after).expect("trace failed");
+        assert_eq!(
+            String::from_utf8(out).expect("invalid trace"),
+            "0x00000008: add r1, r1, r2              r1=0x00000003\n\
+             0x0000000c: addne r1, r1, r2            skipped\n"
+        );
+    }
+}
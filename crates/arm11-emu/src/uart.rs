@@ -0,0 +1,267 @@
+use std::{
+    io,
+    io::{Read, Write},
+};
+
+use super::parse_number;
+use arm11_isa::address::Address;
+
+// The base address of the PL011 UART on the Raspberry Pi
+pub const DEFAULT_UART_BASE: u32 = 0x20201000;
+
+// Register offsets from the base address
+const DATA: u32 = 0x00;
+const FLAGS: u32 = 0x18;
+const INTERRUPT_MASK: u32 = 0x38;
+const RAW_INTERRUPTS: u32 = 0x3c;
+const MASKED_INTERRUPTS: u32 = 0x40;
+const INTERRUPT_CLEAR: u32 = 0x44;
+
+// Flag register bits. The transmit FIFO is never full, as characters are written immediately.
+const RX_EMPTY: u32 = 1 << 4;
+// Interrupt register bits
+const RX_INTERRUPT: u32 = 1 << 4;
+
+// The clock of the ARM11 in the Raspberry Pi, which converts a baud rate to cycles
+const CLOCK_HZ: u64 = 700_000_000;
+// Bits sent for each character with 8N1 framing; a start bit, 8 data bits and a stop bit
+const BITS_PER_CHAR: u64 = 10;
+
+// A memory-mapped UART, with a data register and a flag register laid out like the PL011.
+// Writing the data register sends a character to the output, and reading it receives the next
+// character from the input. The flag register shows whether a character is waiting to be read,
+// so programs can poll it before reading.
+//
+// Setting the receive bit of the interrupt mask register raises an IRQ while a character is
+// waiting to be read, instead of polling. Reading the character clears it.
+//
+// With a baud rate set, characters arrive one at a time at that rate, measured in the cycles
+// the program has taken, so polling loops run as they would on hardware. The sender waits for
+// each character to be read before sending the next, so no input is lost.
+pub struct Uart {
+    base: Address,
+    input: Box<dyn Read>,
+    output: Box<dyn Write>,
+    // The next character to be read, once the input has been polled
+    received: Option<u8>,
+    interrupt_mask: u32,
+    input_closed: bool,
+    // The cycles taken to receive a character, and the cycle the next one finishes arriving
+    cycles_per_char: Option<u64>,
+    next_arrival: u64,
+    // The number of characters the program has sent and received
+    sent: u64,
+    received_count: u64,
+}
+
+impl Uart {
+    pub fn new(base: u32, input: Box<dyn Read>, output: Box<dyn Write>) -> Self {
+        Uart {
+            base: Address(base),
+            input,
+            output,
+            received: None,
+            interrupt_mask: 0,
+            input_closed: false,
+            cycles_per_char: None,
+            next_arrival: 0,
+            sent: 0,
+            received_count: 0,
+        }
+    }
+
+    // Receives characters at the given baud rate, rather than as soon as they are read
+    pub fn set_baud(&mut self, baud: u32) {
+        let cycles_per_char = (CLOCK_HZ * BITS_PER_CHAR / u64::from(baud.max(1))).max(1);
+        self.cycles_per_char = Some(cycles_per_char);
+        self.next_arrival = cycles_per_char;
+    }
+
+    // Whether the address is one of the UART's registers
+    pub fn contains(&self, address: Address) -> bool {
+        [
+            DATA,
+            FLAGS,
+            INTERRUPT_MASK,
+            RAW_INTERRUPTS,
+            MASKED_INTERRUPTS,
+            INTERRUPT_CLEAR,
+        ]
+        .iter()
+        .any(|&offset| address == self.base.wrapping_add(offset))
+    }
+
+    // Whether the UART is raising an IRQ at the given cycle of the run
+    pub fn interrupt(&mut self, now: u64) -> io::Result<bool> {
+        if self.interrupt_mask & RX_INTERRUPT == 0 {
+            return Ok(false);
+        }
+        self.poll(now)?;
+        Ok(self.received.is_some())
+    }
+
+    // Reads a register, at the given cycle of the run
+    pub fn read(&mut self, address: Address, now: u64) -> io::Result<u32> {
+        self.poll(now)?;
+        let raw = if self.received.is_some() {
+            RX_INTERRUPT
+        } else {
+            0
+        };
+        let offset = address.0.wrapping_sub(self.base.0);
+        if offset == FLAGS {
+            Ok(if self.received.is_none() { RX_EMPTY } else { 0 })
+        } else if offset == INTERRUPT_MASK {
+            Ok(self.interrupt_mask)
+        } else if offset == RAW_INTERRUPTS {
+            Ok(raw)
+        } else if offset == MASKED_INTERRUPTS {
+            Ok(raw & self.interrupt_mask)
+        } else if offset == INTERRUPT_CLEAR {
+            Ok(0)
+        } else {
+            let received = self.received.take();
+            // The next character starts arriving once this one has been read
+            if let (Some(_), Some(cycles_per_char)) = (received, self.cycles_per_char) {
+                self.next_arrival = now + cycles_per_char;
+            }
+            self.received_count += received.is_some() as u64;
+            Ok(received.map_or(0, u32::from))
+        }
+    }
+
+    pub fn write(&mut self, address: Address, val: u32) -> io::Result<()> {
+        // Writes to the flag and interrupt status registers are ignored, as they are read only.
+        // The receive interrupt is cleared by reading the character, so clearing it does nothing.
+        if address == self.base.wrapping_add(DATA) {
+            self.output.write_all(&[val as u8])?;
+            self.output.flush()?;
+            self.sent += 1;
+        } else if address == self.base.wrapping_add(INTERRUPT_MASK) {
+            self.interrupt_mask = val & RX_INTERRUPT;
+        }
+        Ok(())
+    }
+
+    // Writes the number of characters sent and received
+    pub fn write_summary(&self, out: &mut dyn Write) -> io::Result<()> {
+        writeln!(
+            out,
+            "UART: {} characters sent, {} received",
+            self.sent, self.received_count
+        )
+    }
+
+    // Reads the next character from the input, if one hasn't been read already and it has had
+    // time to arrive. This blocks until a character is available, or the input is closed.
+    fn poll(&mut self, now: u64) -> io::Result<()> {
+        if self.received.is_some() || self.input_closed || now < self.next_arrival {
+            return Ok(());
+        }
+        let mut byte = [0];
+        match self.input.read(&mut byte)? {
+            0 => self.input_closed = true,
+            _ => self.received = Some(byte[0]),
+        }
+        Ok(())
+    }
+}
+
+// Parses the base address of a UART, which must be word aligned, with every register below the
+// top of the address space rather than wrapping around to the bottom
+pub fn parse_uart_base(s: &str) -> std::result::Result<u32, String> {
+    parse_number(s)
+        .ok()
+        .filter(|base| base % 4 == 0 && base.checked_add(INTERRUPT_CLEAR + 3).is_some())
+        .ok_or_else(|| format!("Invalid UART base address '{}'", s))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{cell::RefCell, rc::Rc};
+
+    // An output which can still be read after it is given to the UART
+    #[derive(Clone, Default)]
+    struct Shared(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_uart() {
+        let output = Shared::default();
+        let base = Address(DEFAULT_UART_BASE);
+        let mut uart = Uart::new(
+            DEFAULT_UART_BASE,
+            Box::new(io::Cursor::new(b"hi".to_vec())),
+            Box::new(output.clone()),
+        );
+        assert!(uart.contains(base.wrapping_add(0x18)));
+        assert!(!uart.contains(base.wrapping_add(0x4)));
+        assert!(uart.contains(base.wrapping_add(0x40)));
+
+        assert_eq!(
+            uart.read(base.wrapping_add(0x18), 0).expect("read failed"),
+            0
+        );
+        assert_eq!(uart.read(base, 0).expect("read failed"), u32::from(b'h'));
+        assert_eq!(uart.read(base, 0).expect("read failed"), u32::from(b'i'));
+        assert_eq!(
+            uart.read(base.wrapping_add(0x18), 0).expect("read failed"),
+            RX_EMPTY
+        );
+
+        assert!(!uart.interrupt(0).expect("interrupt failed"));
+
+        uart.write(base, u32::from(b'!')).expect("write failed");
+        assert_eq!(*output.0.borrow(), b"!");
+
+        let mut summary = Vec::new();
+        uart.write_summary(&mut summary).expect("write failed");
+        assert_eq!(summary, b"UART: 1 characters sent, 2 received\n");
+
+        assert_eq!(parse_uart_base("0x20201000"), Ok(0x20201000));
+        assert!(parse_uart_base("0x20201002").is_err());
+        assert_eq!(parse_uart_base("0xffffffb8"), Ok(0xffffffb8));
+        assert!(parse_uart_base("0xffffffbc").is_err());
+        assert!(parse_uart_base("0xffffffe8").is_err());
+    }
+
+    #[test]
+    fn test_uart_baud() {
+        let base = Address(DEFAULT_UART_BASE);
+        let mut uart = Uart::new(
+            DEFAULT_UART_BASE,
+            Box::new(io::Cursor::new(b"hi".to_vec())),
+            Box::new(io::sink()),
+        );
+        // 7000000 baud is 1000 cycles per character
+        uart.set_baud(7_000_000);
+        let flags = base.wrapping_add(0x18);
+        uart.write(base.wrapping_add(0x38), RX_INTERRUPT)
+            .expect("write failed");
+
+        assert_eq!(uart.read(flags, 999).expect("read failed"), RX_EMPTY);
+        assert!(!uart.interrupt(999).expect("interrupt failed"));
+        assert!(uart.interrupt(1000).expect("interrupt failed"));
+        assert_eq!(uart.read(flags, 1000).expect("read failed"), 0);
+        assert_eq!(
+            uart.read(base.wrapping_add(0x40), 1000)
+                .expect("read failed"),
+            RX_INTERRUPT
+        );
+        // Unread characters wait, rather than being overwritten
+        assert_eq!(uart.read(base, 5000).expect("read failed"), u32::from(b'h'));
+        assert!(!uart.interrupt(5000).expect("interrupt failed"));
+        assert_eq!(uart.read(flags, 5999).expect("read failed"), RX_EMPTY);
+        assert_eq!(uart.read(base, 6000).expect("read failed"), u32::from(b'i'));
+    }
+}
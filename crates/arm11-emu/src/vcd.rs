@@ -0,0 +1,113 @@
+use std::{io, io::Write};
+
+use super::{
+    exception::{FIQ_LINE, IRQ_LINE},
+    gpio::{Gpio, NUM_PINS},
+};
+
+// Writes the GPIO pin levels and the IRQ and FIQ lines over a run as a Value Change Dump, which
+// can be viewed in a waveform viewer such as GTKWave. Time is virtual, with one tick per executed
+// instruction, and only pins which changed level during the run are included. The interrupt
+// lines are always included, from the interrupt history of the emulator.
+pub fn write_vcd(
+    out: &mut dyn Write,
+    gpio: &Gpio,
+    interrupts: &[(u64, u32)],
+    end: u64,
+) -> io::Result<()> {
+    let pins: Vec<usize> = (0..NUM_PINS)
+        .filter(|&pin| gpio.transitions(pin) > 0)
+        .collect();
+    let lines = [("irq", IRQ_LINE), ("fiq", FIQ_LINE)];
+
+    writeln!(out, "$version arm11 emulator $end")?;
+    writeln!(out, "$comment 1 tick per executed instruction $end")?;
+    writeln!(out, "$timescale 1 ns $end")?;
+    writeln!(out, "$scope module gpio $end")?;
+    for &pin in &pins {
+        writeln!(out, "$var wire 1 {} pin{} $end", identifier(pin), pin)?;
+    }
+    writeln!(out, "$upscope $end")?;
+    writeln!(out, "$scope module interrupts $end")?;
+    for (i, (name, _)) in lines.iter().enumerate() {
+        writeln!(
+            out,
+            "$var wire 1 {} {} $end",
+            identifier(NUM_PINS + i),
+            name
+        )?;
+    }
+    writeln!(out, "$upscope $end")?;
+    writeln!(out, "$enddefinitions $end")?;
+
+    // All pins and lines start low
+    writeln!(out, "#0")?;
+    writeln!(out, "$dumpvars")?;
+    for &pin in &pins {
+        writeln!(out, "0{}", identifier(pin))?;
+    }
+    for i in 0..lines.len() {
+        writeln!(out, "0{}", identifier(NUM_PINS + i))?;
+    }
+    writeln!(out, "$end")?;
+
+    // The changes to each variable, in time order, with the GPIO changes first at each time
+    let mut changes = Vec::new();
+    let mut previous = 0;
+    for &(time, levels) in gpio.history() {
+        let changed = levels ^ previous;
+        for &pin in pins.iter().filter(|&&pin| changed & (1 << pin) != 0) {
+            changes.push((time, (levels >> pin) & 1, identifier(pin)));
+        }
+        previous = levels;
+    }
+    let mut previous = 0;
+    for &(time, levels) in interrupts {
+        for (i, &(_, bit)) in lines.iter().enumerate() {
+            if (levels ^ previous) & bit != 0 {
+                changes.push((time, (levels & bit != 0) as u32, identifier(NUM_PINS + i)));
+            }
+        }
+        previous = levels;
+    }
+    changes.sort_by_key(|&(time, _, _)| time);
+
+    let mut last = None;
+    for (time, level, identifier) in changes {
+        if last != Some(time) {
+            writeln!(out, "#{}", time)?;
+            last = Some(time);
+        }
+        writeln!(out, "{}{}", level, identifier)?;
+    }
+    writeln!(out, "#{}", end)
+}
+
+// The short identifier for a pin's or interrupt line's variable, made of printable ASCII
+// characters
+fn identifier(index: usize) -> char {
+    (b'!' + index as u8) as char
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arm11_isa::address::Address;
+
+    #[test]
+    fn test_write_vcd() {
+        let mut gpio = Gpio::new();
+        gpio.write(Address(0x2020001c), 1 << 16 | 1 << 1, 3);
+        gpio.write(Address(0x20200028), 1 << 16, 7);
+
+        let mut out = Vec::new();
+        write_vcd(&mut out, &gpio, &[(2, IRQ_LINE), (7, FIQ_LINE)], 10).expect("vcd failed");
+        let vcd = String::from_utf8(out).expect("invalid vcd");
+
+        assert!(vcd.contains("$var wire 1 \" pin1 $end\n$var wire 1 1 pin16 $end\n"));
+        assert!(vcd.contains("$var wire 1 A irq $end\n$var wire 1 B fiq $end\n"));
+        assert!(vcd.ends_with(
+            "#0\n$dumpvars\n0\"\n01\n0A\n0B\n$end\n#2\n1A\n#3\n1\"\n11\n#7\n01\n0A\n1B\n#10\n"
+        ));
+    }
+}
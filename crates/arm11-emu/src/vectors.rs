@@ -0,0 +1,202 @@
+use std::{fmt, fs, path::Path};
+
+use super::{
+    execute,
+    expect::{parse_document, ExpectedState, Json, Mismatch},
+    parse_number,
+    registers::Register,
+    state::EmulatorState,
+};
+use arm11_isa::{
+    address::{Address, Word},
+    decode::decode,
+    types::*,
+};
+
+// A single step test vector; the state before an instruction, the instruction, and the state it
+// is expected to leave. Both states are given in the form read by --expect-state, with the pc as
+// the instruction reads it, 8 bytes ahead of the instruction, which is placed at pc - 8. The pc
+// is 8 if the initial state doesn't give it, and is only changed by instructions which write it.
+// Vector files hold an array of vectors, and the instruction may be a number or a string.
+//
+// eg: [{
+//   "name": "adds sets carry",
+//   "initial": {"registers": {"r1": -1}},
+//   "instruction": "0xe2910001",
+//   "final": {"registers": {"r0": 0, "pc": 8}, "flags": {"z": true, "c": true}}
+// }]
+//
+#[derive(Debug, Clone, PartialEq)]
+pub struct TestVector {
+    pub name: String,
+    pub initial: ExpectedState,
+    pub instruction: u32,
+    pub expected: ExpectedState,
+}
+
+// The results of running a set of vectors; the number which passed, and the name of each which
+// failed with why it failed
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct VectorReport {
+    pub passed: usize,
+    pub failures: Vec<(String, String)>,
+}
+
+impl TestVector {
+    // Reads the vectors in a vector file
+    pub fn parse_all(s: &str) -> std::result::Result<Vec<TestVector>, String> {
+        let vectors = match parse_document(s) {
+            Some(Json::Array(vectors)) => vectors,
+            _ => return Err(String::from("Invalid test vectors, expected a JSON array")),
+        };
+        vectors.iter().map(TestVector::from_json).collect()
+    }
+
+    fn from_json(json: &Json) -> std::result::Result<Self, String> {
+        let (mut name, mut initial, mut instruction, mut expected) = (None, None, None, None);
+        for (key, value) in json.members("a test vector")? {
+            match (key.as_str(), value) {
+                ("name", Json::String(s)) => name = Some(s.clone()),
+                ("initial", _) => initial = Some(ExpectedState::from_json(value)?),
+                ("instruction", Json::String(s)) => instruction = Some(parse_number(s)?),
+                ("instruction", _) => instruction = Some(value.word(key)?),
+                ("final", _) => expected = Some(ExpectedState::from_json(value)?),
+                _ => return Err(format!("Unexpected key '{}' in a test vector", key)),
+            }
+        }
+        let instruction = instruction.ok_or("Test vectors need an instruction")?;
+        Ok(TestVector {
+            name: name.unwrap_or_else(|| format!("0x{:0>8x}", instruction)),
+            initial: initial.unwrap_or_default(),
+            instruction,
+            expected: expected.ok_or("Test vectors need a final state")?,
+        })
+    }
+
+    // Runs the instruction through the executor from the initial state, returning everything in
+    // the final state which differs from the expected state
+    pub fn run(&self) -> Result<Vec<Mismatch>> {
+        let mut emulator = EmulatorState::new();
+        emulator.write_reg(Register::Pc, 8);
+        for &(reg, value) in &self.initial.registers {
+            emulator.write_reg(reg, value);
+        }
+        for &(flag, set) in &self.initial.flags {
+            emulator.set_flags(flag, set);
+        }
+        for &(address, value) in &self.initial.memory {
+            emulator.write_memory(address, Word(value))?;
+        }
+        let address = Address(emulator.read_reg(Register::Pc)).wrapping_sub(8);
+        emulator.write_memory(address, Word(self.instruction))?;
+
+        execute::execute(&mut emulator, decode(&self.instruction)?)?;
+        Ok(emulator.compare_state(&self.expected))
+    }
+}
+
+impl VectorReport {
+    // Runs every vector in the .json files in the directory, in the order of their names
+    pub fn run_dir(dir: &Path) -> Result<Self> {
+        let mut paths: Vec<_> = fs::read_dir(dir)?
+            .map(|entry| entry.map(|e| e.path()))
+            .collect::<std::result::Result<_, _>>()?;
+        paths.sort();
+
+        let mut report = VectorReport::default();
+        for path in paths
+            .iter()
+            .filter(|p| p.extension().is_some_and(|e| e == "json"))
+        {
+            let vectors = TestVector::parse_all(&fs::read_to_string(path)?)
+                .map_err(|e| ArmError::parse(format!("{}: {}", path.display(), e)))?;
+            report.add(&vectors);
+        }
+        Ok(report)
+    }
+
+    // Runs the vectors, adding their results to the report
+    pub fn add(&mut self, vectors: &[TestVector]) {
+        for vector in vectors {
+            let failure = match vector.run() {
+                Ok(mismatches) if mismatches.is_empty() => {
+                    self.passed += 1;
+                    continue;
+                }
+                Ok(mismatches) => mismatches
+                    .iter()
+                    .map(Mismatch::to_string)
+                    .collect::<Vec<_>>()
+                    .join(", "),
+                Err(e) => e.to_string(),
+            };
+            self.failures.push((vector.name.clone(), failure));
+        }
+    }
+
+    pub fn total(&self) -> usize {
+        self.passed + self.failures.len()
+    }
+
+    // The percentage of the vectors which passed, 100 if there were none
+    pub fn pass_percentage(&self) -> f64 {
+        match self.total() {
+            0 => 100.0,
+            total => 100.0 * self.passed as f64 / total as f64,
+        }
+    }
+}
+
+impl fmt::Display for VectorReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (name, failure) in &self.failures {
+            writeln!(f, "FAIL {}: {}", name, failure)?;
+        }
+        writeln!(
+            f,
+            "{}/{} vectors passed ({:.1}%)",
+            self.passed,
+            self.total(),
+            self.pass_percentage()
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_vectors() {
+        let vectors = TestVector::parse_all(
+            "[{\"name\": \"adds sets carry\", \"initial\": {\"registers\": {\"r1\": -1}}, \
+             \"instruction\": \"0xe2910001\", \"final\": {\"registers\": {\"r0\": 0, \"pc\": 8}, \
+             \"flags\": {\"z\": true, \"c\": true}}},\n\
+             {\"initial\": {\"registers\": {\"pc\": 264}, \"memory\": [{\"address\": 268, \
+             \"value\": 7}]}, \"instruction\": 3852402692, \"final\": {\"registers\": {\"r0\": 7}}},\n\
+             {\"name\": \"b\", \"instruction\": \"0xea000001\", \"final\": {\"registers\": {\"pc\": 12}}},\n\
+             {\"name\": \"wrong\", \"instruction\": \"0xe3a00001\", \"final\": {\"registers\": {\"r0\": 2}}}]",
+        )
+        .expect("parse failed");
+        assert_eq!(vectors[1].name, "0xe59f0004");
+        assert_eq!(vectors[1].initial.memory, vec![(Address(268), 7)]);
+
+        let mut report = VectorReport::default();
+        report.add(&vectors);
+        assert_eq!(report.passed, 3);
+        assert_eq!(
+            report.to_string(),
+            "FAIL wrong: r0: expected 2 (0x00000002), got 1 (0x00000001)\n\
+             3/4 vectors passed (75.0%)\n"
+        );
+
+        assert!(TestVector::parse_all("[{\"final\": {}}]").is_err());
+        assert!(TestVector::parse_all("{}").is_err());
+
+        // The vectors which come with the emulator all pass
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("../../vectors");
+        let report = VectorReport::run_dir(&dir).expect("run failed");
+        assert_eq!(report.failures, vec![]);
+        assert!(report.passed > 0);
+    }
+}
@@ -0,0 +1,169 @@
+use std::cmp::Ordering;
+
+use super::{execute::undefined, registers::Register, state::EmulatorState};
+use arm11_isa::{
+    address::{Address, Word},
+    constants::*,
+    types::*,
+};
+
+// The number of single precision registers, s0 to s31
+pub const NUM_SINGLE_REGS: usize = 32;
+
+// The flags of the FPSCR set by vcmp, in the same bits as the CPSR flags
+const FPSCR_FLAGS: u32 = 0xf << CpsrFlag::V as u32;
+
+// A minimal VFP coprocessor, enough for simple single precision code; the registers s0 to s31,
+// and the flags of the FPSCR. Arithmetic rounds to nearest, as in the default mode of the FPSCR,
+// and exceptions are never raised.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Vfp {
+    registers: [u32; NUM_SINGLE_REGS],
+    pub fpscr: u32,
+}
+
+impl Vfp {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // The bits of a single precision register
+    pub fn read(&self, s: u8) -> u32 {
+        self.registers[usize::from(s)]
+    }
+
+    pub fn write(&mut self, s: u8, val: u32) {
+        self.registers[usize::from(s)] = val;
+    }
+
+    // The value of a single precision register
+    pub fn single(&self, s: u8) -> f32 {
+        f32::from_bits(self.read(s))
+    }
+}
+
+pub fn execute(
+    state: &mut EmulatorState,
+    instr: InstructionVfp,
+    encoded: ConditionalInstruction,
+) -> Result<()> {
+    let mut vfp = state
+        .vfp
+        .ok_or_else(|| undefined(encoded, "VFP", "VFP instructions need --vfp"))?;
+
+    match instr {
+        InstructionVfp::Transfer {
+            load,
+            up_bit,
+            rn,
+            sd,
+            offset,
+        } => {
+            let offset = u32::from(offset) * BYTES_IN_WORD as u32;
+            let base = Address(state.read_reg(Register::from_field(rn)));
+            let address = if up_bit {
+                base.wrapping_add(offset)
+            } else {
+                base.wrapping_sub(offset)
+            };
+            if !state.is_mapped(address, BYTES_IN_WORD as u32) {
+                state.unmapped_access(address)?;
+            } else if load {
+                let val = state.read_memory(address)?.into();
+                state.check_watchpoints(address, BYTES_IN_WORD as u32, true, val);
+                vfp.write(sd, val);
+            } else {
+                let val = vfp.read(sd);
+                state.check_watchpoints(address, BYTES_IN_WORD as u32, false, val);
+                state.write_memory(address, Word(val))?;
+            }
+        }
+        InstructionVfp::Arithmetic { opcode, sd, sn, sm } => {
+            let (n, m) = (vfp.single(sn), vfp.single(sm));
+            let result = match opcode {
+                VfpOpcode::Add => n + m,
+                VfpOpcode::Sub => n - m,
+                VfpOpcode::Mul => n * m,
+                VfpOpcode::Div => n / m,
+            };
+            vfp.write(sd, result.to_bits());
+        }
+        InstructionVfp::Move { to_arm, sn, rt } => {
+            let rt = Register::from_field(rt);
+            if to_arm {
+                state.write_reg(rt, vfp.read(sn));
+            } else {
+                vfp.write(sn, state.read_reg(rt));
+            }
+        }
+        InstructionVfp::Convert { to_int, sd, sm } => {
+            // Conversions to integers saturate, and NaN converts to 0, as Rust's casts do
+            let result = if to_int {
+                vfp.single(sm) as i32 as u32
+            } else {
+                (vfp.read(sm) as i32 as f32).to_bits()
+            };
+            vfp.write(sd, result);
+        }
+        InstructionVfp::Compare { sd, sm } => {
+            // N, Z, C and V are 1000 for less than, 0110 for equal, 0010 for greater than, and
+            // 0011 if either is NaN
+            let flags: u32 = match vfp.single(sd).partial_cmp(&vfp.single(sm)) {
+                Some(Ordering::Less) => 0x8,
+                Some(Ordering::Equal) => 0x6,
+                Some(Ordering::Greater) => 0x2,
+                None => 0x3,
+            };
+            vfp.fpscr = vfp.fpscr & !FPSCR_FLAGS | flags << CpsrFlag::V as u32;
+        }
+        InstructionVfp::MoveFlags => {
+            let cpsr = state.regs().cpsr() & !FPSCR_FLAGS | vfp.fpscr & FPSCR_FLAGS;
+            state.write_reg(Register::Cpsr, cpsr);
+        }
+    }
+
+    state.vfp = Some(vfp);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Config;
+
+    #[test]
+    fn test_vfp() {
+        // Computes (7 * 2.5 + 0.5) / 4 from integers and a constant in memory, truncates it to an
+        // integer, and compares it with 4
+        let source = "ldr r0,=0x40200000\nstr r0,[r1,#0x100]\nmov r0,#7\nvmov s0,r0\n\
+                      vcvt.f32.s32 s0,s0\nvldr s1,[r1,#0x100]\nvmul.f32 s2,s0,s1\n\
+                      mov r0,#1\nvmov s3,r0\nvcvt.f32.s32 s3,s3\nmov r0,#2\nvmov s4,r0\n\
+                      vcvt.f32.s32 s4,s4\nvdiv.f32 s3,s3,s4\nvadd.f32 s2,s2,s3\n\
+                      vmul.f32 s4,s4,s4\nvdiv.f32 s2,s2,s4\nvstr s2,[r1,#0x104]\n\
+                      vcvt.s32.f32 s5,s2\nvmov r2,s5\nvcmp.f32 s2,s4\nvmrs APSR_nzcv,fpscr\n\
+                      movgt r3,#1\nandeq r0,r0,r0\n";
+        let bytes = arm11_asm::assemble(String::from(source))
+            .expect("assemble failed")
+            .to_bytes();
+        let config = Config {
+            vfp: true,
+            ..Default::default()
+        };
+        let mut emulator = EmulatorState::with_config(bytes.clone(), &config).expect("failed");
+        emulator.run().expect("run failed");
+
+        let vfp = emulator.vfp.expect("no VFP");
+        assert_eq!(vfp.single(2), 4.5);
+        assert_eq!(
+            emulator.read_memory(Address(0x104)).expect("read failed").0,
+            4.5f32.to_bits()
+        );
+        assert_eq!(emulator.read_reg(Register::R2), 4);
+        assert_eq!(emulator.read_reg(Register::R3), 1);
+        assert_eq!(vfp.fpscr >> CpsrFlag::V as u32, 0x2);
+
+        // Without the VFP, its instructions are undefined
+        let mut emulator = EmulatorState::with_memory(bytes).expect("load failed");
+        assert!(emulator.run().is_err());
+    }
+}
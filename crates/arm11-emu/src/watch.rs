@@ -0,0 +1,203 @@
+use std::{fmt, str::FromStr};
+
+use super::{parse_number, registers::Register, state::EmulatorState};
+use arm11_isa::address::Address;
+
+// The kinds of access a watchpoint stops on
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum Access {
+    Read,
+    Write,
+    #[default]
+    ReadWrite,
+}
+
+impl FromStr for Access {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "r" => Ok(Access::Read),
+            "w" => Ok(Access::Write),
+            "rw" => Ok(Access::ReadWrite),
+            _ => Err(format!("Invalid access '{}', expected r, w or rw", s)),
+        }
+    }
+}
+
+// A range of memory which stops execution when a load or store touches any byte of it. The
+// range is half open, and a single address watches just that byte.
+//
+// eg: 0x1000, 0x1000..0x1100:w
+//
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Watchpoint {
+    pub start: Address,
+    pub end: Address,
+    pub access: Access,
+}
+
+impl Watchpoint {
+    fn matches(&self, address: Address, len: u32, load: bool) -> bool {
+        let access = match self.access {
+            Access::Read => load,
+            Access::Write => !load,
+            Access::ReadWrite => true,
+        };
+        // Compare as 64 bit numbers, so accesses at the top of memory don't wrap
+        let (start, end) = (u64::from(address.0), u64::from(address.0) + u64::from(len));
+        access && start < u64::from(self.end.0) && u64::from(self.start.0) < end
+    }
+}
+
+impl FromStr for Watchpoint {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let (range, access) = match s.split_once(':') {
+            Some((range, access)) => (range, access.parse()?),
+            None => (s, Access::default()),
+        };
+        let (start, end) = match range.split_once("..") {
+            Some((start, end)) => (parse_number(start)?, parse_number(end)?),
+            None => {
+                let start = parse_number(range)?;
+                (start, start.wrapping_add(1))
+            }
+        };
+        if start >= end {
+            return Err(format!("Invalid watchpoint '{}', the range is empty", s));
+        }
+        Ok(Watchpoint {
+            start: Address(start),
+            end: Address(end),
+            access,
+        })
+    }
+}
+
+// An access which touched a watchpoint; the instruction which made it, the address accessed,
+// and the value loaded or stored
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WatchHit {
+    pub pc: Address,
+    pub address: Address,
+    pub load: bool,
+    pub value: u32,
+}
+
+impl fmt::Display for WatchHit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Watchpoint: {} {} 0x{:0>8x} {} {}",
+            self.pc,
+            if self.load { "loaded" } else { "stored" },
+            self.value,
+            if self.load { "from" } else { "to" },
+            self.address
+        )
+    }
+}
+
+impl EmulatorState {
+    // Stops execution when a load or store touches the watched range. Execution stops once the
+    // instruction making the access has completed.
+    pub fn add_watchpoint(&mut self, watchpoint: Watchpoint) {
+        self.watchpoints.push(watchpoint);
+    }
+
+    // Records a hit if a memory access touches a watchpoint, and adds stores to the history.
+    // This must be called before the access is made, and before the instruction writes any
+    // registers, so that the PC is still that of the instruction.
+    pub(super) fn check_watchpoints(&mut self, address: Address, len: u32, load: bool, value: u32) {
+        if !load {
+            self.record_store(address, len, value);
+        }
+        if self.watch_hit.is_some()
+            || !self
+                .watchpoints
+                .iter()
+                .any(|watchpoint| watchpoint.matches(address, len, load))
+        {
+            return;
+        }
+        let pc = Address(self.read_reg(Register::Pc))
+            .wrapping_sub(self.instruction_width().pipeline_offset());
+        self.watch_hit = Some(WatchHit {
+            pc,
+            address,
+            load,
+            value,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Status;
+    use arm11_asm::assemble;
+
+    #[test]
+    fn test_watchpoints() {
+        let source = "mov r0,#5\nstr r0,[r1,#0x40]\nldrb r2,[r1,#0x40]\nstmia r1,{r0,r2}\n\
+                      andeq r0,r0,r0\n";
+        let bytes = assemble(String::from(source))
+            .expect("assemble failed")
+            .to_bytes();
+        let mut emulator = EmulatorState::with_memory(bytes).expect("load failed");
+        emulator.add_watchpoint("0x40".parse().expect("parse failed"));
+        emulator.add_watchpoint("0x0..0x8:w".parse().expect("parse failed"));
+
+        let result = emulator.run().expect("run failed");
+        let hit = WatchHit {
+            pc: Address(0x4),
+            address: Address(0x40),
+            load: false,
+            value: 5,
+        };
+        assert_eq!(result.watchpoint, Some(hit));
+        assert_eq!(
+            hit.to_string(),
+            "Watchpoint: 0x00000004 stored 0x00000005 to 0x00000040"
+        );
+        // The store has completed, but nothing after it has run
+        assert_eq!(
+            emulator.read_memory(Address(0x40)).expect("read failed").0,
+            5
+        );
+        assert_eq!(emulator.instructions, 2);
+
+        let result = emulator.run().expect("run failed");
+        assert_eq!(
+            result.watchpoint.map(|hit| (hit.pc, hit.load, hit.value)),
+            Some((Address(0x8), true, 5))
+        );
+        let mut status = emulator.step().expect("step failed");
+        while status == Status::Running {
+            status = emulator.step().expect("step failed");
+        }
+        assert_eq!(
+            status,
+            Status::Watchpoint(WatchHit {
+                pc: Address(0xc),
+                address: Address(0x0),
+                load: false,
+                value: 5,
+            })
+        );
+        assert_eq!(emulator.run().expect("run failed").watchpoint, None);
+
+        assert_eq!(
+            "0x10..0x20:r".parse(),
+            Ok(Watchpoint {
+                start: Address(0x10),
+                end: Address(0x20),
+                access: Access::Read,
+            })
+        );
+        assert!("0x20..0x10".parse::<Watchpoint>().is_err());
+        assert!("0x10:x".parse::<Watchpoint>().is_err());
+    }
+}
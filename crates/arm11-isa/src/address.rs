@@ -0,0 +1,147 @@
+use std::{collections::HashMap, fmt};
+
+use crate::constants::BYTES_IN_WORD;
+
+// A byte address in the emulated machine's 32 bit address space. Arithmetic on addresses wraps
+// around the top of the address space, as it does on the hardware, rather than overflowing.
+//
+// eg: Address(0xfffffffc).offset(8) == Address(0x4)
+//
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Address(pub u32);
+
+// A 32 bit value as stored in memory, which is little endian. Words are unsigned, and only
+// become signed when asked for.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Word(pub u32);
+
+// The address of every label in an assembled program
+pub type SymbolTable = HashMap<String, Address>;
+
+impl Address {
+    // The address the given number of bytes after this one
+    pub fn wrapping_add(self, bytes: u32) -> Self {
+        Address(self.0.wrapping_add(bytes))
+    }
+
+    // The address the given number of bytes before this one
+    pub fn wrapping_sub(self, bytes: u32) -> Self {
+        Address(self.0.wrapping_sub(bytes))
+    }
+
+    // The address offset by a signed number of bytes, eg: for a negative load or branch offset
+    pub fn offset(self, bytes: i32) -> Self {
+        Address(self.0.wrapping_add(bytes as u32))
+    }
+
+    // The signed distance in bytes from another address to this one
+    pub fn offset_from(self, other: Address) -> i32 {
+        self.0.wrapping_sub(other.0) as i32
+    }
+
+    // The address rounded down to a multiple of alignment, which must be a power of two
+    pub fn align_down(self, alignment: u32) -> Self {
+        debug_assert!(alignment.is_power_of_two());
+        Address(self.0 & !(alignment - 1))
+    }
+
+    // The address rounded up to a multiple of alignment, which must be a power of two
+    pub fn align_up(self, alignment: u32) -> Self {
+        self.wrapping_add(alignment - 1).align_down(alignment)
+    }
+
+    pub fn is_aligned(self, alignment: u32) -> bool {
+        debug_assert!(alignment.is_power_of_two());
+        self.0 & (alignment - 1) == 0
+    }
+
+    pub fn is_word_aligned(self) -> bool {
+        self.is_aligned(BYTES_IN_WORD as u32)
+    }
+}
+
+impl Word {
+    pub fn from_le_bytes(bytes: [u8; BYTES_IN_WORD]) -> Self {
+        Word(u32::from_le_bytes(bytes))
+    }
+
+    pub fn to_le_bytes(self) -> [u8; BYTES_IN_WORD] {
+        self.0.to_le_bytes()
+    }
+
+    // The word interpreted as a two's complement number
+    pub fn signed(self) -> i32 {
+        self.0 as i32
+    }
+}
+
+impl From<u32> for Address {
+    fn from(address: u32) -> Self {
+        Address(address)
+    }
+}
+
+impl From<Address> for u32 {
+    fn from(address: Address) -> Self {
+        address.0
+    }
+}
+
+impl From<u32> for Word {
+    fn from(word: u32) -> Self {
+        Word(word)
+    }
+}
+
+impl From<Word> for u32 {
+    fn from(word: Word) -> Self {
+        word.0
+    }
+}
+
+impl fmt::Display for Address {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "0x{:0>8x}", self.0)
+    }
+}
+
+impl fmt::LowerHex for Address {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::LowerHex::fmt(&self.0, f)
+    }
+}
+
+impl fmt::Display for Word {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "0x{:0>8x}", self.0)
+    }
+}
+
+impl fmt::LowerHex for Word {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::LowerHex::fmt(&self.0, f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_address_arithmetic() {
+        assert_eq!(Address(0xfffffffc).wrapping_add(8), Address(0x4));
+        assert_eq!(Address(0x4).wrapping_sub(8), Address(0xfffffffc));
+        assert_eq!(Address(0x80000000).offset(-4), Address(0x7ffffffc));
+        assert_eq!(Address(0x4).offset(-8), Address(0xfffffffc));
+        assert_eq!(Address(0x4).offset_from(Address(0x10)), -12);
+
+        assert_eq!(Address(0x1006).align_down(4), Address(0x1004));
+        assert_eq!(Address(0x1006).align_up(4), Address(0x1008));
+        assert_eq!(Address(0x1008).align_up(4), Address(0x1008));
+        assert!(Address(0x1008).is_word_aligned());
+        assert!(!Address(0x1006).is_word_aligned());
+
+        assert_eq!(Address(0x20).to_string(), "0x00000020");
+        assert_eq!(Word::from_le_bytes([0xfe, 0xff, 0xff, 0xff]).signed(), -2);
+    }
+}
@@ -0,0 +1,103 @@
+pub const MEMORY_SIZE: usize = 65536;
+pub const NUM_REGS: usize = 17;
+pub const BYTES_IN_WORD: usize = 4;
+pub const PIPELINE_OFFSET: usize = 8;
+// In Thumb state the PC is two halfwords ahead of the executing instruction
+pub const THUMB_PIPELINE_OFFSET: usize = 4;
+
+// Special Registers
+pub const PC: usize = 15;
+
+// Instruction Fields
+
+pub struct InstructionField {
+    pub size: u8,
+    pub pos: u32,
+}
+
+impl InstructionField {
+    pub const fn new(size: u8, pos: u32) -> Self {
+        InstructionField { size, pos }
+    }
+
+    pub const fn bit(pos: u32) -> Self {
+        InstructionField { size: 1, pos }
+    }
+}
+
+// Common instruction fields
+pub const COND: InstructionField = InstructionField::new(4, 28);
+pub const I: InstructionField = InstructionField::bit(25);
+pub const S: InstructionField = InstructionField::bit(20);
+pub const RN: InstructionField = InstructionField::new(4, 16);
+pub const RD: InstructionField = InstructionField::new(4, 12);
+
+// Processing instruction fields
+pub const OPCODE: InstructionField = InstructionField::new(4, 21);
+
+// Transfer instruction fields
+pub const P: InstructionField = InstructionField::bit(24);
+pub const U: InstructionField = InstructionField::bit(23);
+pub const L: InstructionField = InstructionField::bit(20);
+pub const B: InstructionField = InstructionField::bit(22);
+pub const OFFSET_TRANSFER: InstructionField = InstructionField::new(12, 0);
+
+// Halfword and signed transfer instruction fields
+pub const HALFWORD_IMM: InstructionField = InstructionField::bit(22);
+pub const SH: InstructionField = InstructionField::new(2, 5);
+pub const OFFSET_HI: InstructionField = InstructionField::new(4, 8);
+pub const OFFSET_LO: InstructionField = InstructionField::new(4, 0);
+
+// Block transfer instruction fields
+pub const W: InstructionField = InstructionField::bit(21);
+pub const REGISTER_LIST: InstructionField = InstructionField::new(16, 0);
+
+// Multiply instruction fields
+pub const A: InstructionField = InstructionField::bit(21);
+pub const RD_MULT: InstructionField = InstructionField::new(4, 16);
+pub const RN_MULT: InstructionField = InstructionField::new(4, 12);
+pub const RS: InstructionField = InstructionField::new(4, 8);
+pub const RM: InstructionField = InstructionField::new(4, 0);
+
+// Long multiply instruction fields
+pub const LONG: InstructionField = InstructionField::bit(23);
+pub const SIGNED: InstructionField = InstructionField::bit(22);
+pub const RD_HI: InstructionField = InstructionField::new(4, 16);
+pub const RD_LO: InstructionField = InstructionField::new(4, 12);
+
+// Coprocessor register transfer instruction fields
+pub const CP_OPCODE1: InstructionField = InstructionField::new(3, 21);
+pub const CRN: InstructionField = InstructionField::new(4, 16);
+pub const CP_NUM: InstructionField = InstructionField::new(4, 8);
+pub const CP_OPCODE2: InstructionField = InstructionField::new(3, 5);
+pub const CRM: InstructionField = InstructionField::new(4, 0);
+
+// VFP instruction fields. Single precision registers are numbered with 5 bits, split into the
+// top 4 bits in the Vd, Vn or Vm field (the same as Rd, Rn and Rm) and the bottom bit here.
+pub const VFP_D: InstructionField = InstructionField::bit(22);
+pub const VFP_N: InstructionField = InstructionField::bit(7);
+pub const VFP_M: InstructionField = InstructionField::bit(5);
+pub const VFP_OFFSET: InstructionField = InstructionField::new(8, 0);
+
+// Status register transfer instruction fields
+pub const STATUS_SPSR: InstructionField = InstructionField::bit(22);
+pub const FIELD_MASK: InstructionField = InstructionField::new(4, 16);
+
+// Software interrupt instruction fields
+pub const SWI_COMMENT: InstructionField = InstructionField::new(24, 0);
+
+// Branch instruction fields
+pub const LINK: InstructionField = InstructionField::bit(24);
+pub const OFFSET_BRANCH: InstructionField = InstructionField::new(24, 0);
+
+// Operand2 / Offset sub-fields
+pub const IMM_VALUE: InstructionField = InstructionField::new(8, 0);
+pub const IMM_SHIFT: InstructionField = InstructionField::new(4, 8);
+pub const SHIFT_TYPE: InstructionField = InstructionField::new(2, 5);
+pub const CONST_SHIFT: InstructionField = InstructionField::new(5, 7);
+pub const REG_SHIFT: InstructionField = InstructionField::new(4, 8);
+
+// Bitmasking
+pub const fn mask(size: u8) -> u32 {
+    (1 << size) - 1
+}
@@ -0,0 +1,992 @@
+use nom::{
+    bits,
+    bits::complete::{tag, take},
+    branch::alt,
+    combinator::{map, map_opt, peek},
+    error::context,
+    sequence::{pair, preceded, terminated, tuple},
+};
+
+use num_traits::FromPrimitive;
+
+use crate::{constants::*, parse::*, types::*};
+
+// Bits 27 to 4 of every bx instruction
+const BRANCH_EXCHANGE_PATTERN: u32 = 0x12fff1;
+// Bits 27 to 23 of every long multiply instruction
+const MULTIPLY_LONG_PATTERN: u32 = 0x1;
+// Bits 27 to 24 of every swi instruction
+const SOFTWARE_INTERRUPT_PATTERN: u32 = 0xf;
+// Bits 11 to 9 of every VFP instruction, which use coprocessors 10 (single precision) and 11
+// (double precision)
+const VFP_PATTERN: u32 = 0x5;
+
+pub fn decode(instr: &u32) -> Result<ConditionalInstruction> {
+    // A zero instruction is Halt
+    if *instr == 0 {
+        return Ok(ConditionalInstruction {
+            cond: ConditionCode::Eq,
+            instruction: Instruction::Halt,
+        });
+    }
+
+    let mut decoder = bits(decode_conditional_instruction);
+    Ok(decoder(&instr.to_be_bytes())
+        .map_err(|_| ArmError::BadOpcode {
+            opcode: *instr,
+            kind: if is_vfp(*instr) { "VFP" } else { "ARM" },
+            reason: None,
+        })?
+        .1)
+}
+
+// Whether the instruction is in the coprocessor space of the VFP, whether or not it is one of
+// the supported VFP instructions
+fn is_vfp(instr: u32) -> bool {
+    matches!(instr >> 24 & 0xf, 0xc..=0xe) && instr >> 9 & 0x7 == VFP_PATTERN
+}
+
+// Sign extends the 24 bit offset of a branch
+pub fn signed_24_to_32(num: i32) -> i32 {
+    if (num as u32) >> 23 & 1 == 1 {
+        num | !mask(24) as i32
+    } else {
+        num
+    }
+}
+
+fn decode_conditional_instruction(
+    input: (&[u8], usize),
+) -> NomResult<(&[u8], usize), ConditionalInstruction> {
+    let instr_type: (u32, bool, u32) = context(
+        "peeking conditional instruction type",
+        peek(tuple((
+            preceded(take::<_, u32, _, _>(4u32), take(2u32)),
+            take_bool,
+            preceded(take::<_, u32, _, _>(17u32), take(4u32)),
+        ))),
+    )(input)?
+    .1;
+
+    // Branch and exchange is a single fixed pattern, which otherwise overlaps with processing
+    let is_branch_exchange = context(
+        "peeking branch exchange instruction",
+        peek(preceded(
+            take::<_, u32, _, _>(4u32),
+            take::<_, u32, _, _>(24u32),
+        )),
+    )(input)?
+    .1 == BRANCH_EXCHANGE_PATTERN;
+
+    // Software interrupts share their top bits with coprocessor instructions
+    let is_software_interrupt = context(
+        "peeking software interrupt instruction",
+        peek(preceded(
+            take::<_, u32, _, _>(4u32),
+            take::<_, u32, _, _>(4u32),
+        )),
+    )(input)?
+    .1 == SOFTWARE_INTERRUPT_PATTERN;
+
+    // Long multiplies are told apart from multiplies by bit 23
+    let is_multiply_long = context(
+        "peeking long multiply instruction",
+        peek(preceded(
+            take::<_, u32, _, _>(4u32),
+            take::<_, u32, _, _>(5u32),
+        )),
+    )(input)?
+    .1 == MULTIPLY_LONG_PATTERN;
+
+    // Status register transfers are the processing instructions tst, teq, cmp and cmn without the
+    // S bit, which would otherwise be pointless
+    let is_status_transfer = context(
+        "peeking status register transfer instruction",
+        peek(tuple((
+            preceded(take::<_, u8, _, _>(4u32), take::<_, u8, _, _>(2u32)),
+            preceded(take::<_, u8, _, _>(1u32), take::<_, u8, _, _>(2u32)),
+            preceded(take::<_, u8, _, _>(2u32), take::<_, u8, _, _>(1u32)),
+        ))),
+    )(input)?
+    .1 == (0x0, 0x2, 0x0);
+
+    // VFP instructions are the coprocessor instructions for coprocessors 10 and 11
+    let is_vfp = context(
+        "peeking VFP instruction",
+        peek(tuple((
+            preceded(take::<_, u8, _, _>(4u32), take::<_, u8, _, _>(4u32)),
+            preceded(take::<_, u16, _, _>(12u32), take::<_, u32, _, _>(3u32)),
+        ))),
+    )(input)
+    .map(|(_, (top, cp))| (0xc..=0xe).contains(&top) && cp == VFP_PATTERN)?;
+
+    let decode_instr = match instr_type {
+        _ if is_branch_exchange => decode_branch_exchange,
+        _ if is_software_interrupt => decode_software_interrupt,
+        _ if is_vfp => decode_vfp,
+        (0x0, false, 0x9) if is_multiply_long => decode_multiply_long,
+        (0x0, false, 0x9) => decode_multiply,
+        (0x0, false, 0xb | 0xd | 0xf) => decode_halfword_transfer,
+        (0x0, _, _) if is_status_transfer => decode_status_transfer,
+        (0x0, _, _) => decode_processing,
+        (0x1, _, _) => decode_transfer,
+        (0x2, false, _) => decode_block_transfer,
+        (0x2, true, _) => decode_branch,
+        (0x3, true, cp) if cp & 1 == 1 => decode_coprocessor,
+        _ => return Err(ArmNomError::new(ArmNomErrorKind::InvalidInstructionType).into()),
+    };
+
+    context(
+        "decoding conditional instruction",
+        map(tuple((decode_cond, decode_instr)), |(cond, instruction)| {
+            ConditionalInstruction { instruction, cond }
+        }),
+    )(input)
+}
+
+fn decode_processing(input: (&[u8], usize)) -> NomResult<(&[u8], usize), Instruction> {
+    let is_immediate = peek(preceded(take::<_, u32, _, _>(2u32), take_bool))(input)?.1;
+    context(
+        "decoding processing instruction",
+        map(
+            tuple((
+                tag(0, 2u8),
+                take_bool,
+                decode_opcode,
+                take_bool,
+                take(RN.size),
+                take(RD.size),
+                if is_immediate {
+                    decode_operand2_immediate
+                } else {
+                    decode_operand2_shifted
+                },
+            )),
+            |(_, _, opcode, set_cond, rn, rd, operand2)| {
+                Instruction::Processing(InstructionProcessing {
+                    opcode,
+                    set_cond,
+                    rn,
+                    rd,
+                    operand2,
+                })
+            },
+        ),
+    )(input)
+}
+
+fn decode_transfer(input: (&[u8], usize)) -> NomResult<(&[u8], usize), Instruction> {
+    // Check if its an immediate or shifted register transfer
+    let is_shifted_r = peek(preceded(take::<_, u32, _, _>(2u32), take_bool))(input)?.1;
+    context(
+        "decoding transfer instruction",
+        map_opt(
+            tuple((
+                tag(1, 2u8),
+                take_bool,
+                take_bool,
+                take_bool,
+                take_bool,
+                take_bool,
+                take_bool,
+                take(RN.size),
+                take(RD.size),
+                if is_shifted_r {
+                    decode_transfer_shifted
+                } else {
+                    decode_transfer_immediate
+                },
+            )),
+            |(_, _, is_preindexed, up_bit, byte, writeback, load, rn, rd, offset)| {
+                // Post-indexed transfers with the W bit set are ldrt and strt, which aren't
+                // supported
+                (is_preindexed || !writeback).then_some(Instruction::Transfer(
+                    InstructionTransfer {
+                        is_preindexed,
+                        up_bit,
+                        writeback,
+                        load,
+                        size: if byte {
+                            TransferSize::Byte
+                        } else {
+                            TransferSize::Word
+                        },
+                        rn,
+                        rd,
+                        offset,
+                    },
+                ))
+            },
+        ),
+    )(input)
+}
+
+fn decode_halfword_transfer(input: (&[u8], usize)) -> NomResult<(&[u8], usize), Instruction> {
+    context(
+        "decoding halfword transfer instruction",
+        map_opt(
+            tuple((
+                tag(0, 3u8),
+                take_bool,
+                take_bool,
+                take_bool,
+                take_bool,
+                take_bool,
+                take(RN.size),
+                take(RD.size),
+                take::<_, u8, _, _>(OFFSET_HI.size),
+                preceded(tag(1, 1u8), terminated(take(SH.size), tag(1, 1u8))),
+                take::<_, u8, _, _>(OFFSET_LO.size),
+            )),
+            |(_, is_preindexed, up_bit, is_immediate, writeback, load, rn, rd, hi, sh, lo)| {
+                if writeback && !is_preindexed {
+                    return None;
+                }
+                let size = match sh {
+                    0x1 => TransferSize::Halfword,
+                    0x2 => TransferSize::SignedByte,
+                    0x3 => TransferSize::SignedHalfword,
+                    _ => return None,
+                };
+                let offset = if is_immediate {
+                    TransferOffset::Immediate(u16::from(hi) << OFFSET_LO.size | u16::from(lo))
+                } else {
+                    TransferOffset::ShiftedReg(lo, Shift::ConstantShift(ShiftType::Lsl, 0))
+                };
+                Some(Instruction::Transfer(InstructionTransfer {
+                    is_preindexed,
+                    up_bit,
+                    writeback,
+                    load,
+                    size,
+                    rn,
+                    rd,
+                    offset,
+                }))
+            },
+        ),
+    )(input)
+}
+
+fn decode_multiply(input: (&[u8], usize)) -> NomResult<(&[u8], usize), Instruction> {
+    context(
+        "decoding multiply instruction",
+        map(
+            tuple((
+                tag(0, 6u8),
+                take_bool,
+                take_bool,
+                take(RD_MULT.size),
+                take(RN_MULT.size),
+                take(RS.size),
+                tag(0x9, 4u8),
+                take(RM.size),
+            )),
+            |(_, accumulate, set_cond, rd, rn, rs, _, rm)| {
+                Instruction::Multiply(InstructionMultiply {
+                    accumulate,
+                    set_cond,
+                    rd,
+                    rn,
+                    rs,
+                    rm,
+                })
+            },
+        ),
+    )(input)
+}
+
+fn decode_multiply_long(input: (&[u8], usize)) -> NomResult<(&[u8], usize), Instruction> {
+    context(
+        "decoding long multiply instruction",
+        map(
+            tuple((
+                tag(0x1, 5u8),
+                take_bool,
+                take_bool,
+                take_bool,
+                take(RD_HI.size),
+                take(RD_LO.size),
+                take(RS.size),
+                tag(0x9, 4u8),
+                take(RM.size),
+            )),
+            |(_, signed, accumulate, set_cond, rd_hi, rd_lo, rs, _, rm)| {
+                Instruction::MultiplyLong(InstructionMultiplyLong {
+                    signed,
+                    accumulate,
+                    set_cond,
+                    rd_hi,
+                    rd_lo,
+                    rs,
+                    rm,
+                })
+            },
+        ),
+    )(input)
+}
+
+fn decode_block_transfer(input: (&[u8], usize)) -> NomResult<(&[u8], usize), Instruction> {
+    context(
+        "decoding block transfer instruction",
+        map(
+            tuple((
+                tag(0x4, 3u8),
+                take_bool,
+                take_bool,
+                tag(0, 1u8),
+                take_bool,
+                take_bool,
+                take(RN.size),
+                take(REGISTER_LIST.size),
+            )),
+            |(_, is_preindexed, up_bit, _, writeback, load, rn, register_list)| {
+                Instruction::BlockTransfer(InstructionBlockTransfer {
+                    is_preindexed,
+                    up_bit,
+                    writeback,
+                    load,
+                    rn,
+                    register_list,
+                })
+            },
+        ),
+    )(input)
+}
+
+fn decode_branch(input: (&[u8], usize)) -> NomResult<(&[u8], usize), Instruction> {
+    context(
+        "decoding branch instruction",
+        map(
+            tuple((tag(0x5, 3u8), take_bool, take(OFFSET_BRANCH.size))),
+            |(_, link, offset)| Instruction::Branch(InstructionBranch { link, offset }),
+        ),
+    )(input)
+}
+
+fn decode_branch_exchange(input: (&[u8], usize)) -> NomResult<(&[u8], usize), Instruction> {
+    context(
+        "decoding branch exchange instruction",
+        map(
+            preceded(tag(BRANCH_EXCHANGE_PATTERN, 24u8), take(RM.size)),
+            |rm| Instruction::BranchExchange(InstructionBranchExchange { rm }),
+        ),
+    )(input)
+}
+
+fn decode_coprocessor(input: (&[u8], usize)) -> NomResult<(&[u8], usize), Instruction> {
+    context(
+        "decoding coprocessor instruction",
+        map(
+            tuple((
+                tag(0xe, 4u8),
+                take(CP_OPCODE1.size),
+                take_bool,
+                take(CRN.size),
+                take(RD.size),
+                take(CP_NUM.size),
+                take(CP_OPCODE2.size),
+                tag(1, 1u8),
+                take(CRM.size),
+            )),
+            |(_, opcode1, load, crn, rd, coprocessor, opcode2, _, crm)| {
+                Instruction::Coprocessor(InstructionCoprocessor {
+                    load,
+                    coprocessor,
+                    opcode1,
+                    crn,
+                    rd,
+                    crm,
+                    opcode2,
+                })
+            },
+        ),
+    )(input)
+}
+
+fn decode_vfp(input: (&[u8], usize)) -> NomResult<(&[u8], usize), Instruction> {
+    // Single precision register numbers are split into 4 bits and a separate bottom bit
+    let single = |top: u8, bottom: u8| top << 1 | bottom;
+    let transfer = map(
+        tuple((
+            tag(0xd, 4u8),
+            take_bool,
+            take(VFP_D.size),
+            tag(0, 1u8),
+            take_bool,
+            take(RN.size),
+            take(RD.size),
+            tag(0xa, 4u8),
+            take(VFP_OFFSET.size),
+        )),
+        move |(_, up_bit, d, _, load, rn, vd, _, offset)| InstructionVfp::Transfer {
+            load,
+            up_bit,
+            rn,
+            sd: single(vd, d),
+            offset,
+        },
+    );
+    let arithmetic = map_opt(
+        tuple((
+            tag(0xe, 4u8),
+            take(1u8),
+            take(VFP_D.size),
+            take(2u8),
+            take(RN.size),
+            take(RD.size),
+            tag(0xa, 4u8),
+            take(VFP_N.size),
+            take(1u8),
+            take(VFP_M.size),
+            tag(0, 1u8),
+            take(RM.size),
+        )),
+        move |(_, op23, d, op21, vn, vd, _, n, op6, m, _, vm): (
+            _,
+            u8,
+            _,
+            u8,
+            _,
+            _,
+            _,
+            _,
+            u8,
+            _,
+            _,
+            _,
+        )| {
+            let opcode = match (op23, op21, op6) {
+                (0, 0x2, 0) => VfpOpcode::Mul,
+                (0, 0x3, 0) => VfpOpcode::Add,
+                (0, 0x3, 1) => VfpOpcode::Sub,
+                (1, 0x0, 0) => VfpOpcode::Div,
+                _ => return None,
+            };
+            Some(InstructionVfp::Arithmetic {
+                opcode,
+                sd: single(vd, d),
+                sn: single(vn, n),
+                sm: single(vm, m),
+            })
+        },
+    );
+    let transfer_register = map(
+        tuple((
+            tag(0x70, 7u8),
+            take_bool,
+            take(RN.size),
+            take(RD.size),
+            tag(0xa, 4u8),
+            take(VFP_N.size),
+            tag(0x10, 7u8),
+        )),
+        move |(_, to_arm, vn, rt, _, n, _)| InstructionVfp::Move {
+            to_arm,
+            sn: single(vn, n),
+            rt,
+        },
+    );
+    let convert = map_opt(
+        tuple((
+            tag(0x1d, 5u8),
+            take(VFP_D.size),
+            tag(0x7, 3u8),
+            take(3u8),
+            take(RD.size),
+            tag(0xa, 4u8),
+            tag(0x3, 2u8),
+            take(VFP_M.size),
+            tag(0, 1u8),
+            take(RM.size),
+        )),
+        move |(_, d, _, opc2, vd, _, _, m, _, vm): (_, _, _, u8, _, _, _, _, _, _)| {
+            let to_int = match opc2 {
+                0x0 => false,
+                0x5 => true,
+                _ => return None,
+            };
+            Some(InstructionVfp::Convert {
+                to_int,
+                sd: single(vd, d),
+                sm: single(vm, m),
+            })
+        },
+    );
+    // vcmpe, which differs only in raising exceptions for quiet NaNs, is decoded as vcmp
+    let compare = map(
+        tuple((
+            tag(0x1d, 5u8),
+            take(VFP_D.size),
+            tag(0x34, 6u8),
+            take(RD.size),
+            tag(0xa, 4u8),
+            take(1u8),
+            tag(1, 1u8),
+            take(VFP_M.size),
+            tag(0, 1u8),
+            take(RM.size),
+        )),
+        move |(_, d, _, vd, _, _, _, m, _, vm): (_, _, _, _, _, u8, _, _, _, _)| {
+            InstructionVfp::Compare {
+                sd: single(vd, d),
+                sm: single(vm, m),
+            }
+        },
+    );
+    let move_flags = map(tag(0xef1fa10, 28u32), |_| InstructionVfp::MoveFlags);
+
+    context(
+        "decoding VFP instruction",
+        map(
+            alt((
+                transfer,
+                arithmetic,
+                transfer_register,
+                convert,
+                compare,
+                move_flags,
+            )),
+            Instruction::Vfp,
+        ),
+    )(input)
+}
+
+fn decode_status_transfer(input: (&[u8], usize)) -> NomResult<(&[u8], usize), Instruction> {
+    context(
+        "decoding status register transfer instruction",
+        alt((
+            map(
+                tuple((
+                    tag(0x2, 5u8),
+                    take_bool,
+                    tag(0xf, 6u8),
+                    take(RD.size),
+                    tag(0, 12u16),
+                )),
+                |(_, spsr, _, rd, _)| Instruction::StatusRead(InstructionStatusRead { spsr, rd }),
+            ),
+            map(
+                tuple((
+                    tag(0x2, 5u8),
+                    take_bool,
+                    tag(0x2, 2u8),
+                    take(FIELD_MASK.size),
+                    tag(0xf00, 12u16),
+                    take(RM.size),
+                )),
+                |(_, spsr, _, field_mask, _, rm)| {
+                    Instruction::StatusWrite(InstructionStatusWrite {
+                        spsr,
+                        field_mask,
+                        operand: Operand2::ShiftedReg(rm, Shift::ConstantShift(ShiftType::Lsl, 0)),
+                    })
+                },
+            ),
+            map(
+                tuple((
+                    tag(0x6, 5u8),
+                    take_bool,
+                    tag(0x2, 2u8),
+                    take(FIELD_MASK.size),
+                    tag(0xf, 4u8),
+                    decode_operand2_immediate,
+                )),
+                |(_, spsr, _, field_mask, _, operand)| {
+                    Instruction::StatusWrite(InstructionStatusWrite {
+                        spsr,
+                        field_mask,
+                        operand,
+                    })
+                },
+            ),
+        )),
+    )(input)
+}
+
+fn decode_software_interrupt(input: (&[u8], usize)) -> NomResult<(&[u8], usize), Instruction> {
+    context(
+        "decoding software interrupt instruction",
+        map(
+            preceded(tag(SOFTWARE_INTERRUPT_PATTERN, 4u8), take(SWI_COMMENT.size)),
+            |comment| Instruction::SoftwareInterrupt(InstructionSoftwareInterrupt { comment }),
+        ),
+    )(input)
+}
+
+fn take_bool(input: (&[u8], usize)) -> NomResult<(&[u8], usize), bool> {
+    map(take(1u8), |i: u8| i == 1)(input)
+}
+
+fn decode_opcode(input: (&[u8], usize)) -> NomResult<(&[u8], usize), ProcessingOpcode> {
+    context(
+        "decoding processing opcode",
+        map_opt(take(OPCODE.size), ProcessingOpcode::from_u8),
+    )(input)
+}
+
+fn decode_shift_type(input: (&[u8], usize)) -> NomResult<(&[u8], usize), ShiftType> {
+    context(
+        "decoding shift type",
+        map_opt(take(SHIFT_TYPE.size), ShiftType::from_u8),
+    )(input)
+}
+
+fn decode_cond(input: (&[u8], usize)) -> NomResult<(&[u8], usize), ConditionCode> {
+    context(
+        "decoding condition code",
+        map_opt(take(COND.size), ConditionCode::from_u8),
+    )(input)
+}
+
+fn decode_operand2_immediate(input: (&[u8], usize)) -> NomResult<(&[u8], usize), Operand2> {
+    context(
+        "decoding operand2 immediate",
+        map(
+            tuple((take(IMM_SHIFT.size), take(IMM_VALUE.size))),
+            |(shift_amt, to_shift)| Operand2::ConstantShift(to_shift, shift_amt),
+        ),
+    )(input)
+}
+
+// Decodes the immediate offset of a word or byte transfer, which is a plain 12 bit value
+fn decode_transfer_immediate(input: (&[u8], usize)) -> NomResult<(&[u8], usize), TransferOffset> {
+    context(
+        "decoding transfer immediate",
+        map(take(OFFSET_TRANSFER.size), TransferOffset::Immediate),
+    )(input)
+}
+
+// Decodes the register offset of a word or byte transfer, which is shifted as in an Operand2
+fn decode_transfer_shifted(input: (&[u8], usize)) -> NomResult<(&[u8], usize), TransferOffset> {
+    map_opt(decode_operand2_shifted, |operand2| match operand2 {
+        Operand2::ShiftedReg(rm, shift) => Some(TransferOffset::ShiftedReg(rm, shift)),
+        Operand2::ConstantShift(_, _) => None,
+    })(input)
+}
+
+fn decode_operand2_shifted(input: (&[u8], usize)) -> NomResult<(&[u8], usize), Operand2> {
+    // Check if its an constant shifted register or a shifted register
+    let is_shifted_r = peek(preceded(take::<_, u8, _, _>(7u8), take_bool))(input)?.1;
+    context(
+        "decoding operand2 shifted",
+        map(
+            tuple((
+                alt((
+                    pair(
+                        terminated(take::<_, u8, _, _>(REG_SHIFT.size), tag(0, 1u8)),
+                        terminated(decode_shift_type, tag(1, 1u8)),
+                    ),
+                    pair(
+                        take(CONST_SHIFT.size),
+                        terminated(decode_shift_type, tag(0, 1u8)),
+                    ),
+                )),
+                take(4u8),
+            )),
+            move |((shift_amt, shift_type), reg_to_shift)| {
+                if is_shifted_r {
+                    Operand2::ShiftedReg(reg_to_shift, Shift::RegisterShift(shift_type, shift_amt))
+                } else {
+                    Operand2::ShiftedReg(reg_to_shift, Shift::ConstantShift(shift_type, shift_amt))
+                }
+            },
+        ),
+    )(input)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_operand2_immediate() {
+        let bytes = 0x12a0u16.to_be_bytes();
+        assert_eq!(
+            bits(decode_operand2_immediate)(&bytes[..])
+                .expect("operand2 decode failed")
+                .1,
+            Operand2::ConstantShift(0x2a, 0x1)
+        );
+    }
+
+    #[test]
+    fn test_decode_operand2_shifted() {
+        let bytes = 0x12a0u16.to_be_bytes();
+        assert_eq!(
+            bits(decode_operand2_shifted)(&bytes[..])
+                .expect("operand2 decode failed")
+                .1,
+            Operand2::ShiftedReg(0xa, Shift::ConstantShift(ShiftType::Lsr, 0x2))
+        );
+    }
+
+    #[test]
+    fn test_decode_halt() {
+        assert_eq!(
+            decode(&0u32).expect("parse halt failed").instruction,
+            Instruction::Halt
+        );
+    }
+
+    #[test]
+    fn test_decode_processing() {
+        let bytes = 0xe3a01001u32.to_be_bytes();
+        let expected = ConditionalInstruction {
+            instruction: Instruction::Processing(InstructionProcessing {
+                opcode: ProcessingOpcode::Mov,
+                set_cond: false,
+                rn: 0x0,
+                rd: 0x1,
+                operand2: Operand2::ConstantShift(0x1, 0x0),
+            }),
+            cond: ConditionCode::Al,
+        };
+
+        assert_eq!(
+            bits(decode_conditional_instruction)(&bytes[..])
+                .expect("decode conditional processing failed")
+                .1,
+            expected
+        );
+    }
+
+    #[test]
+    fn test_decode_multiply() {
+        let bytes = 0xe0231290u32.to_be_bytes();
+        let expected = ConditionalInstruction {
+            instruction: Instruction::Multiply(InstructionMultiply {
+                accumulate: true,
+                set_cond: false,
+                rd: 0x3,
+                rn: 0x1,
+                rs: 0x2,
+                rm: 0x0,
+            }),
+            cond: ConditionCode::Al,
+        };
+
+        assert_eq!(
+            bits(decode_conditional_instruction)(&bytes[..])
+                .expect("decode conditional multiply failed")
+                .1,
+            expected
+        );
+    }
+
+    #[test]
+    fn test_decode_multiply_long() {
+        // smlals r0,r1,r2,r3
+        assert_eq!(
+            decode(&0xe0f10392u32)
+                .expect("decode long multiply failed")
+                .instruction,
+            Instruction::MultiplyLong(InstructionMultiplyLong {
+                signed: true,
+                accumulate: true,
+                set_cond: true,
+                rd_hi: 1,
+                rd_lo: 0,
+                rs: 3,
+                rm: 2,
+            })
+        );
+    }
+
+    #[test]
+    fn test_decode_unsupported_vfp() {
+        // vadd.f64 d0,d1,d2, as only single precision is supported
+        assert_eq!(
+            decode(&0xee310b02u32).map_err(|e| e.to_string()),
+            Err(String::from("Unsupported VFP instruction 0xee310b02"))
+        );
+        // vsqrt.f32 s0,s1
+        assert!(decode(&0xeeb10ae0u32).is_err());
+        // Other coprocessors are still decoded as coprocessor instructions
+        assert!(decode(&0xee110f10u32).is_ok());
+    }
+
+    #[test]
+    fn test_decode_status_transfer() {
+        // mrs r3,cpsr
+        assert_eq!(
+            decode(&0xe10f3000u32)
+                .expect("decode mrs failed")
+                .instruction,
+            Instruction::StatusRead(InstructionStatusRead { spsr: false, rd: 3 })
+        );
+        // msr cpsr_f,r2
+        assert_eq!(
+            decode(&0xe128f002u32)
+                .expect("decode msr failed")
+                .instruction,
+            Instruction::StatusWrite(InstructionStatusWrite {
+                spsr: false,
+                field_mask: 0x8,
+                operand: Operand2::ShiftedReg(2, Shift::ConstantShift(ShiftType::Lsl, 0)),
+            })
+        );
+        // msr cpsr_f,#0xf0000000
+        assert_eq!(
+            decode(&0xe328f20fu32)
+                .expect("decode msr failed")
+                .instruction,
+            Instruction::StatusWrite(InstructionStatusWrite {
+                spsr: false,
+                field_mask: 0x8,
+                operand: Operand2::ConstantShift(0xf, 2),
+            })
+        );
+        // mrs r0,spsr
+        assert_eq!(
+            decode(&0xe14f0000u32)
+                .expect("decode mrs failed")
+                .instruction,
+            Instruction::StatusRead(InstructionStatusRead { spsr: true, rd: 0 })
+        );
+        // msr spsr_fc,r2
+        assert_eq!(
+            decode(&0xe169f002u32)
+                .expect("decode msr failed")
+                .instruction,
+            Instruction::StatusWrite(InstructionStatusWrite {
+                spsr: true,
+                field_mask: 0x9,
+                operand: Operand2::ShiftedReg(2, Shift::ConstantShift(ShiftType::Lsl, 0)),
+            })
+        );
+    }
+
+    #[test]
+    fn test_decode_halfword_transfer() {
+        // ldrsh r0,[r1,#-0x2a]
+        assert_eq!(
+            decode(&0xe15102fau32)
+                .expect("decode halfword transfer failed")
+                .instruction,
+            Instruction::Transfer(InstructionTransfer {
+                is_preindexed: true,
+                up_bit: false,
+                writeback: false,
+                load: true,
+                size: TransferSize::SignedHalfword,
+                rn: 1,
+                rd: 0,
+                offset: TransferOffset::Immediate(0x2a),
+            })
+        );
+        // strb r0,[r1],#1
+        assert_eq!(
+            decode(&0xe4c10001u32)
+                .expect("decode byte transfer failed")
+                .instruction,
+            Instruction::Transfer(InstructionTransfer {
+                is_preindexed: false,
+                up_bit: true,
+                writeback: false,
+                load: false,
+                size: TransferSize::Byte,
+                rn: 1,
+                rd: 0,
+                offset: TransferOffset::Immediate(0x1),
+            })
+        );
+    }
+
+    #[test]
+    fn test_decode_transfer() {
+        let bytes = 0xe7196103u32.to_be_bytes();
+        let expected = ConditionalInstruction {
+            instruction: Instruction::Transfer(InstructionTransfer {
+                is_preindexed: true,
+                up_bit: false,
+                writeback: false,
+                load: true,
+                size: TransferSize::Word,
+                rn: 9,
+                rd: 6,
+                offset: TransferOffset::ShiftedReg(3, Shift::ConstantShift(ShiftType::Lsl, 2)),
+            }),
+            cond: ConditionCode::Al,
+        };
+
+        assert_eq!(
+            bits(decode_conditional_instruction)(&bytes[..])
+                .expect("decode conditional transfer failed")
+                .1,
+            expected
+        );
+    }
+
+    #[test]
+    fn test_decode_block_transfer() {
+        let bytes = 0xe8bd400fu32.to_be_bytes();
+        let expected = ConditionalInstruction {
+            instruction: Instruction::BlockTransfer(InstructionBlockTransfer {
+                is_preindexed: false,
+                up_bit: true,
+                writeback: true,
+                load: true,
+                rn: 13,
+                register_list: 0x400f,
+            }),
+            cond: ConditionCode::Al,
+        };
+
+        assert_eq!(
+            bits(decode_conditional_instruction)(&bytes[..])
+                .expect("decode conditional block transfer failed")
+                .1,
+            expected
+        );
+    }
+
+    #[test]
+    fn test_decode_branch() {
+        let bytes = 0x0a000121u32.to_be_bytes();
+        let expected = ConditionalInstruction {
+            instruction: Instruction::Branch(InstructionBranch {
+                link: false,
+                offset: 0x000121,
+            }),
+            cond: ConditionCode::Eq,
+        };
+
+        assert_eq!(
+            bits(decode_conditional_instruction)(&bytes[..])
+                .expect("decode conditional branch failed")
+                .1,
+            expected
+        );
+    }
+
+    #[test]
+    fn test_decode_branch_link() {
+        assert_eq!(
+            decode(&0xebfffffeu32)
+                .expect("decode branch with link failed")
+                .instruction,
+            Instruction::Branch(InstructionBranch {
+                link: true,
+                offset: 0xfffffe,
+            })
+        );
+        assert_eq!(
+            decode(&0xe12fff1eu32)
+                .expect("decode branch exchange failed")
+                .instruction,
+            Instruction::BranchExchange(InstructionBranchExchange { rm: 14 })
+        );
+    }
+
+    #[test]
+    fn test_decode_software_interrupt() {
+        // swi #0x123456, which would be a coprocessor instruction if bit 24 were clear
+        assert_eq!(
+            decode(&0xef123456u32)
+                .expect("decode software interrupt failed")
+                .instruction,
+            Instruction::SoftwareInterrupt(InstructionSoftwareInterrupt { comment: 0x123456 })
+        );
+    }
+}
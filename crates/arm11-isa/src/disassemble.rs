@@ -0,0 +1,462 @@
+use std::{collections::BTreeMap, fmt::Write};
+
+use crate::{
+    address::SymbolTable,
+    constants::*,
+    decode::{decode, signed_24_to_32},
+    types::*,
+};
+
+// The kind of reference that caused a pseudo-symbol to be generated for an address. This
+// decides the prefix of the generated name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Reference {
+    Branch,
+    Literal,
+}
+
+// Disassembles a binary into ARM assembly, one instruction or data word per line.
+//
+// Addresses which are referenced by the program are labelled; with the name from the symbol
+// table if there is one, otherwise with a pseudo-symbol derived from the address and the kind
+// of reference, i.e. loc_<address> for branch targets and lit_<address> for literal pool
+// entries. Generated names only depend on the binary, so repeated disassembly of the same
+// binary produces identical output.
+//
+pub fn disassemble(bytes: &[u8], symbol_table: &SymbolTable) -> String {
+    // Split the binary into words. Only ARM code is disassembled, and words which don't decode
+    // are treated as data.
+    let mut words: Vec<(u32, u32)> = Vec::new();
+    let mut index = 0;
+    while index < bytes.len() {
+        let chunk = &bytes[index..bytes.len().min(index + BYTES_IN_WORD)];
+        let mut word = [0; BYTES_IN_WORD];
+        word[..chunk.len()].copy_from_slice(chunk);
+        let word = u32::from_le_bytes(word);
+
+        words.push((index as u32, word));
+        index += BYTES_IN_WORD;
+    }
+
+    // First pass - find all referenced addresses
+    let mut references = BTreeMap::new();
+    for (address, word) in &words {
+        if let Ok(instr) = decode(word) {
+            if let Some(reference) = reference(&instr, *address) {
+                references.entry(reference.0).or_insert(reference.1);
+            }
+        }
+    }
+
+    // Prefer symbols from the symbol table, picking the first name if there are aliases
+    let mut labels: BTreeMap<u32, String> = BTreeMap::new();
+    for (name, address) in symbol_table {
+        let label = labels.entry(address.0).or_insert_with(|| name.clone());
+        if name < label {
+            *label = name.clone();
+        }
+    }
+    for (address, kind) in &references {
+        labels.entry(*address).or_insert_with(|| match kind {
+            Reference::Branch => format!("loc_{:0>8x}", address),
+            Reference::Literal => format!("lit_{:0>8x}", address),
+        });
+    }
+
+    // Second pass - print each word, labelling referenced addresses
+    let mut out = String::new();
+    for (address, word) in &words {
+        if let Some(label) = labels.get(address) {
+            writeln!(out, "{}:", label).unwrap();
+        }
+
+        let text = match decode(word) {
+            Ok(instr) if references.get(address) != Some(&Reference::Literal) => {
+                format_instruction(&instr, *address, &|target| labels.get(&target).cloned())
+            }
+            _ => format!(".word 0x{:0>8x}", word),
+        };
+        writeln!(out, "    {}", text).unwrap();
+    }
+
+    out
+}
+
+// Disassembles a single instruction at the given address. Branch targets are printed as
+// absolute addresses.
+pub fn disassemble_instruction(instr: &ConditionalInstruction, address: u32) -> String {
+    format_instruction(instr, address, &|_| None)
+}
+
+// Finds the address referenced by an instruction, if any. Only branches and pc-relative loads
+// are tracked, as these are the references the assembler generates.
+fn reference(instr: &ConditionalInstruction, address: u32) -> Option<(u32, Reference)> {
+    match instr.instruction {
+        Instruction::Branch(b) => Some((branch_target(b, address), Reference::Branch)),
+        Instruction::Transfer(InstructionTransfer {
+            is_preindexed: true,
+            load: true,
+            size: TransferSize::Word,
+            rn,
+            offset: TransferOffset::Immediate(offset),
+            up_bit,
+            ..
+        }) if rn as usize == PC => {
+            let offset = u32::from(offset);
+            let pc = address.wrapping_add(PIPELINE_OFFSET as u32);
+            let target = if up_bit {
+                pc.wrapping_add(offset)
+            } else {
+                pc.wrapping_sub(offset)
+            };
+            Some((target, Reference::Literal))
+        }
+        _ => None,
+    }
+}
+
+fn branch_target(instr: InstructionBranch, address: u32) -> u32 {
+    let offset = signed_24_to_32(instr.offset) << 2;
+    (address.wrapping_add(PIPELINE_OFFSET as u32) as i32).wrapping_add(offset) as u32
+}
+
+fn format_instruction(
+    instr: &ConditionalInstruction,
+    address: u32,
+    label_for: &dyn Fn(u32) -> Option<String>,
+) -> String {
+    let cond = format_cond(instr.cond);
+    match instr.instruction {
+        Instruction::Processing(p) => {
+            let opcode = format!("{:?}", p.opcode).to_lowercase();
+            let s = if p.set_cond && !is_test_opcode(p.opcode) {
+                "s"
+            } else {
+                ""
+            };
+            let operand2 = format_operand2(p.operand2);
+            match p.opcode {
+                ProcessingOpcode::Mov | ProcessingOpcode::Mvn => {
+                    format!("{}{}{} r{}, {}", opcode, cond, s, p.rd, operand2)
+                }
+                _ if is_test_opcode(p.opcode) => {
+                    format!("{}{} r{}, {}", opcode, cond, p.rn, operand2)
+                }
+                _ => format!("{}{}{} r{}, r{}, {}", opcode, cond, s, p.rd, p.rn, operand2),
+            }
+        }
+        Instruction::Multiply(m) => {
+            let s = if m.set_cond { "s" } else { "" };
+            if m.accumulate {
+                format!(
+                    "mla{}{} r{}, r{}, r{}, r{}",
+                    cond, s, m.rd, m.rm, m.rs, m.rn
+                )
+            } else {
+                format!("mul{}{} r{}, r{}, r{}", cond, s, m.rd, m.rm, m.rs)
+            }
+        }
+        Instruction::MultiplyLong(m) => {
+            let opcode = match (m.signed, m.accumulate) {
+                (false, false) => "umull",
+                (false, true) => "umlal",
+                (true, false) => "smull",
+                (true, true) => "smlal",
+            };
+            let s = if m.set_cond { "s" } else { "" };
+            format!(
+                "{}{}{} r{}, r{}, r{}, r{}",
+                opcode, cond, s, m.rd_lo, m.rd_hi, m.rm, m.rs
+            )
+        }
+        Instruction::Transfer(t) => {
+            let opcode = if t.load { "ldr" } else { "str" };
+            let size = match t.size {
+                TransferSize::Word => "",
+                TransferSize::Byte => "b",
+                TransferSize::Halfword => "h",
+                TransferSize::SignedByte => "sb",
+                TransferSize::SignedHalfword => "sh",
+            };
+            let sign = if t.up_bit { "" } else { "-" };
+            let offset = match t.offset {
+                TransferOffset::Immediate(offset) => {
+                    (offset != 0).then(|| format!("#{}{}", sign, offset))
+                }
+                TransferOffset::ShiftedReg(rm, shift) => Some(format!(
+                    "{}{}",
+                    sign,
+                    format_operand2(Operand2::ShiftedReg(rm, shift))
+                )),
+            };
+            let addressing = match (t.is_preindexed, offset) {
+                (_, None) if t.writeback => format!("[r{}]!", t.rn),
+                (_, None) => format!("[r{}]", t.rn),
+                (true, Some(offset)) if t.writeback => format!("[r{}, {}]!", t.rn, offset),
+                (true, Some(offset)) => format!("[r{}, {}]", t.rn, offset),
+                (false, Some(offset)) => format!("[r{}], {}", t.rn, offset),
+            };
+            let comment = match reference(instr, address) {
+                Some((target, Reference::Literal)) => format!(
+                    " ; {}",
+                    label_for(target).unwrap_or_else(|| format!("0x{:0>8x}", target))
+                ),
+                _ => String::new(),
+            };
+            format!(
+                "{}{}{} r{}, {}{}",
+                opcode, size, cond, t.rd, addressing, comment
+            )
+        }
+        Instruction::BlockTransfer(b) => {
+            let opcode = if b.load { "ldm" } else { "stm" };
+            let mode = match (b.is_preindexed, b.up_bit) {
+                (false, true) => "ia",
+                (true, true) => "ib",
+                (false, false) => "da",
+                (true, false) => "db",
+            };
+            let writeback = if b.writeback { "!" } else { "" };
+            format!(
+                "{}{}{} r{}{}, {}",
+                opcode,
+                mode,
+                cond,
+                b.rn,
+                writeback,
+                format_register_list(b.register_list)
+            )
+        }
+        Instruction::Branch(b) => {
+            let target = branch_target(b, address);
+            format!(
+                "b{}{} {}",
+                if b.link { "l" } else { "" },
+                cond,
+                label_for(target).unwrap_or_else(|| format!("0x{:0>8x}", target))
+            )
+        }
+        // The halves of a Thumb bl are shown separately, as the prefix only gives the top half of
+        // the target; the prefix with the value it leaves in LR, and the suffix with its offset
+        Instruction::ThumbBranch(b) => {
+            let base = address.wrapping_add(THUMB_PIPELINE_OFFSET as u32);
+            let target = base.wrapping_add(b.offset as u32);
+            match b.kind {
+                ThumbBranchKind::Branch => format!(
+                    "b{} {}",
+                    cond,
+                    label_for(target).unwrap_or_else(|| format!("0x{:0>8x}", target))
+                ),
+                ThumbBranchKind::LinkPrefix => format!("bl.prefix{} 0x{:0>8x}", cond, target),
+                ThumbBranchKind::LinkSuffix => format!("bl.suffix{} #0x{:x}", cond, b.offset),
+            }
+        }
+        Instruction::BranchExchange(bx) => format!("bx{} r{}", cond, bx.rm),
+        Instruction::Coprocessor(c) => format!(
+            "{}{} p{}, {}, r{}, c{}, c{}, {}",
+            if c.load { "mrc" } else { "mcr" },
+            cond,
+            c.coprocessor,
+            c.opcode1,
+            c.rd,
+            c.crn,
+            c.crm,
+            c.opcode2
+        ),
+        Instruction::Vfp(v) => format_vfp(v, &cond),
+        Instruction::StatusRead(s) => {
+            format!("mrs{} r{}, {}", cond, s.rd, status_register(s.spsr))
+        }
+        Instruction::StatusWrite(s) => {
+            let fields: String = "fsxc"
+                .chars()
+                .enumerate()
+                .filter(|&(i, _)| s.field_mask & 0x8 >> i != 0)
+                .map(|(_, field)| field)
+                .collect();
+            format!(
+                "msr{} {}_{}, {}",
+                cond,
+                status_register(s.spsr),
+                fields,
+                format_operand2(s.operand)
+            )
+        }
+        Instruction::SoftwareInterrupt(s) => format!("swi{} #0x{:x}", cond, s.comment),
+        Instruction::Halt => String::from("andeq r0, r0, r0"),
+    }
+}
+
+fn is_test_opcode(opcode: ProcessingOpcode) -> bool {
+    matches!(
+        opcode,
+        ProcessingOpcode::Tst
+            | ProcessingOpcode::Teq
+            | ProcessingOpcode::Cmp
+            | ProcessingOpcode::Cmn
+    )
+}
+
+fn format_cond(cond: ConditionCode) -> String {
+    match cond {
+        ConditionCode::Al => String::new(),
+        _ => format!("{:?}", cond).to_lowercase(),
+    }
+}
+
+fn format_vfp(instr: InstructionVfp, cond: &str) -> String {
+    match instr {
+        InstructionVfp::Transfer {
+            load,
+            up_bit,
+            rn,
+            sd,
+            offset,
+        } => {
+            let opcode = if load { "vldr" } else { "vstr" };
+            if offset == 0 {
+                format!("{}{} s{}, [r{}]", opcode, cond, sd, rn)
+            } else {
+                let sign = if up_bit { "" } else { "-" };
+                let offset = u32::from(offset) * 4;
+                format!("{}{} s{}, [r{}, #{}{}]", opcode, cond, sd, rn, sign, offset)
+            }
+        }
+        InstructionVfp::Arithmetic { opcode, sd, sn, sm } => format!(
+            "v{}{}.f32 s{}, s{}, s{}",
+            format!("{:?}", opcode).to_lowercase(),
+            cond,
+            sd,
+            sn,
+            sm
+        ),
+        InstructionVfp::Move {
+            to_arm: true,
+            sn,
+            rt,
+        } => format!("vmov{} r{}, s{}", cond, rt, sn),
+        InstructionVfp::Move {
+            to_arm: false,
+            sn,
+            rt,
+        } => {
+            format!("vmov{} s{}, r{}", cond, sn, rt)
+        }
+        InstructionVfp::Convert { to_int, sd, sm } => format!(
+            "vcvt{}.{} s{}, s{}",
+            cond,
+            if to_int { "s32.f32" } else { "f32.s32" },
+            sd,
+            sm
+        ),
+        InstructionVfp::Compare { sd, sm } => format!("vcmp{}.f32 s{}, s{}", cond, sd, sm),
+        InstructionVfp::MoveFlags => format!("vmrs{} APSR_nzcv, fpscr", cond),
+    }
+}
+
+fn status_register(spsr: bool) -> &'static str {
+    if spsr {
+        "spsr"
+    } else {
+        "cpsr"
+    }
+}
+
+fn format_operand2(op2: Operand2) -> String {
+    match op2 {
+        Operand2::ConstantShift(imm, rotate) => {
+            let value = u32::from(imm).rotate_right(2 * u32::from(rotate));
+            if value > mask(IMM_VALUE.size) {
+                format!("#0x{:x}", value)
+            } else {
+                format!("#{}", value)
+            }
+        }
+        Operand2::ShiftedReg(reg, Shift::ConstantShift(ShiftType::Lsl, 0)) => format!("r{}", reg),
+        // A rotation by 0 is encoded as a rotation right by 1 through the carry
+        Operand2::ShiftedReg(reg, Shift::ConstantShift(ShiftType::Ror, 0)) => {
+            format!("r{}, rrx", reg)
+        }
+        // lsr and asr by 32 are encoded as by 0
+        Operand2::ShiftedReg(reg, Shift::ConstantShift(shift_type, 0)) => format!(
+            "r{}, {} #32",
+            reg,
+            format!("{:?}", shift_type).to_lowercase()
+        ),
+        Operand2::ShiftedReg(reg, Shift::ConstantShift(shift_type, amount)) => format!(
+            "r{}, {} #{}",
+            reg,
+            format!("{:?}", shift_type).to_lowercase(),
+            amount
+        ),
+        Operand2::ShiftedReg(reg, Shift::RegisterShift(shift_type, shift_reg)) => format!(
+            "r{}, {} r{}",
+            reg,
+            format!("{:?}", shift_type).to_lowercase(),
+            shift_reg
+        ),
+    }
+}
+
+// Formats a register list bitmask, collapsing runs of consecutive registers into ranges.
+// eg: 0x400f => {r0-r3, r14}
+fn format_register_list(register_list: u16) -> String {
+    let mut ranges: Vec<(u8, u8)> = Vec::new();
+    for reg in (0..REGISTER_LIST.size).filter(|r| register_list & (1 << r) != 0) {
+        match ranges.last_mut() {
+            Some((_, last)) if *last + 1 == reg => *last = reg,
+            _ => ranges.push((reg, reg)),
+        }
+    }
+
+    let regs: Vec<String> = ranges
+        .iter()
+        .map(|(first, last)| match last - first {
+            0 => format!("r{}", first),
+            1 => format!("r{}, r{}", first, last),
+            _ => format!("r{}-r{}", first, last),
+        })
+        .collect();
+    format!("{{{}}}", regs.join(", "))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::address::Address;
+
+    fn to_bytes(words: &[u32]) -> Vec<u8> {
+        words.iter().flat_map(|w| w.to_le_bytes()).collect()
+    }
+
+    #[test]
+    fn test_disassemble_pseudo_symbols() {
+        // mov r1,#1; ldr r2,=0x20200000; loop: subs r1,r1,#1; bne loop; andeq r0,r0,r0
+        let bytes = to_bytes(&[
+            0xe3a01001, 0xe59f2008, 0xe2511001, 0x1afffffd, 0x00000000, 0x20200000,
+        ]);
+        let expected = "    mov r1, #1
+    ldr r2, [r15, #8] ; lit_00000014
+loc_00000008:
+    subs r1, r1, #1
+    bne loc_00000008
+    andeq r0, r0, r0
+lit_00000014:
+    .word 0x20200000
+";
+
+        assert_eq!(disassemble(&bytes, &SymbolTable::new()), expected);
+        assert_eq!(disassemble(&bytes, &SymbolTable::new()), expected);
+
+        let mut symbol_table = SymbolTable::new();
+        symbol_table.insert(String::from("loop"), Address(0x8));
+        assert!(disassemble(&bytes, &symbol_table)
+            .contains("loop:\n    subs r1, r1, #1\n    bne loop\n"));
+    }
+
+    #[test]
+    fn test_format_register_list() {
+        assert_eq!(format_register_list(0x400f), "{r0-r3, r14}");
+        assert_eq!(format_register_list(0x0003), "{r0, r1}");
+    }
+}
@@ -0,0 +1,435 @@
+use crate::{constants::*, types::*};
+
+// Encodes an instruction as an ARM word. Thumb branches, which only Thumb code has, have no ARM
+// encoding and are an Encode error.
+pub fn encode(instr: ConditionalInstruction) -> Result<u32> {
+    let cond = (instr.cond as u32) << COND.pos;
+    let body = match instr.instruction {
+        Instruction::Processing(p) => encode_processing(p),
+        Instruction::Transfer(t) => encode_transfer(t),
+        Instruction::BlockTransfer(b) => encode_block_transfer(b),
+        Instruction::Multiply(m) => encode_multiply(m),
+        Instruction::MultiplyLong(m) => encode_multiply_long(m),
+        Instruction::Branch(b) => encode_branch(b),
+        Instruction::BranchExchange(bx) => encode_branch_exchange(bx),
+        Instruction::Coprocessor(c) => encode_coprocessor(c),
+        Instruction::Vfp(v) => encode_vfp(v),
+        Instruction::StatusRead(s) => encode_status_read(s),
+        Instruction::StatusWrite(s) => encode_status_write(s),
+        Instruction::SoftwareInterrupt(s) => encode_software_interrupt(s),
+        Instruction::Halt => 0,
+        Instruction::ThumbBranch(_) => {
+            return Err(ArmError::encode(
+                "Can't encode a Thumb branch as an ARM instruction",
+            ))
+        }
+    };
+    Ok(cond | body)
+}
+
+fn encode_processing(instr: InstructionProcessing) -> u32 {
+    let InstructionProcessing {
+        opcode,
+        set_cond,
+        rn,
+        rd,
+        operand2,
+    } = instr;
+
+    let is_immediate = matches!(operand2, Operand2::ConstantShift(_, _));
+
+    (is_immediate as u32) << I.pos
+        | (opcode as u32) << OPCODE.pos
+        | (set_cond as u32) << S.pos
+        | u32::from(rn) << RN.pos
+        | u32::from(rd) << RD.pos
+        | encode_operand2(operand2)
+}
+
+fn encode_multiply(instr: InstructionMultiply) -> u32 {
+    let InstructionMultiply {
+        accumulate,
+        set_cond,
+        rd,
+        rn,
+        rs,
+        rm,
+    } = instr;
+
+    // Constant base for all multiply instructions
+    const BASE: u32 = 0x9 << 4;
+
+    BASE | (accumulate as u32) << A.pos
+        | (set_cond as u32) << S.pos
+        | u32::from(rd) << RD_MULT.pos
+        | u32::from(rn) << RN_MULT.pos
+        | u32::from(rs) << RS.pos
+        | u32::from(rm)
+}
+
+fn encode_multiply_long(instr: InstructionMultiplyLong) -> u32 {
+    let InstructionMultiplyLong {
+        signed,
+        accumulate,
+        set_cond,
+        rd_hi,
+        rd_lo,
+        rs,
+        rm,
+    } = instr;
+
+    // Constant base for all multiply instructions
+    const BASE: u32 = 0x9 << 4;
+
+    BASE | 1 << LONG.pos
+        | (signed as u32) << SIGNED.pos
+        | (accumulate as u32) << A.pos
+        | (set_cond as u32) << S.pos
+        | u32::from(rd_hi) << RD_HI.pos
+        | u32::from(rd_lo) << RD_LO.pos
+        | u32::from(rs) << RS.pos
+        | u32::from(rm)
+}
+
+fn encode_transfer(instr: InstructionTransfer) -> u32 {
+    let InstructionTransfer {
+        is_preindexed,
+        up_bit,
+        writeback,
+        load,
+        size,
+        rn,
+        rd,
+        offset,
+    } = instr;
+
+    let is_shifted_r = matches!(offset, TransferOffset::ShiftedReg(_, _));
+    let common = (is_preindexed as u32) << P.pos
+        | (up_bit as u32) << U.pos
+        | (writeback as u32) << W.pos
+        | (load as u32) << L.pos
+        | u32::from(rn) << RN.pos
+        | u32::from(rd) << RD.pos;
+
+    let sh = match size {
+        TransferSize::Word | TransferSize::Byte => {
+            // Constant base for all word and byte transfer instructions
+            const BASE: u32 = 0x1 << 26;
+            return BASE
+                | common
+                | (is_shifted_r as u32) << I.pos
+                | ((size == TransferSize::Byte) as u32) << B.pos
+                | match offset {
+                    TransferOffset::Immediate(imm) => u32::from(imm) & mask(OFFSET_TRANSFER.size),
+                    TransferOffset::ShiftedReg(rm, shift) => {
+                        encode_operand2(Operand2::ShiftedReg(rm, shift))
+                    }
+                };
+        }
+        TransferSize::Halfword => 0x1,
+        TransferSize::SignedByte => 0x2,
+        TransferSize::SignedHalfword => 0x3,
+    };
+
+    // Halfword and signed transfers split an 8 bit immediate offset around the SH field, or
+    // give an unshifted offset register
+    let offset = match offset {
+        TransferOffset::Immediate(imm) => {
+            (u32::from(imm) >> OFFSET_LO.size & mask(OFFSET_HI.size)) << OFFSET_HI.pos
+                | u32::from(imm) & mask(OFFSET_LO.size)
+        }
+        TransferOffset::ShiftedReg(rm, _) => u32::from(rm),
+    };
+    // Constant base for all halfword and signed transfer instructions
+    const BASE: u32 = 0x9 << 4;
+    BASE | common | (!is_shifted_r as u32) << HALFWORD_IMM.pos | sh << SH.pos | offset
+}
+
+fn encode_block_transfer(instr: InstructionBlockTransfer) -> u32 {
+    let InstructionBlockTransfer {
+        is_preindexed,
+        up_bit,
+        writeback,
+        load,
+        rn,
+        register_list,
+    } = instr;
+
+    // Constant base for all block transfer instructions
+    const BASE: u32 = 0x4 << 25;
+
+    BASE | (is_preindexed as u32) << P.pos
+        | (up_bit as u32) << U.pos
+        | (writeback as u32) << W.pos
+        | (load as u32) << L.pos
+        | u32::from(rn) << RN.pos
+        | u32::from(register_list)
+}
+
+fn encode_branch(instr: InstructionBranch) -> u32 {
+    let InstructionBranch { link, offset } = instr;
+    // Constant base for all branch instructions
+    const BASE: u32 = 0x5 << 25;
+    BASE | (link as u32) << LINK.pos | ((offset as u32) & mask(OFFSET_BRANCH.size))
+}
+
+fn encode_branch_exchange(instr: InstructionBranchExchange) -> u32 {
+    let InstructionBranchExchange { rm } = instr;
+    // Constant base for all branch and exchange instructions
+    const BASE: u32 = 0x12fff1 << 4;
+    BASE | u32::from(rm)
+}
+
+fn encode_coprocessor(instr: InstructionCoprocessor) -> u32 {
+    let InstructionCoprocessor {
+        load,
+        coprocessor,
+        opcode1,
+        crn,
+        rd,
+        crm,
+        opcode2,
+    } = instr;
+    // Constant bits for all coprocessor register transfers
+    const BASE: u32 = 0xe << 24 | 1 << 4;
+
+    BASE | u32::from(opcode1) << CP_OPCODE1.pos
+        | (load as u32) << L.pos
+        | u32::from(crn) << CRN.pos
+        | u32::from(rd) << RD.pos
+        | u32::from(coprocessor) << CP_NUM.pos
+        | u32::from(opcode2) << CP_OPCODE2.pos
+        | u32::from(crm) << CRM.pos
+}
+
+fn encode_vfp(instr: InstructionVfp) -> u32 {
+    // Constant bits for all single precision VFP instructions, which use coprocessor 10
+    const BASE: u32 = 0xa << CP_NUM.pos;
+    const TRANSFER: u32 = 0xd << 24;
+    const DATA: u32 = 0xe << 24;
+    let sd = |s: u8| encode_single(s, RD.pos, VFP_D.pos);
+    let sn = |s: u8| encode_single(s, RN.pos, VFP_N.pos);
+    let sm = |s: u8| encode_single(s, RM.pos, VFP_M.pos);
+
+    BASE | match instr {
+        InstructionVfp::Transfer {
+            load,
+            up_bit,
+            rn,
+            sd: d,
+            offset,
+        } => {
+            TRANSFER
+                | (up_bit as u32) << U.pos
+                | (load as u32) << L.pos
+                | u32::from(rn) << RN.pos
+                | sd(d)
+                | u32::from(offset) << VFP_OFFSET.pos
+        }
+        InstructionVfp::Arithmetic {
+            opcode,
+            sd: d,
+            sn: n,
+            sm: m,
+        } => {
+            let opcode = match opcode {
+                VfpOpcode::Mul => 0x2 << 20,
+                VfpOpcode::Add => 0x3 << 20,
+                VfpOpcode::Sub => 0x3 << 20 | 1 << 6,
+                VfpOpcode::Div => 1 << 23,
+            };
+            DATA | opcode | sd(d) | sn(n) | sm(m)
+        }
+        InstructionVfp::Move { to_arm, sn: n, rt } => {
+            DATA | (to_arm as u32) << L.pos | sn(n) | u32::from(rt) << RD.pos | 1 << 4
+        }
+        InstructionVfp::Convert {
+            to_int,
+            sd: d,
+            sm: m,
+        } => {
+            let opcode = if to_int { 0xbd } else { 0xb8 };
+            DATA | opcode << 16 | 0x3 << 6 | sd(d) | sm(m)
+        }
+        InstructionVfp::Compare { sd: d, sm: m } => DATA | 0xb4 << 16 | 1 << 6 | sd(d) | sm(m),
+        InstructionVfp::MoveFlags => 0x0ef1fa10,
+    }
+}
+
+// Splits a single precision register number into a 4 bit field and a separate bottom bit
+fn encode_single(s: u8, pos: u32, bit: u32) -> u32 {
+    u32::from(s >> 1) << pos | u32::from(s & 1) << bit
+}
+
+fn encode_status_read(instr: InstructionStatusRead) -> u32 {
+    let InstructionStatusRead { spsr, rd } = instr;
+    // Constant bits for all mrs instructions
+    const BASE: u32 = 0x010f << 16;
+
+    BASE | (spsr as u32) << STATUS_SPSR.pos | u32::from(rd) << RD.pos
+}
+
+fn encode_status_write(instr: InstructionStatusWrite) -> u32 {
+    let InstructionStatusWrite {
+        spsr,
+        field_mask,
+        operand,
+    } = instr;
+    // Constant bits for all msr instructions
+    const BASE: u32 = 0x0120f << 12;
+
+    let is_immediate = matches!(operand, Operand2::ConstantShift(_, _));
+
+    BASE | (is_immediate as u32) << I.pos
+        | (spsr as u32) << STATUS_SPSR.pos
+        | u32::from(field_mask) << FIELD_MASK.pos
+        | encode_operand2(operand)
+}
+
+fn encode_software_interrupt(instr: InstructionSoftwareInterrupt) -> u32 {
+    let InstructionSoftwareInterrupt { comment } = instr;
+    // Constant bits for all software interrupts
+    const BASE: u32 = 0xf << 24;
+    BASE | comment & mask(SWI_COMMENT.size)
+}
+
+fn encode_operand2(op2: Operand2) -> u32 {
+    match op2 {
+        Operand2::ConstantShift(to_shift, shift_amt) => {
+            u32::from(shift_amt) << IMM_SHIFT.pos | u32::from(to_shift)
+        }
+        Operand2::ShiftedReg(reg_to_shift, Shift::ConstantShift(shift_type, constant_shift)) => {
+            u32::from(constant_shift) << CONST_SHIFT.pos
+                | (shift_type as u32) << SHIFT_TYPE.pos
+                | u32::from(reg_to_shift)
+        }
+        Operand2::ShiftedReg(reg_to_shift, Shift::RegisterShift(shift_type, shift_reg)) => {
+            // Constant base for register shifted by register operand2 values
+            const BASE: u32 = 1 << 4;
+            BASE | u32::from(shift_reg) << REG_SHIFT.pos
+                | (shift_type as u32) << SHIFT_TYPE.pos
+                | u32::from(reg_to_shift)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode_operand2() {
+        assert_eq!(encode_operand2(Operand2::ConstantShift(0x8, 0x3)), 0x308);
+        assert_eq!(
+            encode_operand2(Operand2::ShiftedReg(
+                0x7,
+                Shift::ConstantShift(ShiftType::Ror, 0x3)
+            )),
+            0x1e7
+        );
+        assert_eq!(
+            encode_operand2(Operand2::ShiftedReg(
+                0x7,
+                Shift::RegisterShift(ShiftType::Ror, 0x3)
+            )),
+            0x377
+        );
+    }
+
+    #[test]
+    fn test_shifted_register_round_trip() {
+        // Registers shifted by a constant or by a register encode and decode to the same operand
+        for op2 in [
+            Operand2::ShiftedReg(2, Shift::ConstantShift(ShiftType::Lsl, 0)),
+            Operand2::ShiftedReg(2, Shift::ConstantShift(ShiftType::Asr, 31)),
+            Operand2::ShiftedReg(2, Shift::RegisterShift(ShiftType::Lsr, 3)),
+        ] {
+            let instr = ConditionalInstruction {
+                cond: ConditionCode::Al,
+                instruction: Instruction::Processing(InstructionProcessing {
+                    opcode: ProcessingOpcode::Add,
+                    set_cond: false,
+                    rn: 1,
+                    rd: 0,
+                    operand2: op2,
+                }),
+            };
+            let decoded = crate::decode::decode(&encode(instr).expect("encode failed"))
+                .expect("decode failed");
+            assert_eq!(decoded, instr);
+        }
+    }
+
+    #[test]
+    fn test_encode_transfer_sizes() {
+        let transfer = |size, offset| {
+            encode(ConditionalInstruction {
+                cond: ConditionCode::Al,
+                instruction: Instruction::Transfer(InstructionTransfer {
+                    is_preindexed: true,
+                    up_bit: true,
+                    writeback: false,
+                    load: true,
+                    size,
+                    rn: 1,
+                    rd: 0,
+                    offset,
+                }),
+            })
+            .expect("encode failed")
+        };
+
+        // ldrb r0,[r1,#3]
+        assert_eq!(
+            transfer(TransferSize::Byte, TransferOffset::Immediate(0x3)),
+            0xe5d10003
+        );
+        // ldrsh r0,[r1,#0x2a]
+        assert_eq!(
+            transfer(
+                TransferSize::SignedHalfword,
+                TransferOffset::Immediate(0x2a)
+            ),
+            0xe1d102fa
+        );
+        // ldrh r0,[r1,r2]
+        assert_eq!(
+            transfer(
+                TransferSize::Halfword,
+                TransferOffset::ShiftedReg(2, Shift::ConstantShift(ShiftType::Lsl, 0))
+            ),
+            0xe19100b2
+        );
+    }
+
+    #[test]
+    fn test_encode_block_transfer() {
+        assert_eq!(
+            encode(ConditionalInstruction {
+                cond: ConditionCode::Al,
+                instruction: Instruction::BlockTransfer(InstructionBlockTransfer {
+                    is_preindexed: true,
+                    up_bit: false,
+                    writeback: true,
+                    load: false,
+                    rn: 13,
+                    register_list: 0x400f,
+                }),
+            })
+            .expect("encode failed"),
+            0xe92d400f
+        );
+    }
+
+    #[test]
+    fn test_encode_thumb_branch() {
+        let instr = ConditionalInstruction {
+            cond: ConditionCode::Al,
+            instruction: Instruction::ThumbBranch(InstructionThumbBranch {
+                kind: ThumbBranchKind::Branch,
+                offset: 4,
+            }),
+        };
+        assert!(matches!(encode(instr), Err(ArmError::Encode(_))));
+    }
+}
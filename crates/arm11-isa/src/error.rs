@@ -0,0 +1,208 @@
+use std::{error::Error, fmt, io};
+
+// The ways the assembler, the emulator and the tools built on them can fail, so that library
+// users can match on the kind of failure rather than on the wording of the message. Each kind
+// keeps the error it was raised with, whose type is defined by the crate which raised it, eg:
+// the assembler's Diagnostics or the emulator's MemoryError, and downcast_ref gives it back for
+// the details. An ArmError is displayed as the error it holds, so the message is the same
+// whichever tool reports it.
+// eg:
+//
+// match arm11::assemble::assemble_to_bytes(source) {
+//     Err(ArmError::Immediate(e)) => ...,  // eg: mov r0,#0x101
+//     Err(e) => ...,
+//     Ok(bytes) => ...,
+// }
+//
+#[derive(Debug)]
+pub enum ArmError {
+    // A source, file or argument which couldn't be parsed, eg: a source with an unknown mnemonic
+    Parse(Box<dyn Error>),
+    // An instruction which has no encoding where it is used, eg: an ARM-only instruction in
+    // Thumb code
+    Encode(Box<dyn Error>),
+    // An immediate, offset or constant which doesn't fit its field
+    Immediate(Box<dyn Error>),
+    // An access memory couldn't carry out, eg: a fetch from unmapped memory
+    Memory(Box<dyn Error>),
+    // An instruction which isn't supported, or which the emulated machine doesn't have, the kind
+    // of instruction it is, eg: ARM, Thumb, VFP, coprocessor or swi, and why it can't be run if
+    // that isn't plain from the kind, eg: VFP instructions need --vfp
+    BadOpcode {
+        opcode: u32,
+        kind: &'static str,
+        reason: Option<String>,
+    },
+    Io(io::Error),
+    // Anything else, eg: a program which didn't halt within its limits
+    Other(Box<dyn Error>),
+}
+
+impl ArmError {
+    pub fn parse(error: impl Into<Box<dyn Error>>) -> Self {
+        ArmError::Parse(error.into())
+    }
+
+    pub fn encode(error: impl Into<Box<dyn Error>>) -> Self {
+        ArmError::Encode(error.into())
+    }
+
+    pub fn immediate(error: impl Into<Box<dyn Error>>) -> Self {
+        ArmError::Immediate(error.into())
+    }
+
+    pub fn memory(error: impl Into<Box<dyn Error>>) -> Self {
+        ArmError::Memory(error.into())
+    }
+
+    // The same kind of error with its message prefixed by where it happened, eg: the file being
+    // read. The message replaces the error it was raised with, apart from the kind of an I/O
+    // error.
+    pub fn context(self, at: impl fmt::Display) -> Self {
+        let message = format!("{}: {}", at, self);
+        match self {
+            ArmError::Parse(_) => ArmError::parse(message),
+            ArmError::Encode(_) => ArmError::encode(message),
+            ArmError::Immediate(_) => ArmError::immediate(message),
+            ArmError::Memory(_) => ArmError::memory(message),
+            ArmError::Io(e) => ArmError::Io(io::Error::new(e.kind(), message)),
+            ArmError::BadOpcode { .. } | ArmError::Other(_) => ArmError::from(message),
+        }
+    }
+
+    // The error this was raised with, if there was one
+    fn inner(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ArmError::Parse(e)
+            | ArmError::Encode(e)
+            | ArmError::Immediate(e)
+            | ArmError::Memory(e)
+            | ArmError::Other(e) => Some(e.as_ref()),
+            ArmError::Io(e) => Some(e),
+            ArmError::BadOpcode { .. } => None,
+        }
+    }
+
+    // Whether this was raised with an error of type T, of any kind
+    pub fn is<T: Error + 'static>(&self) -> bool {
+        self.downcast_ref::<T>().is_some()
+    }
+
+    pub fn downcast_ref<T: Error + 'static>(&self) -> Option<&T> {
+        self.inner().and_then(|e| e.downcast_ref::<T>())
+    }
+
+    // The error this was raised with, if it is of type T, or this error otherwise
+    pub fn downcast<T: Error + 'static>(self) -> Result<T, Self> {
+        let rewrap = |kind: fn(Box<dyn Error>) -> ArmError, e: Box<dyn Error>| match e.downcast() {
+            Ok(e) => Ok(*e),
+            Err(e) => Err(kind(e)),
+        };
+        match self {
+            ArmError::Parse(e) => rewrap(ArmError::Parse, e),
+            ArmError::Encode(e) => rewrap(ArmError::Encode, e),
+            ArmError::Immediate(e) => rewrap(ArmError::Immediate, e),
+            ArmError::Memory(e) => rewrap(ArmError::Memory, e),
+            ArmError::Other(e) => rewrap(ArmError::Other, e),
+            e => Err(e),
+        }
+    }
+}
+
+impl fmt::Display for ArmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ArmError::BadOpcode {
+                opcode,
+                kind,
+                reason,
+            } => {
+                let digits = if *kind == "Thumb" { 4 } else { 8 };
+                write!(
+                    f,
+                    "Unsupported {} instruction 0x{:0>2$x}",
+                    kind, opcode, digits
+                )?;
+                match reason {
+                    Some(reason) => write!(f, ", {}", reason),
+                    None => Ok(()),
+                }
+            }
+            e => write!(f, "{}", e.inner().expect("no error to display")),
+        }
+    }
+}
+
+impl Error for ArmError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        self.inner().and_then(Error::source)
+    }
+}
+
+impl From<io::Error> for ArmError {
+    fn from(e: io::Error) -> Self {
+        ArmError::Io(e)
+    }
+}
+
+impl From<String> for ArmError {
+    fn from(message: String) -> Self {
+        ArmError::Other(message.into())
+    }
+}
+
+impl From<&str> for ArmError {
+    fn from(message: &str) -> Self {
+        ArmError::Other(message.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_arm_error() {
+        let err = ArmError::memory(io::Error::other("fault"));
+        assert!(matches!(err, ArmError::Memory(_)));
+        assert_eq!(err.to_string(), "fault");
+        assert!(err.is::<io::Error>());
+        assert!(!err.is::<fmt::Error>());
+        let err = err
+            .downcast::<fmt::Error>()
+            .expect_err("downcast to the wrong type");
+        assert!(matches!(err, ArmError::Memory(_)));
+        assert_eq!(
+            err.downcast::<io::Error>()
+                .expect("downcast failed")
+                .to_string(),
+            "fault"
+        );
+
+        let err = ArmError::from(io::Error::from(io::ErrorKind::NotFound)).context("prog.s");
+        assert!(matches!(&err, ArmError::Io(e) if e.kind() == io::ErrorKind::NotFound));
+        assert_eq!(err.to_string(), "prog.s: entity not found");
+        let err = ArmError::parse("expected .macro name").context("Line 3");
+        assert!(matches!(err, ArmError::Parse(_)));
+        assert_eq!(err.to_string(), "Line 3: expected .macro name");
+
+        let err = ArmError::BadOpcode {
+            opcode: 0xb672,
+            kind: "Thumb",
+            reason: None,
+        };
+        assert_eq!(err.to_string(), "Unsupported Thumb instruction 0xb672");
+        let err = ArmError::BadOpcode {
+            opcode: 0xeef10a10,
+            kind: "VFP",
+            reason: Some(String::from("VFP instructions need --vfp")),
+        };
+        assert_eq!(
+            err.to_string(),
+            "Unsupported VFP instruction 0xeef10a10, VFP instructions need --vfp"
+        );
+        let err = ArmError::from("Can't execute halt");
+        assert!(matches!(err, ArmError::Other(_)));
+        assert_eq!(err.to_string(), "Can't execute halt");
+    }
+}
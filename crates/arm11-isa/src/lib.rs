@@ -0,0 +1,25 @@
+// The parts of the ARM11 instruction set shared by the assembler and the emulator: the
+// instruction types, their binary encoding and decoding in ARM and Thumb state, disassembly, the
+// static timing model, and the listing and symbol file formats the assembler writes for the
+// emulator to read.
+extern crate enum_primitive_derive;
+extern crate nom;
+extern crate num_traits;
+pub mod address;
+pub mod constants;
+pub mod decode;
+pub mod disassemble;
+pub mod encode;
+pub mod error;
+pub mod listing;
+#[macro_use]
+pub mod macros;
+pub mod parse;
+pub mod symbols;
+pub mod thumb;
+pub mod timing;
+pub mod types;
+
+pub use address::{Address, SymbolTable, Word};
+pub use error::ArmError;
+pub use types::Result;
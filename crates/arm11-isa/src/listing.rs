@@ -0,0 +1,193 @@
+use std::{fmt, fs};
+
+use super::types::*;
+
+// The contents of a line of the listing, as placed in the binary
+#[derive(Debug, Clone, PartialEq)]
+pub enum ListingData {
+    // An encoded instruction or literal, shown as a word
+    Word(u32),
+    // Data from a directive, shown byte by byte
+    Bytes(Vec<u8>),
+    // An encoded Thumb instruction, shown as a halfword, or two for bl
+    Halfwords(Vec<u16>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ListingLine {
+    // The line number in the file the line was written in, which for the lines of a macro's
+    // expansion is the line using the macro. Literal pool entries, continuations of long
+    // directives and the lines naming the file which follows have no line.
+    pub number: Option<usize>,
+    pub address: Option<u32>,
+    pub data: Option<ListingData>,
+    pub source: String,
+    // The static cycle cost of an instruction, from the emulator's timing model
+    pub timing: Option<String>,
+}
+
+// An assembly listing, showing each line of source alongside its address and encoding. Lines
+// from another file, eg: an included one, follow a comment naming it.
+// eg:
+//
+//    1 00000000 e3a0000c     ldr r0,=msg
+//                            ; lib/data.s
+//    1                       msg:
+//    2 00000004 68 69        .ascii "hi"
+//
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Listing {
+    pub lines: Vec<ListingLine>,
+}
+
+// The column timing annotations start at, so that they line up after short instructions
+const TIMING_COLUMN: usize = 52;
+
+impl Listing {
+    // Formats the listing, optionally annotating each instruction with its cycle cost.
+    // eg:    4 00000008 1afffffd     bne loop                 ; 1 cycle, 1 if skipped, ...
+    pub fn format(&self, timing: bool) -> String {
+        let mut out = String::new();
+        for line in &self.lines {
+            out.push_str(&line.format(timing));
+            out.push('\n');
+        }
+        out
+    }
+
+    pub fn read(filename: &str) -> Result<Self> {
+        Listing::parse(&fs::read_to_string(filename)?)
+            .map_err(|e| ArmError::parse(format!("{}: {}", filename, e)))
+    }
+
+    // Parses a listing written by the assembler, reading each column by its position. Timing
+    // annotations can't be told apart from comments, so are kept as part of the source.
+    pub fn parse(raw: &str) -> std::result::Result<Self, String> {
+        let lines = raw
+            .lines()
+            .enumerate()
+            .map(|(index, line)| {
+                ListingLine::parse(line)
+                    .ok_or_else(|| format!("Line {}: invalid listing line '{}'", index + 1, line))
+            })
+            .collect::<std::result::Result<_, _>>()?;
+        Ok(Listing { lines })
+    }
+}
+
+impl ListingLine {
+    // Formats the line as it is shown in the listing, optionally annotating an instruction with
+    // its cycle cost
+    pub fn format(&self, timing: bool) -> String {
+        let number = self.number.map_or(String::new(), |n| n.to_string());
+        let address = self
+            .address
+            .map_or(String::new(), |a| format!("{:0>8x}", a));
+        let data = match &self.data {
+            Some(ListingData::Word(word)) => format!("{:0>8x}", word),
+            Some(ListingData::Bytes(bytes)) => bytes
+                .iter()
+                .map(|b| format!("{:0>2x}", b))
+                .collect::<Vec<_>>()
+                .join(" "),
+            Some(ListingData::Halfwords(halfwords)) => halfwords
+                .iter()
+                .map(|h| format!("{:0>4x}", h))
+                .collect::<Vec<_>>()
+                .join(" "),
+            None => String::new(),
+        };
+        let mut text = format!(
+            "{: >4} {: <8} {: <11}  {}",
+            number, address, data, self.source
+        );
+        if let (true, Some(cycles)) = (timing, &self.timing) {
+            text = format!("{: <TIMING_COLUMN$} ; {}", text, cycles);
+        }
+        text.trim_end().to_owned()
+    }
+
+    // Parses a line formatted by format, telling words, halfwords and bytes apart by the number
+    // of digits in each
+    fn parse(line: &str) -> Option<Self> {
+        let column = |start: usize, end: usize| {
+            line.get(start.min(line.len())..end.min(line.len()))
+                .map(str::trim)
+        };
+        let number = match column(0, 4)? {
+            "" => None,
+            number => Some(number.parse().ok()?),
+        };
+        let address = match column(5, 13)? {
+            "" => None,
+            address => Some(u32::from_str_radix(address, 16).ok()?),
+        };
+        let data: Vec<&str> = column(14, 25)?.split_whitespace().collect();
+        let data = match data.first().map(|digits| digits.len()) {
+            None => None,
+            Some(8) if data.len() == 1 => {
+                Some(ListingData::Word(u32::from_str_radix(data[0], 16).ok()?))
+            }
+            Some(4) => Some(ListingData::Halfwords(
+                data.iter()
+                    .map(|h| u16::from_str_radix(h, 16).ok())
+                    .collect::<Option<_>>()?,
+            )),
+            Some(2) => Some(ListingData::Bytes(
+                data.iter()
+                    .map(|b| u8::from_str_radix(b, 16).ok())
+                    .collect::<Option<_>>()?,
+            )),
+            Some(_) => return None,
+        };
+        Some(ListingLine {
+            number,
+            address,
+            data,
+            source: line.get(27..).unwrap_or_default().to_owned(),
+            timing: None,
+        })
+    }
+}
+
+impl fmt::Display for Listing {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.format(false))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_and_parse() {
+        let listing = Listing {
+            lines: vec![
+                ListingLine {
+                    number: Some(1),
+                    address: Some(0),
+                    data: Some(ListingData::Word(0xe3a01001)),
+                    source: String::from("mov r1,#1"),
+                    timing: Some(String::from("1 cycle")),
+                },
+                ListingLine {
+                    number: Some(2),
+                    address: Some(4),
+                    data: Some(ListingData::Bytes(vec![0x68, 0x69])),
+                    source: String::from(".ascii \"hi\""),
+                    timing: None,
+                },
+            ],
+        };
+        assert_eq!(
+            listing.format(true),
+            "   1 00000000 e3a01001     mov r1,#1                 ; 1 cycle\n\
+             \u{20}  2 00000004 68 69        .ascii \"hi\"\n"
+        );
+        let mut untimed = listing.clone();
+        untimed.lines[0].timing = None;
+        assert_eq!(Listing::parse(&listing.to_string()), Ok(untimed));
+        assert!(Listing::parse("   1 0000000g e3a01001     mov r1,#1").is_err());
+    }
+}
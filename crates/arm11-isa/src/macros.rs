@@ -0,0 +1,373 @@
+use crate::types::*;
+
+// Builds a ConditionalInstruction from ARM assembly written as Rust tokens, checking the
+// registers and operands as the crate using it compiles. Immediates are encoded in a const, so
+// one which doesn't fit in its field is a compile error rather than a panic. Data processing,
+// multiply, single data transfer, swi and halt instructions are supported, with an optional
+// condition given after `if`. Branches need labels, so are left to asm!.
+//
+// eg: instr!(adds r0, r1, #0x100)
+//     instr!(mov r0, r1, lsl #2)
+//     instr!(ldrb r0, [r1, #-4])
+//     instr!(str r0, [r1], #4)
+//     instr!(mla r0, r1, r2, r3)
+//     instr!(mov r0, #1 if eq)
+//
+#[macro_export]
+macro_rules! instr {
+    ($($tokens:tt)+) => {
+        $crate::__instr_cond!([] $($tokens)+)
+    };
+}
+
+// Splits the condition from the end of the instruction, if it has one
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __instr_cond {
+    ([$($instr:tt)+] if $cond:ident) => {
+        $crate::types::ConditionalInstruction {
+            cond: $crate::__instr!(@cond $cond),
+            instruction: $crate::__instr!($($instr)+),
+        }
+    };
+    ([$($instr:tt)+]) => {
+        $crate::types::ConditionalInstruction {
+            cond: $crate::types::ConditionCode::Al,
+            instruction: $crate::__instr!($($instr)+),
+        }
+    };
+    ([$($instr:tt)*] $next:tt $($rest:tt)*) => {
+        $crate::__instr_cond!([$($instr)* $next] $($rest)*)
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __instr {
+    (halt) => {
+        $crate::types::Instruction::Halt
+    };
+    (swi #$comment:literal) => {
+        $crate::types::Instruction::SoftwareInterrupt($crate::types::InstructionSoftwareInterrupt {
+            comment: {
+                const COMMENT: u32 = $crate::macros::swi_comment($comment);
+                COMMENT
+            },
+        })
+    };
+
+    // Multiplies
+    (mul $rd:ident, $rm:ident, $rs:ident) => {
+        $crate::__instr!(@multiply false, false, $rd, $rm, $rs, r0)
+    };
+    (muls $rd:ident, $rm:ident, $rs:ident) => {
+        $crate::__instr!(@multiply false, true, $rd, $rm, $rs, r0)
+    };
+    (mla $rd:ident, $rm:ident, $rs:ident, $rn:ident) => {
+        $crate::__instr!(@multiply true, false, $rd, $rm, $rs, $rn)
+    };
+    (mlas $rd:ident, $rm:ident, $rs:ident, $rn:ident) => {
+        $crate::__instr!(@multiply true, true, $rd, $rm, $rs, $rn)
+    };
+
+    // Single data transfers, pre-indexed with or without writeback, or post-indexed, by an
+    // immediate or a register
+    ($op:ident $rd:ident, [$rn:ident]) => {
+        $crate::__instr!(@transfer $op, true, false, $rd, $rn, #0)
+    };
+    ($op:ident $rd:ident, [$rn:ident, $($offset:tt)+]) => {
+        $crate::__instr!(@transfer $op, true, false, $rd, $rn, $($offset)+)
+    };
+    ($op:ident $rd:ident, [$rn:ident, $($offset:tt)+]!) => {
+        $crate::__instr!(@transfer $op, true, true, $rd, $rn, $($offset)+)
+    };
+    ($op:ident $rd:ident, [$rn:ident], $($offset:tt)+) => {
+        $crate::__instr!(@transfer $op, false, false, $rd, $rn, $($offset)+)
+    };
+
+    // Data processing; moves and comparisons have two operands, and everything else three
+    (mov $($rest:tt)+) => { $crate::__instr!(@processing mov, $($rest)+) };
+    (movs $($rest:tt)+) => { $crate::__instr!(@processing movs, $($rest)+) };
+    (mvn $($rest:tt)+) => { $crate::__instr!(@processing mvn, $($rest)+) };
+    (mvns $($rest:tt)+) => { $crate::__instr!(@processing mvns, $($rest)+) };
+    (tst $($rest:tt)+) => { $crate::__instr!(@processing tst, $($rest)+) };
+    (teq $($rest:tt)+) => { $crate::__instr!(@processing teq, $($rest)+) };
+    (cmp $($rest:tt)+) => { $crate::__instr!(@processing cmp, $($rest)+) };
+    (cmn $($rest:tt)+) => { $crate::__instr!(@processing cmn, $($rest)+) };
+    ($op:ident $rd:ident, $rn:ident, $($operand2:tt)+) => {
+        $crate::types::Instruction::Processing($crate::types::InstructionProcessing {
+            opcode: $crate::__instr!(@opcode $op).0,
+            set_cond: $crate::__instr!(@opcode $op).1,
+            rn: $crate::__instr!(@reg $rn),
+            rd: $crate::__instr!(@reg $rd),
+            operand2: $crate::__instr!(@operand2 $($operand2)+),
+        })
+    };
+
+    (@processing $op:ident, $reg:ident, $($operand2:tt)+) => {{
+        let (opcode, set_cond) = $crate::__instr!(@opcode $op);
+        // Comparisons name rn, and moves rd
+        let (rn, rd) = match opcode {
+            $crate::types::ProcessingOpcode::Tst
+            | $crate::types::ProcessingOpcode::Teq
+            | $crate::types::ProcessingOpcode::Cmp
+            | $crate::types::ProcessingOpcode::Cmn => ($crate::__instr!(@reg $reg), 0),
+            _ => (0, $crate::__instr!(@reg $reg)),
+        };
+        $crate::types::Instruction::Processing($crate::types::InstructionProcessing {
+            opcode,
+            set_cond,
+            rn,
+            rd,
+            operand2: $crate::__instr!(@operand2 $($operand2)+),
+        })
+    }};
+
+    (@multiply $accumulate:expr, $set_cond:expr, $rd:ident, $rm:ident, $rs:ident, $rn:ident) => {
+        $crate::types::Instruction::Multiply($crate::types::InstructionMultiply {
+            accumulate: $accumulate,
+            set_cond: $set_cond,
+            rd: $crate::__instr!(@reg $rd),
+            rn: $crate::__instr!(@reg $rn),
+            rs: $crate::__instr!(@reg $rs),
+            rm: $crate::__instr!(@reg $rm),
+        })
+    };
+
+    (@transfer $op:ident, $preindexed:expr, $writeback:expr, $rd:ident, $rn:ident,
+     $($offset:tt)+) => {{
+        let (load, size) = $crate::__instr!(@transfer_op $op);
+        let (up_bit, offset) = $crate::__instr!(@offset $op, $($offset)+);
+        $crate::types::Instruction::Transfer($crate::types::InstructionTransfer {
+            is_preindexed: $preindexed,
+            up_bit,
+            writeback: $writeback,
+            load,
+            size,
+            rn: $crate::__instr!(@reg $rn),
+            rd: $crate::__instr!(@reg $rd),
+            offset,
+        })
+    }};
+
+    (@offset $op:ident, #$offset:literal) => {{
+        const OFFSET: (bool, u16) =
+            $crate::macros::transfer_offset($offset, $crate::__instr!(@transfer_op $op).1);
+        (OFFSET.0, $crate::types::TransferOffset::Immediate(OFFSET.1))
+    }};
+    (@offset $op:ident, -$rm:ident $(, $($shift:tt)+)?) => {{
+        let (_, shift) = $crate::__instr!(@shifted $rm $(, $($shift)+)?);
+        (false, $crate::types::TransferOffset::ShiftedReg($crate::__instr!(@reg $rm), shift))
+    }};
+    (@offset $op:ident, $rm:ident $(, $($shift:tt)+)?) => {{
+        let (_, shift) = $crate::__instr!(@shifted $rm $(, $($shift)+)?);
+        (true, $crate::types::TransferOffset::ShiftedReg($crate::__instr!(@reg $rm), shift))
+    }};
+
+    (@operand2 #$imm:literal) => {{
+        const OPERAND2: $crate::types::Operand2 = $crate::macros::immediate_operand2($imm);
+        OPERAND2
+    }};
+    (@operand2 $rm:ident $(, $($shift:tt)+)?) => {{
+        let (rm, shift) = $crate::__instr!(@shifted $rm $(, $($shift)+)?);
+        $crate::types::Operand2::ShiftedReg(rm, shift)
+    }};
+
+    (@shifted $rm:ident) => {
+        (
+            $crate::__instr!(@reg $rm),
+            $crate::types::Shift::ConstantShift($crate::types::ShiftType::Lsl, 0),
+        )
+    };
+    (@shifted $rm:ident, rrx) => {
+        (
+            $crate::__instr!(@reg $rm),
+            $crate::types::Shift::ConstantShift($crate::types::ShiftType::Ror, 0),
+        )
+    };
+    (@shifted $rm:ident, $shift:ident #$amount:literal) => {{
+        const SHIFT: $crate::types::Shift =
+            $crate::macros::constant_shift($crate::__instr!(@shift $shift), $amount);
+        ($crate::__instr!(@reg $rm), SHIFT)
+    }};
+    (@shifted $rm:ident, $shift:ident $rs:ident) => {
+        (
+            $crate::__instr!(@reg $rm),
+            $crate::types::Shift::RegisterShift(
+                $crate::__instr!(@shift $shift),
+                $crate::__instr!(@reg $rs),
+            ),
+        )
+    };
+
+    (@reg r0) => { 0u8 };
+    (@reg r1) => { 1u8 };
+    (@reg r2) => { 2u8 };
+    (@reg r3) => { 3u8 };
+    (@reg r4) => { 4u8 };
+    (@reg r5) => { 5u8 };
+    (@reg r6) => { 6u8 };
+    (@reg r7) => { 7u8 };
+    (@reg r8) => { 8u8 };
+    (@reg r9) => { 9u8 };
+    (@reg r10) => { 10u8 };
+    (@reg r11) => { 11u8 };
+    (@reg r12) => { 12u8 };
+    (@reg r13) => { 13u8 };
+    (@reg sp) => { 13u8 };
+    (@reg r14) => { 14u8 };
+    (@reg lr) => { 14u8 };
+    (@reg r15) => { 15u8 };
+    (@reg pc) => { 15u8 };
+
+    (@shift lsl) => { $crate::types::ShiftType::Lsl };
+    (@shift lsr) => { $crate::types::ShiftType::Lsr };
+    (@shift asr) => { $crate::types::ShiftType::Asr };
+    (@shift ror) => { $crate::types::ShiftType::Ror };
+
+    (@transfer_op ldr) => { (true, $crate::types::TransferSize::Word) };
+    (@transfer_op str) => { (false, $crate::types::TransferSize::Word) };
+    (@transfer_op ldrb) => { (true, $crate::types::TransferSize::Byte) };
+    (@transfer_op strb) => { (false, $crate::types::TransferSize::Byte) };
+    (@transfer_op ldrh) => { (true, $crate::types::TransferSize::Halfword) };
+    (@transfer_op strh) => { (false, $crate::types::TransferSize::Halfword) };
+    (@transfer_op ldrsb) => { (true, $crate::types::TransferSize::SignedByte) };
+    (@transfer_op ldrsh) => { (true, $crate::types::TransferSize::SignedHalfword) };
+
+    (@opcode and) => { ($crate::types::ProcessingOpcode::And, false) };
+    (@opcode ands) => { ($crate::types::ProcessingOpcode::And, true) };
+    (@opcode eor) => { ($crate::types::ProcessingOpcode::Eor, false) };
+    (@opcode eors) => { ($crate::types::ProcessingOpcode::Eor, true) };
+    (@opcode sub) => { ($crate::types::ProcessingOpcode::Sub, false) };
+    (@opcode subs) => { ($crate::types::ProcessingOpcode::Sub, true) };
+    (@opcode rsb) => { ($crate::types::ProcessingOpcode::Rsb, false) };
+    (@opcode rsbs) => { ($crate::types::ProcessingOpcode::Rsb, true) };
+    (@opcode add) => { ($crate::types::ProcessingOpcode::Add, false) };
+    (@opcode adds) => { ($crate::types::ProcessingOpcode::Add, true) };
+    (@opcode adc) => { ($crate::types::ProcessingOpcode::Adc, false) };
+    (@opcode adcs) => { ($crate::types::ProcessingOpcode::Adc, true) };
+    (@opcode sbc) => { ($crate::types::ProcessingOpcode::Sbc, false) };
+    (@opcode sbcs) => { ($crate::types::ProcessingOpcode::Sbc, true) };
+    (@opcode rsc) => { ($crate::types::ProcessingOpcode::Rsc, false) };
+    (@opcode rscs) => { ($crate::types::ProcessingOpcode::Rsc, true) };
+    (@opcode tst) => { ($crate::types::ProcessingOpcode::Tst, true) };
+    (@opcode teq) => { ($crate::types::ProcessingOpcode::Teq, true) };
+    (@opcode cmp) => { ($crate::types::ProcessingOpcode::Cmp, true) };
+    (@opcode cmn) => { ($crate::types::ProcessingOpcode::Cmn, true) };
+    (@opcode orr) => { ($crate::types::ProcessingOpcode::Orr, false) };
+    (@opcode orrs) => { ($crate::types::ProcessingOpcode::Orr, true) };
+    (@opcode mov) => { ($crate::types::ProcessingOpcode::Mov, false) };
+    (@opcode movs) => { ($crate::types::ProcessingOpcode::Mov, true) };
+    (@opcode bic) => { ($crate::types::ProcessingOpcode::Bic, false) };
+    (@opcode bics) => { ($crate::types::ProcessingOpcode::Bic, true) };
+    (@opcode mvn) => { ($crate::types::ProcessingOpcode::Mvn, false) };
+    (@opcode mvns) => { ($crate::types::ProcessingOpcode::Mvn, true) };
+
+    (@cond eq) => { $crate::types::ConditionCode::Eq };
+    (@cond ne) => { $crate::types::ConditionCode::Ne };
+    (@cond cs) => { $crate::types::ConditionCode::Cs };
+    (@cond hs) => { $crate::types::ConditionCode::Cs };
+    (@cond cc) => { $crate::types::ConditionCode::Cc };
+    (@cond lo) => { $crate::types::ConditionCode::Cc };
+    (@cond mi) => { $crate::types::ConditionCode::Mi };
+    (@cond pl) => { $crate::types::ConditionCode::Pl };
+    (@cond vs) => { $crate::types::ConditionCode::Vs };
+    (@cond vc) => { $crate::types::ConditionCode::Vc };
+    (@cond hi) => { $crate::types::ConditionCode::Hi };
+    (@cond ls) => { $crate::types::ConditionCode::Ls };
+    (@cond ge) => { $crate::types::ConditionCode::Ge };
+    (@cond lt) => { $crate::types::ConditionCode::Lt };
+    (@cond gt) => { $crate::types::ConditionCode::Gt };
+    (@cond le) => { $crate::types::ConditionCode::Le };
+    (@cond al) => { $crate::types::ConditionCode::Al };
+}
+
+// The Operand2 of an immediate, as an 8 bit value rotated right by twice a 4 bit count, with the
+// same rotation the assembler chooses. Called in a const by instr!, so a value which can't be
+// encoded fails to compile.
+pub const fn immediate_operand2(value: u32) -> Operand2 {
+    if value <= 0xff {
+        return Operand2::ConstantShift(value as u8, 0);
+    }
+    let mut rotate = 1;
+    while rotate < 16 {
+        let rotated = value.rotate_left(2 * rotate);
+        if rotated <= 0xff {
+            return Operand2::ConstantShift(rotated as u8, rotate as u8);
+        }
+        rotate += 1;
+    }
+    panic!("Immediate can't be encoded as an 8 bit value rotated by an even amount")
+}
+
+// Whether a transfer offset is added, and its magnitude, which must fit in 12 bits, or 8 bits for
+// halfword and signed transfers
+pub const fn transfer_offset(offset: i32, size: TransferSize) -> (bool, u16) {
+    let magnitude = offset.unsigned_abs();
+    let max = match size {
+        TransferSize::Word | TransferSize::Byte => 0xfff,
+        _ => 0xff,
+    };
+    if magnitude > max {
+        panic!("Transfer offset out of range");
+    }
+    (offset >= 0, magnitude as u16)
+}
+
+// An lsr or asr by 32 is encoded as by 0, so an lsr or asr by 0 is lsl #0
+pub const fn constant_shift(shift_type: ShiftType, amount: u32) -> Shift {
+    match (shift_type, amount) {
+        (ShiftType::Lsr, 0) | (ShiftType::Asr, 0) => Shift::ConstantShift(ShiftType::Lsl, 0),
+        (ShiftType::Lsr, 32) | (ShiftType::Asr, 32) => Shift::ConstantShift(shift_type, 0),
+        (_, 0..=31) => Shift::ConstantShift(shift_type, amount as u8),
+        _ => panic!("Shift amount out of range"),
+    }
+}
+
+pub const fn swi_comment(comment: u32) -> u32 {
+    if comment > 0xff_ffff {
+        panic!("Software interrupt comment out of range");
+    }
+    comment
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{decode::decode, disassemble::disassemble_instruction, encode::encode};
+
+    #[test]
+    fn test_instr() {
+        // Each instruction disassembles to the source it was written from
+        let instrs = [
+            (instr!(adds r0, r1, #0x100), "adds r0, r1, #0x100"),
+            (instr!(mov r0, r1, lsl #2), "mov r0, r1, lsl #2"),
+            (instr!(sub r0, r1, r2, asr r3), "sub r0, r1, r2, asr r3"),
+            (instr!(movs r0, r1, rrx), "movs r0, r1, rrx"),
+            (instr!(mov r0, r1, asr #32), "mov r0, r1, asr #32"),
+            (instr!(cmp r0, #0xff000000), "cmp r0, #0xff000000"),
+            (instr!(mvn r2, r3), "mvn r2, r3"),
+            (instr!(mov r0, #1 if eq), "moveq r0, #1"),
+            (instr!(ldrb r0, [r1, #-4]), "ldrb r0, [r1, #-4]"),
+            (instr!(str r0, [r1], #4), "str r0, [r1], #4"),
+            (instr!(ldrh r0, [r1, #-2]!), "ldrh r0, [r1, #-2]!"),
+            (
+                instr!(ldr r0, [r1, r2, lsl #2]!),
+                "ldr r0, [r1, r2, lsl #2]!",
+            ),
+            (instr!(ldr r0, [sp]), "ldr r0, [r13]"),
+            (instr!(ldrsh r0, [r1, r2]), "ldrsh r0, [r1, r2]"),
+            (
+                instr!(str r0, [r1], -r2, asr #1),
+                "str r0, [r1], -r2, asr #1",
+            ),
+            (instr!(mla r0, r1, r2, r3), "mla r0, r1, r2, r3"),
+            (instr!(swi #0x2 if ne), "swine #0x2"),
+        ];
+        for (instr, expected) in instrs.iter() {
+            let decoded = decode(&encode(*instr).expect("encode failed")).expect("decode failed");
+            assert_eq!(decoded, *instr, "{}", expected);
+            assert_eq!(disassemble_instruction(instr, 0), *expected);
+        }
+        assert_eq!(encode(instr!(halt if eq)).expect("encode failed"), 0);
+    }
+}
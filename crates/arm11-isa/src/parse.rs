@@ -0,0 +1,169 @@
+use nom::error::{ContextError, ErrorKind, ParseError};
+use nom::{
+    branch::alt,
+    bytes::complete::{escaped_transform, is_not, tag},
+    character::complete::char,
+    combinator::{map, value},
+    sequence::delimited,
+};
+use nom::{ErrorConvert, IResult, InputLength};
+
+#[derive(Debug)]
+pub struct ArmNomError<I> {
+    pub kind: ArmNomErrorKind<I>,
+    backtrace: Vec<ArmNomErrorKind<I>>,
+}
+
+#[derive(Debug, Copy, Clone)]
+pub enum ArmNomErrorKind<I> {
+    Nom(I, ErrorKind),
+    Context(I, &'static str),
+    InvalidInstructionType,
+    Operand2Constant(u32),
+    HexadecimalValue,
+    DecimalValue,
+    SignedDecimalValue,
+    // The literal pool is out of reach of an ldr =, by the given offset from its PC
+    LiteralOutOfRange(i64),
+    // A value which doesn't fit in its field; the field, the value, and what it would be
+    // truncated to if it was masked to fit
+    Truncated(&'static str, i64, i64),
+}
+
+impl<I> ArmNomError<I> {
+    pub fn new(kind: ArmNomErrorKind<I>) -> Self {
+        ArmNomError {
+            kind,
+            backtrace: Vec::new(),
+        }
+    }
+
+    // The innermost context the error occurred in, eg: "parsing transfer instruction"
+    pub fn context(&self) -> Option<&'static str> {
+        self.backtrace.iter().find_map(|kind| match kind {
+            ArmNomErrorKind::Context(_, context) => Some(*context),
+            _ => None,
+        })
+    }
+}
+
+impl<I: Clone> ArmNomError<I> {
+    // The input which was left when the error occurred, if it is known
+    pub fn input(&self) -> Option<I> {
+        self.kind
+            .input()
+            .or_else(|| self.backtrace.iter().find_map(ArmNomErrorKind::input))
+    }
+}
+
+impl<I: Clone> ArmNomErrorKind<I> {
+    fn input(&self) -> Option<I> {
+        match self {
+            ArmNomErrorKind::Nom(input, _) | ArmNomErrorKind::Context(input, _) => {
+                Some(input.clone())
+            }
+            _ => None,
+        }
+    }
+}
+
+impl<I: Clone + InputLength> ParseError<I> for ArmNomError<I> {
+    fn from_error_kind(input: I, kind: ErrorKind) -> ArmNomError<I> {
+        ArmNomError::new(ArmNomErrorKind::Nom(input, kind))
+    }
+
+    fn append(input: I, kind: ErrorKind, mut other: Self) -> Self {
+        other.backtrace.push(ArmNomErrorKind::Nom(input, kind));
+        other
+    }
+
+    // Keeps the error from whichever alternative parsed furthest into the input, as that is
+    // most likely the one which was intended, eg: a processing instruction with a bad operand
+    // rather than an unknown branch mnemonic. Between alternatives which stopped at the same
+    // point, errors about a specific value are kept over generic parse errors.
+    fn or(self, other: Self) -> Self {
+        let remaining = |e: &Self| e.input().map(|input| input.input_len());
+        let generic = |e: &Self| e.kind.input().is_some();
+        match (remaining(&self), remaining(&other)) {
+            (Some(this), Some(that)) if this < that => self,
+            (Some(this), Some(that)) if this == that && !generic(&self) && generic(&other) => self,
+            _ => other,
+        }
+    }
+}
+
+impl<I> From<ArmNomError<I>> for nom::Err<ArmNomError<I>> {
+    fn from(err: ArmNomError<I>) -> Self {
+        nom::Err::Error(err)
+    }
+}
+
+impl<I> ErrorConvert<ArmNomError<I>> for ArmNomError<(I, usize)> {
+    fn convert(self) -> ArmNomError<I> {
+        let mut new_backtrace = Vec::new();
+        for k in self.backtrace {
+            new_backtrace.push(k.convert());
+        }
+        ArmNomError {
+            kind: self.kind.convert(),
+            backtrace: new_backtrace,
+        }
+    }
+}
+
+impl<I> ContextError<I> for ArmNomError<I> {
+    fn add_context(input: I, ctx: &'static str, mut other: Self) -> Self {
+        other.backtrace.push(ArmNomErrorKind::Context(input, ctx));
+        other
+    }
+}
+
+impl<I> ErrorConvert<ArmNomErrorKind<I>> for ArmNomErrorKind<(I, usize)> {
+    fn convert(self) -> ArmNomErrorKind<I> {
+        match self {
+            ArmNomErrorKind::Nom(t, k) => ArmNomErrorKind::Nom(t.0, k),
+            ArmNomErrorKind::Context(t, c) => ArmNomErrorKind::Context(t.0, c),
+            ArmNomErrorKind::Operand2Constant(c) => ArmNomErrorKind::Operand2Constant(c),
+            ArmNomErrorKind::HexadecimalValue => ArmNomErrorKind::HexadecimalValue,
+            ArmNomErrorKind::DecimalValue => ArmNomErrorKind::DecimalValue,
+            ArmNomErrorKind::SignedDecimalValue => ArmNomErrorKind::SignedDecimalValue,
+            ArmNomErrorKind::InvalidInstructionType => ArmNomErrorKind::InvalidInstructionType,
+            ArmNomErrorKind::LiteralOutOfRange(o) => ArmNomErrorKind::LiteralOutOfRange(o),
+            ArmNomErrorKind::Truncated(f, v, t) => ArmNomErrorKind::Truncated(f, v, t),
+        }
+    }
+}
+
+pub type NomResult<I, T> = IResult<I, T, ArmNomError<I>>;
+
+// Parses a decimal or 0x prefixed hexadecimal number
+pub fn parse_number(s: &str) -> std::result::Result<u32, String> {
+    match s.strip_prefix("0x") {
+        Some(hex) => u32::from_str_radix(hex, 16),
+        None => s.parse(),
+    }
+    .map_err(|_| format!("Invalid number '{}'", s))
+}
+
+// Parses a double quoted string, with the escapes \\, \", \n, \t and \0
+pub fn parse_string(input: &str) -> NomResult<&str, String> {
+    delimited(
+        char('"'),
+        alt((
+            escaped_transform(
+                is_not("\\\""),
+                '\\',
+                alt((
+                    value("\\", char('\\')),
+                    value("\"", char('"')),
+                    value("\n", char('n')),
+                    value("\t", char('t')),
+                    value("\0", char('0')),
+                )),
+            ),
+            // escaped_transform fails on the empty string
+            map(tag(""), String::from),
+        )),
+        char('"'),
+    )(input)
+}
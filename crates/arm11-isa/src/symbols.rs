@@ -0,0 +1,168 @@
+use std::{collections::BTreeMap, fmt, fs};
+
+use super::{
+    address::{Address, SymbolTable},
+    listing::Listing,
+    types::*,
+};
+
+// The version of the symbol file format written by the assembler. Files of later versions are
+// rejected, rather than being read incorrectly.
+pub const SYMBOL_FILE_VERSION: u32 = 1;
+
+const HEADER: &str = "arm11-symbols";
+
+// The symbols and line table of an assembled binary, written by assemble --symbols and read by
+// every tool which names addresses; the disassembler, the emulator's reports, and the debugger.
+// The file starts with the format and its version, followed by a line for each label, sorted by
+// address and then name, and a line for each address the source placed in the binary:
+//
+// eg: arm11-symbols 1
+//     symbol loop 0x00000004
+//     line 0x00000000 1
+//     line 0x00000004 3
+//
+// Files without the header are read as symbol maps from before the format was versioned, which
+// have a label and its address on each line.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct SymbolFile {
+    pub symbol_table: SymbolTable,
+    // The line of source each address was assembled from, in the file it was written in
+    pub lines: BTreeMap<u32, usize>,
+}
+
+impl SymbolFile {
+    // Creates the symbol file of an assembled binary, from its symbols and its listing
+    pub fn new(symbol_table: &SymbolTable, listing: &Listing) -> Self {
+        let lines = listing
+            .lines
+            .iter()
+            .filter(|line| line.data.is_some())
+            .filter_map(|line| Some((line.address?, line.number?)))
+            .collect();
+        SymbolFile {
+            symbol_table: symbol_table.clone(),
+            lines,
+        }
+    }
+
+    pub fn read(filename: &str) -> Result<Self> {
+        SymbolFile::parse(&fs::read_to_string(filename)?)
+            .map_err(|e| ArmError::parse(format!("{}: {}", filename, e)))
+    }
+
+    // Parses a symbol file, or a symbol map without a header, ignoring blank lines
+    pub fn parse(raw: &str) -> std::result::Result<Self, String> {
+        let mut lines = raw
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .peekable();
+        let mut symbol_file = SymbolFile::default();
+
+        let version = match lines.peek().and_then(|line| line.strip_prefix(HEADER)) {
+            Some(version) => {
+                lines.next();
+                version
+                    .trim()
+                    .parse::<u32>()
+                    .map_err(|_| format!("Invalid symbol file version '{}'", version.trim()))?
+            }
+            None => 0,
+        };
+        if version > SYMBOL_FILE_VERSION {
+            return Err(format!(
+                "Symbol file version {} is newer than the supported version {}",
+                version, SYMBOL_FILE_VERSION
+            ));
+        }
+
+        for line in lines {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            match (version, fields.as_slice()) {
+                (0, [name, address]) | (_, ["symbol", name, address]) => {
+                    let address = parse_address(address, line)?;
+                    symbol_file
+                        .symbol_table
+                        .insert(String::from(*name), Address(address));
+                }
+                (1.., ["line", address, number]) => {
+                    let number = number.parse().map_err(|_| {
+                        format!("Invalid line number in symbol file line '{}'", line)
+                    })?;
+                    symbol_file
+                        .lines
+                        .insert(parse_address(address, line)?, number);
+                }
+                _ => return Err(format!("Invalid symbol file line '{}'", line)),
+            }
+        }
+        Ok(symbol_file)
+    }
+
+    // The line of source an address was assembled from, if it is in the line table. Addresses
+    // part way through the data of a line are part of that line.
+    pub fn line_at(&self, address: Address) -> Option<usize> {
+        self.lines
+            .range(..=address.0)
+            .next_back()
+            .map(|(_, &number)| number)
+    }
+}
+
+fn parse_address(address: &str, line: &str) -> std::result::Result<u32, String> {
+    address
+        .strip_prefix("0x")
+        .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+        .ok_or_else(|| format!("Invalid address in symbol file line '{}'", line))
+}
+
+impl fmt::Display for SymbolFile {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{} {}", HEADER, SYMBOL_FILE_VERSION)?;
+        let mut symbols: Vec<(&String, &Address)> = self.symbol_table.iter().collect();
+        symbols.sort_by(|(a_name, a), (b_name, b)| a.cmp(b).then(a_name.cmp(b_name)));
+        for (name, address) in symbols {
+            writeln!(f, "symbol {} {}", name, address)?;
+        }
+        for (&address, number) in &self.lines {
+            writeln!(f, "line {} {}", Address(address), number)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_symbol_file() {
+        let mut symbol_file = SymbolFile::default();
+        symbol_file
+            .symbol_table
+            .insert(String::from("loop"), Address(0x4));
+        symbol_file.lines.extend([(0x0, 1), (0x4, 3), (0xc, 5)]);
+        assert_eq!(
+            symbol_file.to_string(),
+            "arm11-symbols 1\nsymbol loop 0x00000004\nline 0x00000000 1\n\
+             line 0x00000004 3\nline 0x0000000c 5\n"
+        );
+        assert_eq!(symbol_file.line_at(Address(0xe)), Some(5));
+        assert_eq!(
+            SymbolFile::parse(&symbol_file.to_string()).expect("parse failed"),
+            symbol_file
+        );
+
+        // Symbol maps from before the format was versioned are still read
+        let unversioned = SymbolFile::parse("loop 0x00000004\n\nend 0x00000010\n")
+            .expect("parse unversioned failed");
+        assert_eq!(unversioned.symbol_table.len(), 2);
+        assert_eq!(unversioned.symbol_table["end"], Address(0x10));
+        assert!(unversioned.lines.is_empty());
+
+        assert!(SymbolFile::parse("loop 4\n").is_err());
+        assert!(SymbolFile::parse("arm11-symbols 2\n").is_err());
+        assert!(SymbolFile::parse("arm11-symbols 1\nloop 0x00000004\n").is_err());
+        assert!(SymbolFile::parse("arm11-symbols 1\nline 0x00000004 x\n").is_err());
+    }
+}
@@ -0,0 +1,372 @@
+use num_traits::FromPrimitive;
+
+use crate::types::*;
+
+// The stack pointer and link register, which some Thumb instructions use implicitly
+const SP: u8 = 13;
+const LR: u8 = 14;
+const PC: u8 = 15;
+
+// Decodes a Thumb instruction into the ARM instruction which does the same thing, so that it can
+// be executed, traced and timed like any other. eg: add r0,#1 is adds r0,r0,#1, and push {r4,lr}
+// is stmdb r13!,{r4,r14}. Only branches need their own instruction, as their offsets are in
+// halfwords. A zero halfword is Halt, like a zero word in ARM state.
+//
+// Instructions are grouped by their top bits, in the numbered formats of the ARM7TDMI data
+// sheet. The ARMv6 additions to Thumb, such as sxth and cps, aren't supported.
+//
+pub fn decode(instr: u16) -> Result<ConditionalInstruction> {
+    if instr == 0 {
+        return Ok(always(Instruction::Halt));
+    }
+
+    let field = |pos: u32, size: u32| ((u32::from(instr) >> pos) & ((1 << size) - 1)) as u8;
+    let bit = |pos: u32| field(pos, 1) != 0;
+    let (rd, rs) = (field(0, 3), field(3, 3));
+    let unsupported = || ArmError::BadOpcode {
+        opcode: u32::from(instr),
+        kind: "Thumb",
+        reason: None,
+    };
+
+    let instruction = match instr >> 11 {
+        // 1. lsl, lsr or asr Rd,Rs,#<imm5>
+        0x0..=0x2 => {
+            let shift_type = ShiftType::from_u8(field(11, 2)).ok_or_else(unsupported)?;
+            let shift = Shift::ConstantShift(shift_type, field(6, 5));
+            processing(
+                ProcessingOpcode::Mov,
+                true,
+                0,
+                rd,
+                Operand2::ShiftedReg(rs, shift),
+            )
+        }
+        // 2. add or sub Rd,Rs,Rn or Rd,Rs,#<imm3>
+        0x3 => {
+            let opcode = if bit(9) {
+                ProcessingOpcode::Sub
+            } else {
+                ProcessingOpcode::Add
+            };
+            let operand2 = if bit(10) {
+                immediate(field(6, 3))
+            } else {
+                register(field(6, 3))
+            };
+            processing(opcode, true, rs, rd, operand2)
+        }
+        // 3. mov, cmp, add or sub Rd,#<imm8>
+        0x4..=0x7 => {
+            let r = field(8, 3);
+            let (opcode, rn, rd) = match field(11, 2) {
+                0 => (ProcessingOpcode::Mov, 0, r),
+                1 => (ProcessingOpcode::Cmp, r, 0),
+                2 => (ProcessingOpcode::Add, r, r),
+                _ => (ProcessingOpcode::Sub, r, r),
+            };
+            processing(opcode, true, rn, rd, immediate(field(0, 8)))
+        }
+        // 4. ALU operations on low registers, i.e. <op> Rd,Rs
+        0x8 if !bit(10) => decode_alu(field(6, 4), rd, rs),
+        // 5. add, cmp or mov on any registers, and bx
+        0x8 => {
+            let rd = rd | field(7, 1) << 3;
+            let rs = field(3, 4);
+            match field(8, 2) {
+                0 => processing(ProcessingOpcode::Add, false, rd, rd, register(rs)),
+                1 => processing(ProcessingOpcode::Cmp, true, rd, 0, register(rs)),
+                2 => processing(ProcessingOpcode::Mov, false, 0, rd, register(rs)),
+                _ if !bit(7) => Instruction::BranchExchange(InstructionBranchExchange { rm: rs }),
+                _ => return Err(unsupported()),
+            }
+        }
+        // 6. ldr Rd,[pc,#<imm8 * 4>]
+        0x9 => transfer(
+            true,
+            TransferSize::Word,
+            PC,
+            field(8, 3),
+            TransferOffset::Immediate(u16::from(field(0, 8)) * 4),
+        ),
+        // 7 and 8. Loads and stores with a register offset, i.e. <op> Rd,[Rb,Ro]
+        0xa | 0xb => {
+            let (load, size) = match (bit(9), field(10, 2)) {
+                (false, 0) => (false, TransferSize::Word),
+                (false, 1) => (false, TransferSize::Byte),
+                (false, 2) => (true, TransferSize::Word),
+                (false, _) => (true, TransferSize::Byte),
+                (true, 0) => (false, TransferSize::Halfword),
+                (true, 1) => (true, TransferSize::SignedByte),
+                (true, 2) => (true, TransferSize::Halfword),
+                (true, _) => (true, TransferSize::SignedHalfword),
+            };
+            let offset =
+                TransferOffset::ShiftedReg(field(6, 3), Shift::ConstantShift(ShiftType::Lsl, 0));
+            transfer(load, size, rs, rd, offset)
+        }
+        // 9 and 10. Loads and stores with an immediate offset, scaled by the transfer size
+        0xc..=0x11 => {
+            let (size, scale) = match instr >> 12 {
+                0x6 => (TransferSize::Word, 4),
+                0x7 => (TransferSize::Byte, 1),
+                _ => (TransferSize::Halfword, 2),
+            };
+            let offset = TransferOffset::Immediate(u16::from(field(6, 5)) * scale);
+            transfer(bit(11), size, rs, rd, offset)
+        }
+        // 11. ldr or str Rd,[sp,#<imm8 * 4>]
+        0x12 | 0x13 => transfer(
+            bit(11),
+            TransferSize::Word,
+            SP,
+            field(8, 3),
+            TransferOffset::Immediate(u16::from(field(0, 8)) * 4),
+        ),
+        // 12. add Rd,pc or sp,#<imm8 * 4>
+        0x14 | 0x15 => {
+            let rn = if bit(11) { SP } else { PC };
+            processing(
+                ProcessingOpcode::Add,
+                false,
+                rn,
+                field(8, 3),
+                word_immediate(field(0, 8)),
+            )
+        }
+        // 13. add or sub sp,#<imm7 * 4>
+        0x16 if field(8, 3) == 0 => {
+            let opcode = if bit(7) {
+                ProcessingOpcode::Sub
+            } else {
+                ProcessingOpcode::Add
+            };
+            processing(opcode, false, SP, SP, word_immediate(field(0, 7)))
+        }
+        // 14. push {<rlist>{,lr}} or pop {<rlist>{,pc}}, a full descending stack on sp
+        0x16 | 0x17 if field(9, 2) == 2 => {
+            let load = bit(11);
+            let extra = match (bit(8), load) {
+                (false, _) => 0,
+                (true, false) => 1 << LR,
+                (true, true) => 1 << PC,
+            };
+            Instruction::BlockTransfer(InstructionBlockTransfer {
+                is_preindexed: !load,
+                up_bit: load,
+                writeback: true,
+                load,
+                rn: SP,
+                register_list: u16::from(field(0, 8)) | extra,
+            })
+        }
+        // 15. stmia or ldmia Rb!,{<rlist>}
+        0x18 | 0x19 => Instruction::BlockTransfer(InstructionBlockTransfer {
+            is_preindexed: false,
+            up_bit: true,
+            writeback: true,
+            load: bit(11),
+            rn: field(8, 3),
+            register_list: u16::from(field(0, 8)),
+        }),
+        // 17. swi #<imm8>
+        0x1b if field(8, 3) == 7 => Instruction::SoftwareInterrupt(InstructionSoftwareInterrupt {
+            comment: u32::from(field(0, 8)),
+        }),
+        // 16. b<cond> <target>, within 256 bytes
+        0x1a | 0x1b if field(8, 4) != 0xe => {
+            let cond = ConditionCode::from_u8(field(8, 4)).ok_or_else(unsupported)?;
+            let offset = i32::from(field(0, 8) as i8) << 1;
+            return Ok(ConditionalInstruction {
+                cond,
+                instruction: branch(ThumbBranchKind::Branch, offset),
+            });
+        }
+        // 18. b <target>, within 2KB
+        0x1c => branch(ThumbBranchKind::Branch, sign_extend(instr, 11) << 1),
+        // 19. bl <target>, within 4MB, as a prefix and a suffix
+        0x1e => branch(ThumbBranchKind::LinkPrefix, sign_extend(instr, 11) << 12),
+        0x1f => branch(ThumbBranchKind::LinkSuffix, i32::from(instr & 0x7ff) << 1),
+        _ => return Err(unsupported()),
+    };
+
+    Ok(always(instruction))
+}
+
+// Decodes an ALU operation on low registers, which always sets the flags
+fn decode_alu(op: u8, rd: u8, rs: u8) -> Instruction {
+    use ProcessingOpcode::*;
+
+    // Shifts move Rd shifted by Rs, i.e. lsl Rd,Rs is movs Rd,Rd,lsl Rs
+    let shifted = |shift_type| {
+        let shift = Shift::RegisterShift(shift_type, rs);
+        processing(Mov, true, 0, rd, Operand2::ShiftedReg(rd, shift))
+    };
+    match op {
+        0x0 => processing(And, true, rd, rd, register(rs)),
+        0x1 => processing(Eor, true, rd, rd, register(rs)),
+        0x2 => shifted(ShiftType::Lsl),
+        0x3 => shifted(ShiftType::Lsr),
+        0x4 => shifted(ShiftType::Asr),
+        0x5 => processing(Adc, true, rd, rd, register(rs)),
+        0x6 => processing(Sbc, true, rd, rd, register(rs)),
+        0x7 => shifted(ShiftType::Ror),
+        0x8 => processing(Tst, true, rd, 0, register(rs)),
+        // neg Rd,Rs is rsbs Rd,Rs,#0
+        0x9 => processing(Rsb, true, rs, rd, immediate(0)),
+        0xa => processing(Cmp, true, rd, 0, register(rs)),
+        0xb => processing(Cmn, true, rd, 0, register(rs)),
+        0xc => processing(Orr, true, rd, rd, register(rs)),
+        0xd => Instruction::Multiply(InstructionMultiply {
+            accumulate: false,
+            set_cond: true,
+            rd,
+            rn: 0,
+            rs: rd,
+            rm: rs,
+        }),
+        0xe => processing(Bic, true, rd, rd, register(rs)),
+        _ => processing(Mvn, true, 0, rd, register(rs)),
+    }
+}
+
+fn always(instruction: Instruction) -> ConditionalInstruction {
+    ConditionalInstruction {
+        cond: ConditionCode::Al,
+        instruction,
+    }
+}
+
+fn processing(
+    opcode: ProcessingOpcode,
+    set_cond: bool,
+    rn: u8,
+    rd: u8,
+    operand2: Operand2,
+) -> Instruction {
+    Instruction::Processing(InstructionProcessing {
+        opcode,
+        set_cond,
+        rn,
+        rd,
+        operand2,
+    })
+}
+
+fn transfer(load: bool, size: TransferSize, rn: u8, rd: u8, offset: TransferOffset) -> Instruction {
+    Instruction::Transfer(InstructionTransfer {
+        is_preindexed: true,
+        up_bit: true,
+        writeback: false,
+        load,
+        size,
+        rn,
+        rd,
+        offset,
+    })
+}
+
+fn branch(kind: ThumbBranchKind, offset: i32) -> Instruction {
+    Instruction::ThumbBranch(InstructionThumbBranch { kind, offset })
+}
+
+fn register(rm: u8) -> Operand2 {
+    Operand2::ShiftedReg(rm, Shift::ConstantShift(ShiftType::Lsl, 0))
+}
+
+fn immediate(value: u8) -> Operand2 {
+    Operand2::ConstantShift(value, 0)
+}
+
+// An immediate of a number of words, i.e. words * 4. This is rotated as little as possible, as
+// the assembler does, so that equal instructions compare equal.
+fn word_immediate(words: u8) -> Operand2 {
+    let value = u32::from(words) * 4;
+    if value <= 0xff {
+        return Operand2::ConstantShift(value as u8, 0);
+    }
+    let pairs = value.trailing_zeros() / 2;
+    Operand2::ConstantShift((value >> (2 * pairs)) as u8, (16 - pairs) as u8)
+}
+
+// Sign extends the bottom bits of an instruction
+fn sign_extend(instr: u16, bits: u32) -> i32 {
+    let unused = 32 - bits;
+    ((u32::from(instr) << unused) as i32) >> unused
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_thumb() {
+        let decoded = |instr| decode(instr).expect("decode failed").instruction;
+
+        // movs r0,#0x2a
+        assert_eq!(
+            decoded(0x202a),
+            processing(ProcessingOpcode::Mov, true, 0, 0, immediate(0x2a))
+        );
+        // lsls r1,r2,#3
+        assert_eq!(
+            decoded(0x00d1),
+            processing(
+                ProcessingOpcode::Mov,
+                true,
+                0,
+                1,
+                Operand2::ShiftedReg(2, Shift::ConstantShift(ShiftType::Lsl, 3))
+            )
+        );
+        // mov r8,r1
+        assert_eq!(
+            decoded(0x4688),
+            processing(ProcessingOpcode::Mov, false, 0, 8, register(1))
+        );
+        // ldr r2,[r1,#8]
+        assert_eq!(
+            decoded(0x688a),
+            transfer(true, TransferSize::Word, 1, 2, TransferOffset::Immediate(8))
+        );
+        // push {r4,lr}
+        assert_eq!(
+            decoded(0xb510),
+            Instruction::BlockTransfer(InstructionBlockTransfer {
+                is_preindexed: true,
+                up_bit: false,
+                writeback: true,
+                load: false,
+                rn: SP,
+                register_list: 0x4010,
+            })
+        );
+        // sub sp,#16
+        assert_eq!(
+            decoded(0xb084),
+            processing(
+                ProcessingOpcode::Sub,
+                false,
+                SP,
+                SP,
+                Operand2::ConstantShift(16, 0)
+            )
+        );
+        // bne .-4
+        assert_eq!(
+            decode(0xd1fc).expect("decode failed"),
+            ConditionalInstruction {
+                cond: ConditionCode::Ne,
+                instruction: branch(ThumbBranchKind::Branch, -8),
+            }
+        );
+        // bl .+0x1000, as a prefix and a suffix
+        assert_eq!(decoded(0xf001), branch(ThumbBranchKind::LinkPrefix, 0x1000));
+        assert_eq!(decoded(0xf800), branch(ThumbBranchKind::LinkSuffix, 0));
+
+        assert_eq!(
+            decode(0xb672).unwrap_err().to_string(),
+            "Unsupported Thumb instruction 0xb672"
+        );
+    }
+}
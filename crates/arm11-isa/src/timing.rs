@@ -0,0 +1,150 @@
+use crate::{constants::PC, types::*};
+
+// Cycles to refill the fetch and decode stages after a branch flushes the pipeline
+pub const FLUSH_PENALTY: u64 = 2;
+
+// The number of cycles an instruction takes to execute, loosely based on the ARM11 timings:
+//
+// Processing               1, or 2 when shifting by a register
+// Multiply                 2, or 3 with accumulate
+// Long multiply            3, or 4 with accumulate
+// Load                     3, as the result isn't available until 2 cycles after it issues
+// Store                    1
+// Block transfer           1 plus 1 per register
+// Branch, bx, coprocessor  1
+// Software interrupt       1
+// VFP load                 3
+// VFP divide               15
+// Other VFP                1
+//
+// Instructions which fail their condition take 1 cycle. Branches, and any other instruction
+// which writes the PC, also pay the FLUSH_PENALTY, which is added separately as it depends on
+// whether the instruction flushed the pipeline.
+//
+pub fn cycles(instr: &ConditionalInstruction, executed: bool) -> u64 {
+    if !executed {
+        return 1;
+    }
+
+    match instr.instruction {
+        Instruction::Processing(InstructionProcessing {
+            operand2: Operand2::ShiftedReg(_, Shift::RegisterShift(_, _)),
+            ..
+        }) => 2,
+        Instruction::Multiply(m) if m.accumulate => 3,
+        Instruction::Multiply(_) => 2,
+        Instruction::MultiplyLong(m) if m.accumulate => 4,
+        Instruction::MultiplyLong(_) => 3,
+        Instruction::Transfer(t) if t.load => 3,
+        Instruction::BlockTransfer(b) => 1 + u64::from(b.register_list.count_ones()),
+        Instruction::Vfp(InstructionVfp::Transfer { load: true, .. }) => 3,
+        Instruction::Vfp(InstructionVfp::Arithmetic {
+            opcode: VfpOpcode::Div,
+            ..
+        }) => 15,
+        _ => 1,
+    }
+}
+
+// Describes the static cost of an instruction for the assembler listing, noting the cases where
+// the cost depends on the program's state when it runs.
+// eg: "3 cycles, 1 if skipped, +2 if it writes pc"
+pub fn annotation(instr: &ConditionalInstruction) -> String {
+    let executed = cycles(instr, true);
+    let mut notes = vec![format!(
+        "{} cycle{}",
+        executed,
+        if executed == 1 { "" } else { "s" }
+    )];
+
+    match instr.instruction {
+        Instruction::Processing(InstructionProcessing {
+            operand2: Operand2::ShiftedReg(_, Shift::RegisterShift(_, _)),
+            ..
+        }) => notes.push(String::from("register shift")),
+        Instruction::Multiply(_) | Instruction::MultiplyLong(_) => {
+            notes.push(String::from("multiply, may terminate early on hardware"))
+        }
+        _ => (),
+    }
+    if instr.cond != ConditionCode::Al {
+        notes.push(String::from("1 if skipped"));
+    }
+    if writes_pc(&instr.instruction) {
+        notes.push(format!("+{} if it writes pc", FLUSH_PENALTY));
+    }
+    notes.join(", ")
+}
+
+// Whether the instruction may write the PC, flushing the pipeline
+fn writes_pc(instr: &Instruction) -> bool {
+    let pc = PC as u8;
+    match instr {
+        Instruction::Branch(_) | Instruction::BranchExchange(_) => true,
+        Instruction::ThumbBranch(b) => b.kind != ThumbBranchKind::LinkPrefix,
+        Instruction::Processing(p) => {
+            p.rd == pc
+                && !matches!(
+                    p.opcode,
+                    ProcessingOpcode::Tst
+                        | ProcessingOpcode::Teq
+                        | ProcessingOpcode::Cmp
+                        | ProcessingOpcode::Cmn
+                )
+        }
+        Instruction::Transfer(t) => t.load && t.rd == pc,
+        Instruction::BlockTransfer(b) => b.load && b.register_list & (1 << pc) != 0,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cycles() {
+        let mla = ConditionalInstruction {
+            cond: ConditionCode::Al,
+            instruction: Instruction::Multiply(InstructionMultiply {
+                accumulate: true,
+                set_cond: false,
+                rd: 0,
+                rn: 1,
+                rs: 2,
+                rm: 3,
+            }),
+        };
+        assert_eq!(cycles(&mla, true), 3);
+        assert_eq!(cycles(&mla, false), 1);
+
+        let ldm = ConditionalInstruction {
+            cond: ConditionCode::Al,
+            instruction: Instruction::BlockTransfer(InstructionBlockTransfer {
+                is_preindexed: false,
+                up_bit: true,
+                writeback: true,
+                load: true,
+                rn: 13,
+                register_list: 0b1111,
+            }),
+        };
+        assert_eq!(cycles(&ldm, true), 5);
+
+        assert_eq!(
+            annotation(&mla),
+            "3 cycles, multiply, may terminate early on hardware"
+        );
+        let bne = ConditionalInstruction {
+            cond: ConditionCode::Ne,
+            instruction: Instruction::Branch(InstructionBranch {
+                link: false,
+                offset: -3,
+            }),
+        };
+        assert_eq!(
+            annotation(&bne),
+            "1 cycle, 1 if skipped, +2 if it writes pc"
+        );
+    }
+}
@@ -0,0 +1,428 @@
+use enum_primitive_derive::Primitive;
+use std::result;
+
+pub use crate::error::ArmError;
+
+pub type Result<T> = result::Result<T, ArmError>;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct InstructionProcessing {
+    pub opcode: ProcessingOpcode,
+    pub set_cond: bool,
+    pub rn: u8,
+    pub rd: u8,
+    pub operand2: Operand2,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct InstructionMultiply {
+    pub accumulate: bool,
+    pub set_cond: bool,
+    pub rd: u8,
+    pub rn: u8,
+    pub rs: u8,
+    pub rm: u8,
+}
+
+// A multiply giving a 64 bit result, split across RdHi and RdLo, i.e. umull, umlal, smull and
+// smlal. With accumulate, the 64 bit value already in RdHi:RdLo is added to the product.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct InstructionMultiplyLong {
+    pub signed: bool,
+    pub accumulate: bool,
+    pub set_cond: bool,
+    pub rd_hi: u8,
+    pub rd_lo: u8,
+    pub rs: u8,
+    pub rm: u8,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct InstructionTransfer {
+    pub is_preindexed: bool,
+    pub up_bit: bool,
+    // Whether a pre-indexed transfer writes the address back to the base register, eg:
+    // ldr r0,[r1,#4]!. Post-indexed transfers always do.
+    pub writeback: bool,
+    pub load: bool,
+    pub size: TransferSize,
+    pub rn: u8,
+    pub rd: u8,
+    pub offset: TransferOffset,
+}
+
+// The size of a single data transfer, and whether loaded values are sign extended. Signed sizes
+// are only valid for loads.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TransferSize {
+    Word,
+    Byte,
+    Halfword,
+    SignedByte,
+    SignedHalfword,
+}
+
+impl TransferSize {
+    // The number of bytes transferred
+    pub fn bytes(self) -> u32 {
+        match self {
+            TransferSize::Word => crate::constants::BYTES_IN_WORD as u32,
+            TransferSize::Halfword | TransferSize::SignedHalfword => 2,
+            TransferSize::Byte | TransferSize::SignedByte => 1,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct InstructionBlockTransfer {
+    pub is_preindexed: bool,
+    pub up_bit: bool,
+    pub writeback: bool,
+    pub load: bool,
+    pub rn: u8,
+    pub register_list: u16,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct InstructionBranch {
+    pub link: bool,
+    pub offset: i32,
+}
+
+// A Thumb branch, whose offset is in bytes from the PC. A Thumb bl is a pair of halfword
+// instructions; the prefix adds the top half of the offset to the PC, leaving the result in LR,
+// and the suffix branches to LR plus the bottom half of the offset, leaving the return address
+// in LR.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct InstructionThumbBranch {
+    pub kind: ThumbBranchKind,
+    pub offset: i32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ThumbBranchKind {
+    Branch,
+    LinkPrefix,
+    LinkSuffix,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct InstructionBranchExchange {
+    pub rm: u8,
+}
+
+// A coprocessor register transfer, i.e. mrc (load, from the coprocessor to an ARM register) or
+// mcr (from an ARM register to the coprocessor)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct InstructionCoprocessor {
+    pub load: bool,
+    pub coprocessor: u8,
+    pub opcode1: u8,
+    pub crn: u8,
+    pub rd: u8,
+    pub crm: u8,
+    pub opcode2: u8,
+}
+
+// A read of the CPSR, or the SPSR of the current mode, into an ARM register, i.e. mrs Rd,cpsr
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct InstructionStatusRead {
+    pub spsr: bool,
+    pub rd: u8,
+}
+
+// A write of a register or an immediate to the CPSR, or the SPSR of the current mode, i.e.
+// msr cpsr_<fields>,<operand>. Only the bytes of the status register selected by the field
+// mask are written; bit 3 selects the flags (bits 31 to 24), bit 2 the status, bit 1 the
+// extension and bit 0 the control byte (bits 7 to 0). The operand is an immediate, or a
+// register with no shift.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct InstructionStatusWrite {
+    pub spsr: bool,
+    pub field_mask: u8,
+    pub operand: Operand2,
+}
+
+// A software interrupt, i.e. swi #<comment>, which asks the environment for a service. The
+// comment field selects the service, and is otherwise ignored by the processor.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct InstructionSoftwareInterrupt {
+    pub comment: u32,
+}
+
+// A single precision VFP instruction, on the registers s0 to s31 of the VFP's register file.
+// Only the subset used by simple compiled floating point code is supported.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum InstructionVfp {
+    // vldr (load) or vstr Sd,[Rn,#+/-<offset * 4>]
+    Transfer {
+        load: bool,
+        up_bit: bool,
+        rn: u8,
+        sd: u8,
+        offset: u8,
+    },
+    // v<opcode>.f32 Sd,Sn,Sm
+    Arithmetic {
+        opcode: VfpOpcode,
+        sd: u8,
+        sn: u8,
+        sm: u8,
+    },
+    // vmov Rt,Sn (to_arm) or vmov Sn,Rt, which copy the bits unchanged
+    Move {
+        to_arm: bool,
+        sn: u8,
+        rt: u8,
+    },
+    // vcvt.s32.f32 Sd,Sm (to_int, rounding towards zero) or vcvt.f32.s32 Sd,Sm
+    Convert {
+        to_int: bool,
+        sd: u8,
+        sm: u8,
+    },
+    // vcmp.f32 Sd,Sm, which sets the flags in the FPSCR
+    Compare {
+        sd: u8,
+        sm: u8,
+    },
+    // vmrs APSR_nzcv,fpscr, which copies the flags in the FPSCR to the CPSR
+    MoveFlags,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum VfpOpcode {
+    Add,
+    Sub,
+    Mul,
+    Div,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Instruction {
+    Processing(InstructionProcessing),
+    Multiply(InstructionMultiply),
+    MultiplyLong(InstructionMultiplyLong),
+    Branch(InstructionBranch),
+    ThumbBranch(InstructionThumbBranch),
+    BranchExchange(InstructionBranchExchange),
+    Transfer(InstructionTransfer),
+    BlockTransfer(InstructionBlockTransfer),
+    Coprocessor(InstructionCoprocessor),
+    Vfp(InstructionVfp),
+    StatusRead(InstructionStatusRead),
+    StatusWrite(InstructionStatusWrite),
+    SoftwareInterrupt(InstructionSoftwareInterrupt),
+    Halt,
+}
+
+// The width of an encoded instruction. ARM instructions are always a word, while Thumb
+// instructions are a halfword.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum InstructionWidth {
+    Halfword,
+    Word,
+}
+
+impl InstructionWidth {
+    pub fn bytes(self) -> u32 {
+        match self {
+            InstructionWidth::Halfword => 2,
+            InstructionWidth::Word => 4,
+        }
+    }
+
+    // How far ahead of an executing instruction the PC is, as the pipeline has fetched the two
+    // instructions after it
+    pub fn pipeline_offset(self) -> u32 {
+        2 * self.bytes()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ConditionalInstruction {
+    pub instruction: Instruction,
+    pub cond: ConditionCode,
+}
+
+impl ConditionalInstruction {
+    // Whether the instruction executes, rather than being skipped, given the CPSR
+    pub fn satisfies(&self, cpsr: StatusRegister) -> bool {
+        cpsr.satisfies(self.cond)
+    }
+}
+
+// The contents of the CPSR or an SPSR, with the flags and the conditions they decide read by
+// name rather than by bit position
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct StatusRegister(pub u32);
+
+impl StatusRegister {
+    pub fn flag(self, flag: CpsrFlag) -> bool {
+        self.0 & 1 << flag as u32 != 0
+    }
+
+    pub fn with_flag(self, flag: CpsrFlag, set: bool) -> Self {
+        if set {
+            StatusRegister(self.0 | 1 << flag as u32)
+        } else {
+            StatusRegister(self.0 & !(1 << flag as u32))
+        }
+    }
+
+    // Whether the flags meet the condition
+    pub fn satisfies(self, cond: ConditionCode) -> bool {
+        let n = self.flag(CpsrFlag::N);
+        let z = self.flag(CpsrFlag::Z);
+        let c = self.flag(CpsrFlag::C);
+        let v = self.flag(CpsrFlag::V);
+
+        match cond {
+            ConditionCode::Eq => z,
+            ConditionCode::Ne => !z,
+            ConditionCode::Cs => c,
+            ConditionCode::Cc => !c,
+            ConditionCode::Mi => n,
+            ConditionCode::Pl => !n,
+            ConditionCode::Vs => v,
+            ConditionCode::Vc => !v,
+            ConditionCode::Hi => c && !z,
+            ConditionCode::Ls => !c || z,
+            ConditionCode::Ge => n == v,
+            ConditionCode::Lt => n != v,
+            ConditionCode::Gt => !z && (n == v),
+            ConditionCode::Le => z || (n != v),
+            ConditionCode::Al => true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Operand2 {
+    ConstantShift(u8, u8),
+    ShiftedReg(u8, Shift),
+}
+
+// The offset of a single data transfer, which is added to or subtracted from the base register.
+// Unlike an Operand2, an immediate offset is a plain 12 bit value rather than a rotated one, and
+// only 8 bits for halfword and signed transfers, whose register offsets can't be shifted.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TransferOffset {
+    Immediate(u16),
+    ShiftedReg(u8, Shift),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Shift {
+    ConstantShift(ShiftType, u8),
+    RegisterShift(ShiftType, u8),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Primitive)]
+pub enum ShiftType {
+    Lsl = 0x0,
+    Lsr = 0x1,
+    Asr = 0x2,
+    Ror = 0x3,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Primitive)]
+pub enum ProcessingOpcode {
+    And = 0x0,
+    Eor = 0x1,
+    Sub = 0x2,
+    Rsb = 0x3,
+    Add = 0x4,
+    Adc = 0x5,
+    Sbc = 0x6,
+    Rsc = 0x7,
+    Tst = 0x8,
+    Teq = 0x9,
+    Cmp = 0xa,
+    Cmn = 0xb,
+    Orr = 0xc,
+    Mov = 0xd,
+    Bic = 0xe,
+    Mvn = 0xf,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Primitive)]
+pub enum ConditionCode {
+    Eq = 0x0,
+    Ne = 0x1,
+    Cs = 0x2,
+    Cc = 0x3,
+    Mi = 0x4,
+    Pl = 0x5,
+    Vs = 0x6,
+    Vc = 0x7,
+    Hi = 0x8,
+    Ls = 0x9,
+    Ge = 0xa,
+    Lt = 0xb,
+    Gt = 0xc,
+    Le = 0xd,
+    Al = 0xe,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CpsrFlag {
+    // Set in Thumb state
+    T = 5,
+    // Set to disable FIQs and IRQs
+    F = 6,
+    I = 7,
+    V = 28,
+    C = 29,
+    Z = 30,
+    N = 31,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_conditions() {
+        // For each condition, the NZCV values it passes for, as a mask with bit NZCV set when
+        // it does, eg: bit 0b0110 for Z and C set
+        let truth_table = [
+            (ConditionCode::Eq, 0xf0f0),
+            (ConditionCode::Ne, 0x0f0f),
+            (ConditionCode::Cs, 0xcccc),
+            (ConditionCode::Cc, 0x3333),
+            (ConditionCode::Mi, 0xff00),
+            (ConditionCode::Pl, 0x00ff),
+            (ConditionCode::Vs, 0xaaaa),
+            (ConditionCode::Vc, 0x5555),
+            (ConditionCode::Hi, 0x0c0c),
+            (ConditionCode::Ls, 0xf3f3),
+            (ConditionCode::Ge, 0xaa55),
+            (ConditionCode::Lt, 0x55aa),
+            (ConditionCode::Gt, 0x0a05),
+            (ConditionCode::Le, 0xf5fa),
+            (ConditionCode::Al, 0xffff),
+        ];
+        for (cond, passes) in truth_table {
+            for nzcv in 0..16u32 {
+                // The other bits of the register don't affect the condition
+                let cpsr = StatusRegister(nzcv << CpsrFlag::V as u32 | 0x1f);
+                assert_eq!(
+                    cpsr.satisfies(cond),
+                    passes & 1 << nzcv != 0,
+                    "{:?} with NZCV {:04b}",
+                    cond,
+                    nzcv
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_status_register_flags() {
+        let cpsr = StatusRegister(0).with_flag(CpsrFlag::C, true);
+        assert_eq!(cpsr, StatusRegister(0x20000000));
+        assert!(cpsr.flag(CpsrFlag::C) && !cpsr.flag(CpsrFlag::Z));
+        assert_eq!(cpsr.with_flag(CpsrFlag::C, false), StatusRegister(0));
+    }
+}
@@ -0,0 +1,30 @@
+fix_from_int:
+mov r0,r0,lsl #16
+mov r15,r14
+fix_to_int:
+mov r0,r0,asr #16
+mov r15,r14
+fix_mul:
+smull r2,r3,r0,r1
+mov r0,r2,lsr #16
+orr r0,r0,r3,lsl #16
+mov r15,r14
+fix_div:
+eor r12,r0,r1
+cmp r0,#0
+rsblt r0,r0,#0
+cmp r1,#0
+rsblt r1,r1,#0
+mov r2,r0,lsr #16
+mov r3,r0,lsl #16
+mov r0,#1
+fix_div_loop:
+movs r3,r3,lsl #1
+adc r2,r2,r2
+cmp r2,r1
+subhs r2,r2,r1
+adcs r0,r0,r0
+bcc fix_div_loop
+cmp r12,#0
+rsblt r0,r0,#0
+mov r15,r14
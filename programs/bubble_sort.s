@@ -0,0 +1,21 @@
+ldr r0,=array
+mov r1,#6
+outer:
+subs r1,r1,#1
+beq done
+mov r2,r0
+mov r3,r1
+inner:
+ldr r4,[r2]
+ldr r5,[r2,#4]
+cmp r4,r5
+strgt r5,[r2]
+strgt r4,[r2,#4]
+add r2,r2,#4
+subs r3,r3,#1
+bne inner
+b outer
+done:
+andeq r0,r0,r0
+array:
+.word 5, 3, 9, 1, 7, 2
@@ -0,0 +1,12 @@
+mov r0,#1
+mov r1,#5
+loop:
+mul r2,r0,r1
+mov r0,r2
+subs r1,r1,#1
+bne loop
+ldr r3,=result
+str r0,[r3]
+andeq r0,r0,r0
+result:
+.word 0
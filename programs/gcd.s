@@ -0,0 +1,8 @@
+ldr r0,=1071
+ldr r1,=462
+loop:
+cmp r0,r1
+subgt r0,r0,r1
+sublt r1,r1,r0
+bne loop
+andeq r0,r0,r0
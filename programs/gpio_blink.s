@@ -0,0 +1,22 @@
+ldr r0,=0x20200004
+mov r1,#1
+lsl r1,#18
+str r1,[r0]
+ldr r2,=0x2020001c
+ldr r3,=0x20200028
+mov r4,#0x10000
+mov r5,#3
+blink:
+str r4,[r2]
+mov r6,#0x10
+on:
+subs r6,r6,#1
+bne on
+str r4,[r3]
+mov r6,#0x10
+off:
+subs r6,r6,#1
+bne off
+subs r5,r5,#1
+bne blink
+andeq r0,r0,r0
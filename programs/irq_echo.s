@@ -0,0 +1,28 @@
+b start
+b start
+b start
+b start
+b start
+b start
+b irq
+start:
+mov r0,#0x12
+msr cpsr_c,r0
+mov r13,#0x1000
+mov r0,#0x1f
+msr cpsr_c,r0
+ldr r0,=0x20201000
+mov r1,#0x10
+str r1,[r0,#0x38]
+wait:
+cmp r2,#10
+bne wait
+andeq r0,r0,r0
+irq:
+push {r4}
+ldr r4,=0x20201000
+ldr r2,[r4]
+str r2,[r4]
+add r3,r3,#1
+pop {r4}
+subs r15,r14,#4
@@ -0,0 +1,12 @@
+ldr r0,=source
+ldr r1,=destination
+copy:
+ldrb r2,[r0],#1
+strb r2,[r1],#1
+cmp r2,#0
+bne copy
+andeq r0,r0,r0
+source:
+.ascii "Hello, ARM!\0"
+destination:
+.skip 12
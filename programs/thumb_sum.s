@@ -0,0 +1,28 @@
+mov r13,#0x1000
+ldr r0,=main+1
+bx r0
+.thumb
+main:
+ldr r1,=array
+movs r2,#5
+movs r0,#0
+loop:
+bl accumulate
+subs r2,r2,#1
+bne loop
+ldr r3,=result
+str r0,[r3]
+ldr r3,=done
+bx r3
+accumulate:
+push {r4,r14}
+ldmia r1!,{r4}
+adds r0,r0,r4
+pop {r4,r15}
+.arm
+done:
+andeq r0,r0,r0
+array:
+.word 10, 20, 30, 40, 50
+result:
+.word 0
@@ -0,0 +1,10 @@
+ldr r0,=0x20201000
+wait:
+ldr r1,[r0,#0x18]
+tst r1,#0x10
+bne wait
+ldr r2,[r0]
+str r2,[r0]
+cmp r2,#10
+bne wait
+andeq r0,r0,r0
@@ -0,0 +1,11 @@
+use std::process;
+
+use arm11::cli;
+
+// Every tool in one binary, as a subcommand, eg: arm11 assemble prog.s prog.bin
+fn main() {
+    let matches = cli::command().get_matches();
+    if let Some((name, matches)) = matches.subcommand() {
+        process::exit(cli::run(name, matches));
+    }
+}
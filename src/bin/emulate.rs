@@ -1,22 +1,8 @@
-use std::{env, process};
+use std::process;
 
-use arm11::emulate;
+use arm11::cli;
 
 fn main() {
-    let args: Vec<String> = env::args().collect();
-
-    match args.len() {
-        2 => {
-            let filename = &args[1];
-            if let Err(e) = emulate::run(filename) {
-                eprintln!("Error: {}", e);
-                process::exit(1);
-            }
-        }
-
-        _ => {
-            println!("Usage: emulate [binary]");
-            process::exit(1);
-        }
-    }
+    let matches = cli::emulate_command().get_matches();
+    process::exit(cli::run("emulate", &matches));
 }
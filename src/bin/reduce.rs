@@ -0,0 +1,8 @@
+use std::process;
+
+use arm11::cli;
+
+fn main() {
+    let matches = cli::reduce_command().get_matches();
+    process::exit(cli::run("reduce", &matches));
+}
@@ -0,0 +1,8 @@
+use std::process;
+
+use arm11::cli;
+
+fn main() {
+    let matches = cli::stats_command().get_matches();
+    process::exit(cli::run("stats", &matches));
+}
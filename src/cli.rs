@@ -0,0 +1,803 @@
+use std::{fs, panic, path::Path, str::FromStr, time::Duration};
+
+use clap::{Arg, ArgAction, ArgGroup, ArgMatches, Command};
+
+use crate::{
+    assemble, difftest, disassemble, emulate, isa::types::Result, reduce, ArmError, SymbolTable,
+};
+
+// The command line of the tools, shared by the arm11 binary, which has a subcommand for each tool,
+// and the binaries for each tool on its own. Options are parsed into the options of the
+// assembler and emulator, so every way in takes the same options.
+//
+// eg: arm11 assemble prog.s prog.bin --listing prog.lst
+//     arm11 emulate --ram 0x8000:0x8000 prog.bin
+//     arm11 debug prog.bin
+//     arm11 stats src/ -r
+//
+pub fn command() -> Command {
+    Command::new("arm11")
+        .about("An assembler, emulator and disassembler for a subset of the ARM11 instruction set")
+        .subcommand_required(true)
+        .arg_required_else_help(true)
+        .subcommands([
+            assemble_command(),
+            emulate_command(),
+            disassemble_command(),
+            debug_command(),
+            stats_command(),
+            vectors_command(),
+            reduce_command(),
+            difftest_command(),
+        ])
+}
+
+pub fn assemble_command() -> Command {
+    Command::new("assemble")
+        .about("Assemble source files into a binary")
+        .arg(
+            Arg::new("source")
+                .required(true)
+                .num_args(1..)
+                .help("Source files, or - for stdin, then the binary if there's no --output"),
+        )
+        .arg(
+            value("output", "file", "Binary to write, or - for stdout")
+                .short('o')
+                .allow_hyphen_values(true),
+        )
+        .arg(flag(
+            "size-report",
+            "Print the size of each section and the largest symbols",
+        ))
+        .arg(value(
+            "listing",
+            "file",
+            "Write a listing of the source and its encoding",
+        ))
+        .arg(flag(
+            "timing",
+            "Annotate the listing with the cycles each instruction takes",
+        ))
+        .arg(value("symbols", "file", "Write the symbol file"))
+        .arg(
+            value("pad-to", "n[K|M]", "Pad the binary to the given size")
+                .value_parser(emulate::parse_size),
+        )
+        .arg(
+            value("fill", "byte", "Byte to pad the binary with").value_parser(assemble::parse_fill),
+        )
+        .arg(
+            value("emit", "code|data", "Whether the source is code or data")
+                .value_parser(parsed::<assemble::Emit>),
+        )
+        .arg(
+            value(
+                "format",
+                "binary|ihex|hexdump",
+                "Write the image as a raw binary, Intel HEX or a hex dump of its words",
+            )
+            .value_parser(parsed::<assemble::Format>),
+        )
+        .arg(
+            value(
+                "max-errors",
+                "n",
+                "Show at most n distinct errors, or all of them if n is 0 (default 20)",
+            )
+            .value_parser(clap::value_parser!(usize)),
+        )
+}
+
+pub fn emulate_command() -> Command {
+    emulator_args(Command::new("emulate"))
+        .about("Run a binary, or replay a recorded run")
+        .mut_arg("binary", |arg| {
+            arg.required_unless_present_any(["replay", "restore-state"])
+                .conflicts_with("replay")
+        })
+        .arg(flag(
+            "debug",
+            "Run under the debugger, with commands read from stdin",
+        ))
+        .arg(value(
+            "replay",
+            "file.rr",
+            "Replay a recorded run in place of a binary",
+        ))
+        .arg(
+            value("replay-until", "n", "Stop a replay after n instructions")
+                .requires("replay")
+                .value_parser(clap::value_parser!(u64)),
+        )
+}
+
+pub fn debug_command() -> Command {
+    emulator_args(Command::new("debug"))
+        .about("Run a binary under the debugger")
+        .mut_arg("binary", |arg| arg.required_unless_present("restore-state"))
+}
+
+pub fn disassemble_command() -> Command {
+    Command::new("disassemble")
+        .about("Disassemble a binary")
+        .arg(Arg::new("binary").required(true))
+        .arg(value(
+            "symbols",
+            "file",
+            "Symbol file naming the addresses, as written by assemble",
+        ))
+}
+
+pub fn stats_command() -> Command {
+    Command::new("stats")
+        .about("Report instruction usage statistics for sources")
+        .arg(
+            Arg::new("path")
+                .required(true)
+                .help("Source file, or directory of .s files"),
+        )
+        .arg(flag("recursive", "Include the sources in subdirectories").short('r'))
+}
+
+pub fn vectors_command() -> Command {
+    Command::new("vectors")
+        .about("Run a directory of single step test vectors through the executor")
+        .arg(Arg::new("directory").required(true))
+}
+
+pub fn reduce_command() -> Command {
+    Command::new("reduce")
+        .about("Reduce a source which makes the emulator fail to the smallest which still does")
+        .arg(Arg::new("source").required(true))
+        .arg(Arg::new("output").help("File to write the reduced source to, rather than stdout"))
+        .arg(
+            value(
+                "max-steps",
+                "n",
+                "Steps to run each candidate for, before treating it as not failing",
+            )
+            .value_parser(clap::value_parser!(u64))
+            .default_value("1000000"),
+        )
+}
+
+pub fn difftest_command() -> Command {
+    Command::new("difftest")
+        .about("Trace a binary and find where a reference emulator's run differs")
+        .arg(Arg::new("binary").required(true))
+        .arg(
+            value("max-steps", "n", "Instructions to trace")
+                .value_parser(clap::value_parser!(u64))
+                .default_value("1000000"),
+        )
+        .arg(value("write-trace", "file", "Write this emulator's trace"))
+        .arg(value(
+            "reference",
+            "command",
+            "Command given the binary's filename, writing its trace to stdout",
+        ))
+        .arg(value(
+            "reference-trace",
+            "file",
+            "Trace written earlier by the reference",
+        ))
+        .group(
+            ArgGroup::new("reference-run")
+                .args(["reference", "reference-trace"])
+                .multiple(false),
+        )
+        .group(
+            ArgGroup::new("traces")
+                .args(["reference", "reference-trace", "write-trace"])
+                .required(true)
+                .multiple(true),
+        )
+}
+
+// The options shared by the emulate and debug subcommands
+fn emulator_args(command: Command) -> Command {
+    command
+        .arg(Arg::new("binary").help("Binary to run, or - for stdin"))
+        .arg(
+            value("rom", "base:size", "Read-only region holding the binary")
+                .value_parser(parsed::<emulate::Region>),
+        )
+        .arg(value("ram", "base:size", "Region of RAM").value_parser(parsed::<emulate::Region>))
+        .arg(value("memory-size", "n[K|M]", "Size of RAM").value_parser(emulate::parse_size))
+        .arg(
+            value("mirror", "base:size=target", "Region aliasing another")
+                .action(ArgAction::Append)
+                .value_parser(parsed::<emulate::Mirror>),
+        )
+        .arg(flag(
+            "strict-memory",
+            "Stop at unmapped and unaligned accesses",
+        ))
+        .arg(
+            value(
+                "load",
+                "file@address",
+                "Load a file into memory before running",
+            )
+            .action(ArgAction::Append)
+            .value_parser(parsed::<emulate::Load>),
+        )
+        .arg(
+            value("reg", "register=value", "Set a register before running")
+                .action(ArgAction::Append)
+                .value_parser(emulate::parse_register_value),
+        )
+        .arg(
+            value("framebuffer", "base:widthxheight", "Framebuffer in memory")
+                .value_parser(parsed::<emulate::Framebuffer>),
+        )
+        .arg(value(
+            "ppm",
+            "file",
+            "Write the framebuffer as a PPM image at exit",
+        ))
+        .arg(
+            value("led", "pin=n", "GPIO pin with an LED attached")
+                .action(ArgAction::Append)
+                .value_parser(emulate::parse_led_pin),
+        )
+        .arg(value("vcd", "file", "Write a waveform of the GPIO pins"))
+        .arg(
+            value(
+                "decode",
+                "protocol:pins",
+                "Decode a protocol on the GPIO pins at exit",
+            )
+            .action(ArgAction::Append)
+            .value_parser(parsed::<emulate::Decoder>),
+        )
+        .arg(
+            value("gpio-base", "base|pi1|pi2", "Address of the GPIO registers")
+                .value_parser(emulate::parse_gpio_base),
+        )
+        .arg(
+            value("uart", "base", "UART connected to stdin and stdout")
+                .value_parser(emulate::parse_uart_base),
+        )
+        .arg(value("uart-baud", "rate", "Rate the UART receives at").value_parser(parse_baud))
+        .arg(flag(
+            "extended-isa",
+            "Allow instructions beyond the base ISA",
+        ))
+        .arg(flag("vfp", "Allow the single precision VFP instructions"))
+        .arg(
+            value(
+                "cpu-id",
+                "id",
+                "CPU ID reported by CP15, implies --extended-isa",
+            )
+            .value_parser(emulate::parse_cpu_id),
+        )
+        .arg(flag(
+            "peripheral-summary",
+            "Summarise the peripherals at exit",
+        ))
+        .arg(flag("trace", "Print every executed instruction"))
+        .arg(
+            value(
+                "max-steps",
+                "n",
+                "Stop with an error if the program runs n instructions",
+            )
+            .value_parser(clap::value_parser!(u64)),
+        )
+        .arg(
+            value(
+                "timeout",
+                "seconds",
+                "Stop with an error if the program runs this long",
+            )
+            .value_parser(parse_seconds),
+        )
+        .arg(
+            value(
+                "watch",
+                "start[..end][:r|w|rw]",
+                "Report loads and stores to memory",
+            )
+            .action(ArgAction::Append)
+            .value_parser(parsed::<emulate::Watchpoint>),
+        )
+        .arg(flag("loops", "Report the loops found at exit"))
+        .arg(
+            value("profile", "text|json", "Write an execution profile at exit")
+                .value_parser(parsed::<emulate::OutputFormat>),
+        )
+        .arg(value(
+            "coverage",
+            "listing",
+            "Annotate the assembler's listing with the instructions reached at exit",
+        ))
+        .arg(value(
+            "symbols",
+            "file",
+            "Symbol file naming addresses in reports and the debugger",
+        ))
+        .arg(value(
+            "record",
+            "file.rr",
+            "Record the run so it can be replayed",
+        ))
+        .arg(value(
+            "save-state",
+            "file",
+            "Save a snapshot when the run stops",
+        ))
+        .arg(value("restore-state", "file", "Start from a snapshot"))
+        .arg(
+            value("output", "text|json", "Format of the final state")
+                .value_parser(parsed::<emulate::OutputFormat>),
+        )
+        .arg(flag(
+            "memory-ranges",
+            "Show consecutive non-zero words as ranges",
+        ))
+        .arg(
+            value(
+                "on-halt",
+                "dump=regs+mem[start..end]",
+                "Parts of the state to write",
+            )
+            .value_parser(parsed::<emulate::Dump>),
+        )
+        .arg(
+            value(
+                "abi",
+                "aapcs",
+                "Group the registers by a calling convention",
+            )
+            .value_parser(parsed::<emulate::Abi>),
+        )
+        .arg(value(
+            "expect-state",
+            "state.json",
+            "Check the final state against a JSON file",
+        ))
+}
+
+// Runs the tool with the arguments matched by its command, returning the code to exit with
+pub fn run(name: &str, matches: &ArgMatches) -> i32 {
+    let result = match name {
+        "assemble" => run_assemble(matches).map(|_| 0),
+        "emulate" | "debug" => run_emulate(name, matches),
+        "disassemble" => run_disassemble(matches).map(|_| 0),
+        "stats" => run_stats(matches).map(|_| 0),
+        "vectors" => run_vectors(matches),
+        "reduce" => run_reduce(matches).map(|_| 0),
+        // Exit status 1 is kept for a divergence, so errors exit with 2
+        "difftest" => Ok(run_difftest(matches).unwrap_or_else(|e| {
+            eprintln!("Error: {}", e);
+            2
+        })),
+        _ => unreachable!("Unknown subcommand {}", name),
+    };
+    result.unwrap_or_else(|e| {
+        eprintln!("Error: {}", e);
+        1
+    })
+}
+
+fn run_assemble(matches: &ArgMatches) -> Result<()> {
+    let (sources, output) = assemble_files(matches)?;
+    assemble::run(&sources, &output, &assemble_options(matches))
+}
+
+// The sources and binary named by the arguments. Without --output, the last file is the binary,
+// so `assemble prog.s prog.bin` still works. A binary which is one of the sources, or looks like
+// one, is refused, so that `assemble a.s b.s` doesn't overwrite b.s.
+fn assemble_files(matches: &ArgMatches) -> Result<(Vec<String>, String)> {
+    let mut sources = many::<String>(matches, "source");
+    let output = match matches.get_one::<String>("output") {
+        Some(output) => output.clone(),
+        None if sources.len() > 1 => sources.pop().expect("no sources"),
+        None => {
+            return Err("no binary to write, give it after the sources or with --output".into())
+        }
+    };
+    if output.ends_with(".s") || sources.contains(&output) {
+        return Err(format!(
+            "'{}' is a source, give the binary to write after the sources or with --output",
+            output
+        )
+        .into());
+    }
+    Ok((sources, output))
+}
+
+// The assembler's options from the arguments matched by the assemble command
+pub fn assemble_options(matches: &ArgMatches) -> assemble::Options {
+    assemble::Options {
+        size_report: matches.get_flag("size-report"),
+        listing: matches.get_one::<String>("listing").cloned(),
+        timing: matches.get_flag("timing"),
+        symbol_map: matches.get_one::<String>("symbols").cloned(),
+        pad_to: matches.get_one::<u32>("pad-to").copied(),
+        fill: matches.get_one::<u8>("fill").copied().unwrap_or_default(),
+        emit: matches.get_one("emit").copied().unwrap_or_default(),
+        format: matches.get_one("format").copied().unwrap_or_default(),
+        max_errors: matches.get_one("max-errors").copied(),
+    }
+}
+
+fn run_emulate(name: &str, matches: &ArgMatches) -> Result<i32> {
+    let mut options = emulate_options(name, matches);
+    // A replay takes its binary from the recording, and a restored run its memory from the
+    // snapshot, rather than a positional argument
+    let binary = matches.get_one::<String>("binary");
+    let result = match matches.try_get_one::<String>("replay").ok().flatten() {
+        Some(recording) => {
+            options.run_until = matches.get_one("replay-until").copied();
+            emulate::replay(recording, &options)?
+        }
+        None => match binary {
+            Some(binary) => emulate::run(binary, &options)?,
+            None => emulate::resume(&options)?,
+        },
+    };
+    // A program which exited with a syscall passes its exit code on
+    Ok(result.exit_code.map_or(0, |code| code as i32))
+}
+
+// The emulator's options from the arguments matched by the emulate or debug command
+pub fn emulate_options(name: &str, matches: &ArgMatches) -> emulate::Options {
+    let mut options = emulate::Options::default();
+    if let Some(&rom) = matches.get_one("rom") {
+        options.memory_map.rom = Some(rom);
+    }
+    if let Some(&ram) = matches.get_one("ram") {
+        options.memory_map.ram = ram;
+    }
+    if let Some(&size) = matches.get_one("memory-size") {
+        options.memory_map.ram.size = size;
+    }
+    options.memory_map.mirrors = many(matches, "mirror");
+    options.memory_map.framebuffer = matches.get_one("framebuffer").copied();
+    if let Some(&base) = matches.get_one("gpio-base") {
+        options.memory_map.gpio_base = base;
+    }
+    options.ppm = matches.get_one::<String>("ppm").cloned();
+    options.leds = many(matches, "led");
+    options.vcd = matches.get_one::<String>("vcd").cloned();
+    options.decoders = many(matches, "decode");
+    options.uart = matches.get_one("uart").copied();
+    options.uart_baud = matches.get_one("uart-baud").copied();
+    options.cpu_id = matches.get_one("cpu-id").copied();
+    options.extended_isa = matches.get_flag("extended-isa") || options.cpu_id.is_some();
+    options.vfp = matches.get_flag("vfp");
+    options.strict_memory = matches.get_flag("strict-memory");
+    options.loads = many(matches, "load");
+    options.registers = many(matches, "reg");
+    options.peripheral_summary = matches.get_flag("peripheral-summary");
+    options.trace = matches.get_flag("trace");
+    options.max_steps = matches.get_one("max-steps").copied();
+    options.timeout = matches.get_one("timeout").copied();
+    options.watchpoints = many(matches, "watch");
+    options.loops = matches.get_flag("loops");
+    options.profile = matches.get_one("profile").copied();
+    options.coverage = matches.get_one::<String>("coverage").cloned();
+    options.symbols = matches.get_one::<String>("symbols").cloned();
+    options.record = matches.get_one::<String>("record").cloned();
+    options.save_state = matches.get_one::<String>("save-state").cloned();
+    options.restore_state = matches.get_one::<String>("restore-state").cloned();
+    options.output = matches.get_one("output").copied().unwrap_or_default();
+    options.memory_ranges = matches.get_flag("memory-ranges");
+    options.on_halt = matches.get_one::<emulate::Dump>("on-halt").cloned();
+    options.abi = matches.get_one("abi").copied();
+    options.expect_state = matches.get_one::<String>("expect-state").cloned();
+    options.debug = name == "debug" || matches.get_flag("debug");
+    options
+}
+
+fn run_disassemble(matches: &ArgMatches) -> Result<()> {
+    let symbol_table = match matches.get_one::<String>("symbols") {
+        Some(filename) => assemble::SymbolFile::read(filename)?.symbol_table,
+        None => SymbolTable::new(),
+    };
+    let bytes = fs::read(string(matches, "binary"))?;
+    print!("{}", disassemble::disassemble(&bytes, &symbol_table));
+    Ok(())
+}
+
+fn run_stats(matches: &ArgMatches) -> Result<()> {
+    let mut stats = assemble::Stats::new();
+    collect_sources(
+        Path::new(&string(matches, "path")),
+        matches.get_flag("recursive"),
+        &mut stats,
+    )?;
+    print!("{}", stats);
+    Ok(())
+}
+
+// Adds every .s file at the given path to the statistics, descending into subdirectories
+// if recursive is set.
+fn collect_sources(path: &Path, recursive: bool, stats: &mut assemble::Stats) -> Result<()> {
+    if path.is_file() {
+        stats.add_source(&path.display().to_string(), &fs::read_to_string(path)?);
+        return Ok(());
+    }
+
+    let mut entries: Vec<_> = fs::read_dir(path)?
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<std::result::Result<_, _>>()?;
+    entries.sort();
+
+    for entry in entries {
+        if entry.is_dir() {
+            if recursive {
+                collect_sources(&entry, recursive, stats)?;
+            }
+        } else if entry.extension().is_some_and(|ext| ext == "s") {
+            collect_sources(&entry, recursive, stats)?;
+        }
+    }
+
+    Ok(())
+}
+
+// Exits with status 1 if any vector failed
+fn run_vectors(matches: &ArgMatches) -> Result<i32> {
+    let report = emulate::VectorReport::run_dir(Path::new(&string(matches, "directory")))?;
+    print!("{}", report);
+    Ok(if report.failures.is_empty() { 0 } else { 1 })
+}
+
+fn run_reduce(matches: &ArgMatches) -> Result<()> {
+    // Candidates which panic are expected, so don't print each panic
+    panic::set_hook(Box::new(|_| {}));
+
+    let source = fs::read_to_string(string(matches, "source"))?;
+    let reduced = reduce::reduce(&source, *matches.get_one("max-steps").unwrap())?;
+    match matches.get_one::<String>("output") {
+        Some(output) => fs::write(output, reduced)?,
+        None => print!("{}", reduced),
+    }
+    Ok(())
+}
+
+// Exits with status 1 if the runs diverge
+fn run_difftest(matches: &ArgMatches) -> Result<i32> {
+    let binary = string(matches, "binary");
+    let bytes = fs::read(&binary)?;
+    let ours = difftest::trace(bytes.clone(), *matches.get_one("max-steps").unwrap())?;
+    if let Some(filename) = matches.get_one::<String>("write-trace") {
+        fs::write(filename, ours.to_string())?;
+    }
+    let theirs = if let Some(command) = matches.get_one::<String>("reference") {
+        difftest::reference_trace(command, Path::new(&binary))?
+    } else if let Some(filename) = matches.get_one::<String>("reference-trace") {
+        fs::read_to_string(filename)?
+            .parse()
+            .map_err(ArmError::parse)?
+    } else {
+        return Ok(0);
+    };
+    match difftest::compare(&ours, &theirs, &bytes) {
+        Some(divergence) => {
+            println!("{}", divergence);
+            Ok(1)
+        }
+        None => {
+            println!("No divergence");
+            Ok(0)
+        }
+    }
+}
+
+// A flag taking no value
+fn flag(name: &'static str, help: &'static str) -> Arg {
+    Arg::new(name)
+        .long(name)
+        .action(ArgAction::SetTrue)
+        .help(help)
+}
+
+// An option taking a value, shown in the help as the given placeholder
+fn value(name: &'static str, placeholder: &'static str, help: &'static str) -> Arg {
+    Arg::new(name).long(name).value_name(placeholder).help(help)
+}
+
+// Parses a value through its FromStr, for options whose types implement it
+fn parsed<T: FromStr<Err = String>>(s: &str) -> std::result::Result<T, String> {
+    s.parse()
+}
+
+fn parse_baud(s: &str) -> std::result::Result<u32, String> {
+    s.parse()
+        .ok()
+        .filter(|&baud| baud > 0)
+        .ok_or_else(|| format!("Invalid baud rate '{}'", s))
+}
+
+// Parses a duration given in seconds, which may be fractional, eg: 0.5
+fn parse_seconds(s: &str) -> std::result::Result<Duration, String> {
+    s.parse()
+        .ok()
+        .and_then(|seconds| Duration::try_from_secs_f64(seconds).ok())
+        .filter(|duration| !duration.is_zero())
+        .ok_or_else(|| format!("Invalid number of seconds '{}'", s))
+}
+
+// A required positional argument
+fn string(matches: &ArgMatches, name: &str) -> String {
+    matches.get_one::<String>(name).cloned().unwrap_or_default()
+}
+
+// Every value given for an option which can be repeated
+fn many<T: Clone + Send + Sync + 'static>(matches: &ArgMatches, name: &str) -> Vec<T> {
+    matches
+        .get_many(name)
+        .map_or_else(Vec::new, |values| values.cloned().collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cli() {
+        command().debug_assert();
+
+        let matches = command()
+            .try_get_matches_from([
+                "arm11",
+                "emulate",
+                "--ram",
+                "0x8000:0x8000",
+                "--memory-size",
+                "4K",
+                "--led",
+                "pin=16",
+                "--led",
+                "pin=17",
+                "--cpu-id",
+                "0x410fb767",
+                "--timeout",
+                "0.5",
+                "--gpio-base",
+                "pi2",
+                "--load",
+                "data.bin@0x8000",
+                "--reg",
+                "r0=0x42",
+                "--reg",
+                "sp=0xff00",
+                "prog.bin",
+            ])
+            .expect("match failed");
+        let (name, matches) = matches.subcommand().expect("no subcommand");
+        let options = emulate_options(name, matches);
+        assert_eq!(options.memory_map.ram, emulate::Region::new(0x8000, 0x1000));
+        assert_eq!(options.memory_map.gpio_base, emulate::PI2_GPIO_BASE);
+        assert_eq!(
+            options.loads,
+            vec![emulate::Load {
+                path: String::from("data.bin"),
+                address: 0x8000
+            }]
+        );
+        assert_eq!(
+            options.registers,
+            vec![
+                (emulate::Register::R0, 0x42),
+                (emulate::Register::Sp, 0xff00)
+            ]
+        );
+        assert_eq!(options.leds, vec![16, 17]);
+        assert!(options.extended_isa);
+        assert!(!options.debug);
+        assert_eq!(options.timeout, Some(Duration::from_millis(500)));
+
+        let matches = debug_command()
+            .try_get_matches_from(["debug", "--vfp", "prog.bin"])
+            .expect("match failed");
+        let options = emulate_options("debug", &matches);
+        assert!(options.debug && options.vfp);
+
+        let matches = assemble_command()
+            .try_get_matches_from([
+                "assemble",
+                "prog.s",
+                "-",
+                "--fill",
+                "0xff",
+                "--emit",
+                "data",
+                "--max-errors",
+                "0",
+                "--format",
+                "ihex",
+            ])
+            .expect("match failed");
+        let options = assemble_options(&matches);
+        assert_eq!((options.fill, options.emit), (0xff, assemble::Emit::Data));
+        assert_eq!(options.max_errors, Some(0));
+        assert_eq!(options.format, assemble::Format::Ihex);
+        let files = assemble_files(&matches).expect("assemble_files failed");
+        assert_eq!(files, (vec![String::from("prog.s")], String::from("-")));
+
+        // Several sources are linked into the binary given last, or by --output
+        let matches = assemble_command()
+            .try_get_matches_from(["assemble", "a.s", "b.s", "-o", "out.bin"])
+            .expect("match failed");
+        let files = assemble_files(&matches).expect("assemble_files failed");
+        assert_eq!(
+            files,
+            (
+                vec![String::from("a.s"), String::from("b.s")],
+                String::from("out.bin")
+            )
+        );
+        let matches = assemble_command()
+            .try_get_matches_from(["assemble", "a.s"])
+            .expect("match failed");
+        assert!(assemble_files(&matches).is_err());
+
+        // A source is never taken as the binary, so it can't be overwritten
+        for args in [
+            &["assemble", "a.s", "b.s"][..],
+            &["assemble", "a.s", "b.s", "-o", "b.s"],
+            &["assemble", "a", "b", "a"],
+        ] {
+            let matches = assemble_command()
+                .try_get_matches_from(args)
+                .expect("match failed");
+            assert!(assemble_files(&matches).is_err(), "{:?}", args);
+        }
+
+        // Values are checked as they are parsed, and a replay takes the place of the binary
+        assert!(emulate_command()
+            .try_get_matches_from(["emulate", "--rom", "bad", "prog.bin"])
+            .is_err());
+        assert!(emulate_command()
+            .try_get_matches_from(["emulate", "--timeout", "-1", "prog.bin"])
+            .is_err());
+        assert!(emulate_command()
+            .try_get_matches_from(["emulate", "--replay", "run.rr", "prog.bin"])
+            .is_err());
+        assert!(emulate_command()
+            .try_get_matches_from(["emulate", "--replay", "run.rr"])
+            .is_ok());
+        assert!(emulate_command()
+            .try_get_matches_from(["emulate", "--restore-state", "run.state"])
+            .is_ok());
+        assert!(emulate_command().try_get_matches_from(["emulate"]).is_err());
+        assert!(debug_command().try_get_matches_from(["debug"]).is_err());
+
+        // Every tool is a subcommand, and difftest needs a trace to write or compare with
+        assert!(command()
+            .try_get_matches_from(["arm11", "stats", "src/", "-r"])
+            .is_ok());
+        assert!(stats_command()
+            .try_get_matches_from(["stats", "a.s", "-x"])
+            .is_err());
+        assert!(reduce_command()
+            .try_get_matches_from(["reduce", "--max-steps", "x", "a.s"])
+            .is_err());
+        assert!(difftest_command()
+            .try_get_matches_from(["difftest", "prog.bin"])
+            .is_err());
+        assert!(difftest_command()
+            .try_get_matches_from([
+                "difftest",
+                "--reference",
+                "qemu",
+                "--reference-trace",
+                "t",
+                "prog.bin"
+            ])
+            .is_err());
+        assert!(difftest_command()
+            .try_get_matches_from(["difftest", "--write-trace", "t", "--reference", "qemu", "p"])
+            .is_ok());
+        assert!(debug_command()
+            .try_get_matches_from(["debug", "--restore-state", "run.state"])
+            .is_ok());
+    }
+}
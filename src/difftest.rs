@@ -0,0 +1,362 @@
+use std::{fmt, io, path::Path, process::Command, str::FromStr};
+
+use crate::{
+    emulate::{EmulatorState, Register},
+    isa::{decode, disassemble::disassemble_instruction, parse::parse_number, thumb, types::*},
+};
+
+// The values in each step of a trace; the address of the instruction, then r0 to r15 and the
+// CPSR after it
+const STEP_VALUES: usize = 18;
+
+// The bits of the CPSR which are compared; the condition flags and the Thumb bit. The mode an
+// emulator starts in varies, so the rest isn't.
+const COMPARED_CPSR: u32 = 0xf000_0020;
+
+// A run of a binary; the registers after each instruction it executed, and the memory it
+// finished with. Traces are written as text, with a line for each step and for each non-zero
+// word of memory at the end of the run:
+//
+// step 0x00000000 0x00000001 0x00000000 ... 0x0000000c 0x00000000
+// mem 0x00000100 0x00000005
+//
+// where a step is the address of the instruction followed by r0 to r15 and the CPSR after it. Other
+// emulators, eg: qemu-arm or unicorn, are compared with through a wrapper which writes their runs
+// in this format.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Trace {
+    pub steps: Vec<Step>,
+    pub memory: Vec<(u32, u32)>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Step {
+    pub address: u32,
+    // r0 to r15, then the CPSR
+    pub registers: [u32; STEP_VALUES - 1],
+}
+
+impl fmt::Display for Trace {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for step in &self.steps {
+            write!(f, "step 0x{:08x}", step.address)?;
+            for value in &step.registers {
+                write!(f, " 0x{:08x}", value)?;
+            }
+            writeln!(f)?;
+        }
+        for (address, word) in &self.memory {
+            writeln!(f, "mem 0x{:08x} 0x{:08x}", address, word)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Trace {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let mut trace = Trace::default();
+        for (index, line) in s.lines().enumerate() {
+            let mut words = line.split_whitespace();
+            let kind = match words.next() {
+                Some(kind) => kind,
+                None => continue,
+            };
+            let values = words
+                .map(parse_number)
+                .collect::<std::result::Result<Vec<u32>, String>>()
+                .map_err(|e| format!("Line {}: {}", index + 1, e))?;
+            match (kind, values.len()) {
+                ("step", STEP_VALUES) => {
+                    let mut registers = [0; STEP_VALUES - 1];
+                    registers.copy_from_slice(&values[1..]);
+                    trace.steps.push(Step {
+                        address: values[0],
+                        registers,
+                    });
+                }
+                ("mem", 2) => trace.memory.push((values[0], values[1])),
+                _ => {
+                    return Err(format!(
+                        "Line {}: expected 'step' and {} values or 'mem' and 2, found '{}'",
+                        index + 1,
+                        STEP_VALUES,
+                        line
+                    ))
+                }
+            }
+        }
+        Ok(trace)
+    }
+}
+
+// Where two runs of a binary first differ
+#[derive(Debug, Clone, PartialEq)]
+pub enum Divergence {
+    // The step'th instructions executed were at different addresses
+    Address {
+        step: usize,
+        ours: u32,
+        theirs: u32,
+    },
+    // The step'th instruction left registers with different values, given with their names
+    Registers {
+        step: usize,
+        address: u32,
+        instruction: String,
+        registers: Vec<(String, u32, u32)>,
+    },
+    // The runs executed different numbers of instructions
+    Length {
+        ours: usize,
+        theirs: usize,
+    },
+    // The runs finished with a different word in memory
+    Memory {
+        address: u32,
+        ours: u32,
+        theirs: u32,
+    },
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Divergence::Address { step, ours, theirs } => write!(
+                f,
+                "Instruction {} is at 0x{:08x}, but the reference ran 0x{:08x}",
+                step + 1,
+                ours,
+                theirs
+            ),
+            Divergence::Registers {
+                step,
+                address,
+                instruction,
+                registers,
+            } => {
+                write!(
+                    f,
+                    "Instruction {} at 0x{:08x} ({}) left different registers:",
+                    step + 1,
+                    address,
+                    instruction
+                )?;
+                for (name, ours, theirs) in registers {
+                    write!(
+                        f,
+                        "\n  {: <4} 0x{:08x}, reference 0x{:08x}",
+                        name, ours, theirs
+                    )?;
+                }
+                Ok(())
+            }
+            Divergence::Length { ours, theirs } => write!(
+                f,
+                "The run executed {} instructions, but the reference executed {}",
+                ours, theirs
+            ),
+            Divergence::Memory {
+                address,
+                ours,
+                theirs,
+            } => write!(
+                f,
+                "Memory at 0x{:08x} is 0x{:08x}, but the reference left 0x{:08x}",
+                address, ours, theirs
+            ),
+        }
+    }
+}
+
+// Runs the binary on this emulator, tracing at most max_steps instructions
+pub fn trace(bytes: Vec<u8>, max_steps: u64) -> Result<Trace> {
+    let mut emulator = EmulatorState::with_memory_map(bytes, &Default::default())?;
+    emulator.set_output(Box::new(io::sink()));
+    let mut steps = Vec::new();
+    emulator.run_with(|state, address| {
+        let mut registers = [0; STEP_VALUES - 1];
+        for (value, reg) in registers.iter_mut().zip(Register::all()) {
+            *value = state.read_reg(reg);
+        }
+        steps.push(Step {
+            address: address.0,
+            registers,
+        });
+        (steps.len() as u64) < max_steps
+    })?;
+    let memory = emulator
+        .non_zero_words()
+        .into_iter()
+        .map(|(address, word)| (address.0, word.0))
+        .collect();
+    Ok(Trace { steps, memory })
+}
+
+// Runs the binary on a reference emulator; a command which is given the binary's filename after
+// its own arguments, and writes the trace of the run to stdout
+pub fn reference_trace(command: &str, binary: &Path) -> Result<Trace> {
+    let mut words = command.split_whitespace();
+    let program = words.next().ok_or("The reference command is empty")?;
+    let output = Command::new(program).args(words).arg(binary).output()?;
+    if !output.status.success() {
+        return Err(format!(
+            "The reference failed with {}: {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )
+        .into());
+    }
+    String::from_utf8(output.stdout)
+        .map_err(ArmError::parse)?
+        .parse::<Trace>()
+        .map_err(ArmError::parse)
+}
+
+// Finds the first difference between a run of the binary and a reference run of it. The PC is
+// followed through the address of each step rather than compared, as emulators report it at
+// different points in the pipeline, and the CPSR is compared by its flags and Thumb bit.
+pub fn compare(ours: &Trace, theirs: &Trace, bytes: &[u8]) -> Option<Divergence> {
+    for (step, (a, b)) in ours.steps.iter().zip(&theirs.steps).enumerate() {
+        if a.address != b.address {
+            return Some(Divergence::Address {
+                step,
+                ours: a.address,
+                theirs: b.address,
+            });
+        }
+        let registers: Vec<(String, u32, u32)> = Register::all()
+            .filter(|&reg| reg != Register::Pc)
+            .filter_map(|reg| {
+                let index = reg as usize;
+                let mask = match reg {
+                    Register::Cpsr => COMPARED_CPSR,
+                    _ => u32::MAX,
+                };
+                let (x, y) = (a.registers[index], b.registers[index]);
+                (x & mask != y & mask).then(|| (register_name(reg), x, y))
+            })
+            .collect();
+        if !registers.is_empty() {
+            let thumb =
+                step > 0 && ours.steps[step - 1].registers[Register::Cpsr as usize] & 0x20 != 0;
+            return Some(Divergence::Registers {
+                step,
+                address: a.address,
+                instruction: instruction_at(bytes, a.address, thumb),
+                registers,
+            });
+        }
+    }
+    if ours.steps.len() != theirs.steps.len() {
+        return Some(Divergence::Length {
+            ours: ours.steps.len(),
+            theirs: theirs.steps.len(),
+        });
+    }
+
+    // Words missing from a trace are zero
+    let word = |memory: &[(u32, u32)], address| {
+        memory
+            .iter()
+            .find(|&&(a, _)| a == address)
+            .map_or(0, |&(_, word)| word)
+    };
+    let mut addresses: Vec<u32> = ours
+        .memory
+        .iter()
+        .chain(&theirs.memory)
+        .map(|&(address, _)| address)
+        .collect();
+    addresses.sort_unstable();
+    addresses.into_iter().find_map(|address| {
+        let (x, y) = (word(&ours.memory, address), word(&theirs.memory, address));
+        (x != y).then_some(Divergence::Memory {
+            address,
+            ours: x,
+            theirs: y,
+        })
+    })
+}
+
+fn register_name(reg: Register) -> String {
+    match reg {
+        Register::Sp => String::from("sp"),
+        Register::Lr => String::from("lr"),
+        Register::Pc => String::from("pc"),
+        Register::Cpsr => String::from("cpsr"),
+        _ => format!("r{}", reg as usize),
+    }
+}
+
+// The disassembly of the instruction at an address of the binary
+fn instruction_at(bytes: &[u8], address: u32, thumb: bool) -> String {
+    let start = address as usize;
+    let decoded = if thumb {
+        bytes
+            .get(start..start + 2)
+            .and_then(|b| thumb::decode(u16::from_le_bytes([b[0], b[1]])).ok())
+    } else {
+        bytes
+            .get(start..start + 4)
+            .and_then(|b| decode::decode(&u32::from_le_bytes([b[0], b[1], b[2], b[3]])).ok())
+    };
+    decoded.map_or_else(
+        || String::from("not in the binary"),
+        |instr| disassemble_instruction(&instr, address),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assemble::assemble;
+
+    #[test]
+    fn test_difftest() {
+        let source = "mov r0,#1\nloop:\nadd r1,r1,r0\nsubs r0,r0,#1\nbne loop\n\
+                      str r1,[r0,#0x100]\nandeq r0,r0,r0\n";
+        let bytes = assemble(String::from(source))
+            .expect("assemble failed")
+            .to_bytes();
+        let ours = trace(bytes.clone(), 1000).expect("trace failed");
+        assert_eq!(ours.steps.len(), 5);
+        assert!(ours.memory.contains(&(0x100, 1)));
+
+        // A trace reads back as it was written, and matches itself
+        let written: Trace = ours.to_string().parse().expect("parse failed");
+        assert_eq!(written, ours);
+        assert_eq!(compare(&ours, &written, &bytes), None);
+
+        // The first difference is found, ignoring the PC and the CPSR's mode
+        let mut theirs = ours.clone();
+        theirs.steps[1].registers[Register::Pc as usize] = 0x8;
+        theirs.steps[2].registers[Register::Cpsr as usize] |= 0x1f;
+        theirs.steps[3].registers[1] = 7;
+        let divergence = compare(&ours, &theirs, &bytes).expect("no divergence");
+        assert_eq!(
+            divergence.to_string(),
+            "Instruction 4 at 0x0000000c (bne 0x00000004) left different registers:\n  \
+             r1   0x00000001, reference 0x00000007"
+        );
+
+        theirs = ours.clone();
+        theirs.steps.pop();
+        assert_eq!(
+            compare(&ours, &theirs, &bytes),
+            Some(Divergence::Length { ours: 5, theirs: 4 })
+        );
+        theirs = ours.clone();
+        theirs.memory.clear();
+        assert_eq!(
+            compare(&ours, &theirs, &bytes),
+            Some(Divergence::Memory {
+                address: 0x0,
+                ours: ours.memory[0].1,
+                theirs: 0
+            })
+        );
+        assert!("step 0x0 0x1".parse::<Trace>().is_err());
+    }
+}
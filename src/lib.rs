@@ -1,8 +1,19 @@
-extern crate enum_primitive_derive;
-extern crate nom;
-extern crate num_traits;
-pub mod assemble;
-mod constants;
-pub mod emulate;
-mod parse;
-mod types;
+// An assembler, emulator and disassembler for a subset of the ARM11 instruction set. The work is
+// split across three crates, which this crate re-exports under the paths they had before the
+// split: arm11-isa holds the instruction types, encoding, decoding, disassembly and timing shared
+// by the others, arm11-asm the assembler, and arm11-emu the emulator. Projects which only need
+// one part can depend on its crate alone.
+pub use arm11_asm as assemble;
+pub use arm11_emu as emulate;
+pub use arm11_isa as isa;
+pub use arm11_isa::disassemble;
+pub mod cli;
+pub mod difftest;
+pub mod prelude;
+pub mod reduce;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+
+pub use arm11_asm::asm;
+pub use arm11_isa::{instr, Address, ArmError, Result, SymbolTable, Word};
+pub use emulate::{Emulator, EmulatorState, Register, RegisterFile, RunResult, Status};
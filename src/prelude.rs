@@ -0,0 +1,9 @@
+// The types used by most programs built on the crate, so they can be imported together.
+// eg: use arm11::prelude::*;
+pub use crate::{
+    assemble::assemble,
+    disassemble::disassemble,
+    emulate::{EmulatorState, Register, RegisterFile, RunResult, Status},
+    isa::address::{Address, SymbolTable, Word},
+    isa::types::{ArmError, Result},
+};
@@ -0,0 +1,100 @@
+use std::panic::{self, AssertUnwindSafe};
+
+use crate::{
+    assemble::assemble,
+    emulate::{EmulatorState, Status},
+    isa::types::*,
+};
+
+// Reduces a source file which makes the emulator fail to a smaller one which fails in the same
+// way, by delta debugging over its lines. Chunks of lines are removed while the failure still
+// reproduces, halving the chunk size whenever no chunk can be removed, until no single line can
+// be removed. Sources which no longer assemble, or which run for more than max_steps steps, are
+// treated as not reproducing the failure.
+pub fn reduce(source: &str, max_steps: u64) -> Result<String> {
+    let expected = failure(source, max_steps).ok_or("The source doesn't make the emulator fail")?;
+    let reproduces = |lines: &[&str]| failure(&join(lines), max_steps).as_ref() == Some(&expected);
+
+    let mut lines: Vec<&str> = source.lines().collect();
+    let mut chunk_size = lines.len().div_ceil(2).max(1);
+    loop {
+        let mut removed = false;
+        let mut start = 0;
+        while start < lines.len() {
+            let end = (start + chunk_size).min(lines.len());
+            let candidate: Vec<&str> = lines[..start]
+                .iter()
+                .chain(&lines[end..])
+                .copied()
+                .collect();
+            if reproduces(&candidate) {
+                lines = candidate;
+                removed = true;
+            } else {
+                start = end;
+            }
+        }
+
+        if chunk_size == 1 && !removed {
+            break;
+        }
+        if !removed {
+            chunk_size = chunk_size.div_ceil(2);
+        }
+    }
+
+    Ok(join(&lines))
+}
+
+// Assembles and runs the source, returning the error the emulator stopped with, or the message of
+// a panic. Returns None if the source doesn't assemble, halts normally, or runs for more than
+// max_steps steps.
+pub fn failure(source: &str, max_steps: u64) -> Option<String> {
+    let bytes = assemble(String::from(source)).ok()?.to_bytes();
+
+    let outcome = panic::catch_unwind(AssertUnwindSafe(|| -> Result<bool> {
+        let mut emulator = EmulatorState::with_memory_map(bytes, &Default::default())?;
+        for _ in 0..max_steps {
+            if emulator.step()? == Status::Halted {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }));
+
+    match outcome {
+        Ok(Ok(_)) => None,
+        Ok(Err(e)) => Some(e.to_string()),
+        Err(payload) => Some(format!(
+            "panic: {}",
+            payload
+                .downcast_ref::<&str>()
+                .map(|s| s.to_string())
+                .or_else(|| payload.downcast_ref::<String>().cloned())
+                .unwrap_or_default()
+        )),
+    }
+}
+
+fn join(lines: &[&str]) -> String {
+    lines.iter().map(|line| format!("{}\n", line)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reduce() {
+        // The mrc fails as there is no coprocessor, and nothing else is needed to reach it
+        let source = "mov r0,#4\nloop:\nadd r1,r1,r1\nsubs r0,r0,#1\nbne loop\n\
+                      mrc p15, 0, r2, c0, c0, 0\nmov r3,#1\nandeq r0,r0,r0\n";
+        let reduced = reduce(source, 1000).expect("reduce failed");
+        assert_eq!(reduced, "mrc p15, 0, r2, c0, c0, 0\n");
+        assert_eq!(failure(&reduced, 1000), failure(source, 1000));
+
+        assert!(reduce("mov r0,#1\nandeq r0,r0,r0\n", 1000).is_err());
+        // Infinite loops aren't failures
+        assert_eq!(failure("loop:\nb loop\n", 1000), None);
+    }
+}
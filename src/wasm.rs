@@ -0,0 +1,97 @@
+// JavaScript bindings for the assembler and the stepping Emulator, built with the wasm feature for
+// wasm32-unknown-unknown, eg: with cargo rustc --target wasm32-unknown-unknown --features wasm
+// --crate-type cdylib, and then wasm-bindgen. Errors are thrown as JavaScript errors with the
+// message the command line would print.
+// eg:
+//
+// const emulator = Emulator.fromSource("mov r0,#42\nswi #1\nandeq r0,r0,r0\n");
+// while (emulator.run(10000) === "running") {}
+// console.log(emulator.takeOutput());
+//
+use wasm_bindgen::prelude::*;
+
+use crate::{
+    assemble::assemble_to_bytes,
+    emulate::{self, Status},
+    Result,
+};
+
+// Assembles a source to the binary
+#[wasm_bindgen]
+pub fn assemble(source: &str) -> std::result::Result<Vec<u8>, JsError> {
+    to_js(assemble_to_bytes(source))
+}
+
+#[wasm_bindgen]
+pub struct Emulator(emulate::Emulator);
+
+#[wasm_bindgen]
+impl Emulator {
+    #[wasm_bindgen(constructor)]
+    pub fn new(bytes: Vec<u8>) -> std::result::Result<Emulator, JsError> {
+        to_js(emulate::Emulator::new(bytes)).map(Emulator)
+    }
+
+    #[wasm_bindgen(js_name = fromSource)]
+    pub fn from_source(source: &str) -> std::result::Result<Emulator, JsError> {
+        Self::new(to_js(assemble_to_bytes(source))?)
+    }
+
+    // Returns "running", "halted" or "watchpoint"
+    pub fn step(&mut self) -> std::result::Result<String, JsError> {
+        to_js(self.0.step()).map(status_name)
+    }
+
+    pub fn run(&mut self, max_instructions: u32) -> std::result::Result<String, JsError> {
+        to_js(self.0.run(u64::from(max_instructions))).map(status_name)
+    }
+
+    pub fn registers(&self) -> Vec<u32> {
+        self.0.registers()
+    }
+
+    pub fn address(&self) -> u32 {
+        self.0.address()
+    }
+
+    #[wasm_bindgen(js_name = readWord)]
+    pub fn read_word(&self, address: u32) -> Option<u32> {
+        self.0.read_word(address)
+    }
+
+    // The counts are numbers rather than BigInts, which is exact up to 2^53
+    pub fn instructions(&self) -> f64 {
+        self.0.instructions() as f64
+    }
+
+    pub fn cycles(&self) -> f64 {
+        self.0.cycles() as f64
+    }
+
+    #[wasm_bindgen(js_name = pushInput)]
+    pub fn push_input(&mut self, input: &str) {
+        self.0.push_input(input)
+    }
+
+    #[wasm_bindgen(js_name = takeOutput)]
+    pub fn take_output(&mut self) -> String {
+        self.0.take_output()
+    }
+
+    #[wasm_bindgen(js_name = finalState)]
+    pub fn final_state(&self) -> String {
+        self.0.final_state()
+    }
+}
+
+fn status_name(status: Status) -> String {
+    String::from(match status {
+        Status::Running => "running",
+        Status::Halted => "halted",
+        Status::Watchpoint(_) => "watchpoint",
+    })
+}
+
+fn to_js<T>(result: Result<T>) -> std::result::Result<T, JsError> {
+    result.map_err(|e| JsError::new(&e.to_string()))
+}
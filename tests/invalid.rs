@@ -0,0 +1,147 @@
+use arm11::{
+    assemble::{assemble, DiagnosticCode, DiagnosticCode::*, Diagnostics},
+    emulate::{run_from, ImageError, MemoryMap, Options, Region},
+};
+
+// Sources which must fail to assemble, with the code of the diagnostic and the line and column
+// it points to. The codes are part of the assembler's interface, so a change here is a change
+// to what tools matching on them see.
+const SOURCES: &[(&str, DiagnosticCode, usize, usize)] = &[
+    // Invalid registers
+    ("add r16,r1,#1\n", InvalidRegister, 1, 5),
+    ("add r1,rx,#1\n", InvalidRegister, 1, 8),
+    ("ldr r0,[r16]\n", InvalidRegister, 1, 9),
+    ("add r1,spare,#1\n", InvalidRegister, 1, 8),
+    ("count .req r16\n", InvalidRegister, 1, 1),
+    ("lr .req r4\n", InvalidRegister, 1, 1),
+    (".unreq count\n", InvalidRegister, 1, 1),
+    // Malformed operands
+    ("mov r0,#1\nfoo r0,r1\n", UnknownMnemonic, 2, 1),
+    ("mov r0\n", UnexpectedEnd, 1, 7),
+    ("add r1,r2,\n", UnexpectedEnd, 1, 11),
+    ("add r1,r2,#1,r3\n", UnexpectedToken, 1, 14),
+    ("ldr r0,[r1\n", UnexpectedEnd, 1, 11),
+    ("mov r0,#0xzz\n", InvalidExpression, 1, 1),
+    (".skip 8\n.org 4\n", InvalidExpression, 2, 1),
+    ("b nowhere\n", UnexpectedToken, 1, 3),
+    // Directives with text left over
+    (".word 1 junk\n", UnexpectedToken, 1, 9),
+    (".skip 4 garbage\n", UnexpectedToken, 1, 9),
+    (".skip -1\n", InvalidExpression, 1, 1),
+    ("mov r0,#1\n.skip 0xfffffffc\n", InvalidExpression, 2, 1),
+    ("mov r0,#1\n.org 0x80000000\n", InvalidExpression, 2, 1),
+    ("mov r0,#1\n.org 0xfffffff0\n", InvalidExpression, 2, 1),
+    ("mov r0,#1\n.align 31\n", InvalidExpression, 2, 1),
+    ("mov r0,#1<<40\n", InvalidExpression, 1, 1),
+    (".word 0x7fffffff*0x7fffffff\n", InvalidExpression, 1, 1),
+    (".align 2 3\n", UnexpectedToken, 1, 10),
+    (".ltorg now\n", UnexpectedToken, 1, 8),
+    // Only the whole .thumb or .arm switches instruction set
+    (".thumbfoo\nmov r0,r0\n", UnexpectedToken, 1, 7),
+    (".arm_whatever\n", UnexpectedToken, 1, 5),
+    // Unencodable immediates
+    ("mov r0,#0x101\n", UnencodableConstant, 1, 8),
+    ("ldr r0,[r1,#5000]\n", Truncated, 1, 12),
+    ("mov r0,r1,lsl #40\n", Truncated, 1, 15),
+    // Labels defined twice, or made global without being defined
+    ("loop:\nmov r0,r0\nloop:\n", DuplicateLabel, 3, 1),
+    (".global main\nmov r0,r0\n", InvalidLabel, 1, 1),
+];
+
+// Binaries which the emulator must refuse to run, with the code of the error
+const BINARIES: &[(&str, &[u8], &str)] = &[
+    ("odd length", &[0x01, 0x00, 0xa0], "E0101"),
+    ("single byte", &[0x00], "E0101"),
+    (
+        "truncated word",
+        &[0x01, 0x00, 0xa0, 0xe3, 0x00, 0x00],
+        "E0102",
+    ),
+    ("halfword", &[0x00, 0x00], "E0102"),
+];
+
+// Binaries found by running random instructions, which crashed the emulator rather than running
+// or failing with an error, with the RAM they're loaded into. Each word is an instruction.
+const CRASHES: &[(&str, &[u32], Region)] = &[
+    // mvn r0,#0; mul r1,r0,r0, whose product overflows 32 bits
+    (
+        "multiply overflow",
+        &[0xe3e00000, 0xe0010090, 0x0],
+        Region {
+            base: 0,
+            size: 0x100,
+        },
+    ),
+    // mov r1,#40; mov r0,r2,ror r1, rotating by more than 32
+    (
+        "long rotation",
+        &[0xe3a01028, 0xe1a00172, 0x0],
+        Region {
+            base: 0,
+            size: 0x100,
+        },
+    ),
+    // bl at the top of the address space, whose return address wraps past zero
+    (
+        "branch and link past the top",
+        &[0xe1a00000, 0xe1a00000, 0xeb000000, 0x0],
+        Region {
+            base: 0xfffffff0,
+            size: 0x10,
+        },
+    ),
+];
+
+#[test]
+fn test_invalid_sources() {
+    for &(source, code, line, column) in SOURCES {
+        let name = source.trim_end().replace('\n', "; ");
+        let err = match assemble(String::from(source)) {
+            Ok(_) => panic!("{} assembled", name),
+            Err(err) => err,
+        };
+        let diagnostic = err
+            .downcast_ref::<Diagnostics>()
+            .unwrap_or_else(|| panic!("{} failed without a diagnostic: {}", name, err))
+            .first();
+        assert_eq!(
+            (diagnostic.code, diagnostic.line, diagnostic.column),
+            (code, line, column),
+            "{}: {}",
+            name,
+            diagnostic
+        );
+    }
+}
+
+#[test]
+fn test_invalid_binaries() {
+    for &(name, bytes, code) in BINARIES {
+        let err = match run_from(bytes, &Options::default()) {
+            Ok(_) => panic!("{} ran", name),
+            Err(err) => err,
+        };
+        let image_error = err
+            .downcast_ref::<ImageError>()
+            .unwrap_or_else(|| panic!("{} failed without an image error: {}", name, err));
+        assert_eq!(image_error.code(), code, "{}: {}", name, image_error);
+    }
+}
+
+#[test]
+fn test_crashes() {
+    for &(name, words, ram) in CRASHES {
+        let bytes: Vec<u8> = words.iter().flat_map(|word| word.to_le_bytes()).collect();
+        let options = Options {
+            memory_map: MemoryMap {
+                ram,
+                ..MemoryMap::default()
+            },
+            max_steps: Some(100),
+            ..Options::default()
+        };
+        // Running to a halt or failing with an error are both fine, as long as it doesn't panic
+        let result = std::panic::catch_unwind(|| run_from(&bytes[..], &options).map(|_| ()));
+        assert!(result.is_ok(), "{} crashed the emulator", name);
+    }
+}
@@ -0,0 +1,39 @@
+use std::{env, fs, path::Path};
+
+use arm11::{assemble::assemble_to_bytes, emulate::run_program};
+
+// The number of instructions a program may execute before it's considered stuck
+const MAX_INSTRUCTIONS: u64 = 1_000_000;
+
+// Runs every program in the programs/ corpus, with its .in file as UART input if it has one,
+// and compares everything it writes against its .out golden. Setting UPDATE_GOLDENS writes the
+// goldens from the current output instead, eg: after adding a program.
+#[test]
+fn test_programs() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("programs");
+    let update = env::var_os("UPDATE_GOLDENS").is_some();
+
+    let mut sources: Vec<_> = fs::read_dir(&dir)
+        .expect("read programs failed")
+        .map(|entry| entry.expect("read programs failed").path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "s"))
+        .collect();
+    sources.sort();
+    assert!(!sources.is_empty(), "no programs in {}", dir.display());
+
+    for source in sources {
+        let input = fs::read(source.with_extension("in")).unwrap_or_default();
+        let output = assemble_to_bytes(&fs::read_to_string(&source).expect("read source failed"))
+            .and_then(|bytes| run_program(bytes, &input, MAX_INSTRUCTIONS))
+            .unwrap_or_else(|e| panic!("{} failed: {}", source.display(), e));
+
+        let golden = source.with_extension("out");
+        if update {
+            fs::write(&golden, &output).expect("write golden failed");
+            continue;
+        }
+        let expected = fs::read_to_string(&golden)
+            .unwrap_or_else(|_| panic!("{} has no golden output", source.display()));
+        assert_eq!(output, expected, "{} output changed", source.display());
+    }
+}